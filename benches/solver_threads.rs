@@ -0,0 +1,37 @@
+//! This measures the throughput of one shared Solver as the number of threads grows.
+//! Run it with `cargo bench --bench solver_threads`.
+
+use std::time::Instant;
+
+use nalgebra as na;
+
+/// The number of solves each thread performs.
+const SOLVES_PER_THREAD: usize = 20_000;
+
+fn main() {
+    let solver = smas::solve::Solver::new(smas::util::default_s_matrix());
+    let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(smas::matrices::A_STD_015);
+    let max_threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+
+    println!("threads\tsolves/s\tspeedup");
+    let mut single_thread_rate = 0.0;
+    let mut n_threads = 1;
+    while n_threads <= max_threads {
+        let start = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..n_threads {
+                scope.spawn(|| {
+                    for _ in 0..SOLVES_PER_THREAD {
+                        std::hint::black_box(solver.solve(&acc_vector).unwrap());
+                    }
+                });
+            }
+        });
+        let rate = (n_threads * SOLVES_PER_THREAD) as f64 / start.elapsed().as_secs_f64();
+        if n_threads == 1 {
+            single_thread_rate = rate;
+        }
+        println!("{n_threads}\t{rate:.0}\t{:.2}", rate / single_thread_rate);
+        n_threads *= 2;
+    }
+}
@@ -0,0 +1,277 @@
+#!/usr/bin/env python3
+"""Writes the Arrow IPC fixtures read by the tests of src/arrow.rs.
+
+The files are built from the Arrow format specification (Message.fbs, Schema.fbs
+and File.fbs) with a flatbuffer builder that works back to front like the
+reference flatbuffers builders used by pyarrow, independently of the arrow-ipc
+writer: default scalars are omitted, identical vtables are shared, so a table
+may refer to a vtable after it, and objects follow the tables that refer to
+them. pyarrow_layout.arrow follows the layout of
+pyarrow.feather.write_feather(table, compression="uncompressed"): nullable
+fields, pandas metadata, several record batches and an end of stream marker
+before the footer.
+
+The files were not written by pyarrow itself, which is not needed here; with
+pyarrow installed, pyarrow.feather.read_table("pyarrow_layout.arrow") checks
+that it reads the same table.
+
+Usage: python3 make_fixtures.py [directory]
+"""
+
+import json
+import os
+import struct
+import sys
+
+MAGIC = b"ARROW1"
+V5 = 4
+HEADER_SCHEMA, HEADER_DICTIONARY_BATCH, HEADER_RECORD_BATCH = 1, 2, 3
+TYPE_INT, TYPE_FLOATING_POINT, TYPE_UTF8 = 2, 3, 5
+SINGLE, DOUBLE = 1, 2
+
+
+class Builder:
+    """A flatbuffer builder that prepends each object, so objects are referred to by their
+    distance from the end of the buffer."""
+
+    def __init__(self):
+        self.data = bytearray()
+        self.vtables = {}
+
+    def offset(self):
+        return len(self.data)
+
+    def prep(self, size, additional=0):
+        self.data[0:0] = bytes((-(len(self.data) + additional)) % size)
+
+    def prepend(self, raw):
+        self.data[0:0] = raw
+
+    def uoffset(self, target):
+        self.prep(4)
+        self.prepend(struct.pack("<I", self.offset() + 4 - target))
+
+    def string(self, text):
+        raw = text.encode()
+        self.prep(4, len(raw) + 1)
+        self.prepend(raw + b"\0")
+        self.prepend(struct.pack("<I", len(raw)))
+        return self.offset()
+
+    def tables(self, targets):
+        self.prep(4, 4 * len(targets))
+        for target in reversed(targets):
+            self.uoffset(target)
+        self.prepend(struct.pack("<I", len(targets)))
+        return self.offset()
+
+    def structs(self, raw, count):
+        self.prep(4, len(raw))
+        self.prep(8, len(raw))
+        self.prepend(raw)
+        self.prepend(struct.pack("<I", count))
+        return self.offset()
+
+    def table(self, fields):
+        """fields maps a field index to (format, value, default), or to ("offset", target)."""
+        end = self.offset()
+        positions = {}
+        for index in sorted(fields, reverse=True):
+            field = fields[index]
+            if field[0] == "offset":
+                self.uoffset(field[1])
+            else:
+                kind, value, default = field
+                if value == default:
+                    continue
+                self.prep(struct.calcsize(kind))
+                self.prepend(struct.pack("<" + kind, value))
+            positions[index] = self.offset()
+        self.prep(4)
+        self.prepend(bytes(4))
+        table = self.offset()
+        length = max(positions, default=-1) + 1
+        vtable = struct.pack("<HH", 4 + 2 * length, table - end)
+        vtable += b"".join(struct.pack("<H", table - positions[i] if i in positions else 0) for i in range(length))
+        if vtable not in self.vtables:
+            self.prepend(vtable)
+            self.vtables[vtable] = self.offset()
+        start = len(self.data) - table
+        self.data[start:start + 4] = struct.pack("<i", self.vtables[vtable] - table)
+        return table
+
+    def finish(self, root):
+        self.prep(8, 4)
+        self.uoffset(root)
+        return bytes(self.data)
+
+
+def key_values(builder, metadata):
+    return builder.tables([
+        builder.table({0: ("offset", builder.string(key)), 1: ("offset", builder.string(value))})
+        for key, value in metadata
+    ])
+
+
+def schema(builder, columns, metadata):
+    """columns are (name, type, dictionary id or None)."""
+    fields = []
+    for name, column_type, dictionary in columns:
+        if column_type == "utf8":
+            type_type, type_table = TYPE_UTF8, builder.table({})
+        elif column_type in ("f32", "f64"):
+            type_type, type_table = TYPE_FLOATING_POINT, builder.table({0: ("h", SINGLE if column_type == "f32" else DOUBLE, 0)})
+        else:
+            bits = int(column_type[1:])
+            type_type, type_table = TYPE_INT, builder.table({0: ("i", bits, 0), 1: ("?", column_type[0] == "i", False)})
+        field = {
+            0: ("offset", builder.string(name)),
+            1: ("?", True, False),
+            2: ("B", type_type, 0),
+            3: ("offset", type_table),
+            5: ("offset", builder.tables([])),
+        }
+        if dictionary is not None:
+            index_type = builder.table({0: ("i", 32, 0), 1: ("?", True, False)})
+            field[4] = ("offset", builder.table({0: ("q", dictionary, 0), 1: ("offset", index_type)}))
+        fields.append(builder.table(field))
+    return builder.table({
+        1: ("offset", builder.tables(fields)),
+        2: ("offset", key_values(builder, metadata)),
+    })
+
+
+def message(header_type, build_header, body_length):
+    builder = Builder()
+    header = build_header(builder)
+    return builder.finish(builder.table({
+        0: ("h", V5, 0),
+        1: ("B", header_type, 0),
+        2: ("offset", header),
+        3: ("q", body_length, 0),
+    }))
+
+
+def column_buffers(column_type, values):
+    """This returns the buffers of a column without nulls, after its validity buffer."""
+    if column_type == "utf8":
+        offsets, data = [0], b""
+        for value in values:
+            data += value.encode()
+            offsets.append(len(data))
+        return [struct.pack("<%di" % len(offsets), *offsets), data]
+    kind = {"f32": "f", "f64": "d", "i8": "b", "u8": "B", "i16": "h", "i32": "i", "i64": "q", "u64": "Q"}[column_type]
+    return [struct.pack("<%d%s" % (len(values), kind), *values)]
+
+
+def record_batch(columns, rows, validity=None, compressed=False, header_type=HEADER_RECORD_BATCH, dictionary_id=None):
+    """This returns the metadata and body of a record batch of the given columns, each
+    (type, values). validity maps a column to its validity bitmap, which may have nulls."""
+    validity = validity or {}
+    body, buffers, nodes = b"", b"", b""
+    for i, (column_type, values) in enumerate(columns):
+        null_count = sum(1 for j in range(len(values)) if i in validity and not validity[i][j // 8] >> (j % 8) & 1)
+        nodes += struct.pack("<qq", len(values), null_count)
+        for buffer in [validity.get(i, b"")] + column_buffers(column_type, values):
+            if compressed:
+                # ** an uncompressed length of -1 marks a buffer stored without compression
+                buffer = struct.pack("<q", -1) + buffer
+            buffers += struct.pack("<qq", len(body), len(buffer))
+            body += buffer + bytes((-len(buffer)) % 8)
+
+    def build(builder):
+        # ** codec LZ4_FRAME and method BUFFER, both the defaults
+        compression = builder.table({0: ("b", 0, 0), 1: ("b", 0, 0)}) if compressed else None
+        fields = {
+            0: ("q", rows, 0),
+            1: ("offset", builder.structs(nodes, len(columns))),
+            2: ("offset", builder.structs(buffers, len(buffers) // 16)),
+        }
+        if compression is not None:
+            fields[3] = ("offset", compression)
+        batch = builder.table(fields)
+        if header_type == HEADER_DICTIONARY_BATCH:
+            return builder.table({0: ("q", dictionary_id, 0), 1: ("offset", batch)})
+        return batch
+
+    return message(header_type, build, len(body)), body
+
+
+def arrow_file(columns, metadata, batches, dictionaries=()):
+    """This returns an Arrow IPC file of the given schema and messages, each (metadata, body)."""
+    schema_message = message(HEADER_SCHEMA, lambda builder: schema(builder, columns, metadata), 0)
+    file = bytearray(MAGIC + bytes(2))
+    blocks = {"dictionaries": b"", "batches": b""}
+    for kind, messages in [("schema", [(schema_message, b"")]), ("dictionaries", dictionaries), ("batches", batches)]:
+        for metadata_bytes, body in messages:
+            offset = len(file)
+            file += struct.pack("<Ii", 0xFFFFFFFF, len(metadata_bytes)) + metadata_bytes + body
+            if kind in blocks:
+                blocks[kind] += struct.pack("<qi4xq", offset, 8 + len(metadata_bytes), len(body))
+    file += struct.pack("<Ii", 0xFFFFFFFF, 0)
+
+    builder = Builder()
+    footer_schema = schema(builder, columns, metadata)
+    footer = builder.finish(builder.table({
+        0: ("h", V5, 0),
+        1: ("offset", footer_schema),
+        2: ("offset", builder.structs(blocks["dictionaries"], len(blocks["dictionaries"]) // 24)),
+        3: ("offset", builder.structs(blocks["batches"], len(blocks["batches"]) // 24)),
+    }))
+    file += footer + struct.pack("<i", len(footer)) + MAGIC
+    return bytes(file)
+
+
+def pandas_metadata(columns):
+    return json.dumps({
+        "index_columns": [{"kind": "range", "name": None, "start": 0, "stop": 4, "step": 1}],
+        "column_indexes": [],
+        "columns": [
+            {"name": name, "field_name": name, "pandas_type": pandas_type, "numpy_type": numpy_type, "metadata": None}
+            for name, pandas_type, numpy_type in columns
+        ],
+        "creator": {"library": "pyarrow", "version": "14.0.1"},
+        "pandas_version": "2.1.3",
+    })
+
+
+def main():
+    directory = sys.argv[1] if len(sys.argv) > 1 else os.path.dirname(os.path.abspath(__file__))
+    schema_columns = [("reaction", "utf8", None), ("flux", "f64", None), ("count", "i64", None),
+                      ("weight", "f32", None), ("flag", "u8", None)]
+    metadata = [("pandas", pandas_metadata([
+        ("reaction", "unicode", "object"), ("flux", "float64", "float64"), ("count", "int64", "int64"),
+        ("weight", "float32", "float32"), ("flag", "uint8", "uint8"),
+    ]))]
+    types = [column_type for _, column_type, _ in schema_columns]
+    rows = [
+        ("hk", 1.5, -3, 0.25, 0),
+        ("pgi", -2.0e-300, 7, -1.5, 255),
+        ("pfk", 0.0, -9007199254740992, 3.0, 1),
+        ("fba", 1.0e300, 42, -0.125, 2),
+    ]
+
+    def batch(rows, validity=None, types=types, **options):
+        columns = [(column_type, [row[i] for row in rows]) for i, column_type in enumerate(types)]
+        return record_batch(columns, len(rows), validity, **options)
+
+    fixtures = {
+        # ** a validity bitmap with every bit set, as written by some writers for columns without nulls
+        "pyarrow_layout.arrow": arrow_file(schema_columns, metadata, [batch(rows[:3], {1: b"\x07"}), batch(rows[3:])]),
+        "nulls.arrow": arrow_file(schema_columns, metadata, [batch(rows, {1: b"\x0b"})]),
+        "compressed.arrow": arrow_file(schema_columns, metadata, [batch(rows, compressed=True)]),
+        "dictionary.arrow": arrow_file(
+            # ** the field has the type of the dictionary, and the record batch holds int32 indices
+            [("reaction", "utf8", 0)] + schema_columns[1:],
+            metadata,
+            [batch([(0,) + row[1:] for row in rows[:1]], types=["i32"] + types[1:])],
+            [record_batch([("utf8", ["hk"])], 1, header_type=HEADER_DICTIONARY_BATCH, dictionary_id=0)],
+        ),
+    }
+    for name, contents in fixtures.items():
+        with open(os.path.join(directory, name), "wb") as file:
+            file.write(contents)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,350 @@
+use crate::error::SmasError;
+use crate::io::{LabeledMatrix, Provenance};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use nalgebra as na;
+
+/// The magic bytes at the start and end of an Arrow IPC file.
+pub const ARROW_MAGIC: &[u8; 6] = b"ARROW1";
+
+/// This is a column of an Arrow table.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Column {
+    /// Names, e.g. the reaction of each row
+    Utf8(Vec<String>),
+    /// Values
+    Float64(Vec<f64>),
+}
+
+impl Column {
+    /// This returns the number of rows in the column.
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Utf8(values) => values.len(),
+            Column::Float64(values) => values.len(),
+        }
+    }
+
+    /// This returns whether the column has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// This is an Arrow table: named columns of equal length, and key-value metadata.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Table {
+    /// The name of each column
+    pub names: Vec<String>,
+    /// The columns
+    pub columns: Vec<Column>,
+    /// The metadata of the schema, e.g. the provenance of a smas output, sorted by key
+    pub metadata: Vec<(String, String)>,
+}
+
+/// This returns whether a path names an Arrow IPC file by its extension: .arrow, .feather or .ipc.
+///
+/// # Arguments
+/// * `path` - the path
+///
+pub fn is_arrow_path<R: AsRef<Path>>(path: R) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ["arrow", "feather", "ipc"].contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// This builds the table of a labeled matrix: a Utf8 column of row names, then one Float64
+/// column per matrix column, with the provenance as the metadata. This is the layout of a data
+/// frame with a name column, e.g. pandas or R.
+///
+/// # Arguments
+/// * `matrix` - the matrix
+/// * `label_column` - the name of the column of row names, e.g. "reaction"
+/// * `row_labels` - the name of each row
+/// * `col_labels` - the name of each column
+/// * `provenance` - where the matrix came from
+///
+pub fn matrix_table(
+    matrix: &na::DMatrix<f64>,
+    label_column: &str,
+    row_labels: Vec<String>,
+    col_labels: Vec<String>,
+    provenance: &Provenance,
+) -> Table {
+    // ** Arrow metadata is a map, so the keys are unique and their order is not kept
+    let mut metadata = BTreeMap::from([
+        (String::from("title"), provenance.title.clone()),
+        (String::from("smas version"), String::from(env!("CARGO_PKG_VERSION"))),
+        (String::from("subcommand"), provenance.subcommand.clone()),
+    ]);
+    metadata.extend(provenance.parameters.iter().cloned());
+    if !provenance.inputs.is_empty() {
+        metadata.insert(String::from("inputs"), provenance.inputs.join("\n"));
+    }
+    let mut names = vec![label_column.to_string()];
+    names.extend(col_labels);
+    let mut columns = vec![Column::Utf8(row_labels)];
+    columns.extend(matrix.column_iter().map(|column| Column::Float64(column.iter().copied().collect())));
+    Table { names, columns, metadata: metadata.into_iter().collect() }
+}
+
+/// This reads the matrix of a table, the reverse of `matrix_table`: a leading Utf8 column, if
+/// any, names the rows, and every other column, which must be numeric, is a column of the
+/// matrix named after it.
+///
+/// # Arguments
+/// * `table` - the table
+///
+pub fn table_matrix(table: &Table) -> Result<LabeledMatrix, SmasError> {
+    let (row_labels, first) = match table.columns.first() {
+        Some(Column::Utf8(labels)) => (Some(labels.clone()), 1),
+        _ => (None, 0),
+    };
+    let nrows = table.columns.first().map_or(0, Column::len);
+    let mut values = vec!();
+    for (name, column) in table.names.iter().zip(&table.columns).skip(first) {
+        match column {
+            Column::Float64(column) => values.extend(column),
+            Column::Utf8(_) => return Err(arrow_error(format!("the column \"{name}\" holds strings, but only the first column may name the rows"))),
+        }
+    }
+    let col_labels = table.names[first..].to_vec();
+    Ok(LabeledMatrix {
+        matrix: na::DMatrix::from_vec(nrows, col_labels.len(), values),
+        row_labels,
+        col_labels: Some(col_labels),
+    })
+}
+
+/// This returns an error reading an Arrow file. The reader only sees the bytes, so callers record
+/// the path with SmasError::in_file.
+fn arrow_error(message: String) -> SmasError {
+    SmasError::InvalidFile { path: None, message: format!("invalid Arrow IPC file: {message}") }
+}
+
+/// This writes a table as an Arrow IPC file, also known as Feather version 2, with one record
+/// batch. Returns SmasError::InvalidArgument if the columns differ in length.
+///
+/// # Arguments
+/// * `table` - the table
+///
+#[cfg(feature = "arrow")]
+pub fn write_table(table: &Table) -> Result<Vec<u8>, SmasError> {
+    use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+    use arrow_ipc::writer::FileWriter;
+    use arrow_schema::{DataType, Field, Schema};
+    use std::sync::Arc;
+
+    let fields: Vec<Field> = table.names.iter()
+        .zip(&table.columns)
+        .map(|(name, column)| match column {
+            Column::Utf8(_) => Field::new(name, DataType::Utf8, false),
+            Column::Float64(_) => Field::new(name, DataType::Float64, false),
+        })
+        .collect();
+    let schema = Arc::new(Schema::new(fields).with_metadata(table.metadata.iter().cloned().collect()));
+    let arrays: Vec<ArrayRef> = table.columns.iter()
+        .map(|column| -> ArrayRef {
+            match column {
+                Column::Utf8(values) => Arc::new(StringArray::from_iter_values(values)),
+                Column::Float64(values) => Arc::new(Float64Array::from(values.clone())),
+            }
+        })
+        .collect();
+    let write_error = |e: arrow_schema::ArrowError| SmasError::InvalidArgument(format!("failed to write an Arrow table: {e}"));
+    let batch = RecordBatch::try_new(Arc::clone(&schema), arrays).map_err(write_error)?;
+    let mut writer = FileWriter::try_new(vec!(), &schema).map_err(write_error)?;
+    writer.write(&batch).map_err(write_error)?;
+    writer.finish().map_err(write_error)?;
+    writer.into_inner().map_err(write_error)
+}
+
+/// This reads an Arrow IPC file, also known as Feather version 2. Integer and float columns of
+/// any width are read as Float64 and string columns as Utf8. Record batches are concatenated.
+/// Columns with nulls, dictionary encoded columns, compressed buffers and other column types
+/// are not supported.
+///
+/// # Arguments
+/// * `bytes` - the contents of the file
+///
+#[cfg(feature = "arrow")]
+pub fn read_table(bytes: &[u8]) -> Result<Table, SmasError> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+    use arrow_schema::DataType;
+
+    let reader = FileReader::try_new(std::io::Cursor::new(bytes), None)
+        .map_err(|e| arrow_error(e.to_string()))?;
+    let schema = reader.schema();
+    let names: Vec<String> = schema.fields().iter().map(|field| field.name().clone()).collect();
+    let mut columns = vec!();
+    for field in schema.fields() {
+        let name = field.name();
+        columns.push(match field.data_type() {
+            DataType::Utf8 | DataType::LargeUtf8 => Column::Utf8(vec!()),
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+            | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+            | DataType::Float32 | DataType::Float64 => Column::Float64(vec!()),
+            DataType::Dictionary(_, _) => {
+                return Err(arrow_error(format!("the column \"{name}\" is dictionary encoded, which is not supported")))
+            }
+            data_type => {
+                return Err(arrow_error(format!("the column \"{name}\" has Arrow type {data_type}; only numbers and strings are supported")))
+            }
+        });
+    }
+    let mut metadata: Vec<(String, String)> = schema.metadata().clone().into_iter().collect();
+    metadata.sort();
+
+    for batch in reader {
+        let batch = batch.map_err(|e| arrow_error(e.to_string()))?;
+        for ((name, column), array) in names.iter().zip(&mut columns).zip(batch.columns()) {
+            if array.null_count() > 0 {
+                return Err(arrow_error(format!("the column \"{name}\" has {} nulls", array.null_count())));
+            }
+            match column {
+                Column::Utf8(values) => match array.data_type() {
+                    DataType::LargeUtf8 => values.extend(array.as_string::<i64>().iter().flatten().map(String::from)),
+                    _ => values.extend(array.as_string::<i32>().iter().flatten().map(String::from)),
+                },
+                Column::Float64(values) => match array.data_type() {
+                    DataType::Int8 => values.extend(array.as_primitive::<Int8Type>().values().iter().map(|v| *v as f64)),
+                    DataType::Int16 => values.extend(array.as_primitive::<Int16Type>().values().iter().map(|v| *v as f64)),
+                    DataType::Int32 => values.extend(array.as_primitive::<Int32Type>().values().iter().map(|v| *v as f64)),
+                    DataType::Int64 => values.extend(array.as_primitive::<Int64Type>().values().iter().map(|v| *v as f64)),
+                    DataType::UInt8 => values.extend(array.as_primitive::<UInt8Type>().values().iter().map(|v| *v as f64)),
+                    DataType::UInt16 => values.extend(array.as_primitive::<UInt16Type>().values().iter().map(|v| *v as f64)),
+                    DataType::UInt32 => values.extend(array.as_primitive::<UInt32Type>().values().iter().map(|v| *v as f64)),
+                    DataType::UInt64 => values.extend(array.as_primitive::<UInt64Type>().values().iter().map(|v| *v as f64)),
+                    DataType::Float32 => values.extend(array.as_primitive::<Float32Type>().values().iter().map(|v| *v as f64)),
+                    _ => values.extend(array.as_primitive::<Float64Type>().values().iter()),
+                },
+            }
+        }
+    }
+    Ok(Table { names, columns, metadata })
+}
+
+/// This writes a table as an Arrow IPC file. smas was built without the arrow feature, so this
+/// returns SmasError::InvalidArgument.
+#[cfg(not(feature = "arrow"))]
+pub fn write_table(_table: &Table) -> Result<Vec<u8>, SmasError> {
+    Err(SmasError::InvalidArgument(String::from("writing Arrow IPC files needs smas built with the arrow feature")))
+}
+
+/// This reads an Arrow IPC file. smas was built without the arrow feature, so this returns
+/// SmasError::InvalidFile.
+#[cfg(not(feature = "arrow"))]
+pub fn read_table(_bytes: &[u8]) -> Result<Table, SmasError> {
+    Err(arrow_error(String::from("reading Arrow IPC files needs smas built with the arrow feature")))
+}
+
+#[cfg(all(test, feature = "arrow"))]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    /// This reads one of the fixtures in resources/arrow, written by make_fixtures.py.
+    fn read_fixture(name: &str) -> Result<arrow::Table, error::SmasError> {
+        let path = format!("{}/resources/arrow/{name}", env!("CARGO_MANIFEST_DIR"));
+        arrow::read_table(&std::fs::read(&path).unwrap()).map_err(|e| e.in_file(&path))
+    }
+
+    #[test]
+    fn test_arrow_round_trip() {
+        let matrix = na::DMatrix::from_row_slice(3, 2, &[1.0, -0.5, 0.0, 1e-300, f64::MAX, -2.0]);
+        let mut provenance = io::Provenance::new("smas reaction vector", "solve");
+        provenance.inputs.push(String::from("a.txt"));
+        provenance.parameters.push((String::from("method"), String::from("pinv")));
+        let table = arrow::matrix_table(
+            &matrix,
+            "metabolite",
+            vec![String::from("glc"), String::from("g6p"), String::from("f\u{e9}6p")],
+            vec![String::from("hk"), String::from("pgi")],
+            &provenance,
+        );
+        let bytes = arrow::write_table(&table).unwrap();
+        assert!(bytes.starts_with(b"ARROW1\0\0") && bytes.ends_with(b"ARROW1"));
+
+        let read = arrow::read_table(&bytes).unwrap();
+        assert_eq!(read, table);
+        assert!(read.metadata.contains(&(String::from("method"), String::from("pinv"))));
+        let labeled = arrow::table_matrix(&read).unwrap();
+        assert_eq!(labeled.matrix, matrix);
+        assert_eq!(labeled.row_labels.unwrap()[2], "f\u{e9}6p");
+        assert_eq!(labeled.col_labels.unwrap(), ["hk", "pgi"]);
+
+        assert!(arrow::is_arrow_path("out/solution.Feather"));
+        assert!(!arrow::is_arrow_path("solution.txt"));
+        assert!(arrow::read_table(&bytes[..bytes.len() - 1]).is_err());
+        let uneven = arrow::Table {
+            names: vec![String::from("a"), String::from("b")],
+            columns: vec![arrow::Column::Float64(vec![1.0]), arrow::Column::Float64(vec![])],
+            metadata: vec![],
+        };
+        assert!(arrow::write_table(&uneven).is_err());
+    }
+
+    #[test]
+    fn test_arrow_read_fixture() {
+        // ** the layout of pyarrow: shared vtables, omitted defaults, two record batches and pandas metadata
+        let table = read_fixture("pyarrow_layout.arrow").unwrap();
+        assert_eq!(table.names, ["reaction", "flux", "count", "weight", "flag"]);
+        assert_eq!(table.columns, vec![
+            arrow::Column::Utf8(vec![String::from("hk"), String::from("pgi"), String::from("pfk"), String::from("fba")]),
+            arrow::Column::Float64(vec![1.5, -2.0e-300, 0.0, 1.0e300]),
+            arrow::Column::Float64(vec![-3.0, 7.0, -9007199254740992.0, 42.0]),
+            arrow::Column::Float64(vec![0.25, -1.5, 3.0, -0.125]),
+            arrow::Column::Float64(vec![0.0, 255.0, 1.0, 2.0]),
+        ]);
+        assert_eq!(table.metadata.len(), 1);
+        assert_eq!(table.metadata[0].0, "pandas");
+        assert!(table.metadata[0].1.contains("\"creator\": {\"library\": \"pyarrow\""));
+
+        let labeled = arrow::table_matrix(&table).unwrap();
+        assert_eq!(labeled.matrix.shape(), (4, 4));
+        assert_eq!(labeled.matrix[(2, 1)], -9007199254740992.0);
+        assert_eq!(labeled.row_labels.unwrap()[3], "fba");
+        assert_eq!(labeled.col_labels.unwrap(), ["flux", "count", "weight", "flag"]);
+
+        // ** smas writes what it reads
+        assert_eq!(arrow::read_table(&arrow::write_table(&table).unwrap()).unwrap(), table);
+
+        // ** buffers of a compressed batch may be stored uncompressed, which needs no codec
+        let compressed = read_fixture("compressed.arrow").unwrap();
+        assert_eq!(compressed.columns, table.columns);
+    }
+
+    #[test]
+    fn test_arrow_rejected_subsets() {
+        for (name, message) in [
+            ("nulls.arrow", "the column \"flux\" has 1 nulls"),
+            ("dictionary.arrow", "the column \"reaction\" is dictionary encoded"),
+        ] {
+            let error = read_fixture(name).unwrap_err();
+            assert!(matches!(error, error::SmasError::InvalidFile { path: Some(_), .. }), "{name}: {error:?}");
+            assert!(error.to_string().contains(message), "{name}: {error}");
+        }
+    }
+
+    #[test]
+    fn test_arrow_corrupt_files() {
+        let table = read_fixture("pyarrow_layout.arrow").unwrap();
+        let written = arrow::write_table(&table).unwrap();
+        let fixture = std::fs::read(format!("{}/resources/arrow/pyarrow_layout.arrow", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert!(arrow::read_table(b"ARROW1\0\0ARROW1").is_err());
+        assert!(arrow::read_table(&[0; 64]).is_err());
+
+        for bytes in [written, fixture] {
+            // ** a file cut short anywhere is an error
+            for length in 0..bytes.len() {
+                assert!(arrow::read_table(&bytes[..length]).is_err(), "truncated to {length}");
+            }
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use crate::error::SmasError;
+use crate::solve;
+
+use nalgebra as na;
+
+/// Condition numbers above this lose more than half of the digits of a double to roundoff.
+pub const CONDITION_WARNING: f64 = 1e8;
+
+/// Magnitudes above this overflow when squared, e.g. in a norm or a normal equation.
+const OVERFLOW_WARNING: f64 = 1e150;
+
+/// Nonzero magnitudes below this underflow when squared.
+const UNDERFLOW_WARNING: f64 = 1e-150;
+
+/// Singular values less than this factor above the cutoff could fall on either side of it.
+const CUTOFF_MARGIN: f64 = 100.0;
+
+/// This is the result of auditing a solve for values outside of the range where double
+/// precision arithmetic is reliable.
+pub struct NumericAudit {
+    /// The largest singular value of the matrix
+    pub largest_singular_value: f64,
+    /// The smallest singular value kept by the pseudoinverse, i.e. above SVD_EPSILON
+    pub smallest_singular_value: f64,
+    /// The numerical rank of the matrix
+    pub rank: usize,
+    /// The ratio of the largest and smallest kept singular values
+    pub condition_number: f64,
+    /// How much larger ||A|| ||x|| is than ||B||; large values mean B is a small difference of
+    /// large terms, so it is dominated by their roundoff
+    pub cancellation: f64,
+    /// A short explanation of each problem that was found
+    pub warnings: Vec<String>,
+}
+
+/// This finds the entries of a set of values that are too large or too small (but nonzero) to
+/// square, or not finite, and describes them.
+fn check_range(name: &str, values: &[f64], warnings: &mut Vec<String>) {
+    let n_non_finite = values.iter().filter(|v| !v.is_finite()).count();
+    if n_non_finite > 0 {
+        warnings.push(format!("{name} has {n_non_finite} NaN or infinite values, so the answer is meaningless"));
+    }
+    let n_large = values.iter().filter(|v| v.is_finite() && v.abs() > OVERFLOW_WARNING).count();
+    if n_large > 0 {
+        warnings.push(format!(
+            "{name} has {n_large} values above {OVERFLOW_WARNING:e}, whose squares overflow; rescale the units"
+        ));
+    }
+    let n_small = values.iter().filter(|v| **v != 0.0 && v.abs() < UNDERFLOW_WARNING).count();
+    if n_small > 0 {
+        warnings.push(format!(
+            "{name} has {n_small} nonzero values below {UNDERFLOW_WARNING:e}, whose squares underflow to zero; rescale the units"
+        ));
+    }
+}
+
+/// This returns the singular values of a matrix, or none if it is empty or has values that are
+/// not finite.
+fn singular_values(s_matrix: &na::DMatrix<f64>) -> Vec<f64> {
+    if s_matrix.is_empty() || s_matrix.iter().any(|v| !v.is_finite()) {
+        return vec!();
+    }
+    s_matrix.singular_values().iter().copied().collect()
+}
+
+/// This summarizes the singular values of a matrix as the pseudoinverse sees them.
+struct Spectrum {
+    /// The largest singular value
+    largest: f64,
+    /// The smallest singular value kept by the pseudoinverse, i.e. above SVD_EPSILON
+    smallest_kept: f64,
+    /// The number of singular values kept, i.e. the numerical rank
+    rank: usize,
+    /// The ratio of the largest and smallest kept singular values; NaN if none are kept
+    condition_number: f64,
+}
+
+impl Spectrum {
+    /// This summarizes a set of singular values.
+    fn new(singular_values: &[f64]) -> Self {
+        let largest = singular_values.iter().copied().fold(0.0, f64::max);
+        let kept: Vec<f64> = singular_values.iter().copied().filter(|s| *s > solve::SVD_EPSILON).collect();
+        let smallest_kept = kept.iter().copied().fold(f64::INFINITY, f64::min);
+        let condition_number = if kept.is_empty() {
+            f64::NAN
+        } else {
+            largest / smallest_kept
+        };
+        Spectrum { largest, smallest_kept, rank: kept.len(), condition_number }
+    }
+}
+
+/// This warns about a condition number high enough to lose digits to roundoff, and about singular
+/// values so close to the rank cutoff that they could fall on either side of it.
+fn check_conditioning(singular_values: &[f64], spectrum: &Spectrum, warnings: &mut Vec<String>) {
+    let condition_number = spectrum.condition_number;
+    if condition_number > CONDITION_WARNING {
+        warnings.push(format!(
+            "the condition number is {condition_number:.1e}, so up to {:.0} of the ~16 significant digits \
+            of the solution can be lost to roundoff",
+            condition_number.log10()
+        ));
+    }
+    let near_cutoff = singular_values.iter()
+        .filter(|s| **s > solve::SVD_EPSILON / CUTOFF_MARGIN && **s < solve::SVD_EPSILON * CUTOFF_MARGIN)
+        .count();
+    if near_cutoff > 0 {
+        warnings.push(format!(
+            "{near_cutoff} singular values are within a factor of {CUTOFF_MARGIN} of the rank cutoff \
+            ({:e}), so small changes to the matrix can change its rank and the solution discontinuously",
+            solve::SVD_EPSILON
+        ));
+    }
+}
+
+/// This checks the quantities a pseudoinverse solve depends on against the range where double
+/// precision is reliable: the entries of A, B and x, the singular values of A and how close
+/// they are to the rank cutoff, the condition number, and how much cancellation produced B.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the solution, x; (n x 1)
+///
+pub fn audit_numerics(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+) -> NumericAudit {
+    let mut warnings = vec!();
+    check_range("the stoichiometric matrix", s_matrix.as_slice(), &mut warnings);
+    check_range("the accumulation vector", acc_vector.as_slice(), &mut warnings);
+    check_range("the solution", reactions.as_slice(), &mut warnings);
+
+    let singular_values = singular_values(s_matrix);
+    let spectrum = Spectrum::new(&singular_values);
+    check_conditioning(&singular_values, &spectrum, &mut warnings);
+    let (largest_singular_value, condition_number) = (spectrum.largest, spectrum.condition_number);
+
+    let acc_norm = acc_vector.norm();
+    let cancellation = if acc_norm > 0.0 {
+        largest_singular_value * reactions.norm() / acc_norm
+    } else {
+        0.0
+    };
+    if cancellation > CONDITION_WARNING {
+        warnings.push(format!(
+            "||A|| ||x|| is {cancellation:.1e} times ||B||, so B is a small difference of large fluxes \
+            and is dominated by their roundoff"
+        ));
+    }
+
+    NumericAudit {
+        largest_singular_value,
+        smallest_singular_value: spectrum.smallest_kept,
+        rank: spectrum.rank,
+        condition_number,
+        cancellation,
+        warnings,
+    }
+}
+
+/// This formats a numeric audit as comment lines: the key quantities, then one line per warning,
+/// or a line saying that no problems were found.
+pub fn format_audit(audit: &NumericAudit) -> String {
+    let mut result_string = format!(
+        "% largest singular value: {:e}\n% smallest kept singular value: {:e}\n% rank: {}\n% condition number: {:e}\n% cancellation ||A|| ||x|| / ||B||: {:e}",
+        audit.largest_singular_value,
+        audit.smallest_singular_value,
+        audit.rank,
+        audit.condition_number,
+        audit.cancellation,
+    );
+    if audit.warnings.is_empty() {
+        result_string.push_str("\n% no numeric problems were found");
+    }
+    for warning in &audit.warnings {
+        result_string.push_str(&format!("\n% warning: {warning}"));
+    }
+    result_string
+}
+
+/// This is how many solutions Ax = B has, which follows from the rank of A and its shape.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Determination {
+    /// The rank is both the number of metabolites and of reactions: exactly one solution
+    Exact,
+    /// The rank is the number of reactions but not of metabolites: one least squares solution,
+    /// which only fits B exactly if it is consistent
+    Overdetermined,
+    /// The rank is the number of metabolites but not of reactions: every B is fitted exactly, by
+    /// infinitely many solutions, of which the minimum norm one is chosen
+    Underdetermined,
+    /// The rank is below both: infinitely many least squares solutions, which may not fit B exactly
+    RankDeficient,
+}
+
+impl Determination {
+    /// This describes the determination for a report.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Determination::Exact => "exactly determined: one solution, which fits every accumulation vector",
+            Determination::Overdetermined => {
+                "overdetermined: one least squares solution, which fits only consistent accumulation vectors exactly"
+            }
+            Determination::Underdetermined => {
+                "underdetermined: infinitely many solutions fit every accumulation vector; the minimum norm one is chosen"
+            }
+            Determination::RankDeficient => {
+                "rank deficient: infinitely many least squares solutions, which fit only consistent accumulation \
+                vectors exactly; the minimum norm one is chosen"
+            }
+        }
+    }
+}
+
+/// This is a numerical diagnosis of a stoichiometric matrix, before any accumulation vector is
+/// solved for: how many solutions to expect, and how reliably they can be computed.
+pub struct MatrixAnalysis {
+    /// The number of metabolites (rows), m
+    pub metabolites: usize,
+    /// The number of reactions (columns), n
+    pub reactions: usize,
+    /// The numerical rank, the number of singular values above SVD_EPSILON
+    pub rank: usize,
+    /// The dimension of the null space, n - rank: independent flux modes that change no metabolite
+    pub nullity: usize,
+    /// The dimension of the left null space, m - rank: conservation relations among the metabolites
+    pub left_nullity: usize,
+    /// The largest singular value
+    pub largest_singular_value: f64,
+    /// The smallest singular value, 0 for a matrix with more reactions than metabolites
+    pub smallest_singular_value: f64,
+    /// The smallest singular value kept by the pseudoinverse, i.e. above SVD_EPSILON
+    pub smallest_kept_singular_value: f64,
+    /// The ratio of the largest and smallest kept singular values
+    pub condition_number: f64,
+    /// How many solutions Ax = B has
+    pub determination: Determination,
+    /// A short explanation of each problem that was found
+    pub warnings: Vec<String>,
+}
+
+/// This diagnoses a stoichiometric matrix: its rank, nullity and left nullity, its singular values
+/// and condition number, and whether Ax = B is under- or overdetermined, with the same warnings
+/// about the matrix as `audit_numerics`.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn analyze_matrix(s_matrix: &na::DMatrix<f64>) -> MatrixAnalysis {
+    let (m, n) = s_matrix.shape();
+    let mut warnings = vec!();
+    check_range("the stoichiometric matrix", s_matrix.as_slice(), &mut warnings);
+    let singular_values = singular_values(s_matrix);
+    let spectrum = Spectrum::new(&singular_values);
+    check_conditioning(&singular_values, &spectrum, &mut warnings);
+    // ** a wide matrix has n - m singular values of 0 beyond the min(m, n) that are computed
+    let smallest_singular_value = if n > m {
+        0.0
+    } else {
+        singular_values.iter().copied().fold(f64::INFINITY, f64::min)
+    };
+    let determination = match (spectrum.rank == m, spectrum.rank == n) {
+        (true, true) => Determination::Exact,
+        (false, true) => Determination::Overdetermined,
+        (true, false) => Determination::Underdetermined,
+        (false, false) => Determination::RankDeficient,
+    };
+
+    MatrixAnalysis {
+        metabolites: m,
+        reactions: n,
+        rank: spectrum.rank,
+        nullity: n - spectrum.rank,
+        left_nullity: m - spectrum.rank,
+        largest_singular_value: spectrum.largest,
+        smallest_singular_value,
+        smallest_kept_singular_value: spectrum.smallest_kept,
+        condition_number: spectrum.condition_number,
+        determination,
+        warnings,
+    }
+}
+
+/// This formats a matrix analysis as one "name: value" line per quantity, then one line per
+/// warning, or a line saying that no problems were found.
+pub fn format_analysis(analysis: &MatrixAnalysis) -> String {
+    let mut lines = vec![
+        format!("metabolites: {}", analysis.metabolites),
+        format!("reactions: {}", analysis.reactions),
+        format!("rank: {}", analysis.rank),
+        format!("nullity: {} (independent flux modes that change no metabolite)", analysis.nullity),
+        format!("left nullity: {} (conservation relations among the metabolites)", analysis.left_nullity),
+        format!("largest singular value: {:e}", analysis.largest_singular_value),
+        format!("smallest singular value: {:e}", analysis.smallest_singular_value),
+        format!("smallest kept singular value: {:e}", analysis.smallest_kept_singular_value),
+        format!("condition number: {:e}", analysis.condition_number),
+        format!("system: {}", analysis.determination.describe()),
+    ];
+    if analysis.warnings.is_empty() {
+        lines.push(String::from("% no numeric problems were found"));
+    }
+    for warning in &analysis.warnings {
+        lines.push(format!("% warning: {warning}"));
+    }
+    lines.join("\n")
+}
+
+/// The largest fraction of an accumulation vector that may lie outside the column space of the
+/// matrix for it to be consistent by default.
+pub const CONSISTENCY_TOLERANCE: f64 = 1e-6;
+
+/// This is how much of an accumulation vector the stoichiometric matrix can explain: the part of
+/// it in the column space of the matrix is produced exactly by some reaction vector, and the rest
+/// by none, so a least squares solve can only compromise on it.
+pub struct Consistency {
+    /// The norm of the projection of B onto the column space of A, ||AA⁺B||
+    pub explained_norm: f64,
+    /// The norm of the rest of B, ||B - AA⁺B||
+    pub unexplained_norm: f64,
+    /// The fraction of ||B||² in the column space, 1 for B = 0
+    pub explained_fraction: f64,
+}
+
+impl Consistency {
+    /// This returns whether at most `tolerance` of ||B||² lies outside the column space.
+    pub fn is_consistent(&self, tolerance: f64) -> bool {
+        1.0 - self.explained_fraction <= tolerance
+    }
+}
+
+/// This projects an accumulation vector onto the column space of the stoichiometric matrix to
+/// find how much of it any reaction vector can explain; see `Consistency`.
+///
+/// Returns SmasError::Shape if B does not have one value per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn check_consistency(acc_vector: &na::DVector<f64>, s_matrix: &na::DMatrix<f64>) -> Result<Consistency, SmasError> {
+    let solution = solve::solve_pinv(acc_vector, s_matrix)?;
+    let projection = s_matrix * &solution.reactions;
+    let unexplained_norm = (acc_vector - &projection).norm();
+    // ** the projection is orthogonal, so ||B||² = ||AA⁺B||² + ||B - AA⁺B||², and the unexplained part,
+    // ** which is small when it matters, gives the fraction without cancellation
+    let acc_norm = acc_vector.norm();
+    let explained_fraction = if acc_norm == 0.0 {
+        1.0
+    } else {
+        (1.0 - (unexplained_norm / acc_norm).powi(2)).max(0.0)
+    };
+    Ok(Consistency {
+        explained_norm: projection.norm(),
+        unexplained_norm,
+        explained_fraction,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_audit_numerics() {
+        // ** the default problem is well scaled and well conditioned
+        let s_matrix = util::default_s_matrix();
+        let acc_vector = na::DVector::from_row_slice(matrices::A_STD_015);
+        let r_vector = solve::solve(acc_vector.clone(), s_matrix.clone());
+        let audit = audit::audit_numerics(&acc_vector, &s_matrix, &r_vector);
+        assert!(audit.warnings.is_empty(), "{:?}", audit.warnings);
+
+        // ** nearly parallel columns are ill conditioned
+        let s_matrix = na::DMatrix::from_row_slice(2, 2, &[1.0, 1.0, 1.0, 1.0 + 1e-8]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let r_vector = solve::solve(acc_vector.clone(), s_matrix.clone());
+        let audit = audit::audit_numerics(&acc_vector, &s_matrix, &r_vector);
+        assert!(audit.condition_number > audit::CONDITION_WARNING);
+        assert!(audit.warnings.iter().any(|w| w.contains("condition number")));
+        assert!(audit.warnings.iter().any(|w| w.contains("rank cutoff")));
+
+        // ** values whose squares overflow
+        let s_matrix = na::DMatrix::from_row_slice(1, 1, &[1e200]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0]);
+        let audit = audit::audit_numerics(&acc_vector, &s_matrix, &na::DVector::from_row_slice(&[1e-200]));
+        assert!(audit.warnings.iter().any(|w| w.contains("overflow")));
+        assert!(audit.warnings.iter().any(|w| w.contains("underflow")));
+    }
+
+    #[test]
+    fn test_analyze_matrix() {
+        // ** the default matrix has more metabolites than reactions, all of them independent
+        let analysis = audit::analyze_matrix(&util::default_s_matrix());
+        assert_eq!((analysis.metabolites, analysis.reactions), (39, 28));
+        assert_eq!((analysis.rank, analysis.nullity, analysis.left_nullity), (28, 0, 11));
+        assert_eq!(analysis.determination, audit::Determination::Overdetermined);
+        assert!(analysis.warnings.is_empty(), "{:?}", analysis.warnings);
+
+        // ** three reactions between two metabolites leave one flux mode that changes neither
+        let s_matrix = na::DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 1.0, 0.0, 1.0, -1.0]);
+        let analysis = audit::analyze_matrix(&s_matrix);
+        assert_eq!((analysis.rank, analysis.nullity, analysis.left_nullity), (2, 1, 0));
+        assert_eq!(analysis.smallest_singular_value, 0.0);
+        assert_eq!(analysis.determination, audit::Determination::Underdetermined);
+
+        let analysis = audit::analyze_matrix(&na::DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 2.0, 4.0]));
+        assert_eq!(analysis.determination, audit::Determination::RankDeficient);
+        assert!(audit::format_analysis(&analysis).contains("rank: 1\nnullity: 1"));
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        // ** an accumulation vector produced by some reaction vector is explained entirely
+        let s_matrix = na::DMatrix::from_row_slice(3, 2, &[1.0, 0.0, -1.0, 1.0, 0.0, -1.0]);
+        let acc_vector = &s_matrix * na::DVector::from_row_slice(&[2.0, 3.0]);
+        let consistency = audit::check_consistency(&acc_vector, &s_matrix).unwrap();
+        assert!(util::epsilon_eq(consistency.explained_fraction, 1.0, 1e-12));
+        assert!(consistency.unexplained_norm < 1e-12);
+        assert!(consistency.is_consistent(audit::CONSISTENCY_TOLERANCE));
+
+        // ** every reaction conserves the sum of the metabolites, so a net gain cannot be explained
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 1.0, 1.0]);
+        let consistency = audit::check_consistency(&acc_vector, &s_matrix).unwrap();
+        assert!(util::epsilon_eq(consistency.explained_fraction, 0.0, 1e-12));
+        assert!(util::epsilon_eq(consistency.unexplained_norm, 3.0_f64.sqrt(), 1e-12));
+        assert!(!consistency.is_consistent(audit::CONSISTENCY_TOLERANCE));
+
+        assert_eq!(audit::check_consistency(&na::DVector::zeros(3), &s_matrix).unwrap().explained_fraction, 1.0);
+        assert!(matches!(audit::check_consistency(&na::DVector::zeros(2), &s_matrix), Err(error::SmasError::Shape { .. })));
+    }
+}
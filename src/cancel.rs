@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The exit code of a run that was interrupted with Ctrl-C after writing its partial results,
+/// 128 + SIGINT like a shell.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Whether SIGINT has been received since `install_interrupt_handler` was called.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// This is a cooperative cancellation token. Long running loops check it between steps and stop
+/// early, keeping what they have completed, rather than being killed part way through.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    /// Whether the token was cancelled with `cancel`
+    cancelled: Arc<AtomicBool>,
+    /// Whether the token is also cancelled by Ctrl-C; see `install_interrupt_handler`
+    interruptible: bool,
+}
+
+impl CancellationToken {
+    /// This creates a token that is only cancelled by `cancel`.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// This cancels the token, and every clone of it.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// This returns whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || (self.interruptible && INTERRUPTED.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // ** a second Ctrl-C kills the process, in case the run is stuck in a step that never checks
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// This catches Ctrl-C (SIGINT) and returns a token that it cancels, so that a long running run
+/// can write the results it has completed and exit with EXIT_INTERRUPTED. A second Ctrl-C exits
+/// immediately. Off unix, Ctrl-C is not caught and the token is only cancelled by `cancel`.
+pub fn install_interrupt_handler() -> CancellationToken {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+    CancellationToken {
+        cancelled: Arc::new(AtomicBool::new(false)),
+        interruptible: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = cancel::CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(!cancel::CancellationToken::new().is_cancelled());
+    }
+}
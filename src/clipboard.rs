@@ -0,0 +1,60 @@
+use crate::error::SmasError;
+use crate::io;
+
+/// This returns the error for a clipboard that could not be reached or holds no text.
+fn clipboard_error(error: arboard::Error) -> SmasError {
+    SmasError::Io(std::io::Error::other(error))
+}
+
+/// This reads the text on the system clipboard.
+pub fn read() -> Result<String, SmasError> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(clipboard_error)
+}
+
+/// This reads the values pasted on the system clipboard, e.g. a column copied from a spreadsheet.
+/// Returns SmasError::InvalidArgument if the clipboard holds no values.
+pub fn read_values() -> Result<Vec<f64>, SmasError> {
+    pasted_values(&read()?)
+}
+
+/// This parses the values of pasted text, which must hold at least one.
+///
+/// # Arguments
+/// * `text` - The pasted text.
+///
+fn pasted_values(text: &str) -> Result<Vec<f64>, SmasError> {
+    let values = io::parse_pasted_values(text)?;
+    if values.is_empty() {
+        return Err(SmasError::InvalidArgument(String::from("the clipboard holds no values")));
+    }
+    Ok(values)
+}
+
+/// This puts text on the system clipboard.
+///
+/// # Arguments
+/// * `text` - The text to copy.
+///
+pub fn write(text: &str) -> Result<(), SmasError> {
+    // ** on Linux the clipboard is served by the process that set it, so arboard keeps the text
+    // ** available until the clipboard manager has taken it or smas exits
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(clipboard_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_pasted_values() {
+        // ** a column copied from Excel ends each cell with CRLF, a row from LibreOffice separates cells with tabs
+        assert_eq!(clipboard::pasted_values("1.5\r\n-2\r\n0\r\n").unwrap(), vec![1.5, -2.0, 0.0]);
+        assert_eq!(clipboard::pasted_values("1.5\t-2\t0\n").unwrap(), vec![1.5, -2.0, 0.0]);
+        assert!(matches!(clipboard::pasted_values("\r\n"), Err(error::SmasError::InvalidArgument(_))));
+        assert!(matches!(clipboard::pasted_values("glc\t1\n"), Err(error::SmasError::Parse { line: 1, .. })));
+    }
+}
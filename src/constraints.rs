@@ -0,0 +1,221 @@
+use crate::io::{self, FloatFormat};
+
+use nalgebra as na;
+
+/// Direction components smaller than this (relative to the direction's norm) do not limit a chord.
+const DIRECTION_EPSILON: f64 = 1e-12;
+
+/// This holds per-reaction lower and upper bounds on the reaction vector.
+pub struct Bounds {
+    /// The lower bound of each reaction, -inf if unbounded
+    pub lower: na::DVector<f64>,
+    /// The upper bound of each reaction, +inf if unbounded
+    pub upper: na::DVector<f64>,
+}
+
+impl Bounds {
+    /// This creates Bounds that leave every reaction unconstrained.
+    ///
+    /// # Arguments
+    /// * `n_reactions` - the number of reactions, i.e. the number of columns of the matrix
+    ///
+    pub fn unbounded(n_reactions: usize) -> Self {
+        Bounds {
+            lower: na::DVector::from_element(n_reactions, f64::NEG_INFINITY),
+            upper: na::DVector::from_element(n_reactions, f64::INFINITY),
+        }
+    }
+
+    /// This creates Bounds from the direction of each reaction: an irreversible reaction, which
+    /// only runs forward, is kept at or above 0, and a reversible one is unconstrained.
+    ///
+    /// # Arguments
+    /// * `reversible` - whether each reaction is reversible, e.g. from reaction equations
+    ///
+    pub fn from_directions(reversible: &[bool]) -> Self {
+        let mut bounds = Bounds::unbounded(reversible.len());
+        for (lower, reversible) in bounds.lower.iter_mut().zip(reversible) {
+            if !reversible {
+                *lower = 0.0;
+            }
+        }
+        bounds
+    }
+
+    /// This returns the bounds that hold where both sets of bounds hold, i.e. the larger lower
+    /// and the smaller upper bound of each reaction. A reaction whose bounds do not overlap ends
+    /// up with its lower bound above its upper bound, which a bounded solve reports as infeasible.
+    ///
+    /// # Arguments
+    /// * `other` - the other bounds, with one entry per reaction
+    ///
+    pub fn intersect(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            lower: self.lower.zip_map(&other.lower, f64::max),
+            upper: self.upper.zip_map(&other.upper, f64::min),
+        }
+    }
+
+    /// This computes the chord through a point in a direction, i.e. the range of steps t for which
+    /// x + t d stays within the bounds. Either end may be infinite. The range always contains 0,
+    /// so a point marginally outside a bound is never pushed further out.
+    ///
+    /// # Arguments
+    /// * `x` - the point; (n x 1)
+    /// * `direction` - the direction, d; (n x 1)
+    ///
+    pub fn chord(&self, x: &na::DVector<f64>, direction: &na::DVector<f64>) -> (f64, f64) {
+        let min_component = DIRECTION_EPSILON * direction.norm();
+        let mut t_min = f64::NEG_INFINITY;
+        let mut t_max = f64::INFINITY;
+        for i in 0..x.nrows() {
+            let d = direction[i];
+            if d.abs() <= min_component {
+                continue;
+            }
+            let to_lower = (self.lower[i] - x[i]) / d;
+            let to_upper = (self.upper[i] - x[i]) / d;
+            let (lo, hi) = if d > 0.0 { (to_lower, to_upper) } else { (to_upper, to_lower) };
+            t_min = t_min.max(lo);
+            t_max = t_max.min(hi);
+        }
+        (t_min.min(0.0), t_max.max(0.0))
+    }
+}
+
+/// This describes a single constraint at a solution.
+pub struct ConstraintStatus {
+    /// A human readable description of the constraint
+    pub name: String,
+    /// The value of the constrained quantity at the solution
+    pub value: f64,
+    /// The bound or target the quantity is constrained to
+    pub bound: f64,
+    /// How far the quantity is from the bound; negative slack means the constraint is violated
+    pub slack: f64,
+    /// Whether the quantity sits at its bound (within epsilon)
+    pub binding: bool,
+}
+
+/// This evaluates every constraint of the system at a solution: one equality constraint
+/// (Ax = B) per metabolite, and a lower and upper bound constraint per bounded reaction.
+///
+/// For equality constraints the slack is -|Ax - B|, so any mismatch is reported as a violation,
+/// and the constraint is binding when it holds within epsilon.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `reactions` - the solution vector, x; (n x 1)
+/// * `bounds` - the reaction bounds, if any
+/// * `epsilon` - the tolerance used to decide whether a constraint is binding
+///
+pub fn constraint_report(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    reactions: &na::DVector<f64>,
+    bounds: Option<&Bounds>,
+    epsilon: f64,
+) -> Vec<ConstraintStatus> {
+    let mut statuses = vec!();
+
+    let accumulation = s_matrix * reactions;
+    for (i, (value, target)) in accumulation.iter().zip(acc_vector.iter()).enumerate() {
+        let slack = -(value - target).abs();
+        statuses.push(ConstraintStatus {
+            name: format!("metabolite {} accumulation", i + 1),
+            value: *value,
+            bound: *target,
+            slack,
+            binding: slack.abs() <= epsilon,
+        });
+    }
+
+    if let Some(bounds) = bounds {
+        for (i, value) in reactions.iter().enumerate() {
+            let lower = bounds.lower[i];
+            if lower.is_finite() {
+                let slack = value - lower;
+                statuses.push(ConstraintStatus {
+                    name: format!("reaction {} lower bound", i + 1),
+                    value: *value,
+                    bound: lower,
+                    slack,
+                    binding: slack.abs() <= epsilon,
+                });
+            }
+            let upper = bounds.upper[i];
+            if upper.is_finite() {
+                let slack = upper - value;
+                statuses.push(ConstraintStatus {
+                    name: format!("reaction {} upper bound", i + 1),
+                    value: *value,
+                    bound: upper,
+                    slack,
+                    binding: slack.abs() <= epsilon,
+                });
+            }
+        }
+    }
+
+    statuses
+}
+
+/// This formats a constraint report as a tab delimited table.
+///
+/// # Arguments
+/// * `statuses` - the constraint statuses to be formatted
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_constraint_report(
+    statuses: &[ConstraintStatus],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let mut result_string = String::new();
+    result_string.push_str("% constraint \t value \t bound \t slack \t binding");
+    for status in statuses {
+        result_string.push_str(&format!(
+            "\n  {}\t{}\t{}\t{}\t{}",
+            status.name,
+            io::format_float(status.value, float_format, float_precision),
+            io::format_float(status.bound, float_format, float_precision),
+            io::format_float(status.slack, float_format, float_precision),
+            status.binding,
+        ));
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_constraint_report() {
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[2.0]);
+        let reactions = na::DVector::from_row_slice(&[0.0, 2.0]);
+        let mut bounds = constraints::Bounds::unbounded(2);
+        bounds.lower[0] = 0.0;
+        bounds.upper[1] = 5.0;
+
+        let statuses = constraints::constraint_report(&s_matrix, &acc_vector, &reactions, Some(&bounds), 1e-9);
+        assert_eq!(statuses.len(), 3);
+        // ** the equality holds, the lower bound on reaction 1 is binding, the upper bound on
+        // ** reaction 2 has a slack of 3
+        assert!(statuses[0].binding);
+        assert!(statuses[1].binding);
+        assert!(!statuses[2].binding);
+        assert!(util::epsilon_eq(statuses[2].slack, 3.0, 1e-12));
+
+        let mut other = constraints::Bounds::unbounded(2);
+        other.lower[0] = -1.0;
+        other.upper[1] = 4.0;
+        let both = bounds.intersect(&other);
+        assert_eq!(both.lower, na::DVector::from_row_slice(&[0.0, f64::NEG_INFINITY]));
+        assert_eq!(both.upper, na::DVector::from_row_slice(&[f64::INFINITY, 4.0]));
+    }
+}
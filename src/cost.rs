@@ -0,0 +1,249 @@
+use crate::error::SmasError;
+use crate::solve;
+use crate::sparse::CscMatrix;
+
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// The environment variable that overrides where `smas bench` stores its calibration.
+pub const CALIBRATION_VARIABLE: &str = "SMAS_CALIBRATION";
+
+/// The seconds per floating point operation of the dense factorization, before calibration: a
+/// single core at about 1 GFLOP/s.
+const DEFAULT_DENSE_FLOP_SECONDS: f64 = 1e-9;
+
+/// The seconds per floating point operation of an LSQR iteration, before calibration; sparse
+/// products are bound by memory, so they run several times slower than dense ones.
+const DEFAULT_SPARSE_FLOP_SECONDS: f64 = 4e-9;
+
+/// The shape of the dense matrix the calibration factorizes.
+const CALIBRATION_DENSE_SHAPE: (usize, usize) = (600, 800);
+
+/// The shape, nonzero entries per column and iterations of the sparse calibration solve.
+const CALIBRATION_SPARSE_SHAPE: (usize, usize, usize, usize) = (20_000, 30_000, 5, 300);
+
+/// How many times each calibration runs; the fastest run is kept, as the others were slowed by
+/// something else on the machine.
+const CALIBRATION_REPEATS: usize = 3;
+
+/// This is how fast this machine runs the work of a solve, as measured by `calibrate`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Calibration {
+    /// The seconds per floating point operation of the dense factorization (SVD)
+    pub dense_flop_seconds: f64,
+    /// The seconds per floating point operation of an LSQR iteration on a sparse matrix
+    pub sparse_flop_seconds: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            dense_flop_seconds: DEFAULT_DENSE_FLOP_SECONDS,
+            sparse_flop_seconds: DEFAULT_SPARSE_FLOP_SECONDS,
+        }
+    }
+}
+
+impl Calibration {
+    /// This estimates the seconds of factorizing a dense matrix by its SVD; see `dense_solve_flops`.
+    ///
+    /// # Arguments
+    /// * `nrows` - the number of rows (metabolites), m
+    /// * `ncols` - the number of columns (reactions), n
+    ///
+    pub fn estimate_dense(&self, nrows: usize, ncols: usize) -> f64 {
+        dense_solve_flops(nrows, ncols) * self.dense_flop_seconds
+    }
+
+    /// This estimates the seconds of solving with a sparse matrix by LSQR; see `sparse_solve_flops`.
+    ///
+    /// # Arguments
+    /// * `nrows` - the number of rows (metabolites), m
+    /// * `ncols` - the number of columns (reactions), n
+    /// * `nnz` - the number of nonzero entries
+    /// * `iterations` - the number of LSQR iterations
+    ///
+    pub fn estimate_sparse(&self, nrows: usize, ncols: usize, nnz: usize, iterations: usize) -> f64 {
+        sparse_solve_flops(nrows, ncols, nnz, iterations) * self.sparse_flop_seconds
+    }
+}
+
+/// This counts the floating point operations of the SVD of a dense matrix, about 4mnk + 8k³ with
+/// k = min(m, n): the bidiagonalization, then the rotations that diagonalize it and accumulate U
+/// and Vᵀ.
+///
+/// # Arguments
+/// * `nrows` - the number of rows (metabolites), m
+/// * `ncols` - the number of columns (reactions), n
+///
+pub fn dense_solve_flops(nrows: usize, ncols: usize) -> f64 {
+    let (m, n) = (nrows as f64, ncols as f64);
+    let k = m.min(n);
+    4.0 * m * n * k + 8.0 * k * k * k
+}
+
+/// This counts the floating point operations of LSQR on a sparse matrix: each iteration multiplies
+/// by A and by Aᵀ, two operations per nonzero entry each, and updates a handful of vectors of
+/// length m and n.
+///
+/// # Arguments
+/// * `nrows` - the number of rows (metabolites), m
+/// * `ncols` - the number of columns (reactions), n
+/// * `nnz` - the number of nonzero entries
+/// * `iterations` - the number of LSQR iterations
+///
+pub fn sparse_solve_flops(nrows: usize, ncols: usize, nnz: usize, iterations: usize) -> f64 {
+    iterations as f64 * (4.0 * nnz as f64 + 12.0 * (nrows + ncols) as f64)
+}
+
+/// This returns the fastest of CALIBRATION_REPEATS runs of a computation, in seconds.
+fn time_fastest(mut run: impl FnMut()) -> f64 {
+    (0..CALIBRATION_REPEATS)
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed().as_secs_f64()
+        })
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// This calibrates the cost model on this machine: it times the SVD of a dense matrix and LSQR
+/// iterations on a sparse matrix of fixed shapes, and divides each by its operation count. It
+/// takes about a second.
+pub fn calibrate() -> Calibration {
+    // ** the matrices are deterministic, so that every calibration does the same work
+    let (m, n) = CALIBRATION_DENSE_SHAPE;
+    let dense = na::DMatrix::from_fn(m, n, |i, j| ((i * 31 + j * 17) % 23) as f64 - 11.0);
+    let dense_seconds = time_fastest(|| {
+        std::hint::black_box(solve::jacobian(&dense));
+    });
+
+    let (m_sparse, n_sparse, per_column, iterations) = CALIBRATION_SPARSE_SHAPE;
+    let entries: Vec<(usize, usize, f64)> = (0..n_sparse)
+        .flat_map(|j| (0..per_column).map(move |k| ((j * 7919 + k * 104_729) % m_sparse, j, (k as f64) - 2.5)))
+        .collect();
+    let sparse = CscMatrix::from_entries(m_sparse, n_sparse, entries).expect("the calibration entries are in range");
+    let acc_vector = na::DVector::from_fn(m_sparse, |i, _| (i % 13) as f64 - 6.0);
+    // ** a tolerance of 0 runs every iteration
+    let sparse_seconds = time_fastest(|| {
+        std::hint::black_box(solve::solve_lsqr(&acc_vector, &sparse, 0.0, iterations).ok());
+    });
+
+    Calibration {
+        dense_flop_seconds: dense_seconds / dense_solve_flops(m, n),
+        sparse_flop_seconds: sparse_seconds / sparse_solve_flops(m_sparse, n_sparse, sparse.nnz(), iterations),
+    }
+}
+
+/// This formats a calibration as the file `parse_calibration` reads: a '%' comment, then one
+/// tab delimited name and value per line.
+///
+/// # Arguments
+/// * `calibration` - the calibration
+///
+pub fn format_calibration(calibration: &Calibration) -> String {
+    format!(
+        "% smas bench calibration: seconds per floating point operation\ndense_flop_seconds\t{:e}\nsparse_flop_seconds\t{:e}",
+        calibration.dense_flop_seconds, calibration.sparse_flop_seconds,
+    )
+}
+
+/// This parses a calibration file written by `format_calibration`. Lines starting with '%' are
+/// comments; a value that is missing keeps its default.
+///
+/// Returns SmasError::Parse if a line is not a known name and a positive number.
+///
+/// # Arguments
+/// * `contents` - the contents of the calibration file
+///
+pub fn parse_calibration(contents: &str) -> Result<Calibration, SmasError> {
+    let mut calibration = Calibration::default();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('%') {
+            continue;
+        }
+        let parse_error = || SmasError::Parse {
+            line: i + 1,
+            message: format!("expected a name and a positive number of seconds, found \"{trimmed}\""),
+        };
+        let (name, value) = trimmed.split_once(char::is_whitespace).ok_or_else(parse_error)?;
+        let value: f64 = value.trim().parse().ok().filter(|value: &f64| *value > 0.0 && value.is_finite()).ok_or_else(parse_error)?;
+        match name {
+            "dense_flop_seconds" => calibration.dense_flop_seconds = value,
+            "sparse_flop_seconds" => calibration.sparse_flop_seconds = value,
+            _ => return Err(parse_error()),
+        }
+    }
+    Ok(calibration)
+}
+
+/// This reads a calibration file; see `parse_calibration`.
+///
+/// # Arguments
+/// * `path` - the path to the calibration file
+///
+pub fn load_calibration<R: AsRef<Path>>(path: R) -> Result<Calibration, SmasError> {
+    let contents = std::fs::read_to_string(path).map_err(SmasError::Io)?;
+    parse_calibration(&contents)
+}
+
+/// This returns where `smas bench` stores its calibration: the path in CALIBRATION_VARIABLE if it
+/// is set, otherwise smas/calibration.txt in $XDG_CONFIG_HOME or ~/.config, or None if neither
+/// is set.
+pub fn calibration_path() -> Option<PathBuf> {
+    let variable = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    if let Some(path) = variable(CALIBRATION_VARIABLE) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = variable("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| variable("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("smas").join("calibration.txt"))
+}
+
+/// This formats a duration for a person, with a unit that keeps the number small, e.g. "1.5 s"
+/// or "3.2 h".
+///
+/// # Arguments
+/// * `seconds` - the duration in seconds
+///
+pub fn format_seconds(seconds: f64) -> String {
+    if seconds < 1e-3 {
+        format!("{:.1} µs", seconds * 1e6)
+    } else if seconds < 1.0 {
+        format!("{:.1} ms", seconds * 1e3)
+    } else if seconds < 60.0 {
+        format!("{seconds:.1} s")
+    } else if seconds < 3600.0 {
+        format!("{:.1} min", seconds / 60.0)
+    } else {
+        format!("{:.1} h", seconds / 3600.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_cost_model() {
+        // ** 2 x 3: 4 * 2 * 3 * 2 for the bidiagonalization and 8 * 2³ for the rotations
+        assert_eq!(cost::dense_solve_flops(2, 3), 48.0 + 64.0);
+        assert_eq!(cost::sparse_solve_flops(2, 3, 4, 10), 10.0 * (16.0 + 60.0));
+        let calibration = cost::Calibration { dense_flop_seconds: 1e-9, sparse_flop_seconds: 2e-9 };
+        assert!(util::epsilon_eq(calibration.estimate_dense(1000, 1000), 12.0, 1e-9));
+        assert!(util::epsilon_eq(calibration.estimate_sparse(2, 3, 4, 10), 760.0 * 2e-9, 1e-18));
+
+        let parsed = cost::parse_calibration(&cost::format_calibration(&calibration)).unwrap();
+        assert_eq!(parsed, calibration);
+        // ** a value that is missing keeps its default
+        assert_eq!(cost::parse_calibration("% only dense\ndense_flop_seconds 2e-9\n").unwrap().sparse_flop_seconds, 4e-9);
+        assert!(matches!(cost::parse_calibration("dense_flop_seconds -1\n"), Err(error::SmasError::Parse { line: 1, .. })));
+        assert!(matches!(cost::parse_calibration("\nspeed 1\n"), Err(error::SmasError::Parse { line: 2, .. })));
+
+        assert_eq!(cost::format_seconds(0.0125), "12.5 ms");
+        assert_eq!(cost::format_seconds(90.0), "1.5 min");
+    }
+}
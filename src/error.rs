@@ -0,0 +1,143 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// This is the error type returned by the fallible functions in smas.
+#[derive(Debug)]
+pub enum SmasError {
+    /// An error reading or writing a file
+    Io(std::io::Error),
+    /// A file or string did not follow the expected format
+    Parse {
+        /// The 1-based line number the problem was found on
+        line: usize,
+        /// A description of the problem
+        message: String,
+    },
+    /// The number of values read did not match the declared shape
+    Shape {
+        /// The number of values the declared shape requires
+        expected: usize,
+        /// The number of values that were actually read
+        found: usize,
+    },
+    /// An argument, e.g. a reaction index or a unit, does not fit the model or the other inputs
+    InvalidArgument(String),
+    /// A file could not be read because it is malformed or uses an unsupported feature
+    InvalidFile {
+        /// The path to the file, if it is known
+        path: Option<PathBuf>,
+        /// A description of the problem
+        message: String,
+    },
+    /// An output file already exists and overwriting it was not allowed
+    OutputExists(PathBuf),
+    /// An iterative method reached its maximum number of iterations without converging
+    NotConverged {
+        /// The name of the method
+        method: &'static str,
+        /// The number of iterations that were run
+        iterations: usize,
+        /// The two-norm of the residual at the final iteration
+        residual_norm: f64,
+    },
+    /// An iterative method was stopped by its cancellation token; see solve::SolveOptions
+    Cancelled {
+        /// The name of the method
+        method: &'static str,
+        /// The number of iterations that were run
+        iterations: usize,
+    },
+    /// An iterative method ran past its timeout; see solve::SolveOptions
+    TimedOut {
+        /// The name of the method
+        method: &'static str,
+        /// The number of iterations that were run
+        iterations: usize,
+    },
+    /// No solution satisfies the constraints
+    Infeasible,
+    /// The objective can be improved without limit, or the solution set extends without limit
+    Unbounded,
+    /// A combinatorial computation is too large to run
+    TooLarge {
+        /// What was being computed
+        task: &'static str,
+        /// The number of cases the computation would examine
+        size: usize,
+        /// The largest number of cases allowed
+        limit: usize,
+    },
+    /// An input has no values where at least one is needed
+    Empty(&'static str),
+    /// An exact computation could not be carried out with 128-bit fractions; see exact::Fraction
+    Inexact(&'static str),
+    /// A method that needs a matrix of full column rank, i.e. independent reactions, was given one
+    /// without
+    RankDeficient {
+        /// The name of the method
+        method: &'static str,
+        /// The rank of the matrix
+        rank: usize,
+        /// The number of columns of the matrix
+        columns: usize,
+    },
+}
+
+impl fmt::Display for SmasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmasError::Io(err) => write!(f, "{err}"),
+            SmasError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            SmasError::Shape { expected, found } => {
+                write!(f, "expected {expected} values from the declared shape, but found {found}")
+            }
+            SmasError::InvalidArgument(message) => write!(f, "{message}"),
+            SmasError::InvalidFile { path: Some(path), message } => write!(f, "{}: {message}", path.display()),
+            SmasError::InvalidFile { path: None, message } => write!(f, "{message}"),
+            SmasError::OutputExists(path) => {
+                write!(f, "{} already exists, use --force to overwrite it or --backup to keep a copy", path.display())
+            }
+            SmasError::NotConverged { method, iterations, residual_norm } => {
+                write!(f, "{method} did not converge after {iterations} iterations (residual norm: {residual_norm:e})")
+            }
+            SmasError::Cancelled { method, iterations } => write!(f, "{method} was cancelled after {iterations} iterations"),
+            SmasError::TimedOut { method, iterations } => write!(f, "{method} timed out after {iterations} iterations"),
+            SmasError::Infeasible => write!(f, "no solution satisfies the constraints"),
+            SmasError::Unbounded => write!(f, "the problem is unbounded"),
+            SmasError::TooLarge { task, size, limit } => {
+                write!(f, "{task} would examine {size} cases, more than the limit of {limit}")
+            }
+            SmasError::Empty(what) => write!(f, "{what} is empty"),
+            SmasError::Inexact(what) => write!(f, "exact arithmetic with 128-bit fractions failed: {what}"),
+            SmasError::RankDeficient { method, rank, columns } => {
+                write!(f, "{method} needs independent columns, but the matrix has rank {rank} with {columns} columns")
+            }
+        }
+    }
+}
+
+impl SmasError {
+    /// This records the path of the file an InvalidFile error was found in, if it has none yet.
+    /// Other errors are returned unchanged.
+    ///
+    /// # Arguments
+    /// * `file` - the path to the file
+    ///
+    pub fn in_file<R: AsRef<Path>>(self, file: R) -> Self {
+        match self {
+            SmasError::InvalidFile { path: None, message } => SmasError::InvalidFile {
+                path: Some(file.as_ref().to_path_buf()),
+                message,
+            },
+            error => error,
+        }
+    }
+}
+
+impl std::error::Error for SmasError {}
+
+impl From<std::io::Error> for SmasError {
+    fn from(err: std::io::Error) -> Self {
+        SmasError::Io(err)
+    }
+}
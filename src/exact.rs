@@ -0,0 +1,341 @@
+use crate::error::SmasError;
+use crate::io::Provenance;
+
+use std::fmt;
+
+use nalgebra as na;
+
+/// This is an exact fraction, kept in lowest terms with a positive denominator. Arithmetic is
+/// checked, and returns SmasError::Inexact rather than overflowing 128 bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fraction {
+    /// The numerator
+    numer: i128,
+    /// The denominator, always positive
+    denom: i128,
+}
+
+/// This computes the greatest common divisor of two integers, which is never negative.
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a as i128
+}
+
+/// The error of an exact computation that left the range of 128-bit fractions.
+fn overflow() -> SmasError {
+    SmasError::Inexact("a fraction overflowed")
+}
+
+impl Fraction {
+    /// The fraction 0.
+    pub const ZERO: Fraction = Fraction { numer: 0, denom: 1 };
+
+    /// The fraction 1.
+    pub const ONE: Fraction = Fraction { numer: 1, denom: 1 };
+
+    /// This creates the fraction numer/denom in lowest terms. Returns SmasError::Inexact if the
+    /// denominator is 0.
+    ///
+    /// # Arguments
+    /// * `numer` - the numerator
+    /// * `denom` - the denominator
+    ///
+    pub fn new(numer: i128, denom: i128) -> Result<Self, SmasError> {
+        if denom == 0 {
+            return Err(SmasError::Inexact("a fraction has a denominator of 0"));
+        }
+        let divisor = gcd(numer, denom) * denom.signum();
+        Ok(Fraction {
+            numer: numer.checked_div(divisor).ok_or_else(overflow)?,
+            denom: denom.checked_div(divisor).ok_or_else(overflow)?,
+        })
+    }
+
+    /// This converts a float to the fraction its shortest decimal form stands for, e.g. 0.1 to
+    /// 1/10 rather than the binary value nearest to it, so coefficients read from a file are the
+    /// fractions that were written. Returns SmasError::Inexact if the float is not finite or the
+    /// fraction does not fit.
+    ///
+    /// # Arguments
+    /// * `val` - the float
+    ///
+    pub fn from_f64(val: f64) -> Result<Self, SmasError> {
+        if !val.is_finite() {
+            return Err(SmasError::Inexact("a value is not a finite number"));
+        }
+        // ** Display never uses an exponent, so the digits are the whole decimal form
+        let decimal = format!("{}", val + 0.0);
+        let (whole, fractional) = decimal.split_once('.').unwrap_or((&decimal, ""));
+        let numer: i128 = format!("{whole}{fractional}").parse().map_err(|_| overflow())?;
+        let denom = u32::try_from(fractional.len()).ok()
+            .and_then(|places| 10i128.checked_pow(places))
+            .ok_or_else(overflow)?;
+        Fraction::new(numer, denom)
+    }
+
+    /// This returns the fraction nearest to its value as a float.
+    pub fn to_f64(&self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// This returns whether the fraction is 0.
+    pub fn is_zero(&self) -> bool {
+        self.numer == 0
+    }
+
+    /// This adds two fractions.
+    pub fn add(&self, other: &Fraction) -> Result<Fraction, SmasError> {
+        let divisor = gcd(self.denom, other.denom);
+        let (left, right) = (other.denom / divisor, self.denom / divisor);
+        let numer = self.numer.checked_mul(left)
+            .zip(other.numer.checked_mul(right))
+            .and_then(|(a, b)| a.checked_add(b))
+            .ok_or_else(overflow)?;
+        Fraction::new(numer, self.denom.checked_mul(left).ok_or_else(overflow)?)
+    }
+
+    /// This subtracts a fraction from this one.
+    pub fn sub(&self, other: &Fraction) -> Result<Fraction, SmasError> {
+        self.add(&Fraction { numer: other.numer.checked_neg().ok_or_else(overflow)?, denom: other.denom })
+    }
+
+    /// This multiplies two fractions, cancelling across them first to delay overflow.
+    pub fn mul(&self, other: &Fraction) -> Result<Fraction, SmasError> {
+        if self.is_zero() || other.is_zero() {
+            return Ok(Fraction::ZERO);
+        }
+        let (a, b) = (gcd(self.numer, other.denom), gcd(other.numer, self.denom));
+        let numer = (self.numer / a).checked_mul(other.numer / b).ok_or_else(overflow)?;
+        let denom = (self.denom / b).checked_mul(other.denom / a).ok_or_else(overflow)?;
+        Fraction::new(numer, denom)
+    }
+
+    /// This divides this fraction by another. Returns SmasError::Inexact if the other is 0.
+    pub fn div(&self, other: &Fraction) -> Result<Fraction, SmasError> {
+        if other.is_zero() {
+            return Err(SmasError::Inexact("a fraction was divided by 0"));
+        }
+        self.mul(&Fraction::new(other.denom, other.numer)?)
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.denom {
+            1 => write!(f, "{}", self.numer),
+            denom => write!(f, "{}/{denom}", self.numer),
+        }
+    }
+}
+
+/// This is the exact minimum norm least squares solution of Ax = B.
+pub struct ExactSolution {
+    /// The solution vector x, i.e. the reaction vector
+    pub reactions: Vec<Fraction>,
+    /// The rank of A
+    pub rank: usize,
+    /// The squared two-norm of the residual, ||Ax - B||², which is exact where the norm is not
+    pub residual_norm_squared: Fraction,
+}
+
+/// This computes the dot product of two vectors of fractions.
+fn dot<'a>(a: impl Iterator<Item = &'a Fraction>, b: impl Iterator<Item = &'a Fraction>) -> Result<Fraction, SmasError> {
+    a.zip(b).try_fold(Fraction::ZERO, |sum, (a, b)| sum.add(&a.mul(b)?))
+}
+
+/// This converts a matrix of floats to rows of fractions; see `Fraction::from_f64`.
+fn to_rows(matrix: &na::DMatrix<f64>) -> Result<Vec<Vec<Fraction>>, SmasError> {
+    matrix.row_iter()
+        .map(|row| row.iter().map(|val| Fraction::from_f64(*val)).collect())
+        .collect()
+}
+
+/// This reduces a matrix to reduced row echelon form with exact arithmetic, and returns the
+/// nonzero rows along with the column of the leading 1 of each, i.e. the pivot columns.
+///
+/// # Arguments
+/// * `rows` - the rows of the matrix, each with the same number of columns
+/// * `ncols` - the number of columns
+///
+fn rref(mut rows: Vec<Vec<Fraction>>, ncols: usize) -> Result<(Vec<Vec<Fraction>>, Vec<usize>), SmasError> {
+    let mut pivots = vec!();
+    for j in 0..ncols {
+        let r = pivots.len();
+        let Some(p) = (r..rows.len()).find(|i| !rows[*i][j].is_zero()) else {
+            continue;
+        };
+        rows.swap(r, p);
+        let lead = rows[r][j];
+        for val in rows[r].iter_mut() {
+            *val = val.div(&lead)?;
+        }
+        let pivot_row = rows[r].clone();
+        for (i, row) in rows.iter_mut().enumerate() {
+            let factor = row[j];
+            if i == r || factor.is_zero() {
+                continue;
+            }
+            for (val, pivot) in row.iter_mut().zip(&pivot_row).skip(j) {
+                *val = val.sub(&factor.mul(pivot)?)?;
+            }
+        }
+        pivots.push(j);
+    }
+    rows.truncate(pivots.len());
+    Ok((rows, pivots))
+}
+
+/// This solves a square system with a nonsingular matrix exactly for several right hand sides at
+/// once, by Gauss-Jordan elimination, and returns the solutions as the columns of the result.
+///
+/// # Arguments
+/// * `matrix` - the rows of the matrix; (r x r)
+/// * `rhs` - the rows of the right hand sides; (r x k)
+///
+fn solve_square(matrix: Vec<Vec<Fraction>>, rhs: Vec<Vec<Fraction>>) -> Result<Vec<Vec<Fraction>>, SmasError> {
+    let r = matrix.len();
+    let k = rhs.first().map_or(0, |row| row.len());
+    let augmented = matrix.into_iter().zip(rhs).map(|(mut row, b)| {
+        row.extend(b);
+        row
+    }).collect();
+    let (reduced, pivots) = rref(augmented, r + k)?;
+    if pivots.len() != r || pivots.last().is_some_and(|pivot| *pivot >= r) {
+        return Err(SmasError::Inexact("the system is singular"));
+    }
+    Ok(reduced.into_iter().map(|row| row[r..].to_vec()).collect())
+}
+
+/// This computes a basis of the null space of a matrix exactly: one vector per free column of
+/// its reduced row echelon form, with a 1 at that column. Returns SmasError::Inexact if an
+/// entry is not finite or a fraction overflows.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn null_space(s_matrix: &na::DMatrix<f64>) -> Result<Vec<Vec<Fraction>>, SmasError> {
+    let n = s_matrix.ncols();
+    let (reduced, pivots) = rref(to_rows(s_matrix)?, n)?;
+    let mut basis = vec!();
+    for free in (0..n).filter(|j| !pivots.contains(j)) {
+        let mut vector = vec![Fraction::ZERO; n];
+        vector[free] = Fraction::ONE;
+        for (row, pivot) in reduced.iter().zip(&pivots) {
+            vector[*pivot] = Fraction::ZERO.sub(&row[free])?;
+        }
+        basis.push(vector);
+    }
+    Ok(basis)
+}
+
+/// This solves Ax = B exactly, with every entry read as a fraction (see `Fraction::from_f64`),
+/// so integer matrices give exact fluxes without the tiny nonzero artifacts of floating point.
+/// The solution is the same minimum norm least squares solution as the pseudoinverse, computed
+/// from the full rank factorization A = CF, where F is the nonzero rows of the reduced row
+/// echelon form of A and C the pivot columns of A:
+///
+/// x = Fᵀ (FFᵀ)⁻¹ (CᵀC)⁻¹ CᵀB
+///
+/// Returns SmasError::Shape if B does not have one entry per row of A, and SmasError::Inexact if
+/// an entry is not finite or a fraction overflows, e.g. for large matrices with many decimals.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn solve_exact(acc_vector: &na::DVector<f64>, s_matrix: &na::DMatrix<f64>) -> Result<ExactSolution, SmasError> {
+    let (m, n) = s_matrix.shape();
+    if acc_vector.nrows() != m {
+        return Err(SmasError::Shape {
+            expected: m,
+            found: acc_vector.nrows(),
+        });
+    }
+    let a = to_rows(s_matrix)?;
+    let b: Vec<Fraction> = acc_vector.iter().map(|val| Fraction::from_f64(*val)).collect::<Result<_, _>>()?;
+    let (f, pivots) = rref(a.clone(), n)?;
+    let rank = pivots.len();
+
+    // ** the pseudoinverse only involves A, whose small integers keep the fractions small; B,
+    // ** whose decimals have large denominators, is only multiplied in at the end
+    let column = |j: usize| a.iter().map(move |row| &row[j]);
+    let gram_c = pivots.iter()
+        .map(|i| pivots.iter().map(|j| dot(column(*i), column(*j))).collect())
+        .collect::<Result<Vec<Vec<Fraction>>, _>>()?;
+    let c_transpose = pivots.iter().map(|j| column(*j).copied().collect()).collect();
+    let gram_f = f.iter()
+        .map(|row_i| f.iter().map(|row_j| dot(row_i.iter(), row_j.iter())).collect())
+        .collect::<Result<Vec<Vec<Fraction>>, _>>()?;
+    let q = solve_square(gram_f, solve_square(gram_c, c_transpose)?)?;
+    let mut reactions = vec!();
+    for j in 0..n {
+        let pinv_row = (0..m)
+            .map(|i| dot(f.iter().map(|row| &row[j]), q.iter().map(|row| &row[i])))
+            .collect::<Result<Vec<Fraction>, _>>()?;
+        reactions.push(dot(pinv_row.iter(), b.iter())?);
+    }
+
+    let mut residual_norm_squared = Fraction::ZERO;
+    for (row, b) in a.iter().zip(&b) {
+        let residual = dot(row.iter(), reactions.iter())?.sub(b)?;
+        residual_norm_squared = residual_norm_squared.add(&residual.mul(&residual)?)?;
+    }
+    Ok(ExactSolution { reactions, rank, residual_norm_squared })
+}
+
+/// This formats a vector of fractions like a Matrix Market array vector, with each entry written
+/// as an exact fraction, e.g. "3/2", rather than a float.
+///
+/// # Arguments
+/// * `vector` - the fractions
+/// * `provenance` - the provenance written as comment lines at the beginning of the string
+///
+pub fn format_fraction_vector(vector: &[Fraction], provenance: &Provenance) -> String {
+    let mut result_string = provenance.format_header();
+    result_string.push_str(&format!("{} 1", vector.len()));
+    for val in vector {
+        result_string.push_str(&format!("\n  {val}"));
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_solve_exact() {
+        let fraction = |numer, denom| exact::Fraction::new(numer, denom).unwrap();
+        assert_eq!(exact::Fraction::from_f64(0.1).unwrap(), fraction(1, 10));
+        assert_eq!(exact::Fraction::from_f64(-2.5).unwrap(), fraction(-5, 2));
+        assert_eq!(fraction(2, -4).to_string(), "-1/2");
+        assert_eq!(fraction(1, 3).add(&fraction(1, 6)).unwrap().to_string(), "1/2");
+        assert!(exact::Fraction::from_f64(f64::NAN).is_err());
+        assert!(fraction(i128::MAX, 1).add(&exact::Fraction::ONE).is_err());
+
+        // ** two reactions in parallel split the flux evenly, exactly
+        let s_matrix = na::DMatrix::from_row_slice(2, 3, &[1.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        let solution = exact::solve_exact(&na::DVector::from_row_slice(&[1.0, 3.0]), &s_matrix).unwrap();
+        assert_eq!(solution.reactions, vec![fraction(1, 2), fraction(1, 2), fraction(3, 1)]);
+        assert_eq!(solution.rank, 2);
+        assert!(solution.residual_norm_squared.is_zero());
+        assert_eq!(exact::null_space(&s_matrix).unwrap(), vec![vec![fraction(-1, 1), fraction(1, 1), exact::Fraction::ZERO]]);
+
+        // ** an inconsistent system gets the least squares solution
+        let solution = exact::solve_exact(&na::DVector::from_row_slice(&[0.0, 1.0]), &na::DMatrix::from_element(2, 1, 1.0)).unwrap();
+        assert_eq!(solution.reactions, vec![fraction(1, 2)]);
+        assert_eq!(solution.residual_norm_squared, fraction(1, 2));
+
+        // ** the default matrix has independent reactions, so integer fluxes are recovered exactly
+        let s_matrix = util::default_s_matrix();
+        let r_vector = na::DVector::from_fn(s_matrix.ncols(), |j, _| j as f64 - 10.0);
+        let solution = exact::solve_exact(&(&s_matrix * &r_vector), &s_matrix).unwrap();
+        let expected: Vec<exact::Fraction> = r_vector.iter().map(|val| exact::Fraction::from_f64(*val).unwrap()).collect();
+        assert_eq!(solution.reactions, expected);
+        assert!(solution.residual_norm_squared.is_zero());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::io;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The example files bundled into the smas binary, as (file name, contents) pairs.
+pub static EXAMPLE_FILES: &[(&str, &str)] = &[
+    ("smat.txt", include_str!("../resources/smat.txt")),
+    ("astd015.txt", include_str!("../resources/astd015.txt")),
+    ("rstd015.txt", include_str!("../resources/rstd015.txt")),
+    ("amed015.txt", include_str!("../resources/amed015.txt")),
+    ("rmed015.txt", include_str!("../resources/rmed015.txt")),
+    ("glycolysis_smat.txt", include_str!("../resources/glycolysis_smat.txt")),
+    ("glycolysis_acc.txt", include_str!("../resources/glycolysis_acc.txt")),
+    ("glycolysis_flux.txt", include_str!("../resources/glycolysis_flux.txt")),
+];
+
+/// This writes the bundled example files into a directory, creating it if it doesn't exist.
+/// The paths of the written files are returned.
+///
+/// # Arguments
+/// * `dir` - the directory that the example files are written to
+///
+pub fn extract_examples<R: AsRef<Path>>(dir: R) -> std::io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(&dir)?;
+
+    let mut paths = vec!();
+    for (name, contents) in EXAMPLE_FILES {
+        let path = dir.as_ref().join(name);
+        io::write_atomic(&path, contents)?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_extract_examples() {
+        let dir = std::env::temp_dir().join(format!("smas_examples_test_{}", std::process::id()));
+        let paths = examples::extract_examples(&dir).unwrap();
+        assert_eq!(paths.len(), examples::EXAMPLE_FILES.len());
+
+        let smat: na::DMatrix<f64> = io::load_matrix(dir.join("smat.txt")).unwrap();
+        assert!(smat == util::default_s_matrix());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+use crate::error::SmasError;
+use crate::solve::{self, Solution};
+
+use nalgebra as na;
+
+/// This narrates a solve step by step for someone learning how it works: the dimensions of the
+/// system, the (absent) scaling, which singular values of A were kept or discarded and what that
+/// means for the rank, how much of the solution lies in the null space of A, and how the residual
+/// Ax - B is distributed over the rows, largest first. Each line is a '%' comment line.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the matrix of the system that was solved, A: (m x n)
+/// * `solution` - the solution of the system
+/// * `row_labels` - the name of each row of A, e.g. its metabolite; (m x 1)
+///
+pub fn explain_solve(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    solution: &Solution,
+    row_labels: &[String],
+) -> String {
+    let (m, n) = s_matrix.shape();
+    let mut lines = vec!(
+        format!("loaded a system of {m} rows (metabolites and any measurement rows) and {n} columns (reactions), \
+            and an accumulation vector B of {} values", acc_vector.nrows()),
+        String::from("the matrix and accumulation vector are used exactly as given; no scaling is applied"),
+        format!("the solution was computed with the {} method", solution.method),
+    );
+
+    // ** singular values at or below the cutoff are treated as zero by the pseudoinverse
+    let singular_values: Vec<f64> = if s_matrix.is_empty() {
+        vec!()
+    } else {
+        let mut values: Vec<f64> = s_matrix.singular_values().iter().copied().collect();
+        values.sort_by(|a, b| b.total_cmp(a));
+        values
+    };
+    let (kept, discarded): (Vec<f64>, Vec<f64>) = singular_values.iter().partition(|s| **s > solve::SVD_EPSILON);
+    let format_values = |values: &[f64]| {
+        let formatted: Vec<String> = values.iter().map(|v| format!("{v:.3e}")).collect();
+        if formatted.is_empty() { String::from("none") } else { formatted.join(" ") }
+    };
+    lines.push(format!(
+        "the singular values of A above the cutoff {:e} are kept: {}",
+        solve::SVD_EPSILON,
+        format_values(&kept)
+    ));
+    lines.push(format!(
+        "the singular values at or below the cutoff are discarded as roundoff: {}",
+        format_values(&discarded)
+    ));
+    lines.push(format!(
+        "so A has rank {}: {} of its {m} rows are independent, and {} combinations of rows are redundant",
+        kept.len(),
+        kept.len(),
+        m - kept.len()
+    ));
+
+    // ** the part of x that A⁺A does not reproduce lies in the null space
+    let null_dimension = n - kept.len();
+    let null_component = if n == 0 {
+        0.0
+    } else {
+        (&solution.reactions - solve::jacobian(s_matrix) * (s_matrix * &solution.reactions)).norm()
+    };
+    lines.push(format!(
+        "the null space of A has dimension {null_dimension}: that many independent flux changes leave every \
+        accumulation unchanged, so B alone cannot determine them"
+    ));
+    lines.push(format!(
+        "the null space component of the solution has norm {null_component:.3e} out of {:.3e}{}",
+        solution.reactions.norm(),
+        if null_component <= solve::SVD_EPSILON {
+            " (the minimum norm solution has none)"
+        } else {
+            " (constraints moved the solution away from the minimum norm solution)"
+        }
+    ));
+
+    let residual = s_matrix * &solution.reactions - acc_vector;
+    lines.push(format!(
+        "the residual ||Ax - B|| is {:.3e}; by row, largest first (Ax - B):",
+        residual.norm()
+    ));
+    let mut rows: Vec<usize> = (0..m).collect();
+    rows.sort_by(|a, b| residual[*b].abs().total_cmp(&residual[*a].abs()));
+    for i in rows {
+        lines.push(format!("  {}\t{:.3e}", row_labels[i], residual[i]));
+    }
+
+    lines.iter().map(|line| format!("% {line}")).collect::<Vec<String>>().join("\n")
+}
+
+/// These are the intermediate results of a pseudoinverse solve, for walking through the linear
+/// algebra: the singular value decomposition A = UΣVᵀ, the pseudoinverse A⁺ = VΣ⁺Uᵀ built from
+/// it, and the projection AA⁺B of B onto the column space of A, which is the part of B that any
+/// reaction vector can produce.
+pub struct Intermediates {
+    /// The left singular vectors, U; (m x k) where k = min(m, n)
+    pub u: na::DMatrix<f64>,
+    /// The singular values, the diagonal of Σ, largest first; (k x 1)
+    pub singular_values: na::DVector<f64>,
+    /// The right singular vectors, V; (n x k)
+    pub v: na::DMatrix<f64>,
+    /// The pseudoinverse A⁺, which discards singular values at or below SVD_EPSILON; (n x m)
+    pub pseudo_inverse: na::DMatrix<f64>,
+    /// The projection of B onto the column space of A, AA⁺B; (m x 1)
+    pub projection: na::DVector<f64>,
+}
+
+/// This computes the intermediate results of a pseudoinverse solve. Returns SmasError::Shape if
+/// B does not have one entry per row of A, or SmasError::Empty if A is empty.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn intermediates(acc_vector: &na::DVector<f64>, s_matrix: &na::DMatrix<f64>) -> Result<Intermediates, SmasError> {
+    if acc_vector.nrows() != s_matrix.nrows() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.nrows(),
+            found: acc_vector.nrows(),
+        });
+    }
+    if s_matrix.is_empty() {
+        return Err(SmasError::Empty("the stoichiometric matrix"));
+    }
+    let svd = na::linalg::SVD::new(s_matrix.clone(), true, true);
+    let pseudo_inverse = svd.clone().pseudo_inverse(solve::SVD_EPSILON)
+        .expect("failed to compute pseudo-inverse of stoichiometric matrix");
+    let projection = s_matrix * (&pseudo_inverse * acc_vector);
+    Ok(Intermediates {
+        u: svd.u.expect("the SVD was computed with U"),
+        singular_values: svd.singular_values,
+        v: svd.v_t.expect("the SVD was computed with Vᵀ").transpose(),
+        pseudo_inverse,
+        projection,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_explain_solve() {
+        // ** two reactions that make the same metabolite, so one flux change is invisible
+        let s_matrix = na::DMatrix::from_row_slice(2, 2, &[1.0, 1.0, 0.0, 0.0]);
+        let acc_vector = na::DVector::from_row_slice(&[2.0, 0.5]);
+        let solution = solve::solve_pinv(&acc_vector, &s_matrix).unwrap();
+        let labels = vec![String::from("glc"), String::from("atp")];
+        let explanation = explain::explain_solve(&acc_vector, &s_matrix, &solution, &labels);
+
+        assert!(explanation.contains("A has rank 1"), "{explanation}");
+        assert!(explanation.contains("null space of A has dimension 1"), "{explanation}");
+        assert!(explanation.contains("minimum norm solution has none"), "{explanation}");
+        // ** atp cannot accumulate, so it carries the whole residual and is listed first
+        let atp = explanation.find("atp\t").unwrap();
+        assert!(atp < explanation.find("glc\t").unwrap());
+        assert!(explanation[atp..].starts_with("atp\t-5.000e-1"), "{explanation}");
+    }
+
+    #[test]
+    fn test_intermediates() {
+        let s_matrix = util::default_s_matrix();
+        let acc_vector = na::DVector::from_row_slice(matrices::A_STD_015);
+        let steps = explain::intermediates(&acc_vector, &s_matrix).unwrap();
+
+        // ** the factors reassemble A, and A⁺B is the solution
+        let sigma = na::DMatrix::from_diagonal(&steps.singular_values);
+        assert!((&steps.u * sigma * steps.v.transpose() - &s_matrix).norm() < 1e-9);
+        let solution = solve::solve(acc_vector.clone(), s_matrix.clone());
+        assert!((&steps.pseudo_inverse * &acc_vector - solution).norm() < 1e-9);
+        assert!(steps.singular_values.as_slice().windows(2).all(|w| w[0] >= w[1]));
+
+        // ** the projection is in the column space, so projecting it again changes nothing
+        let again = explain::intermediates(&steps.projection, &s_matrix).unwrap().projection;
+        let delta = (again - &steps.projection).norm();
+        assert!(delta < 1e-7, "{delta}");
+        assert!(matches!(
+            explain::intermediates(&na::DVector::zeros(0), &na::DMatrix::zeros(0, 0)),
+            Err(error::SmasError::Empty(_))
+        ));
+    }
+}
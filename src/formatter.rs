@@ -0,0 +1,319 @@
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat, Provenance};
+
+use std::sync::{Arc, OnceLock, RwLock};
+
+use nalgebra as na;
+
+/// The name of the formatter used when none is chosen: the Matrix Market array format.
+pub const DEFAULT_FORMATTER: &str = "mm";
+
+/// This is an output format for vectors and matrices. Formatters are registered by name with
+/// `register_formatter`, after which the CLI (--formatter) and `get_formatter` can use them like
+/// the built in ones, so crates embedding smas can add formats of their own.
+pub trait OutputFormatter: Send + Sync {
+    /// This returns the name the formatter is registered and selected under, e.g. "mm".
+    fn name(&self) -> &str;
+
+    /// This formats a matrix.
+    ///
+    /// # Arguments
+    /// * `matrix` - the matrix to be formatted
+    /// * `float_format` - how to format the floats: scientific or decimal
+    /// * `float_precision` - how many positions the floats have past the decimal point
+    /// * `provenance` - where the output came from, along with its row and column labels
+    ///
+    fn format_matrix(
+        &self,
+        matrix: &na::DMatrix<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String;
+
+    /// This formats a vector. By default it is formatted as a matrix with one column.
+    ///
+    /// # Arguments
+    /// * `vector` - the vector to be formatted
+    /// * `float_format` - how to format the floats: scientific or decimal
+    /// * `float_precision` - how many positions the floats have past the decimal point
+    /// * `provenance` - where the output came from, along with its row labels
+    ///
+    fn format_vector(
+        &self,
+        vector: &na::DVector<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        let matrix = na::DMatrix::from_column_slice(vector.nrows(), 1, vector.as_slice());
+        self.format_matrix(&matrix, float_format, float_precision, provenance)
+    }
+}
+
+/// This wraps a formatter so that each call is recorded as a "format" span.
+struct ProfiledFormatter(Arc<dyn OutputFormatter>);
+
+impl OutputFormatter for ProfiledFormatter {
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn format_matrix(
+        &self,
+        matrix: &na::DMatrix<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        let _span = tracing::info_span!("format", formatter = %self.name(), rows = matrix.nrows(), cols = matrix.ncols()).entered();
+        self.0.format_matrix(matrix, float_format, float_precision, provenance)
+    }
+
+    fn format_vector(
+        &self,
+        vector: &na::DVector<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        let _span = tracing::info_span!("format", formatter = %self.name(), rows = vector.nrows(), cols = 1).entered();
+        self.0.format_vector(vector, float_format, float_precision, provenance)
+    }
+}
+
+/// This wraps a formatter so that the time spent formatting shows up in profiles.
+///
+/// # Arguments
+/// * `formatter` - the formatter to wrap
+///
+pub fn profiled(formatter: Arc<dyn OutputFormatter>) -> Arc<dyn OutputFormatter> {
+    Arc::new(ProfiledFormatter(formatter))
+}
+
+/// This writes the Matrix Market array format, one matrix row per line.
+pub struct MatrixMarketFormatter;
+
+impl OutputFormatter for MatrixMarketFormatter {
+    fn name(&self) -> &str {
+        DEFAULT_FORMATTER
+    }
+
+    fn format_matrix(
+        &self,
+        matrix: &na::DMatrix<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        io::format_matrix_mm_array(matrix, float_format, float_precision, provenance)
+    }
+
+    fn format_vector(
+        &self,
+        vector: &na::DVector<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        io::format_vector_mm_array(vector, float_format, float_precision, provenance)
+    }
+}
+
+/// This writes a tab separated table with a header row of column labels and a row label at the
+/// start of each row, for spreadsheets. Unlabeled rows and columns are numbered from 1. The
+/// provenance is written as '#' comment lines.
+pub struct TsvFormatter;
+
+impl OutputFormatter for TsvFormatter {
+    fn name(&self) -> &str {
+        "tsv"
+    }
+
+    fn format_matrix(
+        &self,
+        matrix: &na::DMatrix<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        // ** unlabeled rows and columns are numbered from 1
+        let label = |labels: &Option<Vec<String>>, i: usize| match labels {
+            Some(labels) => labels[i].clone(),
+            None => (i + 1).to_string(),
+        };
+        let mut result_string = String::new();
+        for line in provenance.format_header().lines().filter(|line| line.starts_with("% ")) {
+            result_string.push_str(&format!("#{}\n", &line[1..]));
+        }
+        for j in 0..matrix.ncols() {
+            result_string.push('\t');
+            result_string.push_str(&label(&provenance.col_labels, j));
+        }
+        for (i, row) in matrix.row_iter().enumerate() {
+            result_string.push('\n');
+            result_string.push_str(&label(&provenance.row_labels, i));
+            for val in row.iter() {
+                result_string.push('\t');
+                result_string.push_str(&io::format_float(*val, float_format, float_precision));
+            }
+        }
+        result_string
+    }
+}
+
+/// This writes the nonzero entries of a vector as label=value pairs, one per line, e.g.
+/// "hk=1.5", the form -a reads them in, so that small results are easy to read and to diff. Zero
+/// entries are left out, and unlabeled rows are numbered from 1. A matrix of several columns is
+/// written as a [column label] section for each column. The provenance is written as '%' comment
+/// lines, without the row and column labels.
+pub struct KeyedFormatter;
+
+impl OutputFormatter for KeyedFormatter {
+    fn name(&self) -> &str {
+        "keyed"
+    }
+
+    fn format_matrix(
+        &self,
+        matrix: &na::DMatrix<f64>,
+        float_format: FloatFormat,
+        float_precision: usize,
+        provenance: &Provenance,
+    ) -> String {
+        let label = |labels: &Option<Vec<String>>, i: usize| match labels {
+            Some(labels) => labels[i].clone(),
+            None => (i + 1).to_string(),
+        };
+        let mut lines: Vec<String> = provenance.format_header().lines()
+            .filter(|line| line.starts_with("% "))
+            .map(String::from)
+            .collect();
+        for (j, column) in matrix.column_iter().enumerate() {
+            if matrix.ncols() != 1 {
+                lines.push(format!("[{}]", label(&provenance.col_labels, j)));
+            }
+            for (i, value) in column.iter().enumerate().filter(|(_, value)| **value != 0.0) {
+                lines.push(format!("{}={}", label(&provenance.row_labels, i), io::format_float(*value, float_format, float_precision)));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// This returns the registered formatters, starting with the built in ones.
+fn registry() -> &'static RwLock<Vec<Arc<dyn OutputFormatter>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn OutputFormatter>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(vec![Arc::new(MatrixMarketFormatter), Arc::new(TsvFormatter), Arc::new(KeyedFormatter)]))
+}
+
+/// This registers a formatter for the whole process, replacing any formatter with the same name.
+///
+/// # Arguments
+/// * `formatter` - the formatter to register
+///
+pub fn register_formatter(formatter: Arc<dyn OutputFormatter>) {
+    let mut formatters = registry().write().expect("the formatter registry is poisoned");
+    formatters.retain(|f| f.name() != formatter.name());
+    formatters.push(formatter);
+}
+
+/// This returns the names of the registered formatters, in order of registration.
+pub fn formatter_names() -> Vec<String> {
+    registry().read().expect("the formatter registry is poisoned")
+        .iter()
+        .map(|f| f.name().to_string())
+        .collect()
+}
+
+/// This looks up a registered formatter by name.
+///
+/// # Arguments
+/// * `name` - the name of the formatter, e.g. "mm"
+///
+pub fn get_formatter(name: &str) -> Result<Arc<dyn OutputFormatter>, SmasError> {
+    let formatter = registry().read().expect("the formatter registry is poisoned")
+        .iter()
+        .find(|f| f.name() == name)
+        .cloned();
+    // ** the names are listed after the lookup has released the lock
+    formatter.ok_or_else(|| SmasError::InvalidArgument(
+        format!("unknown output format \"{name}\", expected one of: {}", formatter_names().join(", ")),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    struct XmlFormatter;
+
+    impl formatter::OutputFormatter for XmlFormatter {
+        fn name(&self) -> &str {
+            "test-xml"
+        }
+
+        fn format_matrix(
+            &self,
+            matrix: &na::DMatrix<f64>,
+            float_format: io::FloatFormat,
+            float_precision: usize,
+            _provenance: &io::Provenance,
+        ) -> String {
+            let values: Vec<String> = matrix.iter()
+                .map(|v| format!("<v>{}</v>", io::format_float(*v, float_format, float_precision)))
+                .collect();
+            format!("<matrix rows=\"{}\">{}</matrix>", matrix.nrows(), values.join(""))
+        }
+    }
+
+    #[test]
+    fn test_register_formatter() {
+        let provenance = io::Provenance::new("smas test", "test");
+        let vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        assert!(formatter::get_formatter("test-xml").is_err());
+
+        formatter::register_formatter(std::sync::Arc::new(XmlFormatter));
+        let xml = formatter::get_formatter("test-xml").unwrap();
+        assert_eq!(
+            xml.format_vector(&vector, io::FloatFormat::Decimal, 1, &provenance),
+            "<matrix rows=\"2\"><v>1.0</v><v>2.0</v></matrix>"
+        );
+
+        // ** the built in formatters are registered too
+        let mm = formatter::get_formatter(formatter::DEFAULT_FORMATTER).unwrap();
+        assert_eq!(
+            mm.format_vector(&vector, io::FloatFormat::Decimal, 1, &provenance),
+            io::format_vector_mm_array(&vector, io::FloatFormat::Decimal, 1, &provenance)
+        );
+        let mut labeled = provenance;
+        labeled.row_labels = Some(vec![String::from("hk"), String::from("pgi")]);
+        let tsv = formatter::get_formatter("tsv").unwrap()
+            .format_vector(&vector, io::FloatFormat::Decimal, 1, &labeled);
+        assert!(tsv.ends_with("\t1\nhk\t1.0\npgi\t2.0"), "{tsv}");
+    }
+
+    #[test]
+    fn test_keyed_formatter() {
+        let keyed = formatter::get_formatter("keyed").unwrap();
+        let mut provenance = io::Provenance::new("smas test", "test");
+        provenance.row_labels = Some(vec![String::from("hk"), String::from("pgi"), String::from("pfk")]);
+        let vector = na::DVector::from_row_slice(&[1.5, 0.0, -2.0]);
+        let output = keyed.format_vector(&vector, io::FloatFormat::Decimal, 1, &provenance);
+        assert!(output.starts_with("% smas test\n"), "{output}");
+        assert!(!output.contains("%rows"), "{output}");
+        assert!(output.ends_with("\n% subcommand: test\nhk=1.5\npfk=-2.0"), "{output}");
+
+        // ** the pairs read back with -a
+        let pairs: Vec<&str> = output.lines().filter(|line| !line.starts_with('%')).collect();
+        let labels = provenance.row_labels.clone().unwrap();
+        assert_eq!(io::parse_keyed_vector(&pairs.join(" "), &labels).unwrap(), vector);
+
+        provenance.row_labels = None;
+        provenance.col_labels = Some(vec![String::from("a"), String::from("b")]);
+        let matrix = na::DMatrix::from_row_slice(2, 2, &[0.0, 1.0, 2.0, 0.0]);
+        let output = keyed.format_matrix(&matrix, io::FloatFormat::Decimal, 1, &provenance);
+        assert!(output.ends_with("\n[a]\n2=2.0\n[b]\n1=1.0"), "{output}");
+    }
+}
@@ -0,0 +1,71 @@
+use crate::error::SmasError;
+use crate::util;
+
+use nalgebra as na;
+
+/// This is an enum used to parametrize how noise is scaled when generating accumulation vectors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoiseKind {
+    /// The standard deviation of the noise on each value is the noise level times its magnitude
+    Relative,
+    /// The standard deviation of the noise on each value is the noise level
+    Absolute,
+}
+
+/// This generates an accumulation vector from a known reaction vector, a = A r, plus Gaussian
+/// noise, so that solvers can be validated against the reaction vector that produced it.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the known reaction vector, r; (n x 1)
+/// * `noise` - the noise level; 0 for an exact accumulation vector
+/// * `kind` - whether the noise level is relative to each value or absolute
+/// * `seed` - the seed of the random number generator
+///
+pub fn generate_accumulation(
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    noise: f64,
+    kind: NoiseKind,
+    seed: u64,
+) -> Result<na::DVector<f64>, SmasError> {
+    if reactions.nrows() != s_matrix.ncols() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.ncols(),
+            found: reactions.nrows(),
+        });
+    }
+
+    let mut rng = util::SplitMix64::new(seed);
+    let mut acc_vector = s_matrix * reactions;
+    for val in acc_vector.iter_mut() {
+        let std_dev = match kind {
+            NoiseKind::Relative => noise * val.abs(),
+            NoiseKind::Absolute => noise,
+        };
+        *val += std_dev * rng.next_gaussian();
+    }
+    Ok(acc_vector)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_generate_accumulation() {
+        let s_matrix = util::default_s_matrix();
+        let reactions: na::DVector<f64> = na::DVector::from_row_slice(matrices::R_STD_015);
+
+        // ** without noise the generated vector is exactly A r
+        let exact = generate::generate_accumulation(&s_matrix, &reactions, 0.0, generate::NoiseKind::Relative, 0).unwrap();
+        assert!(exact == &s_matrix * &reactions);
+
+        let noisy = generate::generate_accumulation(&s_matrix, &reactions, 0.05, generate::NoiseKind::Relative, 7).unwrap();
+        let again = generate::generate_accumulation(&s_matrix, &reactions, 0.05, generate::NoiseKind::Relative, 7).unwrap();
+        assert!(noisy == again);
+        assert!(noisy != exact);
+        assert!(noisy.iter().zip(exact.iter()).all(|(n, e)| (n - e).abs() <= 0.5 * e.abs()));
+    }
+}
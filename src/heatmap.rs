@@ -0,0 +1,120 @@
+use crate::io::LabeledMatrix;
+
+/// The ANSI escape sequences used to color terminal output.
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+
+/// The shades of the magnitude scale, from the smallest to the largest quarter of |max|.
+const UNICODE_SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+const ASCII_SHADES: [char; 4] = [':', '=', '*', '#'];
+
+/// This is how a heatmap is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct HeatmapStyle {
+    /// Shade each entry by its magnitude relative to the largest, rather than only by its sign
+    pub magnitude: bool,
+    /// Color positive (product) entries green and negative (substrate) entries red
+    pub color: bool,
+    /// Use only ASCII characters, for terminals and files without Unicode
+    pub ascii: bool,
+}
+
+impl HeatmapStyle {
+    /// This returns the character of a nonzero entry.
+    pub(crate) fn cell(&self, val: f64, max: f64) -> char {
+        if !self.magnitude {
+            return if val > 0.0 { '+' } else { '-' };
+        }
+        let shades = if self.ascii { ASCII_SHADES } else { UNICODE_SHADES };
+        let shade = ((val.abs() / max) * shades.len() as f64).ceil() as usize;
+        shades[shade.clamp(1, shades.len()) - 1]
+    }
+}
+
+/// This draws the pattern of a matrix in the terminal, one line per row (metabolite) and one
+/// character per column (reaction): '+' and '-' for the sign of each nonzero entry, or shades of
+/// its magnitude, and a dot for zeros. A ruler above the columns marks every fifth reaction and
+/// numbers every tenth, which makes a transposed or shifted matrix easy to spot.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix
+/// * `style` - how the entries are drawn
+/// * `width` - the width of the terminal; columns that do not fit are left out
+///
+pub fn format_heatmap(s_matrix: &LabeledMatrix, style: HeatmapStyle, width: Option<usize>) -> String {
+    let matrix = &s_matrix.matrix;
+    let labels: Vec<String> = (0..matrix.nrows()).map(|i| s_matrix.row_label(i)).collect();
+    let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+    let max = matrix.amax();
+    let n_visible = match width {
+        Some(width) => matrix.ncols().min(width.saturating_sub(label_width + 1)),
+        None => matrix.ncols(),
+    };
+    let zero = if style.ascii { '.' } else { '·' };
+
+    let mut lines = vec![format!(
+        "{} metabolites x {} reactions, {} nonzero, |max| = {max:.3e}{}",
+        matrix.nrows(),
+        matrix.ncols(),
+        matrix.iter().filter(|val| **val != 0.0).count(),
+        if n_visible < matrix.ncols() { format!(", showing the first {n_visible} reactions") } else { String::new() }
+    )];
+    let ruler: String = (1..=n_visible)
+        .map(|j| match (j % 10, j % 5) {
+            (0, _) => char::from_digit((j / 10 % 10) as u32, 10).unwrap(),
+            (_, 0) => '+',
+            _ => ' ',
+        })
+        .collect();
+    lines.push(format!("{:label_width$} {ruler}", ""));
+    for (label, row) in labels.iter().zip(matrix.row_iter()) {
+        let mut line = format!("{label:<label_width$} ");
+        for val in row.iter().take(n_visible) {
+            if *val == 0.0 {
+                line.push(zero);
+            } else if style.color {
+                let color = if *val > 0.0 { GREEN } else { RED };
+                line.push_str(&format!("{color}{}{RESET}", style.cell(*val, max)));
+            } else {
+                line.push(style.cell(*val, max));
+            }
+        }
+        lines.push(line);
+    }
+    if style.magnitude {
+        let shades = if style.ascii { ASCII_SHADES } else { UNICODE_SHADES };
+        let legend: Vec<String> = shades.iter()
+            .enumerate()
+            .map(|(k, shade)| format!("{shade} <= {}%", 25 * (k + 1)))
+            .collect();
+        lines.push(format!("{} of |max|{}", legend.join("  "), if style.color { "; green > 0, red < 0" } else { "" }));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_format_heatmap() {
+        let s_matrix = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 3, &[1.0, -4.0, 0.0, 0.0, 2.0, 0.5]),
+            row_labels: Some(vec![String::from("g6p"), String::from("atp")]),
+            col_labels: None,
+        };
+        let signs = heatmap::format_heatmap(&s_matrix, heatmap::HeatmapStyle::default(), None);
+        let lines: Vec<&str> = signs.lines().collect();
+        assert!(lines[0].starts_with("2 metabolites x 3 reactions, 4 nonzero"), "{signs}");
+        assert_eq!(&lines[2..], &["g6p +-·", "atp ·++"]);
+
+        let style = heatmap::HeatmapStyle { magnitude: true, color: false, ascii: true };
+        let shades = heatmap::format_heatmap(&s_matrix, style, Some(6));
+        let lines: Vec<&str> = shades.lines().collect();
+        assert!(lines[0].ends_with("showing the first 2 reactions"), "{shades}");
+        assert_eq!(&lines[2..4], &["g6p :#", "atp .="]);
+        assert!(lines[4].starts_with(": <= 25%"), "{shades}");
+    }
+}
@@ -0,0 +1,260 @@
+use crate::error::SmasError;
+use crate::io::LabeledMatrix;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The prefixes that databases and exchange formats put in front of identifiers, e.g. "rn:R00299"
+/// in KEGG or "R_HEX1" in SBML exports of BiGG models. Identifiers are also looked up without them.
+const ID_PREFIXES: [&str; 10] = ["rn:", "cpd:", "kegg:", "bigg:", "metacyc:", "META:", "bigg.reaction:", "bigg.metabolite:", "R_", "M_"];
+
+/// This strips the database prefix from an identifier, if it has one; see ID_PREFIXES.
+///
+/// # Arguments
+/// * `id` - the identifier, e.g. "cpd:C00031"
+///
+pub fn normalize_id(id: &str) -> &str {
+    let id = id.trim();
+    ID_PREFIXES.iter()
+        .find_map(|prefix| id.strip_prefix(prefix).filter(|rest| !rest.is_empty()))
+        .unwrap_or(id)
+}
+
+/// This is a table of identifiers of the same reactions and metabolites in several namespaces,
+/// e.g. BiGG, KEGG and MetaCyc, read from a user-supplied file.
+pub struct IdMap {
+    /// The name of each namespace, in the order of the columns
+    pub namespaces: Vec<String>,
+    /// The identifiers of each entity, one per namespace; None where the entity has none
+    rows: Vec<Vec<Option<String>>>,
+    /// The rows each normalized identifier appears in
+    index: HashMap<String, Vec<usize>>,
+}
+
+/// This describes how the labels of a model were translated to a namespace.
+#[derive(Default)]
+pub struct Translation {
+    /// The labels that are not in the table, or have no identifier in the namespace, and were kept
+    pub unmapped: Vec<String>,
+    /// The labels that map to several identifiers in the namespace, with the one that was used
+    pub ambiguous: Vec<(String, Vec<String>)>,
+    /// The translated labels that two or more labels map to, e.g. two BiGG compartment variants
+    pub collisions: Vec<String>,
+}
+
+impl Translation {
+    /// This formats the translation as '%' comment lines, naming what is being translated, e.g.
+    /// "metabolites of old.mtx".
+    ///
+    /// # Arguments
+    /// * `what` - what was translated
+    /// * `namespace` - the namespace it was translated to
+    ///
+    pub fn format_report(&self, what: &str, namespace: &str) -> String {
+        let mut lines = vec!();
+        if !self.unmapped.is_empty() {
+            lines.push(format!("% {} {what} have no {namespace} identifier and were kept: {}", self.unmapped.len(), self.unmapped.join(" ")));
+        }
+        for (label, ids) in &self.ambiguous {
+            lines.push(format!("% {label} in the {what} has {} {namespace} identifiers ({}); using {}", ids.len(), ids.join(" "), ids[0]));
+        }
+        for label in &self.collisions {
+            lines.push(format!("% several {what} map to {label}"));
+        }
+        lines.join("\n")
+    }
+}
+
+impl IdMap {
+    /// This parses an identifier table. The first line names the namespaces, e.g.
+    ///
+    /// ```text
+    /// bigg    kegg    metacyc
+    /// HEX1    R00299  GLUCOKIN-RXN
+    /// glc__D  C00031  Glucopyranose
+    /// ```
+    ///
+    /// and every other line lists the identifiers of one reaction or metabolite, delimited by tabs
+    /// or whitespace. An entity without an identifier in a namespace has "-" in its column. Blank
+    /// lines and lines starting with '#' are skipped.
+    ///
+    /// # Arguments
+    /// * `text` - the table
+    ///
+    pub fn parse(text: &str) -> Result<IdMap, SmasError> {
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let Some((_, header)) = lines.next() else {
+            return Err(SmasError::Parse { line: 1, message: String::from("the identifier table has no header naming its namespaces") });
+        };
+        let namespaces: Vec<String> = header.split_whitespace().map(|namespace| namespace.to_lowercase()).collect();
+
+        let mut rows = vec!();
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (line_number, line) in lines {
+            let ids: Vec<&str> = line.split_whitespace().collect();
+            if ids.len() != namespaces.len() {
+                return Err(SmasError::Parse {
+                    line: line_number,
+                    message: format!("expected {} identifiers ({}), found {}", namespaces.len(), namespaces.join(" "), ids.len()),
+                });
+            }
+            let row: Vec<Option<String>> = ids.iter()
+                .map(|id| (*id != "-").then(|| id.to_string()))
+                .collect();
+            for id in row.iter().flatten() {
+                let rows_of_id = index.entry(normalize_id(id).to_string()).or_default();
+                if !rows_of_id.contains(&rows.len()) {
+                    rows_of_id.push(rows.len());
+                }
+            }
+            rows.push(row);
+        }
+        Ok(IdMap { namespaces, rows, index })
+    }
+
+    /// This returns the column of a namespace, looked up without regard to case.
+    fn column(&self, namespace: &str) -> Result<usize, SmasError> {
+        self.namespaces.iter()
+            .position(|name| name.eq_ignore_ascii_case(namespace))
+            .ok_or_else(|| SmasError::Parse {
+                line: 1,
+                message: format!("the identifier table has no {namespace} column; its namespaces are {}", self.namespaces.join(" ")),
+            })
+    }
+
+    /// This returns the identifiers an identifier of any namespace has in the given namespace,
+    /// in table order, without duplicates. The identifier is looked up without its database
+    /// prefix.
+    ///
+    /// # Arguments
+    /// * `id` - the identifier, e.g. "cpd:C00031"
+    /// * `namespace` - the namespace to translate to, e.g. "bigg"
+    ///
+    pub fn lookup(&self, id: &str, namespace: &str) -> Result<Vec<String>, SmasError> {
+        let column = self.column(namespace)?;
+        let mut ids: Vec<String> = vec!();
+        for row in self.index.get(normalize_id(id)).into_iter().flatten() {
+            if let Some(target) = &self.rows[*row][column] {
+                if !ids.contains(target) {
+                    ids.push(target.clone());
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// This translates labels to a namespace. Labels that cannot be translated are kept, and
+    /// labels with several identifiers in the namespace take the first; both are reported.
+    ///
+    /// # Arguments
+    /// * `labels` - the labels
+    /// * `namespace` - the namespace to translate to
+    ///
+    pub fn translate(&self, labels: &[String], namespace: &str) -> Result<(Vec<String>, Translation), SmasError> {
+        let mut translation = Translation::default();
+        let mut translated = Vec::with_capacity(labels.len());
+        for label in labels {
+            let ids = self.lookup(label, namespace)?;
+            match ids.first() {
+                None => {
+                    translation.unmapped.push(label.clone());
+                    translated.push(label.clone());
+                }
+                Some(id) => {
+                    translated.push(id.clone());
+                    if ids.len() > 1 {
+                        translation.ambiguous.push((label.clone(), ids.clone()));
+                    }
+                }
+            }
+        }
+        for (i, label) in translated.iter().enumerate() {
+            if translated[..i].contains(label) && !translation.collisions.contains(label) {
+                translation.collisions.push(label.clone());
+            }
+        }
+        Ok((translated, translation))
+    }
+
+    /// This translates the metabolite (row) and reaction (column) labels of a matrix to a
+    /// namespace, and returns the translations of both. Unlabeled rows and columns are left as
+    /// they are.
+    ///
+    /// # Arguments
+    /// * `matrix` - the labeled matrix
+    /// * `namespace` - the namespace to translate to
+    ///
+    pub fn translate_matrix(&self, matrix: &mut LabeledMatrix, namespace: &str) -> Result<(Translation, Translation), SmasError> {
+        let translate = |labels: &mut Option<Vec<String>>| -> Result<Translation, SmasError> {
+            let Some(labels) = labels else {
+                return Ok(Translation::default());
+            };
+            let (translated, translation) = self.translate(labels, namespace)?;
+            *labels = translated;
+            Ok(translation)
+        };
+        Ok((translate(&mut matrix.row_labels)?, translate(&mut matrix.col_labels)?))
+    }
+}
+
+/// This reads an identifier table file; see `IdMap::parse` for the format.
+///
+/// # Arguments
+/// * `path` - the path to the file
+///
+pub fn load_id_map<R: AsRef<Path>>(path: R) -> Result<IdMap, SmasError> {
+    IdMap::parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_id_map() {
+        let table = "# reactions and metabolites of glycolysis\n\
+            BiGG  KEGG    MetaCyc\n\
+            HEX1  R00299  GLUCOKIN-RXN\n\
+            PGI   R02740  PGLUCISOM-RXN\n\
+            glc__D_c  C00031  Glucopyranose\n\
+            glc__D_e  C00031  -\n\
+            g6p_c   C00668  -\n";
+        let id_map = id_map::IdMap::parse(table).unwrap();
+        assert_eq!(id_map.namespaces, ["bigg", "kegg", "metacyc"]);
+        assert_eq!(id_map::normalize_id("cpd:C00031"), "C00031");
+        assert_eq!(id_map::normalize_id("R_"), "R_");
+        assert_eq!(id_map.lookup("rn:R00299", "BiGG").unwrap(), ["HEX1"]);
+        assert_eq!(id_map.lookup("C00031", "bigg").unwrap(), ["glc__D_c", "glc__D_e"]);
+        assert!(id_map.lookup("g6p_c", "metacyc").unwrap().is_empty());
+        assert!(id_map.lookup("HEX1", "chebi").is_err());
+
+        let mut kegg = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(3, 2, &[-1.0, 0.0, 1.0, -1.0, 0.0, 1.0]),
+            row_labels: Some(vec![String::from("C00031"), String::from("C00668"), String::from("C05345")]),
+            col_labels: Some(vec![String::from("R00299"), String::from("R02740")]),
+        };
+        let (metabolites, reactions) = id_map.translate_matrix(&mut kegg, "bigg").unwrap();
+        assert_eq!(kegg.row_labels.unwrap(), ["glc__D_c", "g6p_c", "C05345"]);
+        assert_eq!(kegg.col_labels.unwrap(), ["HEX1", "PGI"]);
+        assert_eq!(metabolites.unmapped, ["C05345"]);
+        assert_eq!(metabolites.ambiguous.len(), 1);
+        assert!(reactions.unmapped.is_empty() && reactions.ambiguous.is_empty());
+        assert_eq!(
+            metabolites.format_report("metabolites", "bigg"),
+            "% 1 metabolites have no bigg identifier and were kept: C05345\n\
+            % C00031 in the metabolites has 2 bigg identifiers (glc__D_c glc__D_e); using glc__D_c"
+        );
+
+        let (_, translation) = id_map.translate(&[String::from("glc__D_c"), String::from("glc__D_e")], "kegg").unwrap();
+        assert_eq!(translation.collisions, ["C00031"]);
+        let Err(error) = id_map::IdMap::parse("bigg kegg\nHEX1\n") else {
+            panic!("a short row should fail");
+        };
+        assert_eq!(error.to_string(), "line 2: expected 2 identifiers (bigg kegg), found 1");
+    }
+}
@@ -1,12 +1,22 @@
+use crate::arrow;
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::measurements::FluxMeasurement;
+use crate::sparse::CscMatrix;
+use crate::units::Unit;
 use crate::util;
 
-use std::io::{BufRead, BufReader};
-use std::fs::File;
-use std::path::Path;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use nalgebra as na;
 
+/// The value of the entries listed in a Matrix Market pattern file, unless another is given.
+pub const DEFAULT_PATTERN_VALUE: f64 = 1.0;
+
 /// This is a simple internal struct that describes the shape of a matrix and its data.
 struct MatrixData {
     /// The number of rows in the matrix
@@ -15,117 +25,1651 @@ struct MatrixData {
     ncols: usize,
     /// A flat vector of floats that contains the values of the matrix in row major order
     values: Vec<f64>,
+    /// The row names listed in the comments, if any
+    row_labels: Option<Vec<String>>,
+    /// The column names listed in the comments, if any
+    col_labels: Option<Vec<String>>,
+}
+
+impl MatrixData {
+    /// This converts the data to a labeled matrix.
+    fn into_labeled(self) -> LabeledMatrix {
+        // ** from_vec() expects the data presented in column major order
+        // ** so, we swap the row and column arguments then transpose
+        LabeledMatrix {
+            matrix: na::DMatrix::from_vec(self.ncols, self.nrows, self.values).transpose(),
+            row_labels: self.row_labels,
+            col_labels: self.col_labels,
+        }
+    }
+}
+
+/// This is a matrix along with the row and column names listed in its Matrix Market comments.
+#[derive(Clone)]
+pub struct LabeledMatrix {
+    /// The matrix
+    pub matrix: na::DMatrix<f64>,
+    /// The name of each row, e.g. the metabolites of a stoichiometric matrix
+    pub row_labels: Option<Vec<String>>,
+    /// The name of each column, e.g. the reactions of a stoichiometric matrix
+    pub col_labels: Option<Vec<String>>,
+}
+
+impl LabeledMatrix {
+    /// This returns the name of row i, or M<i+1> (a metabolite) if the rows are unlabeled.
+    pub fn row_label(&self, i: usize) -> String {
+        match &self.row_labels {
+            Some(labels) => labels[i].clone(),
+            None => format!("M{}", i + 1),
+        }
+    }
+
+    /// This returns the name of column j, or R<j+1> (a reaction) if the columns are unlabeled.
+    pub fn col_label(&self, j: usize) -> String {
+        match &self.col_labels {
+            Some(labels) => labels[j].clone(),
+            None => format!("R{}", j + 1),
+        }
+    }
+
+    /// This returns the transposed matrix, with the row and column labels swapped.
+    pub fn transpose(self) -> LabeledMatrix {
+        LabeledMatrix {
+            matrix: self.matrix.transpose(),
+            row_labels: self.col_labels,
+            col_labels: self.row_labels,
+        }
+    }
+
+    /// This checks whether a stoichiometric matrix looks like it was supplied transposed: its
+    /// rows do not match the number of metabolites, but its columns do.
+    ///
+    /// # Arguments
+    /// * `n_metabolites` - the number of metabolites, e.g. the length of the accumulation vector
+    ///
+    pub fn is_transposed(&self, n_metabolites: usize) -> bool {
+        self.matrix.nrows() != n_metabolites && self.matrix.ncols() == n_metabolites
+    }
 }
 
 /// This is an enum used to parametrize the float format in formatting/output functions.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum FloatFormat {
     /// Format floats in scientific notation, e.g. 1.0e-3
     Scientific,
     /// Format floats in decimal notation, e.g. 0.001
     Decimal,
+    /// Format floats with the fewest digits that read back as the same value, e.g. 1e-3, ignoring
+    /// the precision and writing -0 as 0, so equal values are always written the same way
+    Canonical,
+}
+
+/// This is the orientation a vector file declares in its size line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VectorOrientation {
+    /// A column vector, declared "n 1"; a 1x1 vector is a column
+    Column,
+    /// A row vector, declared "1 n"
+    Row,
+}
+
+/// This is an enum used to parametrize how strictly Matrix Market files are parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseMode {
+    /// Deviations from the Matrix Market array format are errors
+    Strict,
+    /// Deviations from the Matrix Market array format are accepted where the meaning is clear
+    Lenient,
+}
+
+/// This is an enum used to parametrize what happens when an output file already exists.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OverwritePolicy {
+    /// Refuse to write the output, returning an error
+    Refuse,
+    /// Overwrite the existing file
+    Force,
+    /// Rename the existing file to a backup before writing
+    Backup,
+}
+
+/// This parses a whitespace delimited string of floats into an nalgebra::DVector<f64>
+///
+/// # Arguments
+/// * `vector_string` - the whitespace delimited string of floats that describes the vector
+///
+pub fn parse_vector(vector_string: &str) -> na::DVector<f64> {
+    let mut vector: Vec<f64> = Vec::with_capacity(vector_string.len() / 8);
+    parse_floats(vector_string, &mut vector)
+        .unwrap_or_else(|token| panic!("failed to parse a float from vector string: \"{token}\""));
+    na::DVector::from_vec(vector)
+}
+
+/// This returns whether a vector string gives its values by label, e.g. "glc=-1.2 co2=2.3",
+/// rather than by position; see `parse_keyed_vector`.
+///
+/// # Arguments
+/// * `vector_string` - the vector string, e.g. the -a argument
+///
+pub fn is_keyed_vector(vector_string: &str) -> bool {
+    vector_string.contains('=')
+}
+
+/// This parses a whitespace delimited string of label=value pairs, e.g. "glc=-1.2 o2=-0.8",
+/// into a vector with one entry per label, in the order of `labels`. Labels that are not given
+/// are 0. Returns SmasError::Parse for a pair without '=', a value that is not a float, a label
+/// that is not in `labels`, or a label given twice.
+///
+/// # Arguments
+/// * `vector_string` - the whitespace delimited label=value pairs
+/// * `labels` - the label of each entry of the vector, e.g. the metabolites of the model
+///
+pub fn parse_keyed_vector(vector_string: &str, labels: &[String]) -> Result<na::DVector<f64>, SmasError> {
+    let parse_error = |message: String| SmasError::Parse { line: 0, message };
+    let positions: HashMap<&str, usize> = labels.iter().enumerate().map(|(i, label)| (label.as_str(), i)).collect();
+    let mut vector = na::DVector::zeros(labels.len());
+    let mut given = vec![false; labels.len()];
+    for pair in vector_string.split_whitespace() {
+        let (label, value) = pair.split_once('=')
+            .ok_or_else(|| parse_error(format!("\"{pair}\" is not a label=value pair")))?;
+        let value: f64 = value.parse()
+            .map_err(|_| parse_error(format!("failed to parse a float for {label} from \"{value}\"")))?;
+        let i = *positions.get(label)
+            .ok_or_else(|| parse_error(format!("\"{label}\" is not one of the {} metabolites of the model", labels.len())))?;
+        if given[i] {
+            return Err(parse_error(format!("{label} is given more than once")));
+        }
+        given[i] = true;
+        vector[i] = value;
+    }
+    Ok(vector)
+}
+
+/// This parses a whitespace delimited string of floats into an nalgebra::DMatrix<f64>
+///
+/// # Arguments
+/// * `matrix_string` - the whitespace delimited string of floats that describes the matrix
+/// * `ncols` - the number of columns in the matrix
+/// * `nrows` - the number of rows in the matrix
+///
+pub fn parse_matrix(matrix_string: &str, nrows: usize, ncols: usize) -> na::DMatrix<f64> {
+    let mut vector: Vec<f64> = Vec::with_capacity(nrows * ncols);
+    parse_floats(matrix_string, &mut vector)
+        .unwrap_or_else(|token| panic!("failed to parse a float from matrix string: \"{token}\""));
+    // ** from_vec() expects the data presented in column major order
+    // ** so, we swap the row and column arguments then transpose
+    na::DMatrix::from_vec(
+        ncols,
+        nrows,
+        vector,
+    ).transpose()
+}
+
+/// This parses whitespace delimited floats directly from the bytes of a string, without splitting
+/// it into tokens first, and appends them to `values`. On failure the offending token is returned.
+fn parse_floats<'a>(string: &'a str, values: &mut Vec<f64>) -> Result<(), &'a str> {
+    let bytes = string.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token_end = bytes[i..].iter()
+            .position(|b| b.is_ascii_whitespace())
+            .map_or(bytes.len(), |offset| i + offset);
+        // ** a float must span its whole token, e.g. "1.0-2.0" is not two floats
+        match fast_float2::parse_partial::<f64, _>(&bytes[i..token_end]) {
+            Ok((value, length)) if i + length == token_end => values.push(value),
+            _ => return Err(&string[i..token_end]),
+        }
+        i = token_end;
+    }
+    Ok(())
+}
+
+/// This parses values pasted from a spreadsheet or typed by hand, e.g. a copied column, separated
+/// by whitespace, tabs, commas or new lines.
+///
+/// # Arguments
+/// * `text` - The pasted text.
+///
+pub fn parse_pasted_values(text: &str) -> Result<Vec<f64>, SmasError> {
+    let mut values = vec!();
+    for (index, line) in text.lines().enumerate() {
+        for token in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()) {
+            let value = token.parse::<f64>().map_err(|_| SmasError::Parse {
+                line: index + 1,
+                message: format!("\"{token}\" is not a number"),
+            })?;
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+/// This reads a Matrix Market array formatted file and returns a nalgebra::DVector<F64>.
+/// The file is parsed in lenient mode.
+///
+/// # Arguments
+/// * `path` - The path to the file.
+///
+pub fn load_vector<R: AsRef<Path>>(path: R) -> Result<na::DVector<f64>, SmasError> {
+    load_vector_with_mode(path, ParseMode::Lenient)
+}
+
+/// This reads a Matrix Market array formatted file and returns a nalgebra::DVector<F64>. The file
+/// must hold a single row or column, which may be empty (e.g. "0 1").
+///
+/// # Arguments
+/// * `path` - The path to the file.
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+///
+pub fn load_vector_with_mode<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+) -> Result<na::DVector<f64>, SmasError> {
+    Ok(load_oriented_vector(path, mode)?.0)
+}
+
+/// This reads a Matrix Market array formatted vector file like `load_vector_with_mode`, along
+/// with the orientation declared by its size line, "n 1" for a column or "1 n" for a row. The
+/// number of values must match the declared size either way.
+///
+/// # Arguments
+/// * `path` - The path to the file.
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+///
+pub fn load_oriented_vector<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+) -> Result<(na::DVector<f64>, VectorOrientation), SmasError> {
+    let data = read_matrix_file(path, mode, DEFAULT_PATTERN_VALUE)?;
+    let orientation = match (data.nrows, data.ncols) {
+        (_, 1) => VectorOrientation::Column,
+        (1, _) => VectorOrientation::Row,
+        (nrows, ncols) => {
+            return Err(SmasError::Parse {
+                line: 0,
+                message: format!("expected a vector, but found a {nrows}x{ncols} matrix"),
+            });
+        }
+    };
+
+    Ok((na::DVector::from_vec(data.values), orientation))
+}
+
+/// This checks that a vector has the expected number of values. The error names the declared
+/// shape, so a row vector (1 x n) of the wrong length is reported as such rather than as a
+/// column.
+///
+/// # Arguments
+/// * `n_values` - the number of values in the vector
+/// * `orientation` - the orientation the vector was declared with
+/// * `expected` - the number of values expected, e.g. the number of metabolites
+///
+pub fn check_vector_length(n_values: usize, orientation: VectorOrientation, expected: usize) -> Result<(), SmasError> {
+    if n_values == expected {
+        return Ok(());
+    }
+    let declared = match orientation {
+        VectorOrientation::Column => format!("{n_values}x1 column vector"),
+        VectorOrientation::Row => format!("1x{n_values} row vector"),
+    };
+    Err(SmasError::Parse {
+        line: 0,
+        message: format!("expected a vector of {expected} values, but found a {declared}"),
+    })
+}
+
+/// This reads a Matrix Market array formatted file and returns a nalgebra::DMatrix<F64>.
+/// The file is parsed in lenient mode.
+///
+/// # Arguments
+/// * `path` - the path to the file.
+///
+pub fn load_matrix<R: AsRef<Path>>(path: R) -> Result<na::DMatrix<f64>, SmasError> {
+    load_matrix_with_mode(path, ParseMode::Lenient)
+}
+
+/// This reads a Matrix Market array formatted file and returns a nalgebra::DMatrix<F64>.
+///
+/// # Arguments
+/// * `path` - the path to the file.
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+///
+pub fn load_matrix_with_mode<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    load_matrix_with_pattern_value(path, mode, DEFAULT_PATTERN_VALUE)
+}
+
+/// This reads a Matrix Market formatted file and returns a nalgebra::DMatrix<F64>, setting every
+/// entry listed in a pattern file to the given value.
+///
+/// # Arguments
+/// * `path` - the path to the file.
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+/// * `pattern_value` - the value of the entries of a pattern file
+///
+pub fn load_matrix_with_pattern_value<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+    pattern_value: f64,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    Ok(load_labeled_matrix(path, mode, pattern_value)?.matrix)
+}
+
+/// This reads a Matrix Market formatted file along with the row and column names listed in its
+/// comments, using the common convention of whitespace delimited names after "%rows:" and
+/// "%cols:" (or "%columns:"), e.g. "%rows: glc g6p f6p". A list may continue over several comment
+/// lines. A list with the wrong number of names is an error in strict mode and ignored otherwise.
+///
+/// # Arguments
+/// * `path` - the path to the file.
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+/// * `pattern_value` - the value of the entries of a pattern file
+///
+pub fn load_labeled_matrix<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+    pattern_value: f64,
+) -> Result<LabeledMatrix, SmasError> {
+    Ok(read_matrix_file(path, mode, pattern_value)?.into_labeled())
+}
+
+/// This parses the contents of a Matrix Market file, e.g. one bundled into the binary, as a
+/// labeled matrix; see `load_labeled_matrix`.
+///
+/// # Arguments
+/// * `contents` - the contents of the file
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+/// * `pattern_value` - the value of the entries of a pattern file
+///
+pub fn parse_labeled_matrix(contents: &str, mode: ParseMode, pattern_value: f64) -> Result<LabeledMatrix, SmasError> {
+    Ok(parse_matrix_data(contents, mode, pattern_value)?.into_labeled())
+}
+
+/// This parses a single float from a Matrix Market file, reporting the line number on failure.
+fn parse_entry(entry: &str, line_number: usize) -> Result<f64, SmasError> {
+    fast_float2::parse(entry).map_err(|_| SmasError::Parse {
+        line: line_number,
+        message: format!("failed to parse a float from \"{entry}\""),
+    })
+}
+
+/// This parses a single dimension from a Matrix Market size line, reporting the line number on failure.
+fn parse_dimension(entry: &str, line_number: usize) -> Result<usize, SmasError> {
+    usize::from_str(entry).map_err(|_| SmasError::Parse {
+        line: line_number,
+        message: format!("failed to parse a dimension from \"{entry}\""),
+    })
+}
+
+/// This is the axis a label comment line names.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LabelAxis {
+    Rows,
+    Columns,
+}
+
+/// This returns the model version declared in the header of a matrix, vector or reaction equation
+/// file: the value of a "% model:" (or "# model:") comment line before the data, e.g.
+/// "% model: iML1515 2023-04". Outputs record the version of the model they were computed with the
+/// same way, so an output can be traced back to its model, and compared artifacts checked against
+/// each other.
+///
+/// # Arguments
+/// * `contents` - the contents of the file, or its header
+///
+pub fn parse_model_version(contents: &str) -> Option<String> {
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let comment = trimmed.strip_prefix('%').or_else(|| trimmed.strip_prefix('#'))?;
+        if let Some((key, version)) = comment.split_once(':') {
+            if key.trim().eq_ignore_ascii_case("model") && !version.trim().is_empty() {
+                return Some(version.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+/// This reads the model version declared in the header of a file; see `parse_model_version`. Only
+/// the header is read, and binary files, e.g. Arrow files, have no version.
+///
+/// # Arguments
+/// * `path` - the path to the file.
+///
+pub fn load_model_version<R: AsRef<Path>>(path: R) -> Result<Option<String>, SmasError> {
+    let file = std::fs::File::open(path).map_err(SmasError::Io)?;
+    let mut header = String::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let trimmed = line.trim_start();
+        if !trimmed.is_empty() && !trimmed.starts_with('%') && !trimmed.starts_with('#') {
+            break;
+        }
+        header.push_str(&line);
+        header.push('\n');
+    }
+    Ok(parse_model_version(&header))
+}
+
+/// This splits a label comment line, e.g. "%rows: glc g6p f6p", into its axis and names.
+fn parse_label_line(line: &str) -> Option<(LabelAxis, std::str::SplitAsciiWhitespace<'_>)> {
+    let comment = line.strip_prefix('%')?.trim_start();
+    let (key, names) = comment.split_once(':')?;
+    let axis = match key.trim_end().to_lowercase().as_str() {
+        "rows" => LabelAxis::Rows,
+        "cols" | "columns" => LabelAxis::Columns,
+        _ => return None,
+    };
+    Some((axis, names.split_ascii_whitespace()))
+}
+
+/// This checks that a list of labels has one name per row or column, returning None if there
+/// were no labels, or if the count is wrong in lenient mode.
+fn check_labels(
+    labels: Vec<String>,
+    expected: usize,
+    axis: &str,
+    mode: ParseMode,
+) -> Result<Option<Vec<String>>, SmasError> {
+    if labels.is_empty() {
+        return Ok(None);
+    }
+    if labels.len() != expected {
+        if mode == ParseMode::Strict {
+            return Err(SmasError::Parse {
+                line: 0,
+                message: format!("expected {expected} {axis} labels, found {}", labels.len()),
+            });
+        }
+        return Ok(None);
+    }
+    Ok(Some(labels))
+}
+
+/// This is the storage format declared in a Matrix Market header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StorageFormat {
+    /// Every value is listed
+    Array,
+    /// Only nonzero values are listed, each with its row and column
+    Coordinate,
+}
+
+/// This is the value type declared in a Matrix Market header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// Floating point values
+    Real,
+    /// Integer values
+    Integer,
+    /// No values; every listed entry has the same value
+    Pattern,
+}
+
+/// This is the symmetry declared in a Matrix Market header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Symmetry {
+    /// Every entry is listed
+    General,
+    /// Only the lower triangle is listed, and A(j, i) = A(i, j)
+    Symmetric,
+    /// Only the lower triangle is listed, and A(j, i) = -A(i, j)
+    SkewSymmetric,
+}
+
+/// This is a parsed Matrix Market header line, e.g. "%%MatrixMarket matrix coordinate pattern general".
+struct Header {
+    format: StorageFormat,
+    field: Field,
+    symmetry: Symmetry,
+    /// Whether array values are listed column by column, as the Matrix Market format specifies.
+    /// Files without a header list them row by row.
+    column_major: bool,
+}
+
+impl Header {
+    /// This is the header assumed for files without one: a real, general array listed row by row.
+    fn array() -> Self {
+        Header {
+            format: StorageFormat::Array,
+            field: Field::Real,
+            symmetry: Symmetry::General,
+            column_major: false,
+        }
+    }
+
+    /// This parses a Matrix Market header line. In lenient mode missing qualifiers take their
+    /// default values (matrix array real general); in strict mode all four are required.
+    fn parse(line: &str, line_number: usize, mode: ParseMode) -> Result<Self, SmasError> {
+        let tokens: Vec<String> = line.split_whitespace().map(|t| t.to_lowercase()).collect();
+        let error = |message: String| SmasError::Parse {
+            line: line_number,
+            message,
+        };
+        if mode == ParseMode::Strict && tokens.len() != 5 {
+            return Err(error(format!("expected a header with four qualifiers, found \"{}\"", line.trim())));
+        }
+
+        let mut header = Header {
+            column_major: true,
+            ..Header::array()
+        };
+        if let Some(object) = tokens.get(1) {
+            if object != "matrix" {
+                return Err(error(format!("unsupported Matrix Market object \"{object}\"")));
+            }
+        }
+        if let Some(format) = tokens.get(2) {
+            header.format = match format.as_str() {
+                "array" => StorageFormat::Array,
+                "coordinate" => StorageFormat::Coordinate,
+                _ => return Err(error(format!("unsupported Matrix Market format \"{format}\""))),
+            };
+        }
+        if let Some(field) = tokens.get(3) {
+            header.field = match field.as_str() {
+                "real" | "double" => Field::Real,
+                "integer" => Field::Integer,
+                "pattern" => Field::Pattern,
+                _ => return Err(error(format!("unsupported Matrix Market field \"{field}\""))),
+            };
+        }
+        if let Some(symmetry) = tokens.get(4) {
+            header.symmetry = match symmetry.as_str() {
+                "general" => Symmetry::General,
+                "symmetric" => Symmetry::Symmetric,
+                "skew-symmetric" => Symmetry::SkewSymmetric,
+                _ => return Err(error(format!("unsupported Matrix Market symmetry \"{symmetry}\""))),
+            };
+        }
+
+        if header.format == StorageFormat::Array && header.field == Field::Pattern {
+            return Err(error(String::from("the pattern field requires the coordinate format")));
+        }
+        if header.format == StorageFormat::Array && header.symmetry != Symmetry::General {
+            return Err(error(String::from("symmetric array files are not supported, use the coordinate format")));
+        }
+        Ok(header)
+    }
+}
+
+/// This parses a single value of the given field. In strict mode integer fields must hold integers.
+fn parse_value(entry: &str, field: Field, mode: ParseMode, line_number: usize) -> Result<f64, SmasError> {
+    if field == Field::Integer && mode == ParseMode::Strict {
+        return i64::from_str(entry).map(|v| v as f64).map_err(|_| SmasError::Parse {
+            line: line_number,
+            message: format!("failed to parse an integer from \"{entry}\""),
+        });
+    }
+    parse_entry(entry, line_number)
+}
+
+/// This reads a Matrix Market formatted file and returns a MatrixData struct.
+///
+/// Files without a "%%MatrixMarket" header line are read as real, general arrays with the values
+/// of each row on one line. Array files with a header list their values column by column, as the
+/// Matrix Market format specifies. Coordinate files may hold real, integer or pattern values, and may be
+/// symmetric or skew-symmetric; every entry listed in a pattern file is set to `pattern_value`.
+///
+/// In strict mode the header must have all four qualifiers, the size line must contain exactly two
+/// entries (three for coordinate files), each data line must contain exactly one value (or one
+/// entry for coordinate files), integer fields must hold integers, and comments may only appear
+/// before the size line. In lenient mode, any number of values are accepted per line, extra
+/// entries on the size and data lines are ignored, and comments are skipped wherever they appear.
+fn read_matrix_file<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+    pattern_value: f64,
+) -> Result<MatrixData, SmasError> {
+    let _span = tracing::info_span!("load", path = %path.as_ref().display()).entered();
+    // ** the whole file is read at once so that lines and entries borrow from it without copying
+    let contents = fs::read(&path)?;
+    if contents.starts_with(arrow::ARROW_MAGIC) {
+        let labeled = arrow::read_table(&contents)
+            .and_then(|table| arrow::table_matrix(&table))
+            .map_err(|e| e.in_file(&path))?;
+        return Ok(MatrixData {
+            nrows: labeled.matrix.nrows(),
+            ncols: labeled.matrix.ncols(),
+            values: labeled.matrix.transpose().as_slice().to_vec(),
+            row_labels: labeled.row_labels,
+            col_labels: labeled.col_labels,
+        });
+    }
+    let contents = String::from_utf8(contents)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    parse_matrix_data(&contents, mode, pattern_value)
+}
+
+/// This parses the contents of a Matrix Market formatted file; see `read_matrix_file`.
+fn parse_matrix_data(contents: &str, mode: ParseMode, pattern_value: f64) -> Result<MatrixData, SmasError> {
+    let mut header = Header::array();
+    let mut mat_data: Vec<f64> = vec!();
+    let mut size: Option<(usize, usize, usize)> = None;
+    let mut n_entries: usize = 0;
+    let mut split: Vec<&str> = vec!();
+    let mut row_labels: Vec<String> = vec!();
+    let mut col_labels: Vec<String> = vec!();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line_number = i + 1;
+
+        if i == 0 && line.to_lowercase().starts_with("%%matrixmarket") {
+            header = Header::parse(line, line_number, mode)?;
+            continue;
+        }
+
+        if line.starts_with('%') {
+            if size.is_some() && mode == ParseMode::Strict {
+                return Err(SmasError::Parse {
+                    line: line_number,
+                    message: String::from("comments are not allowed after the size line"),
+                });
+            }
+            match parse_label_line(line) {
+                Some((LabelAxis::Rows, names)) => row_labels.extend(names.map(String::from)),
+                Some((LabelAxis::Columns, names)) => col_labels.extend(names.map(String::from)),
+                None => {}
+            }
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // ** array data lines are the bulk of a file, so they are parsed without splitting them
+        let array_data = size.is_some()
+            && header.format == StorageFormat::Array
+            && !(header.field == Field::Integer && mode == ParseMode::Strict);
+        split.clear();
+        if !array_data {
+            split.extend(line.split_ascii_whitespace());
+        }
+
+        match size {
+            None => {
+                let expected = match header.format {
+                    StorageFormat::Array => 2,
+                    StorageFormat::Coordinate => 3,
+                };
+                if split.len() < expected || (mode == ParseMode::Strict && split.len() != expected) {
+                    return Err(SmasError::Parse {
+                        line: line_number,
+                        message: format!(
+                            "expected a size line with {}, found \"{}\"",
+                            match header.format {
+                                StorageFormat::Array => "a row and column count",
+                                StorageFormat::Coordinate => "a row, column and entry count",
+                            },
+                            line.trim()
+                        ),
+                    });
+                }
+                let rows = parse_dimension(split[0], line_number)?;
+                let cols = parse_dimension(split[1], line_number)?;
+                let nnz = match header.format {
+                    StorageFormat::Array => {
+                        mat_data.reserve(rows * cols);
+                        rows * cols
+                    }
+                    StorageFormat::Coordinate => {
+                        mat_data = vec![0.0; rows * cols];
+                        parse_dimension(split[2], line_number)?
+                    }
+                };
+                size = Some((rows, cols, nnz));
+            }
+            Some((rows, cols, _)) => match header.format {
+                StorageFormat::Array => {
+                    if array_data {
+                        parse_floats(line, &mut mat_data).map_err(|token| SmasError::Parse {
+                            line: line_number,
+                            message: format!("failed to parse a float from \"{token}\""),
+                        })?;
+                    } else {
+                        for entry in &split {
+                            mat_data.push(parse_value(entry, header.field, mode, line_number)?);
+                        }
+                    }
+                    let n_values = mat_data.len() - n_entries;
+                    if mode == ParseMode::Strict && n_values != 1 {
+                        return Err(SmasError::Parse {
+                            line: line_number,
+                            message: format!("expected exactly one value per line, found {n_values}"),
+                        });
+                    }
+                    n_entries = mat_data.len();
+                }
+                StorageFormat::Coordinate => {
+                    let expected = match header.field {
+                        Field::Pattern => 2,
+                        _ => 3,
+                    };
+                    if split.len() < expected || (mode == ParseMode::Strict && split.len() != expected) {
+                        return Err(SmasError::Parse {
+                            line: line_number,
+                            message: format!("expected {expected} entries per line, found {}", split.len()),
+                        });
+                    }
+                    let row = parse_dimension(split[0], line_number)?;
+                    let col = parse_dimension(split[1], line_number)?;
+                    if row == 0 || row > rows || col == 0 || col > cols {
+                        return Err(SmasError::Parse {
+                            line: line_number,
+                            message: format!("entry ({row}, {col}) is outside of the {rows}x{cols} matrix"),
+                        });
+                    }
+                    let value = match header.field {
+                        Field::Pattern => pattern_value,
+                        _ => parse_value(split[2], header.field, mode, line_number)?,
+                    };
+                    // ** coordinates are 1-based; the values are stored in row major order
+                    mat_data[(row - 1) * cols + (col - 1)] = value;
+                    if row != col {
+                        match header.symmetry {
+                            Symmetry::General => {}
+                            Symmetry::Symmetric => mat_data[(col - 1) * cols + (row - 1)] = value,
+                            Symmetry::SkewSymmetric => mat_data[(col - 1) * cols + (row - 1)] = -value,
+                        }
+                    }
+                    n_entries += 1;
+                }
+            },
+        }
+    }
+
+    let (rows, cols, total) = size.ok_or(SmasError::Parse {
+        line: 0,
+        message: String::from("no size line was found"),
+    })?;
+
+    if n_entries != total {
+        return Err(SmasError::Shape {
+            expected: total,
+            found: n_entries,
+        });
+    }
+    // ** MatrixData holds the values in row major order
+    if header.format == StorageFormat::Array && header.column_major {
+        mat_data = na::DMatrix::from_vec(rows, cols, mat_data).transpose().as_slice().to_vec();
+    }
+
+    Ok(MatrixData {
+        ncols: cols,
+        nrows: rows,
+        values: mat_data,
+        row_labels: check_labels(row_labels, rows, "row", mode)?,
+        col_labels: check_labels(col_labels, cols, "column", mode)?,
+    })
+}
+
+/// This streams the nonzero entries of a Matrix Market file to a visitor, one buffered line at a
+/// time, so that matrices too large to hold in memory can still be multiplied with. It accepts the
+/// same files as `load_matrix_with_mode`, and visits the mirrored entries of symmetric and
+/// skew-symmetric files too. Label comments are skipped. Returns the shape of the matrix.
+///
+/// # Arguments
+/// * `path` - the path to the matrix file
+/// * `mode` - whether deviations from the format are errors (strict) or accepted (lenient)
+/// * `pattern_value` - the value of every entry listed in a pattern file
+/// * `visit` - called with the (0-based) row, column and value of each nonzero entry
+///
+pub fn stream_matrix_entries<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+    pattern_value: f64,
+    mut visit: impl FnMut(usize, usize, f64),
+) -> Result<(usize, usize), SmasError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = Header::array();
+    let mut size: Option<(usize, usize, usize)> = None;
+    let mut n_entries: usize = 0;
+    let mut line = String::new();
+    let mut line_number = 0;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+        if line_number == 1 && line.to_lowercase().starts_with("%%matrixmarket") {
+            header = Header::parse(&line, line_number, mode)?;
+            continue;
+        }
+        if line.starts_with('%') {
+            if size.is_some() && mode == ParseMode::Strict {
+                return Err(SmasError::Parse {
+                    line: line_number,
+                    message: String::from("comments are not allowed after the size line"),
+                });
+            }
+            continue;
+        }
+        let split: Vec<&str> = line.split_ascii_whitespace().collect();
+        if split.is_empty() {
+            continue;
+        }
+
+        let Some((rows, cols, _)) = size else {
+            let expected = match header.format {
+                StorageFormat::Array => 2,
+                StorageFormat::Coordinate => 3,
+            };
+            if split.len() < expected || (mode == ParseMode::Strict && split.len() != expected) {
+                return Err(SmasError::Parse {
+                    line: line_number,
+                    message: format!("expected a size line with {expected} entries, found \"{}\"", line.trim()),
+                });
+            }
+            let rows = parse_dimension(split[0], line_number)?;
+            let cols = parse_dimension(split[1], line_number)?;
+            let total = match header.format {
+                StorageFormat::Array => rows * cols,
+                StorageFormat::Coordinate => parse_dimension(split[2], line_number)?,
+            };
+            size = Some((rows, cols, total));
+            continue;
+        };
+
+        match header.format {
+            StorageFormat::Array => {
+                if mode == ParseMode::Strict && split.len() != 1 {
+                    return Err(SmasError::Parse {
+                        line: line_number,
+                        message: format!("expected exactly one value per line, found {}", split.len()),
+                    });
+                }
+                for entry in split {
+                    let value = parse_value(entry, header.field, mode, line_number)?;
+                    if value != 0.0 && n_entries < rows * cols {
+                        if header.column_major {
+                            visit(n_entries % rows, n_entries / rows, value);
+                        } else {
+                            visit(n_entries / cols, n_entries % cols, value);
+                        }
+                    }
+                    n_entries += 1;
+                }
+            }
+            StorageFormat::Coordinate => {
+                let expected = match header.field {
+                    Field::Pattern => 2,
+                    _ => 3,
+                };
+                if split.len() < expected || (mode == ParseMode::Strict && split.len() != expected) {
+                    return Err(SmasError::Parse {
+                        line: line_number,
+                        message: format!("expected {expected} entries per line, found {}", split.len()),
+                    });
+                }
+                let row = parse_dimension(split[0], line_number)?;
+                let col = parse_dimension(split[1], line_number)?;
+                if row == 0 || row > rows || col == 0 || col > cols {
+                    return Err(SmasError::Parse {
+                        line: line_number,
+                        message: format!("entry ({row}, {col}) is outside of the {rows}x{cols} matrix"),
+                    });
+                }
+                let value = match header.field {
+                    Field::Pattern => pattern_value,
+                    _ => parse_value(split[2], header.field, mode, line_number)?,
+                };
+                visit(row - 1, col - 1, value);
+                if row != col {
+                    match header.symmetry {
+                        Symmetry::General => {}
+                        Symmetry::Symmetric => visit(col - 1, row - 1, value),
+                        Symmetry::SkewSymmetric => visit(col - 1, row - 1, -value),
+                    }
+                }
+                n_entries += 1;
+            }
+        }
+    }
+
+    let (rows, cols, total) = size.ok_or(SmasError::Parse {
+        line: 0,
+        message: String::from("no size line was found"),
+    })?;
+    if n_entries != total {
+        return Err(SmasError::Shape {
+            expected: total,
+            found: n_entries,
+        });
+    }
+    Ok((rows, cols))
+}
+
+/// This loads a Matrix Market file as a sparse matrix, streaming its entries so that the dense
+/// matrix is never held in memory; see `stream_matrix_entries` for the files it accepts. Label
+/// comments are skipped.
+///
+/// # Arguments
+/// * `path` - the path to the matrix file
+/// * `mode` - whether deviations from the format are errors (strict) or accepted (lenient)
+/// * `pattern_value` - the value of every entry listed in a pattern file
+///
+pub fn load_sparse_matrix<R: AsRef<Path>>(path: R, mode: ParseMode, pattern_value: f64) -> Result<CscMatrix, SmasError> {
+    let _span = tracing::info_span!("load", path = %path.as_ref().display(), storage = %"sparse").entered();
+    let mut entries = vec!();
+    let (nrows, ncols) = stream_matrix_entries(path, mode, pattern_value, |i, j, value| entries.push((i, j, value)))?;
+    CscMatrix::from_entries(nrows, ncols, entries)
+}
+
+/// This reads the shape and number of entries a Matrix Market coordinate file declares on its
+/// size line, without reading its entries, e.g. to decide whether to load it as a sparse matrix.
+/// Symmetric files list half of their off-diagonal entries, so the count is doubled for them.
+/// Returns None for array files and files without a Matrix Market header.
+///
+/// # Arguments
+/// * `path` - the path to the matrix file
+///
+pub fn read_coordinate_shape<R: AsRef<Path>>(path: R) -> Result<Option<(usize, usize, usize)>, SmasError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut header: Option<Header> = None;
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        if line_number == 1 && line.to_lowercase().starts_with("%%matrixmarket") {
+            header = Some(Header::parse(&line, line_number, ParseMode::Lenient)?);
+            continue;
+        }
+        let Some(header) = header.as_ref().filter(|header| header.format == StorageFormat::Coordinate) else {
+            return Ok(None);
+        };
+        if line.starts_with('%') || line.trim().is_empty() {
+            continue;
+        }
+        let split: Vec<&str> = line.split_ascii_whitespace().collect();
+        if split.len() < 3 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected a size line with 3 entries, found \"{}\"", line.trim()),
+            });
+        }
+        let nnz = parse_dimension(split[2], line_number)?;
+        return Ok(Some((
+            parse_dimension(split[0], line_number)?,
+            parse_dimension(split[1], line_number)?,
+            if header.symmetry == Symmetry::General { nnz } else { nnz.saturating_mul(2) },
+        )));
+    }
+    Ok(None)
+}
+
+/// This reads the units a vector or matrix file declares with a "%units: umol/min" comment before
+/// its size line, or an Arrow file with a "units" schema metadata key, if it declares any.
+///
+/// # Arguments
+/// * `path` - the path to the file
+///
+pub fn load_declared_units<R: AsRef<Path>>(path: R) -> Result<Option<Unit>, SmasError> {
+    let mut file = BufReader::new(File::open(&path)?);
+    if file.fill_buf()?.starts_with(arrow::ARROW_MAGIC) {
+        // ** Arrow files declare their units in the schema metadata
+        let table = arrow::read_table(&fs::read(&path)?).map_err(|e| e.in_file(&path))?;
+        return table.metadata.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("units"))
+            .map(|(_, unit)| unit.parse())
+            .transpose();
+    }
+    for line in file.lines() {
+        let line = line?;
+        let Some(comment) = line.strip_prefix('%') else {
+            // ** comments end at the size line
+            if line.trim().is_empty() {
+                continue;
+            }
+            break;
+        };
+        if let Some((key, unit)) = comment.trim_start().split_once(':') {
+            if key.trim_end().eq_ignore_ascii_case("units") {
+                return Ok(Some(unit.parse()?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// This reads a labels file naming the metabolites or reactions of a matrix, with one
+/// whitespace delimited `index name` row per label, e.g. "3 f6p". Indices are numbered from 1 and
+/// may appear in any order, but every index from 1 to `expected` must be named exactly once.
+/// Blank lines and lines starting with '#' or '%' are skipped.
+///
+/// # Arguments
+/// * `path` - the path to the file
+/// * `expected` - the number of labels, i.e. the number of rows or columns being named
+///
+pub fn load_labels<R: AsRef<Path>>(path: R, expected: usize) -> Result<Vec<String>, SmasError> {
+    let file = File::open(path)?;
+    let mut labels: Vec<Option<String>> = vec![None; expected];
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%') {
+            continue;
+        }
+
+        let fields: Vec<&str> = trimmed.split_ascii_whitespace().collect();
+        if fields.len() != 2 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected an index and a name without whitespace, found \"{trimmed}\""),
+            });
+        }
+        let index = parse_dimension(fields[0], line_number)?;
+        if index == 0 || index > expected {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("index {index} is outside of 1 to {expected}"),
+            });
+        }
+        if labels[index - 1].is_some() {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("index {index} is named more than once"),
+            });
+        }
+        labels[index - 1] = Some(fields[1].to_string());
+    }
+
+    let n_named = labels.iter().filter(|label| label.is_some()).count();
+    if n_named != expected {
+        return Err(SmasError::Parse {
+            line: 0,
+            message: format!("expected {expected} labels to match the matrix, found {n_named}"),
+        });
+    }
+    Ok(labels.into_iter().flatten().collect())
+}
+
+/// This is a stoichiometric matrix built from reaction equations.
+pub struct ReactionModel {
+    /// The stoichiometric matrix, labeled with the metabolite and reaction names
+    pub s_matrix: LabeledMatrix,
+    /// Whether each reaction is reversible, i.e. was written with "<->" or "<=>"
+    pub reversible: Vec<bool>,
+}
+
+/// This parses one side of a reaction equation, e.g. "2 A + B", into (coefficient, metabolite)
+/// terms. An empty side has no terms.
+fn parse_equation_side(side: &str, line_number: usize) -> Result<Vec<(f64, String)>, SmasError> {
+    let mut terms = vec!();
+    let tokens: Vec<&str> = side.split_ascii_whitespace().collect();
+    if tokens.is_empty() {
+        return Ok(terms);
+    }
+    for term in tokens.split(|token| *token == "+") {
+        let (coefficient, name) = match term {
+            [name] => (1.0, *name),
+            [coefficient, name] => (parse_entry(coefficient, line_number)?, *name),
+            _ => {
+                return Err(SmasError::Parse {
+                    line: line_number,
+                    message: format!("expected a term like \"2 A\" or \"A\", found \"{}\"", term.join(" ")),
+                })
+            }
+        };
+        terms.push((coefficient, name.to_string()));
+    }
+    Ok(terms)
+}
+
+/// This builds a labeled stoichiometric matrix from reaction equations, one per line, e.g.
+///
+/// ```text
+/// hk: glc + atp -> g6p + adp
+/// pgi: g6p <=> f6p
+/// uptake: -> glc
+/// ```
+///
+/// A reaction may be named with a "name:" prefix; unnamed reactions are named R1, R2, ... after
+/// their column. "->" and "=>" are irreversible, "<-" and "<=" are irreversible in the reverse
+/// direction (the reaction is stored forward), and "<->" and "<=>" are reversible. Terms are
+/// separated by " + " and have an optional coefficient. Either side may be empty, e.g. for
+/// exchange reactions. Metabolites are numbered in order of first appearance. Blank lines and
+/// lines starting with '#' or '%' are skipped.
+///
+/// # Arguments
+/// * `equations` - the reaction equations
+///
+pub fn parse_reaction_equations(equations: &str) -> Result<ReactionModel, SmasError> {
+    let mut metabolites: Vec<String> = vec!();
+    let mut reactions: Vec<String> = vec!();
+    let mut reversible: Vec<bool> = vec!();
+    let mut columns: Vec<Vec<(usize, f64)>> = vec!();
+
+    for (i, line) in equations.lines().enumerate() {
+        let line_number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%') {
+            continue;
+        }
+
+        let (name, equation) = match trimmed.split_once(':') {
+            Some((name, equation)) => (name.trim().to_string(), equation),
+            None => (format!("R{}", reactions.len() + 1), trimmed),
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("reaction names cannot be empty or contain whitespace, found \"{name}\""),
+            });
+        }
+
+        // ** the two character arrows are searched for after the three character ones they are part of
+        let arrow = ["<->", "<=>", "->", "=>", "<-", "<="].iter()
+            .find_map(|arrow| equation.find(arrow).map(|position| (*arrow, position)));
+        let Some((arrow, position)) = arrow else {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected an arrow (->, <-, <->) in \"{trimmed}\""),
+            });
+        };
+        let mut substrates = parse_equation_side(&equation[..position], line_number)?;
+        let mut products = parse_equation_side(&equation[position + arrow.len()..], line_number)?;
+        if arrow == "<-" || arrow == "<=" {
+            std::mem::swap(&mut substrates, &mut products);
+        }
+
+        let mut column: Vec<(usize, f64)> = vec!();
+        let terms = substrates.into_iter()
+            .map(|(coefficient, name)| (-coefficient, name))
+            .chain(products);
+        for (coefficient, metabolite) in terms {
+            let row = match metabolites.iter().position(|m| *m == metabolite) {
+                Some(row) => row,
+                None => {
+                    metabolites.push(metabolite);
+                    metabolites.len() - 1
+                }
+            };
+            match column.iter_mut().find(|(r, _)| *r == row) {
+                Some((_, value)) => *value += coefficient,
+                None => column.push((row, coefficient)),
+            }
+        }
+
+        reactions.push(name);
+        reversible.push(arrow == "<->" || arrow == "<=>");
+        columns.push(column);
+    }
+
+    let mut matrix = na::DMatrix::zeros(metabolites.len(), reactions.len());
+    for (j, column) in columns.iter().enumerate() {
+        for (i, value) in column {
+            matrix[(*i, j)] = *value;
+        }
+    }
+    Ok(ReactionModel {
+        s_matrix: LabeledMatrix {
+            matrix,
+            row_labels: Some(metabolites),
+            col_labels: Some(reactions),
+        },
+        reversible,
+    })
+}
+
+/// This reads a reaction equations file and builds its labeled stoichiometric matrix; see
+/// `parse_reaction_equations` for the format.
+///
+/// # Arguments
+/// * `path` - the path to the file
+///
+pub fn load_reaction_equations<R: AsRef<Path>>(path: R) -> Result<ReactionModel, SmasError> {
+    parse_reaction_equations(&fs::read_to_string(path)?)
+}
+
+/// The number of decimal places of the coefficients in the canonical model serialization.
+pub const CANONICAL_PRECISION: usize = 6;
+
+/// This formats one reaction equation from its (metabolite, coefficient) terms, in the order
+/// given, writing each coefficient with `format_coefficient` (a coefficient of 1 is omitted).
+fn format_equation(
+    name: &str,
+    terms: &[(String, f64)],
+    arrow: &str,
+    format_coefficient: impl Fn(f64) -> String,
+) -> String {
+    let side = |sign: f64| -> String {
+        let terms: Vec<String> = terms.iter()
+            .filter(|(_, v)| v * sign > 0.0)
+            .map(|(metabolite, v)| match format_coefficient(v * sign).as_str() {
+                "1" => metabolite.clone(),
+                coefficient => format!("{coefficient} {metabolite}"),
+            })
+            .collect();
+        terms.join(" + ")
+    };
+    let sides = [side(-1.0), String::from(arrow), side(1.0)];
+    let equation: Vec<&str> = sides.iter().map(|side| side.as_str()).filter(|side| !side.is_empty()).collect();
+    format!("{name}: {}", equation.join(" "))
+}
+
+/// This returns the arrow of reaction j: "<=>" if it is flagged as reversible, "->" otherwise.
+fn equation_arrow(reversible: Option<&[bool]>, j: usize) -> &'static str {
+    match reversible {
+        Some(reversible) if reversible[j] => "<=>",
+        _ => "->",
+    }
+}
+
+/// This formats each column of a stoichiometric matrix as a reaction equation, one per line, in
+/// the format `parse_reaction_equations` reads, e.g. "hk: glc + atp -> g6p + adp". Unlabeled
+/// metabolites and reactions are named M1, M2, ... and R1, R2, ... Reactions are written with
+/// "->" unless they are flagged as reversible, in which case "<=>" is used.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `reversible` - whether each reaction is reversible, if known
+///
+pub fn format_reaction_equations(s_matrix: &LabeledMatrix, reversible: Option<&[bool]>) -> String {
+    let equations: Vec<String> = (0..s_matrix.matrix.ncols())
+        .map(|j| format_reaction_equation(s_matrix, j, reversible))
+        .collect();
+    equations.join("\n")
+}
+
+/// This formats column j of a stoichiometric matrix as a reaction equation like
+/// `format_reaction_equations`, e.g. "hk: glc + atp -> g6p + adp".
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `j` - the reaction (column)
+/// * `reversible` - whether each reaction is reversible, if known
+///
+pub fn format_reaction_equation(s_matrix: &LabeledMatrix, j: usize, reversible: Option<&[bool]>) -> String {
+    let terms: Vec<(String, f64)> = s_matrix.matrix.column(j).iter()
+        .enumerate()
+        .map(|(i, v)| (s_matrix.row_label(i), *v))
+        .collect();
+    format_equation(&s_matrix.col_label(j), &terms, equation_arrow(reversible, j), |v| v.to_string())
+}
+
+/// This formats a stoichiometric matrix as reaction equations in a canonical form meant for
+/// version control: reactions are sorted by name, the terms of each side by metabolite name, and
+/// coefficients are written with CANONICAL_PRECISION decimal places (trailing zeros removed), so
+/// the output depends only on the model and a change to one reaction changes one line. There is
+/// no provenance header, since it would differ between runs. Metabolites that take part in no
+/// reaction are not written.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `reversible` - whether each reaction is reversible, if known
+///
+pub fn format_reaction_equations_canonical(s_matrix: &LabeledMatrix, reversible: Option<&[bool]>) -> String {
+    let format_coefficient = |v: f64| {
+        let fixed = format!("{v:.CANONICAL_PRECISION$}");
+        fixed.trim_end_matches('0').trim_end_matches('.').to_string()
+    };
+
+    let mut equations: Vec<(String, String)> = s_matrix.matrix.column_iter()
+        .enumerate()
+        .map(|(j, column)| {
+            // ** coefficients that round to zero are dropped so that noise cannot add a term
+            let mut terms: Vec<(String, f64)> = column.iter()
+                .enumerate()
+                .filter(|(_, v)| format_coefficient(v.abs()) != "0")
+                .map(|(i, v)| (s_matrix.row_label(i), *v))
+                .collect();
+            terms.sort_by(|a, b| a.0.cmp(&b.0));
+            let name = s_matrix.col_label(j);
+            let equation = format_equation(&name, &terms, equation_arrow(reversible, j), format_coefficient);
+            (name, equation)
+        })
+        .collect();
+    equations.sort();
+
+    let mut result_string = String::new();
+    for (_, equation) in equations {
+        result_string.push_str(&equation);
+        result_string.push('\n');
+    }
+    result_string
 }
 
-/// This parses a whitespace delimited string of floats into an nalgebra::DVector<f64>
+/// This reads a comma separated flux measurements file with one `reaction,value,std_dev` row per
+/// measured reaction. Reactions are numbered from 1. Blank lines, lines starting with '#' or '%',
+/// and a `reaction,value,std_dev` header line are skipped.
 ///
 /// # Arguments
-/// * `vector_string` - the whitespace delimited string of floats that describes the vector
+/// * `path` - the path to the file
 ///
-pub fn parse_vector(vector_string: &String) -> na::DVector<f64> {
-    let vector: Vec<f64> = vector_string.split_whitespace()
-        .map(|s| f64::from_str(s).expect("failed to parse a float from vector string"))
-        .collect();
-    na::DVector::from_vec(vector)
+pub fn load_flux_measurements<R: AsRef<Path>>(path: R) -> Result<Vec<FluxMeasurement>, SmasError> {
+    let file = File::open(path)?;
+    let mut measurements = vec!();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%') {
+            continue;
+        }
+
+        let fields: Vec<&str> = trimmed.split(',').map(|f| f.trim()).collect();
+        if fields.len() != 3 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected 3 comma separated fields, found {}", fields.len()),
+            });
+        }
+        if fields[0] == "reaction" {
+            continue;
+        }
+
+        let reaction = parse_dimension(fields[0], line_number)?;
+        if reaction == 0 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: String::from("reactions are numbered from 1"),
+            });
+        }
+        let std_dev = parse_entry(fields[2], line_number)?;
+        if std_dev <= 0.0 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("the standard deviation must be positive, found {std_dev}"),
+            });
+        }
+        measurements.push(FluxMeasurement {
+            reaction: reaction - 1,
+            value: parse_entry(fields[1], line_number)?,
+            std_dev,
+        });
+    }
+
+    Ok(measurements)
 }
 
-/// This parses a whitespace delimited string of floats into an nalgebra::DMatrix<f64>
+/// This parses per-reaction flux bounds: one `lower upper` line per reaction, in column order,
+/// separated by whitespace or a comma. "inf" and "-inf" leave a side unbounded, e.g. "0 inf" for
+/// an irreversible reaction. A '#' starts a comment that runs to the end of the line. Blank lines,
+/// lines starting with '%', and a `lower upper` header line are skipped. Returns SmasError::Parse if a bound is not a number, a lower bound
+/// exceeds its upper bound, or there is not exactly one line per reaction (line 0 if too few).
 ///
 /// # Arguments
-/// * `matrix_string` - the whitespace delimited string of floats that describes the matrix
-/// * `ncols` - the number of columns in the matrix
-/// * `nrows` - the number of rows in the matrix
+/// * `text` - the bounds
+/// * `n_reactions` - the number of reactions, i.e. the number of columns of the matrix
 ///
-pub fn parse_matrix(matrix_string: &String, nrows: usize, ncols: usize) -> na::DMatrix<f64> {
-    let vector: Vec<f64> = matrix_string.split_whitespace()
-        .map(|s| f64::from_str(s).expect("failed to parse a float from vector string"))
-        .collect();
-    // ** from_vec() expects the data presented in column major order
-    // ** so, we swap the row and column arguments then transpose
-    na::DMatrix::from_vec(
-        ncols,
-        nrows,
-        vector,
-    ).transpose()
+pub fn parse_bounds(text: &str, n_reactions: usize) -> Result<Bounds, SmasError> {
+    let mut lower = vec!();
+    let mut upper = vec!();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let trimmed = line.split('#').next().unwrap_or_default().trim();
+        if trimmed.is_empty() || trimmed.starts_with('%') {
+            continue;
+        }
+        let fields: Vec<&str> = trimmed.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.len() != 2 {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected a lower and an upper bound, found {} fields", fields.len()),
+            });
+        }
+        if fields[0] == "lower" {
+            continue;
+        }
+        let (low, high) = (parse_entry(fields[0], line_number)?, parse_entry(fields[1], line_number)?);
+        if low.is_nan() || high.is_nan() || low > high {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("the lower bound {low} of reaction {} is not at most its upper bound {high}", lower.len() + 1),
+            });
+        }
+        if lower.len() == n_reactions {
+            return Err(SmasError::Parse {
+                line: line_number,
+                message: format!("expected bounds for {n_reactions} reactions, found more"),
+            });
+        }
+        lower.push(low);
+        upper.push(high);
+    }
+    if lower.len() != n_reactions {
+        return Err(SmasError::Parse {
+            line: 0,
+            message: format!("expected bounds for {n_reactions} reactions, found {}", lower.len()),
+        });
+    }
+    Ok(Bounds {
+        lower: na::DVector::from_vec(lower),
+        upper: na::DVector::from_vec(upper),
+    })
 }
 
-/// This reads a Matrix Market array formatted file and returns a nalgebra::DVector<F64>.
+/// This reads a per-reaction bounds file; see `parse_bounds` for the format.
 ///
 /// # Arguments
-/// * `path` - The path to the file.
+/// * `path` - the path to the file
+/// * `n_reactions` - the number of reactions, i.e. the number of columns of the matrix
 ///
-pub fn load_vector<R: AsRef<Path>>(path: R) -> Option<na::DVector<f64>> {
-    let data = read_matrix_file(path)?;
-
-    Some(na::DVector::from_vec(
-        data.values
-    ))
+pub fn load_bounds<R: AsRef<Path>>(path: R, n_reactions: usize) -> Result<Bounds, SmasError> {
+    parse_bounds(&fs::read_to_string(path)?, n_reactions)
 }
 
-/// This reads a Matrix Market array formatted file and returns a nalgebra::DMatrix<F64>.
+/// This formats per-reaction flux bounds in the format `parse_bounds` reads: a `lower upper`
+/// header, then one line per reaction with a comment, e.g. its name.
 ///
 /// # Arguments
-/// * `path` - the path to the file.
+/// * `bounds` - the bounds
+/// * `comments` - the comment written after the bounds of each reaction; none if empty
 ///
-pub fn load_matrix<R: AsRef<Path>>(path: R) -> Option<na::DMatrix<f64>> {
-    let data = read_matrix_file(path)?;
-
-    // ** from_vec() expects the data presented in column major order
-    // ** so, we swap the row and column arguments then transpose
-    Some(na::DMatrix::from_vec(
-        data.ncols,
-        data.nrows,
-        data.values,
-    ).transpose())
+pub fn format_bounds(bounds: &Bounds, comments: &[String]) -> String {
+    let mut result_string = String::from("lower upper\n");
+    for (j, (lower, upper)) in bounds.lower.iter().zip(bounds.upper.iter()).enumerate() {
+        match comments.get(j).filter(|comment| !comment.is_empty()) {
+            Some(comment) => result_string.push_str(&format!("{lower} {upper}\t# {comment}\n")),
+            None => result_string.push_str(&format!("{lower} {upper}\n")),
+        }
+    }
+    result_string
 }
 
-/// This reads a Matrix Market array formatted file and returns a MatrixData struct.
-fn read_matrix_file<R: AsRef<Path>>(path: R) -> Option<MatrixData> {
-    let mat_file = File::open(path).unwrap();
-    let mut mat_lines = BufReader::new(mat_file).lines();
-
-    let mut mat_data: Vec<f64> = vec!();
+/// This describes how an output was produced. It is written as comment lines at the beginning
+/// of every Matrix Market file that smas writes.
+pub struct Provenance {
+    /// A short description of the output, e.g. "smas reaction vector"
+    pub title: String,
+    /// The subcommand that produced the output
+    pub subcommand: String,
+    /// The key parameters of the run as (name, value) pairs
+    pub parameters: Vec<(String, String)>,
+    /// The input files that the output was derived from
+    pub inputs: Vec<String>,
+    /// The names of the rows of the output, written as a "%rows:" line
+    pub row_labels: Option<Vec<String>>,
+    /// The names of the columns of the output, written as a "%cols:" line
+    pub col_labels: Option<Vec<String>>,
+}
 
-    let mut rows: usize = 0;
-    let mut cols: usize = 0;
+impl Provenance {
+    /// This creates a Provenance with no parameters or inputs.
+    ///
+    /// # Arguments
+    /// * `title` - a short description of the output
+    /// * `subcommand` - the subcommand that produced the output
+    ///
+    pub fn new(title: &str, subcommand: &str) -> Self {
+        Provenance {
+            title: title.to_string(),
+            subcommand: subcommand.to_string(),
+            parameters: vec!(),
+            inputs: vec!(),
+            row_labels: None,
+            col_labels: None,
+        }
+    }
 
-    while let Some(Ok(line)) = mat_lines.next() {
-        if !line.starts_with('%') {
-            let split: Vec<&str> = line.split_whitespace().collect();
-            rows = usize::from_str(split[0]).expect("failed to parse row count");
-            cols = usize::from_str(split[1]).expect("failed to parse column count");
-            break;
+    /// This formats the provenance as Matrix Market comment lines, each ending with a newline.
+    pub fn format_header(&self) -> String {
+        let mut header = String::new();
+        header.push_str(&format!("% {}\n", self.title));
+        header.push_str(&format!("% smas version: {}\n", env!("CARGO_PKG_VERSION")));
+        header.push_str(&format!("% subcommand: {}\n", self.subcommand));
+        for (name, value) in &self.parameters {
+            header.push_str(&format!("% {name}: {value}\n"));
         }
+        for input in &self.inputs {
+            header.push_str(&format!("% input: {input}\n"));
+        }
+        if let Some(labels) = &self.row_labels {
+            header.push_str(&format!("%rows: {}\n", labels.join(" ")));
+        }
+        if let Some(labels) = &self.col_labels {
+            header.push_str(&format!("%cols: {}\n", labels.join(" ")));
+        }
+        header
     }
+}
+
+/// These are the values substituted into an output file name template.
+pub struct OutTemplateVars<'a> {
+    /// The path of the input file the output is derived from, if there is one
+    pub input_path: Option<&'a Path>,
+    /// The position of the input in the list of inputs
+    pub index: usize,
+    /// The name of the solver method that produced the output
+    pub method: &'a str,
+}
+
+/// This renders an output file name template, e.g. "results/{stem}_solution.{ext}".
+///
+/// The supported placeholders are `{stem}` (the input file name without its extension),
+/// `{ext}` (the input file extension, "txt" if there is none), `{index}` (the position of the
+/// input), `{date}` (the current UTC date, YYYY-MM-DD), and `{method}` (the solver method).
+///
+/// # Arguments
+/// * `template` - the template string
+/// * `vars` - the values substituted into the template
+///
+pub fn render_out_template(template: &str, vars: &OutTemplateVars) -> String {
+    let stem = vars.input_path
+        .and_then(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("accumulation"));
+    let ext = vars.input_path
+        .and_then(|p| p.extension())
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("txt"));
+
+    template
+        .replace("{stem}", &stem)
+        .replace("{ext}", &ext)
+        .replace("{index}", &vars.index.to_string())
+        .replace("{date}", &util::utc_date_string())
+        .replace("{method}", vars.method)
+}
 
-    let total: usize = rows * cols;
+/// This checks that an output file name template gives each input its own path. A template for
+/// several inputs must contain `{stem}` or `{index}`, otherwise every output would be written to
+/// the same file. Returns SmasError::InvalidArgument if it does not.
+///
+/// # Arguments
+/// * `template` - the template string
+/// * `n_inputs` - the number of inputs the template is rendered for
+///
+pub fn check_out_template(template: &str, n_inputs: usize) -> Result<(), SmasError> {
+    if n_inputs > 1 && !template.contains("{stem}") && !template.contains("{index}") {
+        return Err(SmasError::InvalidArgument(format!(
+            "the output template \"{template}\" gives all {n_inputs} inputs the same path, add {{stem}} or {{index}}"
+        )));
+    }
+    Ok(())
+}
 
-    for line in mat_lines {
-        if let Ok(line) = line {
-            let split: Vec<&str> = line.split_whitespace().collect();
-            for entry in split {
-                mat_data.push(f64::from_str(entry).unwrap());
+/// This writes a string to a file, creating its parent directories if needed. If the file
+/// already exists, the overwrite policy decides whether it is replaced, backed up, or left alone.
+/// The file is written atomically, see `write_atomic`.
+///
+/// # Arguments
+/// * `path` - the path to the file
+/// * `contents` - the string to write
+/// * `policy` - what to do if the file already exists
+///
+pub fn write_output_file<R: AsRef<Path>>(
+    path: R,
+    contents: impl AsRef<[u8]>,
+    policy: OverwritePolicy,
+) -> Result<(), SmasError> {
+    let path = path.as_ref();
+    if path.exists() {
+        match policy {
+            OverwritePolicy::Refuse => return Err(SmasError::OutputExists(path.to_path_buf())),
+            OverwritePolicy::Force => {}
+            OverwritePolicy::Backup => {
+                fs::copy(path, backup_path(path))?;
             }
         }
     }
 
-    if mat_data.len() != total {
-        return None;
+    write_atomic(path, contents)?;
+
+    Ok(())
+}
+
+/// This writes a string to a file atomically: the string is written to a temporary file next to
+/// the destination, flushed to disk, and then renamed into place. An interrupted write never
+/// leaves a truncated file at `path`. Parent directories are created if needed.
+///
+/// # Arguments
+/// * `path` - the path to the file
+/// * `contents` - the string or bytes to write
+///
+pub fn write_atomic<R: AsRef<Path>>(path: R, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+
+    let file_name = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = parent.join(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let result = (|| {
+        let mut tmp_file = File::create(&tmp_path)?;
+        tmp_file.write_all(contents.as_ref())?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
     }
+    result
+}
 
-    Some(MatrixData {
-        ncols: cols,
-        nrows: rows,
-        values: mat_data,
-    })
+/// This finds an unused backup path for a file: "<path>.bak", then "<path>.bak.1", and so on.
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = PathBuf::from(format!("{}.bak", path.display()));
+    let mut n = 1;
+    while backup.exists() {
+        backup = PathBuf::from(format!("{}.bak.{n}", path.display()));
+        n += 1;
+    }
+    backup
 }
 
 /// This formats a nalgebra::DVector<f64> as a flat, whitespace delimited string.
@@ -143,16 +1687,9 @@ pub fn format_vector_flat(
     let mut result_string = String::new();
     for (i, row) in vector.row_iter().enumerate() {
         let val: f64 = row[0];
-        match float_format {
-            FloatFormat::Decimal => {
-                result_string.push_str(&format!("{val:.float_precision$}"))
-            }
-            FloatFormat::Scientific => {
-                result_string.push_str(&format!("{val:.float_precision$e}"));
-            }
-        }
+        result_string.push_str(&format_float(val, float_format, float_precision));
         if i < vector.nrows() - 1 {
-            result_string.push_str(" ");
+            result_string.push(' ');
         }
     }
 
@@ -165,37 +1702,76 @@ pub fn format_vector_flat(
 /// * `vector` - the vector to be formatted
 /// * `float_format` - how to format the floats: scientific or decimal
 /// * `float_precision` - how many positions the floats have past the decimal point
-/// * `header` - the header text at the beginning of the string
+/// * `provenance` - the provenance written as comment lines at the beginning of the string
 ///
 pub fn format_vector_mm_array(
     vector: &na::DVector<f64>,
     float_format: FloatFormat,
     float_precision: usize,
-    header: &str,
+    provenance: &Provenance,
 ) -> String {
     let mut result_string = String::new();
     let n_rows = vector.nrows();
-    result_string.push_str(&format!("% {}\n", header));
-    result_string.push_str(&format!("{} 1 {}\n", n_rows, n_rows));
+    result_string.push_str(&provenance.format_header());
+    result_string.push_str(&format!("{} 1\n", n_rows));
     for (i, row) in vector.row_iter().enumerate() {
         let val: f64 = row[0];
-        match float_format {
-            FloatFormat::Decimal => {
-                result_string.push_str(&format!("  {val:.float_precision$}"))
-            }
-            FloatFormat::Scientific => {
-                result_string.push_str(&format!("  {val:.float_precision$e}"));
-            }
-        }
+        result_string.push_str(&format!("  {}", format_float(val, float_format, float_precision)));
         if i < vector.nrows() - 1 {
-            result_string.push_str("\n");
+            result_string.push('\n');
         }
     }
 
     result_string
 }
 
-// This formats the results for ground truth comparison
+/// This formats a single float with the given format and precision.
+///
+/// # Arguments
+/// * `val` - the float to be formatted
+/// * `float_format` - how to format the float: scientific, decimal or canonical
+/// * `float_precision` - how many positions the float has past the decimal point
+///
+pub fn format_float(val: f64, float_format: FloatFormat, float_precision: usize) -> String {
+    match float_format {
+        FloatFormat::Decimal => format!("{val:.float_precision$}"),
+        FloatFormat::Scientific => format!("{val:.float_precision$e}"),
+        // ** adding 0.0 turns -0 into 0 and leaves every other value unchanged
+        FloatFormat::Canonical => format!("{:e}", val + 0.0),
+    }
+}
+
+/// This formats a nalgebra::DMatrix<f64> as a String in the Matrix Market array format, with one
+/// whitespace delimited matrix row per line (the same layout `load_matrix` reads).
+///
+/// # Arguments
+/// * `matrix` - the matrix to be formatted
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+/// * `provenance` - the provenance written as comment lines at the beginning of the string
+///
+pub fn format_matrix_mm_array(
+    matrix: &na::DMatrix<f64>,
+    float_format: FloatFormat,
+    float_precision: usize,
+    provenance: &Provenance,
+) -> String {
+    let mut result_string = String::new();
+    result_string.push_str(&provenance.format_header());
+    result_string.push_str(&format!("{} {}", matrix.nrows(), matrix.ncols()));
+    for row in matrix.row_iter() {
+        let values: Vec<String> = row.iter()
+            .map(|val| format_float(*val, float_format, float_precision))
+            .collect();
+        result_string.push_str(&format!("\n  {}", values.join(" ")));
+    }
+
+    result_string
+}
+
+// This formats the results for ground truth comparison, one row per reaction, followed by a
+// summary whose root mean square delta is accumulated with the given summation method.
+// Returns SmasError::Shape if the vectors have different lengths.
 // TODO: needs some reworking
 pub fn format_comparison_results(
     reactions_computed: &na::DVector<f64>,
@@ -203,39 +1779,110 @@ pub fn format_comparison_results(
     float_format: FloatFormat,
     float_precision: usize,
     epsilon: f64,
-) -> String {
+    summation: util::Summation,
+) -> Result<String, SmasError> {
+    if reactions_computed.nrows() != reactions_true.nrows() {
+        return Err(SmasError::Shape {
+            expected: reactions_true.nrows(),
+            found: reactions_computed.nrows(),
+        });
+    }
+
     let mut result_string = String::new();
     // let reactions_delta = reactions_computed - reactions_true;
 
-    result_string.push_str("% computed \t true \t |delta| \t |delta|<=epsilon\n");
+    result_string.push_str("% computed \t true \t |delta| \t |delta|<=epsilon");
 
     let n_rows = reactions_computed.nrows();
-    for i in 0..n_rows {
-        let val_computed = reactions_computed.get(i)
-            .expect(&format!("failed to retrieve computed value at index: {i}"));
-        let val_true = reactions_true.get(i)
-            .expect(&format!("failed to retrieve true value at index: {i}"));
+    for (val_computed, val_true) in reactions_computed.iter().zip(reactions_true.iter()) {
         let val_delta = (val_computed - val_true).abs();
 
-        match float_format {
-            FloatFormat::Decimal => {
-                result_string.push_str(&format!(
-                    "  {:.float_precision$}\t{:.float_precision$}\t{:.float_precision$}\t{}",
-                    val_computed, val_true, val_delta, util::epsilon_eq(*val_true, *val_computed, epsilon)
-                ))
-            }
-            FloatFormat::Scientific => {
-                result_string.push_str(&format!(
-                    "  {:.float_precision$e}\t{:.float_precision$e}\t{:.float_precision$e}\t{}",
-                    val_computed, val_true, val_delta, util::epsilon_eq(*val_true, *val_computed, epsilon)
-                ));
-            }
-        }
-        if i < n_rows - 1 {
-            result_string.push_str("\n");
-        }
+        result_string.push_str(&format!(
+            "\n  {}\t{}\t{}\t{}",
+            format_float(*val_computed, float_format, float_precision),
+            format_float(*val_true, float_format, float_precision),
+            format_float(val_delta, float_format, float_precision),
+            util::epsilon_eq(*val_true, *val_computed, epsilon)
+        ));
     }
-    result_string
+
+    let n_within = util::count_epsilon_eq(reactions_computed.as_slice(), reactions_true.as_slice(), epsilon);
+    let max_delta = util::max_abs_delta(reactions_computed.as_slice(), reactions_true.as_slice());
+    let rms_delta = if n_rows == 0 {
+        0.0
+    } else {
+        (util::sum_squared_delta_with(reactions_computed.as_slice(), reactions_true.as_slice(), summation) / n_rows as f64).sqrt()
+    };
+    result_string.push_str(&format!(
+        "\n% {n_within}/{n_rows} within epsilon, max |delta|: {}, rms delta: {}",
+        format_float(max_delta, float_format, float_precision),
+        format_float(rms_delta, float_format, float_precision)
+    ));
+    Ok(result_string)
+}
+
+/// This formats a check that the true reaction vector is consistent with the stoichiometric matrix
+/// and the accumulation vector, S r_true = a, one row per metabolite, followed by a summary. A true
+/// vector that does not reproduce the accumulation vector usually belongs to another version of
+/// the model, e.g. one with a reaction added or its metabolites reordered.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `reactions_true` - the true reaction vector, r_true: (n x 1)
+/// * `acc_vector` - the accumulation vector, a: (m x 1)
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+/// * `epsilon` - the largest |delta| of a consistent metabolite
+///
+pub fn format_consistency_results(
+    s_matrix: &LabeledMatrix,
+    reactions_true: &na::DVector<f64>,
+    acc_vector: &na::DVector<f64>,
+    float_format: FloatFormat,
+    float_precision: usize,
+    epsilon: f64,
+) -> Result<String, SmasError> {
+    if reactions_true.nrows() != s_matrix.matrix.ncols() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.matrix.ncols(),
+            found: reactions_true.nrows(),
+        });
+    }
+    if acc_vector.nrows() != s_matrix.matrix.nrows() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.matrix.nrows(),
+            found: acc_vector.nrows(),
+        });
+    }
+
+    let produced = &s_matrix.matrix * reactions_true;
+    let mut result_string = String::from("% metabolite \t S*r_true \t a \t |delta| \t |delta|<=epsilon");
+    for (i, (val_produced, val_acc)) in produced.iter().zip(acc_vector.iter()).enumerate() {
+        result_string.push_str(&format!(
+            "\n  {}\t{}\t{}\t{}\t{}",
+            s_matrix.row_label(i),
+            format_float(*val_produced, float_format, float_precision),
+            format_float(*val_acc, float_format, float_precision),
+            format_float((val_produced - val_acc).abs(), float_format, float_precision),
+            util::epsilon_eq(*val_produced, *val_acc, epsilon)
+        ));
+    }
+
+    let n_rows = produced.nrows();
+    let n_within = util::count_epsilon_eq(produced.as_slice(), acc_vector.as_slice(), epsilon);
+    let max_delta = util::max_abs_delta(produced.as_slice(), acc_vector.as_slice());
+    result_string.push_str(&format!(
+        "\n% {n_within}/{n_rows} metabolites consistent within epsilon, max |delta|: {}",
+        format_float(max_delta, float_format, float_precision)
+    ));
+    if n_within < n_rows {
+        result_string.push_str(&format!(
+            "\n% warning: the true reaction vector does not reproduce the accumulation vector for {} metabolites; \
+            it may belong to another version of the model",
+            n_rows - n_within
+        ));
+    }
+    Ok(result_string)
 }
 
 #[cfg(test)]
@@ -324,4 +1971,444 @@ mod tests {
 
         assert!(smat == smat_static)
     }
+
+    #[test]
+    fn test_render_out_template() {
+        let vars = io::OutTemplateVars {
+            input_path: Some(std::path::Path::new("inputs/astd015.mtx")),
+            index: 3,
+            method: "pinv",
+        };
+        assert_eq!(
+            io::render_out_template("results/{stem}_{index}_{method}.{ext}", &vars),
+            "results/astd015_3_pinv.mtx"
+        );
+
+        assert!(io::check_out_template("results/{stem}.{ext}", 2).is_ok());
+        assert!(io::check_out_template("results/{index}_{method}.txt", 2).is_ok());
+        assert!(io::check_out_template("results/solution_{date}.txt", 1).is_ok());
+        assert!(matches!(
+            io::check_out_template("results/solution_{date}.txt", 2),
+            Err(error::SmasError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_vector_mm_array_provenance() {
+        let mut provenance = io::Provenance::new("smas reaction vector", "solve");
+        provenance.parameters.push((String::from("epsilon"), String::from("1e-3")));
+        provenance.inputs.push(String::from("astd015.txt"));
+
+        let vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let formatted = io::format_vector_mm_array(&vector, io::FloatFormat::Decimal, 1, &provenance);
+        let lines: Vec<&str> = formatted.lines().collect();
+        assert_eq!(lines[0], "% smas reaction vector");
+        assert!(lines.contains(&"% subcommand: solve"));
+        assert!(lines.contains(&"% epsilon: 1e-3"));
+        assert!(lines.contains(&"% input: astd015.txt"));
+        assert_eq!(&lines[lines.len() - 3..], &["2 1", "  1.0", "  2.0"]);
+    }
+
+    #[test]
+    fn test_write_output_file_policies() {
+        let path = std::env::temp_dir().join(format!("smas_overwrite_test_{}.txt", std::process::id()));
+        let backup = std::path::PathBuf::from(format!("{}.bak", path.display()));
+
+        io::write_output_file(&path, "first", io::OverwritePolicy::Refuse).unwrap();
+        assert!(matches!(
+            io::write_output_file(&path, "second", io::OverwritePolicy::Refuse),
+            Err(error::SmasError::OutputExists(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first");
+
+        io::write_output_file(&path, "second", io::OverwritePolicy::Backup).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "first");
+
+        io::write_output_file(&path, "third", io::OverwritePolicy::Force).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third");
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&backup).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("smas_atomic_test_{}", std::process::id()));
+        let path = dir.join("out.txt");
+        io::write_atomic(&path, "contents").unwrap();
+        io::write_atomic(&path, "replaced").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "replaced");
+
+        // ** only the destination file should remain, no temporary files
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_format_matrix_mm_array_round_trip() {
+        let path = std::env::temp_dir().join(format!("smas_matrix_test_{}.txt", std::process::id()));
+        let provenance = io::Provenance::new("test matrix", "test");
+        let matrix = util::default_s_matrix();
+        let formatted = io::format_matrix_mm_array(&matrix, io::FloatFormat::Decimal, 1, &provenance);
+        std::fs::write(&path, formatted).unwrap();
+
+        assert!(io::load_matrix(&path).unwrap() == matrix);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_format_float_canonical() {
+        // ** canonical floats read back exactly, whatever the precision, and -0 is written as 0
+        let vector = na::DVector::from_row_slice(&[0.1 + 0.2, -1.0 / 3.0, 6.02214076e23, -0.0]);
+        let formatted = io::format_vector_flat(&vector, io::FloatFormat::Canonical, 2);
+        assert_eq!(io::parse_vector(&formatted), vector);
+        assert_eq!(formatted, io::format_vector_flat(&vector, io::FloatFormat::Canonical, 9));
+        assert!(formatted.ends_with(" 0e0"), "{formatted}");
+    }
+
+    #[test]
+    fn test_load_vector_strict() {
+        let dir = std::env::temp_dir();
+        let vec_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/rstd015.txt");
+        assert!(io::load_vector_with_mode(vec_path, io::ParseMode::Strict).is_ok());
+
+        let two_per_line = dir.join(format!("smas_strict_test_{}.txt", std::process::id()));
+        std::fs::write(&two_per_line, "% two values per line\n4 1\n1.0 2.0\n3.0 4.0\n").unwrap();
+        assert!(io::load_vector_with_mode(&two_per_line, io::ParseMode::Lenient).is_ok());
+        assert!(matches!(
+            io::load_vector_with_mode(&two_per_line, io::ParseMode::Strict),
+            Err(error::SmasError::Parse { line: 3, .. })
+        ));
+        std::fs::remove_file(&two_per_line).unwrap();
+    }
+
+    #[test]
+    fn test_load_matrix_coordinate_fields() {
+        let dir = std::env::temp_dir();
+        let expected = na::DMatrix::from_row_slice(2, 3, &[
+            1.0, 0.0, -2.0,
+            0.0, 3.0, 0.0,
+        ]);
+
+        let integer = dir.join(format!("smas_integer_test_{}.mtx", std::process::id()));
+        std::fs::write(&integer, "%%MatrixMarket matrix coordinate integer general\n2 3 3\n1 1 1\n1 3 -2\n2 2 3\n").unwrap();
+        assert!(io::load_matrix_with_mode(&integer, io::ParseMode::Strict).unwrap() == expected);
+
+        let pattern = dir.join(format!("smas_pattern_test_{}.mtx", std::process::id()));
+        std::fs::write(&pattern, "%%MatrixMarket matrix coordinate pattern general\n2 3 2\n1 1\n2 2\n").unwrap();
+        let loaded = io::load_matrix_with_pattern_value(&pattern, io::ParseMode::Strict, -1.0).unwrap();
+        assert!(loaded == na::DMatrix::from_row_slice(2, 3, &[-1.0, 0.0, 0.0, 0.0, -1.0, 0.0]));
+
+        let array = dir.join(format!("smas_integer_array_test_{}.mtx", std::process::id()));
+        std::fs::write(&array, "%%MatrixMarket matrix array integer general\n1 2\n1.5\n2\n").unwrap();
+        assert!(io::load_matrix_with_mode(&array, io::ParseMode::Lenient).is_ok());
+        assert!(matches!(
+            io::load_matrix_with_mode(&array, io::ParseMode::Strict),
+            Err(error::SmasError::Parse { line: 3, .. })
+        ));
+
+        std::fs::remove_file(&integer).unwrap();
+        std::fs::remove_file(&pattern).unwrap();
+        std::fs::remove_file(&array).unwrap();
+    }
+
+    #[test]
+    fn test_load_matrix_array_column_major() {
+        let dir = std::env::temp_dir();
+        let expected = na::DMatrix::from_row_slice(2, 2, &[
+            1.0, 2.0,
+            0.0, 1.0,
+        ]);
+
+        // ** arrays with a header are listed column by column, as the format specifies
+        let path = dir.join(format!("smas_column_major_test_{}.mtx", std::process::id()));
+        std::fs::write(&path, "%%MatrixMarket matrix array real general\n2 2\n1\n0\n2\n1\n").unwrap();
+        assert!(io::load_matrix_with_mode(&path, io::ParseMode::Strict).unwrap() == expected);
+        let mut streamed = na::DMatrix::zeros(2, 2);
+        io::stream_matrix_entries(&path, io::ParseMode::Strict, io::DEFAULT_PATTERN_VALUE, |i, j, value| streamed[(i, j)] = value)
+            .unwrap();
+        assert!(streamed == expected);
+
+        // ** legacy files without a header are listed row by row
+        std::fs::write(&path, "2 2\n1 2\n0 1\n").unwrap();
+        assert!(io::load_matrix(&path).unwrap() == expected);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_sparse_matrix() {
+        let path = std::env::temp_dir().join(format!("smas_sparse_test_{}.mtx", std::process::id()));
+        std::fs::write(&path, "%%MatrixMarket matrix coordinate real symmetric\n% comment\n3 3 2\n2 1 4\n3 3 -1\n").unwrap();
+        let expected = io::load_matrix_with_mode(&path, io::ParseMode::Strict).unwrap();
+        let loaded = io::load_sparse_matrix(&path, io::ParseMode::Strict, 1.0).unwrap();
+        assert_eq!(loaded.to_dense(), expected);
+        assert_eq!(loaded.nnz(), 3);
+        assert_eq!(io::read_coordinate_shape(&path).unwrap(), Some((3, 3, 4)));
+
+        let vec_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/rstd015.txt");
+        assert_eq!(io::read_coordinate_shape(vec_path).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_floats_whole_tokens() {
+        assert!(io::parse_vector(" 1\t-2.5e3\n+4 ") == na::DVector::from_row_slice(&[1.0, -2500.0, 4.0]));
+
+        let path = std::env::temp_dir().join(format!("smas_token_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "% a missing space\n2 1\n1.0-2.0\n").unwrap();
+        assert!(matches!(
+            io::load_vector_with_mode(&path, io::ParseMode::Lenient),
+            Err(error::SmasError::Parse { line: 3, .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_keyed_vector() {
+        let labels: Vec<String> = ["glc", "o2", "co2", "atp"].iter().map(|label| label.to_string()).collect();
+        assert!(io::is_keyed_vector("glc=-1.2 co2=2.3"));
+        assert!(!io::is_keyed_vector("-1.2 0 2.3 0"));
+        let vector = io::parse_keyed_vector(" co2=2.3\tglc=-1.2 o2=-8e-1 ", &labels).unwrap();
+        assert_eq!(vector, na::DVector::from_row_slice(&[-1.2, -0.8, 2.3, 0.0]));
+        assert_eq!(io::parse_keyed_vector("", &labels).unwrap(), na::DVector::zeros(4));
+
+        for (string, found) in [("glc=1 nadh=2", "nadh"), ("glc=1 glc=2", "more than once"), ("glc=x", "\"x\""), ("glc=1 2", "\"2\"")] {
+            match io::parse_keyed_vector(string, &labels) {
+                Err(error::SmasError::Parse { message, .. }) => assert!(message.contains(found), "{message}"),
+                other => panic!("expected a parse error for {string}, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_pasted_values() {
+        assert_eq!(io::parse_pasted_values("1\n2.5\r\n-3e-2\n").unwrap(), vec![1.0, 2.5, -0.03]);
+        assert_eq!(io::parse_pasted_values("1\t2, 3,4").unwrap(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert!(io::parse_pasted_values("").unwrap().is_empty());
+        match io::parse_pasted_values("1\nglc\t2") {
+            Err(error::SmasError::Parse { line, message }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("glc"));
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_degenerate_shapes() {
+        let path = std::env::temp_dir().join(format!("smas_degenerate_test_{}.txt", std::process::id()));
+        let provenance = io::Provenance::new("smas test", "test");
+
+        // ** empty matrices round trip through the array format
+        for (nrows, ncols) in [(0, 3), (3, 0), (0, 0)] {
+            let matrix = na::DMatrix::zeros(nrows, ncols);
+            std::fs::write(&path, io::format_matrix_mm_array(&matrix, io::FloatFormat::Scientific, 3, &provenance)).unwrap();
+            assert_eq!(io::load_matrix(&path).unwrap().shape(), (nrows, ncols));
+        }
+
+        // ** an empty vector loads, a matrix with several rows and columns does not
+        std::fs::write(&path, io::format_vector_mm_array(&na::DVector::zeros(0), io::FloatFormat::Scientific, 3, &provenance)).unwrap();
+        assert_eq!(io::load_vector(&path).unwrap().nrows(), 0);
+        std::fs::write(&path, "2 2\n1 2\n3 4\n").unwrap();
+        assert!(matches!(io::load_vector(&path), Err(error::SmasError::Parse { .. })));
+
+        // ** rows and columns both load, and keep their declared orientation for length errors
+        std::fs::write(&path, "1 3\n1 2 3\n").unwrap();
+        let (vector, orientation) = io::load_oriented_vector(&path, io::ParseMode::Lenient).unwrap();
+        assert_eq!((vector.nrows(), orientation), (3, io::VectorOrientation::Row));
+        let error = io::check_vector_length(vector.nrows(), orientation, 4).unwrap_err();
+        assert!(error.to_string().contains("found a 1x3 row vector"), "{error}");
+        std::fs::write(&path, "1 3\n1 2 3 4\n").unwrap();
+        assert!(matches!(io::load_vector(&path), Err(error::SmasError::Shape { expected: 3, found: 4 })));
+        std::fs::remove_file(&path).unwrap();
+
+        // ** comparisons have no blank lines when empty, and fail when the lengths differ
+        let empty = na::DVector::zeros(0);
+        let results = io::format_comparison_results(&empty, &empty, io::FloatFormat::Scientific, 3, 1e-3, util::Summation::default()).unwrap();
+        assert!(results.lines().all(|line| !line.is_empty()));
+        let mismatched = io::format_comparison_results(&empty, &na::DVector::zeros(2), io::FloatFormat::Scientific, 3, 1e-3, util::Summation::default());
+        assert!(matches!(mismatched, Err(error::SmasError::Shape { expected: 2, found: 0 })));
+    }
+
+    #[test]
+    fn test_parse_model_version() {
+        assert_eq!(io::parse_model_version("%%MatrixMarket matrix array real general\n% model: iML1515 2023-04\n2 1\n1\n2\n"), Some(String::from("iML1515 2023-04")));
+        assert_eq!(io::parse_model_version("# Model: core v2\nhk: glc -> g6p\n"), Some(String::from("core v2")));
+        // ** only the header declares a version, and other comments are not versions
+        assert_eq!(io::parse_model_version("%rows: glc g6p\n2 1\n% model: late\n"), None);
+        assert_eq!(io::parse_model_version("% model:\n2 1\n"), None);
+
+        // ** a version recorded in provenance is read back the same way
+        let mut provenance = io::Provenance::new("smas reaction vector", "solve");
+        provenance.parameters.push((String::from("model"), String::from("core v2")));
+        assert_eq!(io::parse_model_version(&provenance.format_header()), Some(String::from("core v2")));
+    }
+
+    #[test]
+    fn test_format_consistency_results() {
+        let model = io::parse_reaction_equations("up: -> glc\nhk: glc -> g6p").unwrap();
+        let reactions_true = na::DVector::from_row_slice(&[3.0, 2.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let results = io::format_consistency_results(&model.s_matrix, &reactions_true, &acc_vector, io::FloatFormat::Decimal, 1, 1e-3).unwrap();
+        assert_eq!(results, "% metabolite \t S*r_true \t a \t |delta| \t |delta|<=epsilon\n  glc\t1.0\t1.0\t0.0\ttrue\n  g6p\t2.0\t2.0\t0.0\ttrue\n% 2/2 metabolites consistent within epsilon, max |delta|: 0.0");
+
+        // ** a true vector from another model version is flagged metabolite by metabolite
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.5]);
+        let results = io::format_consistency_results(&model.s_matrix, &reactions_true, &acc_vector, io::FloatFormat::Decimal, 1, 1e-3).unwrap();
+        assert!(results.contains("\n  g6p\t2.0\t2.5\t0.5\tfalse\n% 1/2 metabolites"), "{results}");
+        assert!(results.contains("for 1 metabolites"), "{results}");
+
+        let mismatched = io::format_consistency_results(&model.s_matrix, &na::DVector::zeros(3), &acc_vector, io::FloatFormat::Decimal, 1, 1e-3);
+        assert!(matches!(mismatched, Err(error::SmasError::Shape { expected: 2, found: 3 })));
+    }
+
+    #[test]
+    fn test_labels_round_trip() {
+        let path = std::env::temp_dir().join(format!("smas_labels_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "%%MatrixMarket matrix array real general\n%rows: glc g6p\n%cols: hk\n% columns: pgi\n2 2\n1 2\n3 4\n").unwrap();
+        let labeled = io::load_labeled_matrix(&path, io::ParseMode::Lenient, io::DEFAULT_PATTERN_VALUE).unwrap();
+        assert_eq!(labeled.row_labels, Some(vec![String::from("glc"), String::from("g6p")]));
+        assert_eq!(labeled.col_labels, Some(vec![String::from("hk"), String::from("pgi")]));
+
+        // ** the labels are written back as comments and survive a round trip
+        let mut provenance = io::Provenance::new("smas test", "test");
+        provenance.row_labels = labeled.row_labels.clone();
+        provenance.col_labels = labeled.col_labels.clone();
+        std::fs::write(&path, io::format_matrix_mm_array(&labeled.matrix, io::FloatFormat::Scientific, 3, &provenance)).unwrap();
+        let reloaded = io::load_labeled_matrix(&path, io::ParseMode::Lenient, io::DEFAULT_PATTERN_VALUE).unwrap();
+        assert_eq!(reloaded.matrix, labeled.matrix);
+        assert_eq!(reloaded.row_labels, labeled.row_labels);
+        assert_eq!(reloaded.col_labels, labeled.col_labels);
+
+        // ** the wrong number of labels is ignored in lenient mode and an error in strict mode
+        std::fs::write(&path, "%%MatrixMarket matrix array real general\n%rows: glc\n2 1\n1\n2\n").unwrap();
+        let labeled = io::load_labeled_matrix(&path, io::ParseMode::Lenient, io::DEFAULT_PATTERN_VALUE).unwrap();
+        assert_eq!(labeled.row_labels, None);
+        assert!(io::load_labeled_matrix(&path, io::ParseMode::Strict, io::DEFAULT_PATTERN_VALUE).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_transpose_labeled_matrix() {
+        let labeled = io::parse_reaction_equations("hk: glc + atp -> g6p + adp\npgi: g6p -> f6p").unwrap().s_matrix;
+        assert!(!labeled.is_transposed(5));
+
+        let transposed = labeled.transpose();
+        assert_eq!(transposed.matrix.shape(), (2, 5));
+        assert_eq!((transposed.row_label(1), transposed.col_label(0)), (String::from("pgi"), String::from("glc")));
+        assert!(transposed.is_transposed(5));
+        // ** a square matrix always fits, so it is never reported as transposed
+        assert!(!io::LabeledMatrix { matrix: na::DMatrix::zeros(3, 3), row_labels: None, col_labels: None }.is_transposed(3));
+    }
+
+    #[test]
+    fn test_load_labels() {
+        let path = std::env::temp_dir().join(format!("smas_label_file_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "# index name\n2 g6p\n1 glc\n\n3 f6p\n").unwrap();
+        let labels = io::load_labels(&path, 3).unwrap();
+        assert_eq!(labels, vec![String::from("glc"), String::from("g6p"), String::from("f6p")]);
+
+        // ** the labels must cover the matrix dimension exactly
+        assert!(matches!(io::load_labels(&path, 4), Err(error::SmasError::Parse { line: 0, .. })));
+        assert!(matches!(io::load_labels(&path, 2), Err(error::SmasError::Parse { line: 5, .. })));
+        std::fs::write(&path, "1 glc\n1 g6p\n").unwrap();
+        assert!(matches!(io::load_labels(&path, 2), Err(error::SmasError::Parse { line: 2, .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        let bounds = io::parse_bounds("% lower upper\nlower,upper\n-inf inf\n0, 1e5\n\n-2\t-inf\n", 3);
+        assert!(matches!(bounds, Err(error::SmasError::Parse { line: 6, .. })));
+        let bounds = io::parse_bounds("lower upper\n-inf inf\n0, 1e5\n-2 -1\n", 3).unwrap();
+        assert_eq!(bounds.lower, na::DVector::from_row_slice(&[f64::NEG_INFINITY, 0.0, -2.0]));
+        assert_eq!(bounds.upper, na::DVector::from_row_slice(&[f64::INFINITY, 1e5, -1.0]));
+
+        assert!(matches!(io::parse_bounds("0 1\n", 2), Err(error::SmasError::Parse { line: 0, .. })));
+        assert!(matches!(io::parse_bounds("0 1\n0 1\n", 1), Err(error::SmasError::Parse { line: 2, .. })));
+        assert!(matches!(io::parse_bounds("0 1 2\n", 1), Err(error::SmasError::Parse { line: 1, .. })));
+        assert!(matches!(io::parse_bounds("nan 1\n", 1), Err(error::SmasError::Parse { line: 1, .. })));
+
+        // ** a formatted template reads back, comments and all
+        let template = constraints::Bounds::from_directions(&[true, false]);
+        let text = io::format_bounds(&template, &[String::from("R1 exchange"), String::new()]);
+        assert_eq!(text, "lower upper\n-inf inf\t# R1 exchange\n0 inf\n");
+        let bounds = io::parse_bounds(&text, 2).unwrap();
+        assert_eq!(bounds.lower, template.lower);
+        assert_eq!(bounds.upper, template.upper);
+    }
+
+    #[test]
+    fn test_load_declared_units() {
+        let path = std::env::temp_dir().join(format!("smas_units_file_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "% measured at 30C\n%units: umol/min\n2 1\n1.0\n2.0\n").unwrap();
+        let unit = io::load_declared_units(&path).unwrap().unwrap();
+        assert_eq!(unit.to_string(), "umol/min");
+        assert_eq!(io::load_vector(&path).unwrap(), na::DVector::from_row_slice(&[1.0, 2.0]));
+
+        std::fs::write(&path, "2 1\n%units: mmol\n1.0\n2.0\n").unwrap();
+        assert!(io::load_declared_units(&path).unwrap().is_none());
+        std::fs::write(&path, "%units: kg\n2 1\n1.0\n2.0\n").unwrap();
+        assert!(io::load_declared_units(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_reaction_equations() {
+        let model = io::parse_reaction_equations("\
+            # a small glycolysis fragment
+            hk: glc + atp -> g6p + adp
+            pgi: g6p <=> f6p
+            f6p <- 2 fbp
+            -> glc
+        ").unwrap();
+        let labeled = &model.s_matrix;
+        let names = |labels: &[&str]| Some(labels.iter().map(|l| l.to_string()).collect::<Vec<String>>());
+        assert_eq!(labeled.row_labels, names(&["glc", "atp", "g6p", "adp", "f6p", "fbp"]));
+        assert_eq!(labeled.col_labels, names(&["hk", "pgi", "R3", "R4"]));
+        assert_eq!(model.reversible, vec![false, true, false, false]);
+        assert_eq!(labeled.matrix, na::DMatrix::from_row_slice(6, 4, &[
+            -1.0,  0.0,  0.0, 1.0,
+            -1.0,  0.0,  0.0, 0.0,
+             1.0, -1.0,  0.0, 0.0,
+             1.0,  0.0,  0.0, 0.0,
+             0.0,  1.0,  1.0, 0.0,
+             0.0,  0.0, -2.0, 0.0,
+        ]));
+
+        assert!(matches!(io::parse_reaction_equations("A + B"), Err(error::SmasError::Parse { line: 1, .. })));
+        assert!(matches!(io::parse_reaction_equations("A -> x y z"), Err(error::SmasError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_format_reaction_equations_round_trip() {
+        let model = io::parse_reaction_equations("hk: glc + atp -> g6p + adp\npgi: g6p <=> 0.5 f6p\nup: -> glc").unwrap();
+        let equations = io::format_reaction_equations(&model.s_matrix, Some(&model.reversible));
+        assert_eq!(equations, "hk: glc + atp -> g6p + adp\npgi: g6p <=> 0.5 f6p\nup: -> glc");
+
+        let reparsed = io::parse_reaction_equations(&equations).unwrap();
+        assert_eq!(reparsed.s_matrix.matrix, model.s_matrix.matrix);
+        assert_eq!(reparsed.s_matrix.row_labels, model.s_matrix.row_labels);
+        assert_eq!(reparsed.reversible, model.reversible);
+
+        let unlabeled = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 1, &[-2.0, 1.0]),
+            row_labels: None,
+            col_labels: None,
+        };
+        assert_eq!(io::format_reaction_equations(&unlabeled, None), "R1: 2 M1 -> M2");
+    }
+
+    #[test]
+    fn test_format_reaction_equations_canonical() {
+        let model = io::parse_reaction_equations("pgi: g6p <=> f6p\nhk: glc + atp -> 1.0000001 g6p + 0.3333333333 adp").unwrap();
+        let canonical = io::format_reaction_equations_canonical(&model.s_matrix, Some(&model.reversible));
+        assert_eq!(canonical, "hk: atp + glc -> 0.333333 adp + g6p\npgi: g6p <=> f6p\n");
+
+        // ** the same model with its rows and columns in another order serializes identically
+        let reordered = io::parse_reaction_equations("hk: atp + glc -> 0.3333333333 adp + g6p\npgi: g6p <=> f6p").unwrap();
+        assert_eq!(io::format_reaction_equations_canonical(&reordered.s_matrix, Some(&reordered.reversible)), canonical);
+    }
 }
@@ -0,0 +1,407 @@
+use crate::io;
+use crate::solve;
+use crate::util;
+
+use std::time::Duration;
+
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The default epsilon of a request, the same as the -e default of the command line.
+const DEFAULT_EPSILON: f64 = 1e-3;
+
+/// The default maximum number of iterations of lsqr and nnls.
+const DEFAULT_MAX_ITERATIONS: usize = 10000;
+
+/// The members a request may have.
+const REQUEST_MEMBERS: [&str; 7] = ["id", "matrix", "equations", "metabolites", "reactions", "vector", "options"];
+
+/// The members "options" may have.
+const OPTION_MEMBERS: [&str; 5] = ["method", "epsilon", "tolerance", "max_iterations", "timeout"];
+
+/// This is a request, once `check_request` has validated its JSON.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Request {
+    /// The stoichiometric matrix, as an array of rows
+    matrix: Option<Vec<Vec<f64>>>,
+    /// The stoichiometric matrix, as reaction equations
+    equations: Option<String>,
+    /// The name of each row of the matrix
+    metabolites: Option<Vec<String>>,
+    /// The name of each column of the matrix
+    reactions: Option<Vec<String>>,
+    /// The accumulation vector
+    vector: Vec<f64>,
+    /// How to solve
+    #[serde(default)]
+    options: Options,
+    /// The id, which is echoed in the response; read by `handle_request` before validation
+    #[serde(default, rename = "id")]
+    _id: Value,
+}
+
+/// This is the "options" member of a request.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct Options {
+    /// The solver
+    method: Method,
+    /// Fluxes below it are written as 0
+    epsilon: f64,
+    /// The tolerance of lsqr
+    tolerance: f64,
+    /// The maximum number of iterations of lsqr and nnls
+    max_iterations: usize,
+    /// The time limit of lsqr and nnls, in seconds
+    timeout: Option<f64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            method: Method::Pinv,
+            epsilon: DEFAULT_EPSILON,
+            tolerance: solve::LSQR_TOLERANCE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            timeout: None,
+        }
+    }
+}
+
+/// This is the solver of a request.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// The minimum norm pseudoinverse
+    Pinv,
+    /// A QR factorization
+    Qr,
+    /// LSQR
+    Lsqr,
+    /// Non-negative least squares
+    Nnls,
+}
+
+/// This is a response. A response that is ok has a result and no error, and the other way around.
+#[derive(Serialize, Debug)]
+struct Response {
+    /// The id of the request, or null
+    id: Value,
+    /// Whether the request was solved
+    ok: bool,
+    /// Why the request was not solved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The solution
+    #[serde(flatten)]
+    result: Option<SolveResult>,
+}
+
+/// This is the solution of a request.
+#[derive(Serialize, Debug)]
+struct SolveResult {
+    /// The name of the solver
+    method: &'static str,
+    /// Whether an iterative solver converged
+    converged: bool,
+    /// The number of iterations of an iterative solver
+    iterations: Option<usize>,
+    /// The two-norm of the residual
+    residual_norm: f64,
+    /// The name of each reaction
+    reactions: Vec<String>,
+    /// The flux of each reaction
+    fluxes: Vec<f64>,
+}
+
+/// This returns the name of the type of a JSON value, for errors.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// This checks that an object only has known members, so that a misspelled member, e.g.
+/// "options.max_iteration", is an error rather than silently ignored. Errors name the member by
+/// its path in the request.
+///
+/// # Arguments
+/// * `value` - the object
+/// * `path` - the path of the object in the request, e.g. "options"; empty for the request
+/// * `known` - the members the object may have
+///
+fn check_members(value: &Value, path: &str, known: &[&str]) -> Result<(), String> {
+    let Value::Object(members) = value else {
+        return Err(match path {
+            "" => format!("the request must be an object, found {}", type_name(value)),
+            _ => format!("{path} must be an object, found {}", type_name(value)),
+        });
+    };
+    match members.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => Err(format!(
+            "{} is not a known member{}; the members are {}",
+            member_path(path, key),
+            if path.is_empty() { String::new() } else { format!(" of {path}") },
+            known.join(", "),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// This returns the path of a member of an object, e.g. "options.method".
+fn member_path(path: &str, key: &str) -> String {
+    match path {
+        "" => String::from(key),
+        _ => format!("{path}.{key}"),
+    }
+}
+
+/// This checks an array of numbers. Errors name the value by its path, e.g. "vector[3] must be a
+/// number, found a string".
+fn check_numbers(value: &Value, path: &str) -> Result<(), String> {
+    let Value::Array(values) = value else {
+        return Err(format!("{path} must be an array of numbers, found {}", type_name(value)));
+    };
+    match values.iter().enumerate().find(|(_, value)| !value.is_number()) {
+        Some((i, value)) => Err(format!("{path}[{i}] must be a number, found {}", type_name(value))),
+        None => Ok(()),
+    }
+}
+
+/// This checks an array of names.
+fn check_names(value: &Value, path: &str) -> Result<(), String> {
+    let Value::Array(values) = value else {
+        return Err(format!("{path} must be an array of strings, found {}", type_name(value)));
+    };
+    match values.iter().enumerate().find(|(_, value)| !value.is_string()) {
+        Some((i, value)) => Err(format!("{path}[{i}] must be a string, found {}", type_name(value))),
+        None => Ok(()),
+    }
+}
+
+/// This checks the JSON of a request before it is deserialized, so that errors name the
+/// offending value by its path, e.g. "matrix[2][1] must be a number, found a string", rather
+/// than by its position in the text.
+///
+/// # Arguments
+/// * `request` - the request
+///
+fn check_request(request: &Value) -> Result<(), String> {
+    check_members(request, "", &REQUEST_MEMBERS)?;
+    match (request.get("matrix"), request.get("equations")) {
+        (Some(_), Some(_)) => return Err(String::from("give either matrix or equations, not both")),
+        (Some(Value::Array(rows)), None) => {
+            for (i, row) in rows.iter().enumerate() {
+                check_numbers(row, &format!("matrix[{i}]"))?;
+            }
+        }
+        (Some(value), None) => return Err(format!("matrix must be an array of rows, found {}", type_name(value))),
+        (None, Some(Value::String(_))) => (),
+        (None, Some(value)) => return Err(format!("equations must be a string, found {}", type_name(value))),
+        (None, None) => return Err(String::from("the request needs a matrix or equations")),
+    }
+    for key in ["metabolites", "reactions"] {
+        if let Some(names) = request.get(key) {
+            check_names(names, key)?;
+        }
+    }
+    match request.get("vector") {
+        Some(vector) => check_numbers(vector, "vector")?,
+        None => return Err(String::from("the request needs a vector")),
+    }
+
+    let Some(options) = request.get("options") else {
+        return Ok(());
+    };
+    check_members(options, "options", &OPTION_MEMBERS)?;
+    for key in ["epsilon", "tolerance", "max_iterations", "timeout"] {
+        match options.get(key) {
+            Some(value) if !value.is_number() => {
+                return Err(format!("options.{key} must be a number, found {}", type_name(value)))
+            }
+            _ => (),
+        }
+    }
+    match options.get("max_iterations") {
+        Some(value) if value.as_u64().is_none() => {
+            return Err(format!("options.max_iterations must be a whole number, found {value}"))
+        }
+        _ => (),
+    }
+    match options.get("method") {
+        Some(value) if !["pinv", "qr", "lsqr", "nnls"].contains(&value.as_str().unwrap_or_default()) => {
+            Err(format!("options.method must be \"pinv\", \"qr\", \"lsqr\" or \"nnls\", found {value}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// This builds the stoichiometric matrix of a request: either "matrix", an array of rows, or
+/// "equations", reaction equations as for --equations. "metabolites" and "reactions" name the
+/// rows and columns of a matrix.
+fn request_matrix(request: &Request) -> Result<io::LabeledMatrix, String> {
+    let mut s_matrix = match (&request.matrix, &request.equations) {
+        (Some(rows), _) => {
+            let ncols = rows.first().map_or(0, |row| row.len());
+            if let Some((i, row)) = rows.iter().enumerate().find(|(_, row)| row.len() != ncols) {
+                return Err(format!("matrix[{i}] has {} values, but matrix[0] has {ncols}", row.len()));
+            }
+            io::LabeledMatrix {
+                matrix: na::DMatrix::from_row_iterator(rows.len(), ncols, rows.iter().flatten().copied()),
+                row_labels: None,
+                col_labels: None,
+            }
+        }
+        (None, Some(equations)) => io::parse_reaction_equations(equations)
+            .map_err(|e| format!("failed to parse the equations: {e}"))?
+            .s_matrix,
+        (None, None) => return Err(String::from("the request needs a matrix or equations")),
+    };
+    let names = |names: &Option<Vec<String>>, path: &str, expected: usize| match names {
+        Some(names) if names.len() != expected => {
+            Err(format!("{path} has {} names, but there are {expected}", names.len()))
+        }
+        names => Ok(names.clone()),
+    };
+    if request.metabolites.is_some() {
+        s_matrix.row_labels = names(&request.metabolites, "metabolites", s_matrix.matrix.nrows())?;
+    }
+    if request.reactions.is_some() {
+        s_matrix.col_labels = names(&request.reactions, "reactions", s_matrix.matrix.ncols())?;
+    }
+    Ok(s_matrix)
+}
+
+/// This solves a request.
+fn solve_request(request: &Value) -> Result<SolveResult, String> {
+    check_request(request)?;
+    let request = Request::deserialize(request).map_err(|e| format!("invalid request: {e}"))?;
+    let s_matrix = request_matrix(&request)?;
+    let acc_vector = na::DVector::from_column_slice(&request.vector);
+
+    let options = &request.options;
+    let timeout = match options.timeout {
+        None => None,
+        Some(seconds) => Some(
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|_| format!("options.timeout must be a non-negative number of seconds, found {seconds}"))?,
+        ),
+    };
+    let solve_options = solve::SolveOptions { timeout, cancel_token: None };
+    let solution = match options.method {
+        Method::Pinv => solve::solve_pinv(&acc_vector, &s_matrix.matrix),
+        Method::Qr => solve::solve_qr(&acc_vector, &s_matrix.matrix),
+        Method::Lsqr => {
+            solve::solve_lsqr_with_options(&acc_vector, &s_matrix.matrix, options.tolerance, options.max_iterations, &solve_options)
+        }
+        Method::Nnls => solve::solve_nnls_with_options(&acc_vector, &s_matrix.matrix, options.max_iterations, &solve_options),
+    };
+    let solution = solution.map_err(|e| format!("failed to solve: {e}"))?;
+
+    let mut fluxes = solution.reactions.clone();
+    util::clamp_to_zero(&mut fluxes, options.epsilon);
+    Ok(SolveResult {
+        method: solution.method,
+        converged: solution.converged,
+        iterations: solution.iterations,
+        residual_norm: solution.residual_norm,
+        reactions: (0..fluxes.nrows()).map(|j| s_matrix.col_label(j)).collect(),
+        fluxes: fluxes.iter().copied().collect(),
+    })
+}
+
+/// This answers a single JSON request, e.g.
+///
+/// ```text
+/// {"id": 7, "matrix": [[1, -1], [0, 1]], "vector": [1, 2], "options": {"method": "lsqr"}}
+/// ```
+///
+/// The stoichiometric matrix is "matrix", an array of rows, optionally labeled by "metabolites"
+/// and "reactions", or "equations", reaction equations as for --equations. The options are
+/// "method" ("pinv", the default, "qr", "lsqr" or "nnls"), "epsilon" (fluxes below it are written as 0,
+/// 1e-3 by default), "tolerance" for lsqr, and "max_iterations" and "timeout" (in seconds, after
+/// which the solve is abandoned with an error) for lsqr and nnls. The response
+/// echoes "id", and has "ok": true with the "method", "converged", "iterations", "residual_norm",
+/// "reactions" (names) and "fluxes", or "ok": false with an "error" that names the offending
+/// value by its path, e.g. "matrix[2][1] must be a number, found a string". Unknown members are
+/// errors. Returns the text of the response and whether it is ok.
+///
+/// # Arguments
+/// * `request` - the text of the request
+///
+pub fn handle_request(request: &str) -> (String, bool) {
+    let parsed = serde_json::from_str::<Value>(request).map_err(|e| format!("failed to parse the request: {e}"));
+    let id = parsed.as_ref().ok().and_then(|request| request.get("id")).cloned().unwrap_or(Value::Null);
+    let response = match parsed.and_then(|request| solve_request(&request)) {
+        Ok(result) => Response { id, ok: true, error: None, result: Some(result) },
+        Err(message) => Response { id, ok: false, error: Some(message), result: None },
+    };
+    let text = serde_json::to_string(&response).expect("responses serialize to JSON");
+    (text, response.ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_handle_request() {
+        let request = "{\"id\": \"run-1\", \"matrix\": [[1, -1], [0, 1]], \"vector\": [1, 2], \
+            \"reactions\": [\"hk\", \"pgi\"], \"options\": {\"method\": \"lsqr\", \"epsilon\": 0}}";
+        let (response, ok) = json_rpc::handle_request(request);
+        assert!(ok, "{response}");
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], json!("run-1"));
+        assert_eq!(response["method"], json!("lsqr"));
+        assert_eq!(response["reactions"], json!(["hk", "pgi"]));
+        let fluxes: Vec<f64> = serde_json::from_value(response["fluxes"].clone()).unwrap();
+        assert!((fluxes[0] - 3.0).abs() < 1e-9 && (fluxes[1] - 2.0).abs() < 1e-9, "{fluxes:?}");
+
+        let error = |request: &str| {
+            let (response, ok) = json_rpc::handle_request(request);
+            assert!(!ok);
+            let response: Value = serde_json::from_str(&response).unwrap();
+            response["error"].as_str().unwrap().to_string()
+        };
+        assert_eq!(error("{\"matrix\":\n[1,]}"), "failed to parse the request: trailing comma at line 2 column 4");
+        assert_eq!(error("{\"matrix\": [[1, 0], [0, \"1\"]], \"vector\": [1, 2]}"), "matrix[1][1] must be a number, found a string");
+        assert_eq!(error("{\"matrix\": [[1, 0], [0]], \"vector\": [1, 2]}"), "matrix[1] has 1 values, but matrix[0] has 2");
+        assert_eq!(error("{\"matrix\": [[1]], \"vector\": [1], \"reactions\": [7]}"), "reactions[0] must be a string, found a number");
+        assert_eq!(error("{\"matrix\": [[1]], \"vector\": [1], \"reactions\": [\"a\", \"b\"]}"), "reactions has 2 names, but there are 1");
+        assert_eq!(
+            error("{\"matrix\": [[1]], \"vector\": [1], \"options\": {\"max_iteration\": 5}}"),
+            "options.max_iteration is not a known member of options; the members are method, epsilon, tolerance, max_iterations, timeout"
+        );
+        assert_eq!(
+            error("{\"matrix\": [[1]], \"vector\": [1], \"options\": {\"epsilon\": null}}"),
+            "options.epsilon must be a number, found null"
+        );
+        assert_eq!(
+            error("{\"matrix\": [[1]], \"vector\": [1], \"options\": {\"max_iterations\": -2}}"),
+            "options.max_iterations must be a whole number, found -2"
+        );
+        assert_eq!(
+            error("{\"matrix\": [[1]], \"vector\": [1], \"options\": {\"method\": \"svd\"}}"),
+            "options.method must be \"pinv\", \"qr\", \"lsqr\" or \"nnls\", found \"svd\""
+        );
+        assert_eq!(
+            error("{\"matrix\": [[1]], \"vector\": [1], \"options\": {\"method\": \"lsqr\", \"timeout\": 0}}"),
+            "failed to solve: lsqr timed out after 0 iterations"
+        );
+
+        let (response, ok) = json_rpc::handle_request("{\"id\": 3, \"equations\": \"hk: A -> B\", \"vector\": [1]}");
+        assert!(!ok);
+        assert_eq!(
+            response,
+            "{\"id\":3,\"ok\":false,\"error\":\"failed to solve: expected 2 values from the declared shape, but found 1\"}"
+        );
+    }
+}
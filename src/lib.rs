@@ -1,5 +1,48 @@
 pub mod solve;
+pub mod arrow;
+pub mod audit;
+pub mod cancel;
 pub mod io;
+pub mod lp;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod constraints;
+pub mod cost;
+pub mod error;
+pub mod exact;
+pub mod examples;
+pub mod explain;
+pub mod formatter;
+pub mod generate;
+pub mod heatmap;
+pub mod id_map;
+pub mod json_rpc;
 pub mod matrices;
+pub mod measurements;
+pub mod memory;
+pub mod model_diff;
+pub mod moieties;
+pub mod out_of_core;
+pub mod pathway;
+#[cfg(feature = "rhai")]
+pub mod post_script;
+pub mod profile;
+pub mod regress;
+pub mod repl;
+pub mod replicates;
+pub mod sample;
+pub mod search;
+pub mod selftest;
+pub mod sensitivity;
+pub mod snap;
+pub mod solution_set;
+pub mod sparse;
+pub mod testing;
+pub mod thermo;
+pub mod timecourse;
+pub mod units;
+#[cfg(feature = "tui")]
+pub mod tui;
 pub mod util;
+pub mod validation;
 pub mod wasm_api;
@@ -0,0 +1,370 @@
+use crate::error::SmasError;
+use crate::solve::{Deadline, SolveOptions};
+
+use nalgebra as na;
+
+/// The tolerance used by the simplex method to decide whether a value is zero.
+pub const LP_EPSILON: f64 = 1e-9;
+
+/// The default maximum number of simplex pivots before giving up.
+pub const LP_MAX_ITERATIONS: usize = 100_000;
+
+/// This describes a linear program in the form:
+///
+/// minimize c·x subject to Ax = B and lower <= x <= upper
+///
+/// Bounds may be infinite.
+pub struct LinearProgram {
+    /// The objective vector, c; (n x 1)
+    pub objective: na::DVector<f64>,
+    /// The equality constraint matrix, A; (m x n)
+    pub eq_matrix: na::DMatrix<f64>,
+    /// The equality constraint right hand side, B; (m x 1)
+    pub eq_rhs: na::DVector<f64>,
+    /// The lower bound of each variable, -inf if unbounded; (n x 1)
+    pub lower: na::DVector<f64>,
+    /// The upper bound of each variable, +inf if unbounded; (n x 1)
+    pub upper: na::DVector<f64>,
+}
+
+/// This is the optimal solution of a LinearProgram.
+pub struct LpSolution {
+    /// The optimal variable values, x; (n x 1)
+    pub x: na::DVector<f64>,
+    /// The objective value at the optimum, c·x
+    pub objective_value: f64,
+    /// The dual value of each equality constraint; (m x 1)
+    pub shadow_prices: na::DVector<f64>,
+    /// The reduced cost of each variable, c - Aᵀy; (n x 1)
+    pub reduced_costs: na::DVector<f64>,
+    /// The number of simplex pivots performed across both phases
+    pub iterations: usize,
+}
+
+/// This describes how an original variable maps onto the non-negative standard form variables.
+enum VarMap {
+    /// x = lower + s[col]
+    Shifted { col: usize, lower: f64 },
+    /// x = upper - s[col]
+    Mirrored { col: usize, upper: f64 },
+    /// x = s[pos] - s[neg]
+    Split { pos: usize, neg: usize },
+}
+
+/// A dense simplex tableau. The last row holds the reduced costs and the last column holds the
+/// right hand side.
+struct Tableau {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+    basis: Vec<usize>,
+}
+
+impl Tableau {
+    fn at(&self, r: usize, c: usize) -> f64 {
+        self.data[r * self.cols + c]
+    }
+
+    fn set(&mut self, r: usize, c: usize, val: f64) {
+        self.data[r * self.cols + c] = val;
+    }
+
+    fn rhs(&self, r: usize) -> f64 {
+        self.at(r, self.cols - 1)
+    }
+
+    /// This pivots the tableau on (row, col), making col basic in row.
+    fn pivot(&mut self, row: usize, col: usize) {
+        let pivot = self.at(row, col);
+        for c in 0..self.cols {
+            let val = self.at(row, c) / pivot;
+            self.set(row, c, val);
+        }
+        for r in 0..=self.rows {
+            if r == row {
+                continue;
+            }
+            let factor = self.at(r, col);
+            if factor.abs() > 0.0 {
+                for c in 0..self.cols {
+                    let val = self.at(r, c) - factor * self.at(row, c);
+                    self.set(r, c, val);
+                }
+            }
+        }
+        self.basis[row] = col;
+    }
+
+    /// This recomputes the objective row for the given costs of every column.
+    fn set_objective(&mut self, costs: &[f64]) {
+        let obj = self.rows;
+        for c in 0..self.cols {
+            let mut val = if c < costs.len() { costs[c] } else { 0.0 };
+            for r in 0..self.rows {
+                let basic = self.basis[r];
+                let cost = if basic < costs.len() { costs[basic] } else { 0.0 };
+                val -= cost * self.at(r, c);
+            }
+            self.set(obj, c, val);
+        }
+    }
+
+    /// This runs simplex pivots with Bland's rule until optimal, considering only the columns
+    /// below `n_enterable` for entry, and checking the deadline between pivots. Returns the
+    /// number of pivots.
+    fn optimize(&mut self, n_enterable: usize, max_iterations: usize, deadline: &Deadline) -> Result<usize, SmasError> {
+        let obj = self.rows;
+        let mut iterations = 0;
+        loop {
+            deadline.check("simplex", iterations)?;
+            let entering = (0..n_enterable).find(|c| self.at(obj, *c) < -LP_EPSILON);
+            let entering = match entering {
+                Some(c) => c,
+                None => return Ok(iterations),
+            };
+
+            // ** ratio test, ties broken by the smallest basic variable index (Bland's rule)
+            let mut leaving: Option<usize> = None;
+            let mut best_ratio = f64::INFINITY;
+            for r in 0..self.rows {
+                let coef = self.at(r, entering);
+                if coef > LP_EPSILON {
+                    let ratio = self.rhs(r) / coef;
+                    let better = match leaving {
+                        None => true,
+                        Some(lr) => ratio < best_ratio - LP_EPSILON
+                            || (ratio <= best_ratio + LP_EPSILON && self.basis[r] < self.basis[lr]),
+                    };
+                    if better {
+                        leaving = Some(r);
+                        best_ratio = ratio;
+                    }
+                }
+            }
+            let row = leaving.ok_or(SmasError::Unbounded)?;
+
+            self.pivot(row, entering);
+            iterations += 1;
+            if iterations >= max_iterations {
+                return Err(SmasError::NotConverged {
+                    method: "simplex",
+                    iterations,
+                    residual_norm: f64::NAN,
+                });
+            }
+        }
+    }
+}
+
+/// This solves a LinearProgram with the two-phase dense simplex method.
+///
+/// Returns SmasError::Infeasible if no x satisfies the constraints, and SmasError::Unbounded if
+/// the objective can be decreased without limit.
+///
+/// # Arguments
+/// * `lp` - the linear program to solve
+/// * `max_iterations` - the maximum number of simplex pivots
+///
+pub fn solve_lp(lp: &LinearProgram, max_iterations: usize) -> Result<LpSolution, SmasError> {
+    solve_lp_with_options(lp, max_iterations, &SolveOptions::default())
+}
+
+/// This solves a LinearProgram like `solve_lp`, checking the cancellation token and timeout of
+/// the options between simplex pivots.
+///
+/// # Arguments
+/// * `lp` - the linear program to solve
+/// * `max_iterations` - the maximum number of simplex pivots
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_lp_with_options(lp: &LinearProgram, max_iterations: usize, options: &SolveOptions) -> Result<LpSolution, SmasError> {
+    let deadline = options.start();
+    let m = lp.eq_matrix.nrows();
+    let n = lp.eq_matrix.ncols();
+
+    // ** map each original variable onto one or two non-negative standard form variables, and
+    // ** record upper bounds that need their own row
+    let mut var_maps = vec!();
+    let mut upper_rows: Vec<(usize, f64)> = vec!();
+    let mut n_std = 0;
+    for j in 0..n {
+        let (lower, upper) = (lp.lower[j], lp.upper[j]);
+        if lower > upper + LP_EPSILON {
+            return Err(SmasError::Infeasible);
+        }
+        if lower.is_finite() {
+            var_maps.push(VarMap::Shifted { col: n_std, lower });
+            if upper.is_finite() {
+                upper_rows.push((n_std, upper - lower));
+            }
+            n_std += 1;
+        } else if upper.is_finite() {
+            var_maps.push(VarMap::Mirrored { col: n_std, upper });
+            n_std += 1;
+        } else {
+            var_maps.push(VarMap::Split { pos: n_std, neg: n_std + 1 });
+            n_std += 2;
+        }
+    }
+    let n_slack = upper_rows.len();
+    let rows = m + n_slack;
+    let n_vars = n_std + n_slack;
+    // ** columns: standard variables, upper bound slacks, one artificial per row, then the rhs
+    let cols = n_vars + rows + 1;
+
+    let mut tableau = Tableau {
+        rows,
+        cols,
+        data: vec![0.0; (rows + 1) * cols],
+        basis: (0..rows).map(|r| n_vars + r).collect(),
+    };
+
+    let mut costs = vec![0.0; n_vars];
+    let mut row_signs = vec![1.0; rows];
+    for i in 0..m {
+        let mut rhs = lp.eq_rhs[i];
+        for (j, var_map) in var_maps.iter().enumerate() {
+            let a = lp.eq_matrix[(i, j)];
+            match var_map {
+                VarMap::Shifted { col, lower } => {
+                    tableau.set(i, *col, a);
+                    rhs -= a * lower;
+                }
+                VarMap::Mirrored { col, upper } => {
+                    tableau.set(i, *col, -a);
+                    rhs -= a * upper;
+                }
+                VarMap::Split { pos, neg } => {
+                    tableau.set(i, *pos, a);
+                    tableau.set(i, *neg, -a);
+                }
+            }
+        }
+        tableau.set(i, cols - 1, rhs);
+    }
+    for (k, (col, range)) in upper_rows.iter().enumerate() {
+        tableau.set(m + k, *col, 1.0);
+        tableau.set(m + k, n_std + k, 1.0);
+        tableau.set(m + k, cols - 1, *range);
+    }
+    for (j, var_map) in var_maps.iter().enumerate() {
+        let c = lp.objective[j];
+        match var_map {
+            VarMap::Shifted { col, .. } => costs[*col] = c,
+            VarMap::Mirrored { col, .. } => costs[*col] = -c,
+            VarMap::Split { pos, neg } => {
+                costs[*pos] = c;
+                costs[*neg] = -c;
+            }
+        }
+    }
+
+    // ** make every rhs non-negative so the artificial variables form a feasible basis
+    for (r, sign) in row_signs.iter_mut().enumerate() {
+        if tableau.rhs(r) < 0.0 {
+            *sign = -1.0;
+            for c in 0..cols {
+                let val = -tableau.at(r, c);
+                tableau.set(r, c, val);
+            }
+        }
+        tableau.set(r, n_vars + r, 1.0);
+    }
+
+    // ** phase 1: minimize the sum of the artificial variables
+    let mut phase1_costs = vec![0.0; n_vars + rows];
+    for cost in phase1_costs.iter_mut().skip(n_vars) {
+        *cost = 1.0;
+    }
+    tableau.set_objective(&phase1_costs);
+    let mut iterations = tableau.optimize(n_vars, max_iterations, &deadline)?;
+
+    let infeasibility: f64 = (0..rows)
+        .filter(|r| tableau.basis[*r] >= n_vars)
+        .map(|r| tableau.rhs(r))
+        .sum();
+    let scale = 1.0 + lp.eq_rhs.amax();
+    if infeasibility > LP_EPSILON * scale * 1e3 {
+        return Err(SmasError::Infeasible);
+    }
+
+    // ** drive any remaining (zero valued) artificial variables out of the basis
+    for r in 0..rows {
+        if tableau.basis[r] >= n_vars {
+            if let Some(c) = (0..n_vars).find(|c| tableau.at(r, *c).abs() > LP_EPSILON) {
+                tableau.pivot(r, c);
+            }
+        }
+    }
+
+    // ** phase 2: minimize the real objective, never letting an artificial variable re-enter
+    tableau.set_objective(&costs);
+    iterations += tableau.optimize(n_vars, max_iterations.saturating_sub(iterations).max(1), &deadline)?;
+
+    let mut x_std = vec![0.0; n_vars + rows];
+    for r in 0..rows {
+        x_std[tableau.basis[r]] = tableau.rhs(r);
+    }
+    let x = na::DVector::from_iterator(n, var_maps.iter().map(|var_map| match var_map {
+        VarMap::Shifted { col, lower } => lower + x_std[*col],
+        VarMap::Mirrored { col, upper } => upper - x_std[*col],
+        VarMap::Split { pos, neg } => x_std[*pos] - x_std[*neg],
+    }));
+
+    // ** the reduced cost of an artificial column is -y for its row, see the derivation of the
+    // ** simplex multipliers: r_j = c_j - yᵀA_j with A_j = e_i and c_j = 0
+    let shadow_prices = na::DVector::from_iterator(
+        m,
+        (0..m).map(|i| -tableau.at(rows, n_vars + i) * row_signs[i]),
+    );
+    let reduced_costs = &lp.objective - lp.eq_matrix.transpose() * &shadow_prices;
+
+    Ok(LpSolution {
+        objective_value: lp.objective.dot(&x),
+        x,
+        shadow_prices,
+        reduced_costs,
+        iterations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_solve_lp() {
+        // ** maximize x + y subject to x + 2y = 4, 0 <= x <= 3, 0 <= y
+        let lp = lp::LinearProgram {
+            objective: na::DVector::from_row_slice(&[-1.0, -1.0]),
+            eq_matrix: na::DMatrix::from_row_slice(1, 2, &[1.0, 2.0]),
+            eq_rhs: na::DVector::from_row_slice(&[4.0]),
+            lower: na::DVector::from_row_slice(&[0.0, 0.0]),
+            upper: na::DVector::from_row_slice(&[3.0, f64::INFINITY]),
+        };
+        let solution = lp::solve_lp(&lp, lp::LP_MAX_ITERATIONS).unwrap();
+        assert!(util::epsilon_eq(solution.x[0], 3.0, 1e-9));
+        assert!(util::epsilon_eq(solution.x[1], 0.5, 1e-9));
+        assert!(util::epsilon_eq(solution.objective_value, -3.5, 1e-9));
+        // ** relaxing the equality by one unit changes the objective by the shadow price
+        assert!(util::epsilon_eq(solution.shadow_prices[0], -0.5, 1e-9));
+    }
+
+    #[test]
+    fn test_solve_lp_free_variables_and_infeasible() {
+        // ** minimize x subject to x - y = -2, y in [0, 1], x free
+        let mut lp = lp::LinearProgram {
+            objective: na::DVector::from_row_slice(&[1.0, 0.0]),
+            eq_matrix: na::DMatrix::from_row_slice(1, 2, &[1.0, -1.0]),
+            eq_rhs: na::DVector::from_row_slice(&[-2.0]),
+            lower: na::DVector::from_row_slice(&[f64::NEG_INFINITY, 0.0]),
+            upper: na::DVector::from_row_slice(&[f64::INFINITY, 1.0]),
+        };
+        let solution = lp::solve_lp(&lp, lp::LP_MAX_ITERATIONS).unwrap();
+        assert!(util::epsilon_eq(solution.x[0], -2.0, 1e-9));
+
+        lp.lower[0] = 0.0;
+        assert!(matches!(lp::solve_lp(&lp, lp::LP_MAX_ITERATIONS), Err(error::SmasError::Infeasible)));
+    }
+}
@@ -1,11 +1,68 @@
-use clap::{arg, App, value_parser, Command, AppSettings};
-use smas;
+use clap::{arg, App, ArgAction, ArgMatches, value_parser, Command, AppSettings, ValueSource};
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+
+// ** with mem-report, every allocation is counted so that --mem-report can size the phases of a solve
+#[cfg(feature = "mem-report")]
+#[global_allocator]
+static ALLOCATOR: smas::memory::CountingAllocator = smas::memory::CountingAllocator;
+
+/// The environment variables that configure the shared options, as (variable, argument id). They
+/// sit between the built-in defaults and the command line: a variable replaces the default, and a
+/// flag given on the command line replaces the variable.
+const ENVIRONMENT: [(&str, &str); 11] = [
+    ("SMAS_MATRIX", "matrix_path"),
+    ("SMAS_EQUATIONS", "equations_path"),
+    ("SMAS_EPSILON", "epsilon"),
+    ("SMAS_FLOAT_PRECISION", "float_precision"),
+    ("SMAS_FLOAT_FORMAT", "float_format"),
+    ("SMAS_PARSE", "parse"),
+    ("SMAS_FORMATTER", "formatter"),
+    ("SMAS_PATTERN_VALUE", "pattern_value"),
+    ("SMAS_METABOLITE_LABELS", "metabolite_labels_path"),
+    ("SMAS_REACTION_LABELS", "reaction_labels_path"),
+    ("SMAS_SUMMATION", "summation"),
+];
+
+/// The help for ENVIRONMENT, shown after the options of each subcommand.
+const ENVIRONMENT_HELP: &str = "ENVIRONMENT:\nSMAS_MATRIX (-s), SMAS_EQUATIONS (--equations), SMAS_EPSILON (-e), SMAS_FLOAT_PRECISION (-p), \
+    SMAS_FLOAT_FORMAT (-f), SMAS_PARSE (--parse), SMAS_FORMATTER (--formatter), SMAS_PATTERN_VALUE (--pattern-value), \
+    SMAS_METABOLITE_LABELS (--metabolite-labels), SMAS_REACTION_LABELS (--reaction-labels) and SMAS_SUMMATION \
+    (--summation) replace the defaults of their options; options given on the command line replace them.";
+
+/// This makes each environment variable in ENVIRONMENT that is set and not empty the default of its
+/// argument. They are defaults rather than clap's env values so that they never conflict with flags
+/// given on the command line, e.g. SMAS_MATRIX with --equations, or SMAS_FLOAT_FORMAT with
+/// --reproducible.
+fn add_environment_defaults(mut app: App) -> App {
+    for (variable, id) in ENVIRONMENT {
+        let Some(value) = std::env::var(variable).ok().filter(|value| !value.is_empty()) else {
+            continue;
+        };
+        // ** the value is checked with the argument's own parser, as clap only checks defaults in debug builds
+        let value_parser = app.get_arguments().find(|arg| arg.get_id() == id).unwrap().get_value_parser().clone();
+        Command::new(variable)
+            .arg(clap::Arg::new(variable).allow_hyphen_values(true).value_parser(value_parser))
+            .try_get_matches_from([variable, value.as_str()])
+            .unwrap_or_else(|e| panic!("{}", e.to_string().lines().next().unwrap_or_default().trim_start_matches("error: ")));
+        // ** clap keeps borrowed defaults, and each variable is read once per subcommand
+        let value: &'static str = Box::leak(value.into_boxed_str());
+        app = app.mut_arg(id, |arg| arg.default_value(value));
+    }
+    app
+}
 
 fn add_common_args(app: App) -> App {
-    app.arg(
-        arg!(-s <matrix_path> "The path to a stoichiometric matrix file in the Matrix Market array format.")
+    let app = app.after_help(ENVIRONMENT_HELP).arg(
+        arg!(-s <matrix_path> "The path to a stoichiometric matrix file in the Matrix Market array or coordinate format.")
             .required(false)
     )
+        .arg(
+            arg!(--equations <equations_path> "The path to a file of reaction equations, e.g. \"hk: glc + atp -> g6p + adp\", to build the stoichiometric matrix from. A KEGG KGML or BioPAX pathway file is imported instead (see import-pathway).")
+                .id("equations_path")
+                .required(false)
+                .conflicts_with("matrix_path")
+        )
         .arg(
             arg!(-o <out_path> "The path to the output (printed to stdout by default).")
                 .required(false)
@@ -27,104 +84,3860 @@ fn add_common_args(app: App) -> App {
                 .help("Adjust the formatting of floating point numbers in the output.")
                 .required(false)
                 .default_value("scientific")
-                .value_parser(["scientific", "decimal"])
+                .value_parser(["scientific", "decimal", "canonical"])
+        )
+        .arg(
+            arg!(--parse <mode>)
+                .help("Whether deviations from the Matrix Market array format in input files are errors (strict) or accepted (lenient).")
+                .required(false)
+                .default_value("lenient")
+                .value_parser(["strict", "lenient"])
+        )
+        .arg(
+            arg!(--formatter <name> "The format of vector and matrix outputs: mm (Matrix Market array), tsv, \
+                keyed (label=value pairs of the nonzero entries, as -a reads them), or a format registered by a program \
+                embedding smas.")
+                .required(false)
+                .default_value(smas::formatter::DEFAULT_FORMATTER)
+        )
+        .arg(
+            arg!(--"pattern-value" <value> "The value of every entry listed in a Matrix Market pattern matrix file.")
+                .id("pattern_value")
+                .required(false)
+                .default_value("1.0")
+                .allow_hyphen_values(true)
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"metabolite-labels" <path> "The path to a labels file naming each metabolite (matrix row), one \"index name\" row per metabolite.")
+                .id("metabolite_labels_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--"reaction-labels" <path> "The path to a labels file naming each reaction (matrix column), one \"index name\" row per reaction.")
+                .id("reaction_labels_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--transpose "Transpose the stoichiometric matrix after loading it, e.g. if its rows are reactions. \
+                Vectors are read from a single row or a single column alike, so they never need transposing.")
+        )
+        .arg(
+            arg!(--"auto-transpose" "Transpose the stoichiometric matrix if only its transpose fits the accumulation vector, \
+                rather than stopping with an error.")
+                .id("auto_transpose")
+        )
+        .arg(
+            arg!(--summation <method> "How sums in statistics and residuals are accumulated: naive (fastest), \
+                compensated (Neumaier, the default) or pairwise. Choose one explicitly to reproduce results exactly.")
+                .required(false)
+                .value_parser(["naive", "compensated", "pairwise"])
+                .default_value("compensated")
+        )
+        .arg(
+            arg!(--reproducible "Make the output the same for identical inputs on the same machine and build: \
+                floats are written canonically (-f canonical) with the Matrix Market formatter, and sums use \
+                compensated summation, which does not depend on the simd feature. Matrix products pick CPU specific \
+                kernels at runtime, so the last digits may still differ between machines.")
+                .conflicts_with_all(&["float_format", "formatter", "summation"])
+        )
+        .arg(
+            arg!(--force "Overwrite output files that already exist.")
+                .conflicts_with("backup")
+        )
+        .arg(
+            arg!(--backup "Rename output files that already exist to <name>.bak before writing.")
+        )
+        .arg(
+            arg!(--"dry-run" "Check the inputs, and describe their formats and dimensions, what would run and an estimate \
+                of its memory, without computing or writing anything, e.g. before starting a long genome-scale job.")
+                .id("dry_run")
         )
+        .arg(
+            arg!(--"profile-json" <path> "Record how long loading, factorizing, solving, formatting and writing take, and \
+                write the spans to a Chrome trace file, which chrome://tracing, Perfetto or Speedscope can open.")
+                .id("profile_json")
+                .required(false)
+        );
+    add_environment_defaults(app)
 }
 
-fn main() {
-    let mut solve_command = Command::new("solve")
-        .about("Solve for a reaction vector given an accumulation vector")
+/// This returns the path of the stoichiometric matrix, and whether it is a reaction equations
+/// file rather than a Matrix Market file. A path given on the command line wins over one
+/// configured by the environment.
+fn get_matrix_source(matches: &ArgMatches) -> Option<(&String, bool)> {
+    let matrix = matches.get_one::<String>("matrix_path").map(|path| (path, false));
+    let equations = matches.get_one::<String>("equations_path").map(|path| (path, true));
+    match (matrix, equations) {
+        (Some(_), Some(equations)) if matches.value_source("equations_path") == Some(ValueSource::CommandLine) => Some(equations),
+        (matrix, equations) => matrix.or(equations),
+    }
+}
+
+/// This adds the -a argument of the subcommands that read an accumulation vector.
+fn add_accumulation_args(app: App) -> App {
+    app.arg(
+        arg!(-a <accumulation_string> "Optionally, provide the input accumulation vector via stdin. \
+            The vector should be enclosed in quotes and whitespace delimited, \
+            e.g. \"0.0 1e5 0.5 0.3 0.0 ...\", or give metabolites by label, \
+            e.g. \"glc=-1.2 o2=-0.8 co2=2.3\", with the others 0.")
+            .required(false)
+    )
+}
+
+/// This adds the --dg0 and --temperature arguments of the subcommands that constrain reaction
+/// directions with thermodynamics.
+fn add_thermo_args(app: App) -> App {
+    app.arg(
+        arg!(--dg0 <dg0_path> "The path to a Matrix Market array file with the standard Gibbs energy of each reaction (dG0', kJ/mol, NaN if unknown). \
+            Reactions that are irreversible for metabolite concentrations between 1 uM and 10 mM are constrained to their feasible direction.")
+            .id("dg0_path")
+            .required(false)
+    )
+        .arg(
+            arg!(--temperature <kelvin> "The temperature in Kelvin used with --dg0.")
+                .required(false)
+                .default_value("298.15")
+                .value_parser(value_parser!(f64))
+        )
+}
+
+/// This reads the --reproducible flag shared by the subcommands.
+fn is_reproducible(matches: &ArgMatches) -> bool {
+    matches.contains_id("reproducible")
+}
+
+/// This writes the intermediate results of a pseudoinverse solve to a directory, one file each:
+/// U, the singular values, V, the pseudoinverse and the projection of B onto the column space.
+fn write_intermediates(dir: &Path, acc_vector: &na::DVector<f64>, s_matrix: &na::DMatrix<f64>, matches: &ArgMatches) {
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap() as usize;
+    let overwrite_policy = get_overwrite_policy(matches);
+    let formatter = get_formatter(matches);
+    let steps = smas::explain::intermediates(acc_vector, s_matrix)
+        .unwrap_or_else(|e| panic!("failed to compute the intermediate results: {e}"));
+
+    let provenance = |title: &str| {
+        let mut provenance = smas::io::Provenance::new(title, "solve");
+        add_matrix_provenance(&mut provenance, matches);
+        provenance
+    };
+    let outputs = [
+        ("U.txt", formatter.format_matrix(&steps.u, float_format, float_precision, &provenance("smas left singular vectors U (m x k)"))),
+        ("sigma.txt", formatter.format_vector(&steps.singular_values, float_format, float_precision, &provenance("smas singular values, the diagonal of Sigma (k x 1)"))),
+        ("V.txt", formatter.format_matrix(&steps.v, float_format, float_precision, &provenance("smas right singular vectors V (n x k)"))),
+        ("pinv.txt", formatter.format_matrix(&steps.pseudo_inverse, float_format, float_precision, &provenance("smas pseudoinverse of S (n x m)"))),
+        ("projection.txt", formatter.format_vector(&steps.projection, float_format, float_precision, &provenance("smas projection of B onto the column space of S (m x 1)"))),
+    ];
+    for (name, contents) in outputs {
+        smas::io::write_output_file(dir.join(name), format!("{contents}\n"), overwrite_policy)
+            .unwrap_or_else(|e| panic!("failed to write {name}: {e}"));
+    }
+}
+
+/// This writes the residual of each metabolite, Sx - a, for --residuals-out, labeled with the
+/// metabolite labels if there are any.
+fn write_residuals(
+    path: &str,
+    residuals: &na::DVector<f64>,
+    metabolite_labels: Option<Vec<String>>,
+    input: String,
+    matches: &ArgMatches,
+) {
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap() as usize;
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let mut provenance = smas::io::Provenance::new("smas residual vector Sx - a (metabolites)", "solve");
+    provenance.row_labels = metabolite_labels;
+    provenance.inputs.push(input);
+    add_matrix_provenance(&mut provenance, matches);
+    let residuals_matrix = na::DMatrix::from_column_slice(residuals.len(), 1, residuals.as_slice());
+    if !write_arrow_output(&residuals_matrix, "metabolite", "M", vec![String::from("residual")], &provenance, Some(path), overwrite_policy) {
+        let results = get_formatter(matches).format_vector(residuals, float_format, float_precision, &provenance);
+        write_output(&results, Some(path), overwrite_policy);
+    }
+}
+
+/// This adds the arguments that preprocess the accumulation vector before it is solved. Each can be
+/// given several times, and the steps are applied in the order they are given.
+fn add_preprocessing_args(app: App) -> App {
+    app.arg(
+        arg!(--add <path> "Add a vector file to the accumulation vector before solving.")
+            .id("add_path")
+            .required(false)
+            .action(ArgAction::Append)
+    )
+        .arg(
+            arg!(--subtract <path> "Subtract a vector file, e.g. the accumulation of a control condition, from the \
+                accumulation vector before solving.")
+                .id("subtract_path")
+                .required(false)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            arg!(--scale <factor> "Multiply the accumulation vector by a factor before solving.")
+                .required(false)
+                .allow_hyphen_values(true)
+                .value_parser(value_parser!(f64))
+                .action(ArgAction::Append)
+        )
+        .arg(
+            arg!(--units <unit> "Convert vector files that declare their units with a \"%units: umol/min\" comment to these \
+                units, e.g. mmol/h, before solving. Without --units, files that declare different units are an error.")
+                .required(false)
+        )
         .arg(
-            arg!(<accumulation_path> "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+            arg!(--"acc-scale" <factor> "Multiply the accumulation vector by a conversion factor right after loading it, \
+                before any other preprocessing, for units that cannot be declared.")
+                .id("acc_scale")
                 .required(false)
+                .value_parser(value_parser!(f64))
         )
+}
+
+/// This adds the arguments of the subcommands that run REPL commands: the model to start from.
+fn add_repl_args(app: App) -> App {
+    app.arg(
+        arg!([accumulation_path] "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+    )
         .arg(
             arg!(-a <accumulation_string> "Optionally, provide the input accumulation vector via stdin. \
                 The vector should be enclosed in quotes and whitespace delimited, \
-                e.g. \"0.0 1e5 0.5 0.3 0.0 ...\"")
+                e.g. \"0.0 1e5 0.5 0.3 0.0 ...\", or give metabolites by label, \
+                e.g. \"glc=-1.2 o2=-0.8 co2=2.3\", with the others 0.")
                 .required(false)
-        );
+        )
+        .arg(
+            arg!(--session <session_path> "The path to a session file saved with save-session, to resume curating \
+                instead of loading a matrix and accumulation vector.")
+                .id("session_path")
+                .required(false)
+                .conflicts_with_all(&["accumulation_path", "accumulation_string", "matrix_path", "equations_path"])
+        )
+}
 
-    let mut validate_command = Command::new("validate")
-        .about("A set of utilities designed to help validate computed results using ground truth data")
+/// This adds the arguments of the subcommands that line up models from different sources: an
+/// identifier table, and the namespace to translate labels to.
+fn add_id_map_args(app: App) -> App {
+    app.arg(
+        arg!(--"id-map" <id_map_path> "The path to a table of the identifiers of reactions and metabolites in several \
+            namespaces, e.g. a header \"bigg kegg metacyc\" and then one row per reaction or metabolite, with \"-\" for a \
+            missing identifier. Labels are translated to one namespace before they are compared.")
+            .id("id_map_path")
+            .required(false)
+    )
         .arg(
-            arg!(-r <reactions_path> "The path to a stoichiometric reaction vector file in the Matrix Market array format. \
-                        If provided, smas will compare the vector to the computed solution.")
+            arg!(--"id-namespace" <namespace> "The namespace of the identifier table to translate labels to, e.g. bigg. \
+                Defaults to the first column of the table.")
+                .id("id_namespace")
                 .required(false)
-        );
+                .requires("id_map_path")
+        )
+}
 
-    solve_command = add_common_args(solve_command);
-    validate_command = add_common_args(validate_command);
+/// This reads the identifier table given with --id-map, if any, and the namespace to translate to.
+fn get_id_map(matches: &ArgMatches) -> Option<(smas::id_map::IdMap, String)> {
+    let path = matches.get_one::<String>("id_map_path")?;
+    let id_map = smas::id_map::load_id_map(path)
+        .unwrap_or_else(|e| panic!("failed to load identifier table file {path}: {e}"));
+    let namespace = match matches.get_one::<String>("id_namespace") {
+        Some(namespace) => namespace.clone(),
+        None => id_map.namespaces.first().cloned()
+            .unwrap_or_else(|| panic!("the identifier table {path} names no namespaces")),
+    };
+    Some((id_map, namespace))
+}
 
-    let matches = App::new("smas")
-        .version("0.1.0")
-        .author("Jack Roddy <jack.w.roddy@gmail.com>")
-        .about("A simple tool to help with finding a solution to a particular stoichiometric matrix equation")
-        .setting(AppSettings::SubcommandRequiredElseHelp)
-        .set_term_width(80)
-        .subcommand(solve_command)
-        .subcommand(validate_command)
-        .get_matches();
-
-    match matches.subcommand_name() {
-        Some("solve") => {
-            let matches = matches.subcommand_matches("solve").unwrap();
-            let accumulation_path = matches.get_one::<String>("accumulation_path");
-            let accumulation_string = matches.get_one::<String>("accumulation_string");
-            let matrix_path = matches.get_one::<String>("matrix_path");
-            let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
-            let float_format = if let Some(format) = matches.get_one::<String>("float_format") {
-                match format.as_str() {
-                    "scientific" => smas::io::FloatFormat::Scientific,
-                    "decimal" => smas::io::FloatFormat::Decimal,
-                    _ => unreachable!()
-                }
-            } else {
-                smas::io::FloatFormat::Scientific
-            };
+/// This translates the labels of a matrix read from `path` with an identifier table, and writes
+/// what could not be translated to stderr.
+fn translate_labels(matrix: &mut smas::io::LabeledMatrix, id_map: &smas::id_map::IdMap, namespace: &str, path: &str) {
+    let (rows, columns) = id_map.translate_matrix(matrix, namespace)
+        .unwrap_or_else(|e| panic!("failed to translate the labels of {path}: {e}"));
+    for report in [rows.format_report(&format!("rows of {path}"), namespace), columns.format_report(&format!("columns of {path}"), namespace)] {
+        if !report.is_empty() {
+            eprintln!("{report}");
+        }
+    }
+}
+
+/// This reads the units given with --units, if any.
+fn get_target_units(matches: &ArgMatches) -> Option<smas::units::Unit> {
+    matches.get_one::<String>("units").map(|unit| {
+        unit.parse().unwrap_or_else(|e| panic!("failed to read --units: {e}"))
+    })
+}
+
+/// This reads the units a vector file declares, if any.
+fn get_declared_units(path: &str) -> Option<smas::units::Unit> {
+    smas::io::load_declared_units(path).unwrap_or_else(|e| panic!("failed to read the units of {path}: {e}"))
+}
 
-            let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
-            let a_vector = match accumulation_path {
-                Some(path) => smas::io::load_vector(path)
-                    .expect("failed to load accumulation vector file"),
-                None =>
-                    match accumulation_string {
-                        Some(vector_string) => smas::io::parse_vector(vector_string),
-                        None => panic!()
-                    }
+/// This checks that the vector files of a subcommand can be combined: without --units, every
+/// file that declares units must declare the same ones, so that vectors in different units are
+/// never added or subtracted silently.
+fn check_declared_units(matches: &ArgMatches) {
+    if matches.contains_id("units") {
+        return;
+    }
+    let mut first: Option<(&String, smas::units::Unit)> = None;
+    for id in ["accumulation_path", "replicates", "add_path", "subtract_path"] {
+        for path in matches.try_get_many::<String>(id).ok().flatten().into_iter().flatten() {
+            let Some(unit) = get_declared_units(path) else {
+                continue;
             };
+            match &first {
+                Some((first_path, first_unit)) if *first_unit != unit => panic!(
+                    "{first_path} is in {first_unit}, but {path} is in {unit}; use --units to convert them to the same units"
+                ),
+                Some(_) => {}
+                None => first = Some((path, unit)),
+            }
+        }
+    }
+}
+
+/// This converts a vector loaded from a file from the units the file declares to the units given
+/// with --units, and describes the conversion, e.g. "umol/min -> mmol/h, x0.06". Vectors of
+/// files that are already in those units, or declare none, are unchanged.
+fn convert_units(vector: na::DVector<f64>, path: &str, matches: &ArgMatches) -> (na::DVector<f64>, Option<String>) {
+    match (get_declared_units(path), get_target_units(matches)) {
+        (Some(from), Some(to)) if from != to => {
+            let factor = from.conversion_factor(&to)
+                .unwrap_or_else(|e| panic!("failed to convert the units of {path}: {e}"));
+            (vector * factor, Some(format!("{from} -> {to}, x{factor}")))
+        }
+        _ => (vector, None),
+    }
+}
 
-            let s_matrix = match matrix_path {
-                Some(path) => smas::io::load_matrix(path)
-                    .expect("failed to load custom stoichiometric matrix file"),
-                None => smas::util::default_s_matrix()
+/// This reads the preprocessing steps given with --add, --subtract and --scale, in the order they
+/// were given on the command line, along with a description of each for the provenance.
+fn get_preprocessing(matches: &ArgMatches, parse_mode: smas::io::ParseMode) -> (Vec<smas::util::Preprocess>, Vec<String>) {
+    let mut steps: Vec<(usize, smas::util::Preprocess, String)> = vec!();
+    for (id, name) in [("add_path", "add"), ("subtract_path", "subtract")] {
+        let (Some(paths), Some(indices)) = (matches.get_many::<String>(id), matches.indices_of(id)) else {
+            continue;
+        };
+        for (path, index) in paths.zip(indices) {
+            let vector = smas::io::load_vector_with_mode(path, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to load the vector file to {name}: {e}"));
+            let (vector, conversion) = convert_units(vector, path, matches);
+            let description = match conversion {
+                Some(conversion) => format!("{name} {path} ({conversion})"),
+                None => format!("{name} {path}"),
+            };
+            let step = match name {
+                "add" => smas::util::Preprocess::Add(vector),
+                _ => smas::util::Preprocess::Subtract(vector),
             };
+            steps.push((index, step, description));
+        }
+    }
+    if let (Some(factors), Some(indices)) = (matches.get_many::<f64>("scale"), matches.indices_of("scale")) {
+        for (factor, index) in factors.zip(indices) {
+            steps.push((index, smas::util::Preprocess::Scale(*factor), format!("scale {factor}")));
+        }
+    }
+    steps.sort_by_key(|(index, _, _)| *index);
+    steps.into_iter().map(|(_, step, description)| (step, description)).unzip()
+}
+
+/// This applies the preprocessing steps to an accumulation vector.
+fn preprocess_accumulation(acc_vector: na::DVector<f64>, steps: &[smas::util::Preprocess]) -> na::DVector<f64> {
+    smas::util::preprocess(acc_vector, steps)
+        .unwrap_or_else(|e| panic!("failed to preprocess the accumulation vector: {e}"))
+}
+
+/// This reads the float format argument shared by the subcommands.
+fn get_float_format(matches: &ArgMatches) -> smas::io::FloatFormat {
+    if is_reproducible(matches) {
+        return smas::io::FloatFormat::Canonical;
+    }
+    match matches.get_one::<String>("float_format").map(|s| s.as_str()) {
+        Some("decimal") => smas::io::FloatFormat::Decimal,
+        Some("canonical") => smas::io::FloatFormat::Canonical,
+        _ => smas::io::FloatFormat::Scientific,
+    }
+}
+
+/// This reads the parse mode argument shared by the subcommands.
+fn get_parse_mode(matches: &ArgMatches) -> smas::io::ParseMode {
+    match matches.get_one::<String>("parse").map(|s| s.as_str()) {
+        Some("strict") => smas::io::ParseMode::Strict,
+        _ => smas::io::ParseMode::Lenient,
+    }
+}
+
+/// This reads the summation method argument shared by the subcommands.
+fn get_summation(matches: &ArgMatches) -> smas::util::Summation {
+    if is_reproducible(matches) {
+        return smas::util::Summation::Compensated;
+    }
+    match matches.get_one::<String>("summation").map(|s| s.as_str()) {
+        Some("naive") => smas::util::Summation::Naive,
+        Some("pairwise") => smas::util::Summation::Pairwise,
+        _ => smas::util::Summation::Compensated,
+    }
+}
+
+/// This reads the --residual-norm of solve.
+fn get_residual_norm(matches: &ArgMatches) -> smas::solve::ResidualNorm {
+    match matches.get_one::<String>("residual_norm").map(|s| s.as_str()) {
+        Some("l1") => smas::solve::ResidualNorm::L1,
+        Some("linf") => smas::solve::ResidualNorm::LInf,
+        _ => smas::solve::ResidualNorm::L2,
+    }
+}
+
+/// This reads the overwrite policy arguments shared by the subcommands.
+fn get_overwrite_policy(matches: &ArgMatches) -> smas::io::OverwritePolicy {
+    if matches.contains_id("force") {
+        smas::io::OverwritePolicy::Force
+    } else if matches.contains_id("backup") {
+        smas::io::OverwritePolicy::Backup
+    } else {
+        smas::io::OverwritePolicy::Refuse
+    }
+}
+
+/// This writes the output of a subcommand to the given path, or stdout if there is none.
+fn write_output(output: &str, out_path: Option<&str>, policy: smas::io::OverwritePolicy) {
+    let _span = tracing::info_span!("write", path = %out_path.unwrap_or("stdout")).entered();
+    match out_path {
+        Some(path) => smas::io::write_output_file(path, format!("{output}\n"), policy)
+            .unwrap_or_else(|e| panic!("failed to write output file: {e}")),
+        None => println!("{output}"),
+    }
+}
+
+/// This writes a matrix as an Arrow IPC (Feather) table when the output path ends in .arrow,
+/// .feather or .ipc, and returns whether it did. Rows without labels are named with
+/// `row_prefix` and their number.
+fn write_arrow_output(
+    matrix: &na::DMatrix<f64>,
+    label_column: &str,
+    row_prefix: &str,
+    col_labels: Vec<String>,
+    provenance: &smas::io::Provenance,
+    out_path: Option<&str>,
+    policy: smas::io::OverwritePolicy,
+) -> bool {
+    let Some(path) = out_path.filter(|path| smas::arrow::is_arrow_path(path)) else {
+        return false;
+    };
+    let _span = tracing::info_span!("write", path = %path).entered();
+    let row_labels = provenance.row_labels.clone()
+        .unwrap_or_else(|| (1..=matrix.nrows()).map(|i| format!("{row_prefix}{i}")).collect());
+    let table = smas::arrow::matrix_table(matrix, label_column, row_labels, col_labels, provenance);
+    let bytes = smas::arrow::write_table(&table).unwrap_or_else(|e| panic!("failed to write output file: {e}"));
+    smas::io::write_output_file(path, bytes, policy)
+        .unwrap_or_else(|e| panic!("failed to write output file: {e}"));
+    true
+}
+
+/// This looks up the output formatter chosen with --formatter.
+fn get_formatter(matches: &ArgMatches) -> std::sync::Arc<dyn smas::formatter::OutputFormatter> {
+    let name = if is_reproducible(matches) {
+        smas::formatter::DEFAULT_FORMATTER
+    } else {
+        matches.get_one::<String>("formatter").unwrap()
+    };
+    let formatter = smas::formatter::get_formatter(name).unwrap_or_else(|e| panic!("failed to choose an output format: {e}"));
+    smas::formatter::profiled(formatter)
+}
+
+/// This reads the stoichiometric matrix of a subcommand, from a matrix file or from reaction
+/// equations, along with its metabolite (row) and reaction (column) names, if there are any.
+/// Names from --metabolite-labels and --reaction-labels take precedence over those in the file.
+fn get_labeled_s_matrix(matches: &ArgMatches, parse_mode: smas::io::ParseMode) -> smas::io::LabeledMatrix {
+    get_oriented_s_matrix(matches, parse_mode, None)
+}
 
-            let results_vector = smas::solve::solve(a_vector, s_matrix);
-            smas::util::print_matrix(&results_vector);
+/// This reads the labeled stoichiometric matrix like `get_labeled_s_matrix`, and checks its
+/// orientation against the length and declared orientation of the accumulation vector. A matrix
+/// that only fits when transposed is an error that suggests --transpose, or is transposed with
+/// --auto-transpose; a vector that does not fit either way is an error.
+fn get_oriented_s_matrix(
+    matches: &ArgMatches,
+    parse_mode: smas::io::ParseMode,
+    accumulation: Option<(usize, smas::io::VectorOrientation)>,
+) -> smas::io::LabeledMatrix {
+    let pattern_value = *matches.get_one::<f64>("pattern_value").unwrap();
+    let mut labeled = match get_matrix_source(matches) {
+        Some((path, false)) => smas::io::load_labeled_matrix(path, parse_mode, pattern_value)
+            .unwrap_or_else(|e| panic!("failed to load custom stoichiometric matrix file: {e}")),
+        Some((path, true)) => load_reaction_model(path).s_matrix,
+        None => smas::io::LabeledMatrix {
+            matrix: smas::util::default_s_matrix(),
+            row_labels: None,
+            col_labels: None,
+        },
+    };
+    if matches.contains_id("transpose") {
+        labeled = labeled.transpose();
+    }
+    if let Some((n_metabolites, _)) = accumulation.filter(|(n, _)| labeled.is_transposed(*n)) {
+        if !matches.contains_id("auto_transpose") {
+            panic!(
+                "the accumulation vector has {n_metabolites} values, but the stoichiometric matrix has {} rows and {} columns; \
+                it looks transposed, so rerun {} (or with --auto-transpose)",
+                labeled.matrix.nrows(),
+                labeled.matrix.ncols(),
+                if matches.contains_id("transpose") { "without --transpose" } else { "with --transpose" }
+            );
         }
-        Some("validate") => {
-            let validate_args = matches.subcommand_matches("validate").unwrap();
+        labeled = labeled.transpose();
+    }
+    if let Some((n_values, orientation)) = accumulation {
+        smas::io::check_vector_length(n_values, orientation, labeled.matrix.nrows())
+            .unwrap_or_else(|e| panic!("the accumulation vector does not fit the stoichiometric matrix: {e}"));
+    }
+    // ** label files name the metabolites and reactions of the matrix after any transpose
+    if let Some(path) = matches.get_one::<String>("metabolite_labels_path") {
+        labeled.row_labels = Some(smas::io::load_labels(path, labeled.matrix.nrows())
+            .unwrap_or_else(|e| panic!("failed to load metabolite labels file: {e}")));
+    }
+    if let Some(path) = matches.get_one::<String>("reaction_labels_path") {
+        labeled.col_labels = Some(smas::io::load_labels(path, labeled.matrix.ncols())
+            .unwrap_or_else(|e| panic!("failed to load reaction labels file: {e}")));
+    }
+    labeled
+}
+
+/// This reads the model of a reaction equations file given with --equations. A KGML or BioPAX
+/// pathway file is imported instead, and its import report is written to stderr.
+fn load_reaction_model(path: &str) -> smas::io::ReactionModel {
+    let contents = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to load reaction equations file: {e}"));
+    if !smas::pathway::is_xml(&contents) {
+        return smas::io::parse_reaction_equations(&contents)
+            .unwrap_or_else(|e| panic!("failed to load reaction equations file: {e}"));
+    }
+    let import = smas::pathway::import_pathway(&contents)
+        .unwrap_or_else(|e| panic!("failed to import pathway file {path}: {e}"));
+    eprintln!("{}", smas::pathway::format_import_report(&import));
+    import.model
+}
+
+/// This records where the stoichiometric matrix of a subcommand came from in the provenance.
+fn add_matrix_provenance(provenance: &mut smas::io::Provenance, matches: &ArgMatches) {
+    match get_matrix_source(matches).map(|(path, _)| path) {
+        Some(path) => {
+            if let Some(version) = get_model_version(path) {
+                provenance.parameters.push((String::from("model"), version));
+            }
+            provenance.inputs.push(path.clone());
         }
-        _ => unreachable!()
+        None => provenance.parameters.push((String::from("matrix"), String::from("default"))),
+    }
+}
+
+/// This reads the model version declared in the header of a file, if any.
+fn get_model_version(path: &str) -> Option<String> {
+    smas::io::load_model_version(path)
+        .unwrap_or_else(|e| panic!("failed to read the model version of {path}: {e}"))
+}
+
+/// This warns on stderr when files that are compared with each other declare different model
+/// versions, e.g. a computed reaction vector and a true one from another version of the model.
+/// Files without a version are not checked.
+fn warn_model_versions(paths: &[&String]) {
+    let versions: Vec<(&String, String)> = paths.iter()
+        .filter_map(|path| get_model_version(path).map(|version| (*path, version)))
+        .collect();
+    let Some((first_path, first_version)) = versions.first() else {
+        return;
+    };
+    for (path, version) in &versions[1..] {
+        if version != first_version {
+            eprintln!("% warning: {first_path} is from model {first_version}, but {path} is from model {version}");
+        }
+    }
+}
+
+/// This reads how replicates are combined.
+fn get_aggregate(matches: &ArgMatches) -> smas::replicates::Aggregate {
+    match matches.get_one::<String>("aggregate").map(|s| s.as_str()) {
+        Some("median") => smas::replicates::Aggregate::Median,
+        _ => smas::replicates::Aggregate::Mean,
+    }
+}
+
+/// This reads the outlier test replicates are screened with, if any.
+fn get_outlier_test(matches: &ArgMatches) -> Option<smas::replicates::OutlierTest> {
+    match matches.get_one::<String>("reject_outliers").map(|s| s.as_str()) {
+        Some("mad") => Some(smas::replicates::OutlierTest::Mad {
+            threshold: *matches.get_one::<f64>("mad_threshold").unwrap(),
+        }),
+        Some("grubbs") => Some(smas::replicates::OutlierTest::Grubbs {
+            alpha: *matches.get_one::<f64>("grubbs_alpha").unwrap(),
+        }),
+        _ => None,
+    }
+}
+
+/// This records the replicates a solve combined, and how, in the provenance.
+fn add_replicate_provenance(provenance: &mut smas::io::Provenance, matches: &ArgMatches) {
+    let Some(paths) = matches.get_many::<String>("replicates") else {
+        return;
+    };
+    let paths: Vec<&String> = paths.collect();
+    provenance.parameters.push((
+        String::from("replicates"),
+        format!("{} of {}", get_aggregate(matches).name(), paths.len()),
+    ));
+    match get_outlier_test(matches) {
+        Some(smas::replicates::OutlierTest::Mad { threshold }) => {
+            provenance.parameters.push((String::from("outliers"), format!("modified z-score > {threshold}")));
+        }
+        Some(smas::replicates::OutlierTest::Grubbs { alpha }) => {
+            provenance.parameters.push((String::from("outliers"), format!("Grubbs test at alpha {alpha}")));
+        }
+        None => {}
+    }
+    if matches.contains_id("weight_by_spread") {
+        provenance.parameters.push((String::from("weights"), String::from("1 / spread")));
+    }
+    provenance.inputs.extend(paths.into_iter().cloned());
+}
+
+/// The most metabolite labels the accumulation vector prompt lists.
+const PROMPT_MAX_LABELS: usize = 20;
+
+/// This reads the accumulation vector pasted on the system clipboard, for --from-clipboard, e.g. a
+/// column copied from a spreadsheet.
+#[cfg(feature = "clipboard")]
+fn clipboard_accumulation_vector() -> na::DVector<f64> {
+    let values = smas::clipboard::read_values()
+        .unwrap_or_else(|e| panic!("failed to read an accumulation vector from the clipboard: {e}"));
+    na::DVector::from_vec(values)
+}
+
+/// This copies a matrix of results to the system clipboard, for --to-clipboard, as tab separated
+/// rows under a header row, each starting with its label, so that they paste into spreadsheet
+/// cells. Rows without labels are named with `row_prefix` and their number; the provenance is
+/// left out.
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(
+    matrix: &na::DMatrix<f64>,
+    label_column: &str,
+    row_prefix: &str,
+    col_labels: Vec<String>,
+    provenance: &smas::io::Provenance,
+    float_format: smas::io::FloatFormat,
+    float_precision: usize,
+) {
+    let mut table = format!("{label_column}\t{}\n", col_labels.join("\t"));
+    for (i, row) in matrix.row_iter().enumerate() {
+        match &provenance.row_labels {
+            Some(labels) => table.push_str(&labels[i]),
+            None => table.push_str(&format!("{row_prefix}{}", i + 1)),
+        }
+        for value in row.iter() {
+            table.push('\t');
+            table.push_str(&smas::io::format_float(*value, float_format, float_precision));
+        }
+        table.push('\n');
+    }
+    smas::clipboard::write(&table).unwrap_or_else(|e| panic!("failed to copy the results to the clipboard: {e}"));
+    eprintln!("% copied {} rows to the clipboard", matrix.nrows());
+}
+
+/// An accumulation vector read at the prompt: the path of the file it came from, if one was typed,
+/// and the vector with its orientation.
+type PromptedVector = (Option<String>, (na::DVector<f64>, smas::io::VectorOrientation));
+
+/// This asks for the accumulation vector on the terminal when none was given, rather than failing:
+/// the values can be pasted, separated by spaces, commas or new lines, or read from a file whose
+/// path is typed. Invalid input is explained and asked for again. Returns None if stdin or stderr
+/// is not a terminal, e.g. in a script, or if stdin ends first; otherwise the vector, converted to
+/// the --units.
+fn prompt_accumulation_vector(
+    matches: &ArgMatches,
+    parse_mode: smas::io::ParseMode,
+) -> Option<PromptedVector> {
+    use std::io::{BufRead, IsTerminal};
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let m = s_matrix.matrix.nrows();
+    eprintln!("% no accumulation vector was given, so it is read here; the model has {m} metabolites");
+    if s_matrix.row_labels.is_some() {
+        let labels: Vec<String> = (0..m.min(PROMPT_MAX_LABELS)).map(|i| s_matrix.row_label(i)).collect();
+        let more = if m > PROMPT_MAX_LABELS { ", ..." } else { "" };
+        eprintln!("% in the order {}{more}", labels.join(", "));
+    }
+    eprintln!("% paste {m} values separated by spaces, commas or new lines, or type the path of a vector file:");
+
+    let mut values: Vec<f64> = vec!();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.ok()?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if values.is_empty() && Path::new(trimmed).is_file() {
+            match smas::io::load_oriented_vector(trimmed, parse_mode) {
+                Ok((vector, _)) if vector.nrows() != m => {
+                    eprintln!("% {trimmed} has {} values, but the model has {m} metabolites; try again:", vector.nrows());
+                }
+                Ok((vector, orientation)) => {
+                    return Some((Some(trimmed.to_string()), (convert_units(vector, trimmed, matches).0, orientation)));
+                }
+                Err(e) => eprintln!("% failed to read {trimmed}: {e}; try again:"),
+            }
+            continue;
+        }
+        match smas::io::parse_pasted_values(trimmed) {
+            Ok(line_values) => values.extend(line_values),
+            Err(smas::error::SmasError::Parse { message, .. }) => {
+                eprintln!("% {message}; paste all {m} values again:");
+                values.clear();
+                continue;
+            }
+            Err(e) => panic!("failed to read the accumulation vector: {e}"),
+        }
+        if values.len() > m {
+            eprintln!("% that is {} values, but the model has {m} metabolites; paste them again:", values.len());
+            values.clear();
+        } else if values.len() == m {
+            return Some((None, (na::DVector::from_vec(values), smas::io::VectorOrientation::Column)));
+        } else if !values.is_empty() {
+            eprintln!("% {} of {m} values so far", values.len());
+        }
+    }
+    None
+}
+
+/// This parses the accumulation vector given with -a: whitespace delimited values in the order of
+/// the metabolites, or label=value pairs, e.g. "glc=-1.2 co2=2.3", with the metabolites that are
+/// not given at 0. The labels are those of the model, or M1, M2, ... if it has none.
+fn parse_accumulation_string(vector_string: &str, matches: &ArgMatches, parse_mode: smas::io::ParseMode) -> na::DVector<f64> {
+    if !smas::io::is_keyed_vector(vector_string) {
+        return smas::io::parse_vector(vector_string);
+    }
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let labels: Vec<String> = (0..s_matrix.matrix.nrows()).map(|i| s_matrix.row_label(i)).collect();
+    smas::io::parse_keyed_vector(vector_string, &labels)
+        .unwrap_or_else(|e| panic!("failed to parse the accumulation vector given with -a: {e}"))
+}
+
+/// This reads the accumulation vector of a subcommand from its positional path, or from -a, along
+/// with the orientation it was declared with, converts it to the --units, and applies --acc-scale
+/// and any preprocessing steps. Vectors given with -a are columns.
+fn get_accumulation_vector(
+    matches: &ArgMatches,
+    parse_mode: smas::io::ParseMode,
+) -> (na::DVector<f64>, smas::io::VectorOrientation) {
+    check_declared_units(matches);
+    let (steps, _) = get_preprocessing(matches, parse_mode);
+    let (acc_vector, orientation) = match (matches.get_one::<String>("accumulation_path"), matches.get_one::<String>("accumulation_string")) {
+        (Some(path), _) => {
+            let (acc_vector, orientation) = smas::io::load_oriented_vector(path, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to load accumulation vector file: {e}"));
+            (convert_units(acc_vector, path, matches).0, orientation)
+        }
+        (None, Some(vector_string)) => (
+            parse_accumulation_string(vector_string, matches, parse_mode),
+            smas::io::VectorOrientation::Column,
+        ),
+        (None, None) => prompt_accumulation_vector(matches, parse_mode)
+            .map(|(_, vector)| vector)
+            .unwrap_or_else(|| panic!("an accumulation vector must be provided as a path or with -a")),
+    };
+    let acc_scale = matches.get_one::<f64>("acc_scale").copied().unwrap_or(1.0);
+    (preprocess_accumulation(acc_vector * acc_scale, &steps), orientation)
+}
+
+/// This derives reaction direction bounds from a file of dG0' values.
+fn load_direction_bounds(
+    path: &str,
+    s_matrix: &na::DMatrix<f64>,
+    temperature: f64,
+    parse_mode: smas::io::ParseMode,
+) -> smas::constraints::Bounds {
+    let dg0 = smas::io::load_vector_with_mode(path, parse_mode)
+        .unwrap_or_else(|e| panic!("failed to load dG0 file: {e}"));
+    if dg0.nrows() != s_matrix.ncols() {
+        panic!("the dG0 file has {} values but the matrix has {} reactions", dg0.nrows(), s_matrix.ncols());
+    }
+    let directions = smas::thermo::reaction_directions(
+        &dg0,
+        s_matrix,
+        temperature,
+        smas::thermo::DEFAULT_MIN_CONCENTRATION,
+        smas::thermo::DEFAULT_MAX_CONCENTRATION,
+    );
+    smas::thermo::direction_bounds(&directions)
+}
+
+/// The options that --out-of-core and --sparse cannot be combined with, because they need the
+/// dense matrix or its labels, or solve several vectors at once.
+const LSQR_CONFLICTS: &[&str] = &[
+    "equations_path", "transpose", "auto_transpose", "metabolite_labels_path", "replicates", "fuse",
+    "dg0_path", "bounds_path", "flux_measurements_path", "prior_path", "loopless", "explain", "dump_intermediates_path",
+    "constraint_report", "audit_numerics", "out_template", "merge_suffix", "check_consistency", "integerize",
+];
+
+/// This reads the path of the matrix file that --out-of-core or --sparse solves against, and
+/// checks that a single accumulation vector was given.
+fn get_lsqr_matrix_path<'a>(matches: &'a ArgMatches, flag: &str) -> &'a String {
+    let matrix_path = get_matrix_source(matches).filter(|(_, equations)| !*equations).map(|(path, _)| path)
+        .unwrap_or_else(|| panic!("{flag} needs the path of a matrix file (-s)"));
+    if matches.get_many::<String>("accumulation_path").map_or(0, |paths| paths.len()) > 1 {
+        panic!("{flag} solves a single accumulation vector");
     }
+    matrix_path
+}
+
+/// This solves with LSQR against a matrix that stays on disk, reading a memory mapped cache of its
+/// nonzero entries twice per iteration, for systems whose matrix does not fit in memory.
+fn run_solve_out_of_core(matches: &ArgMatches) {
+    let matrix_path = get_lsqr_matrix_path(matches, "--out-of-core");
+    let s_matrix = smas::out_of_core::StreamedMatrix::open(
+        matrix_path,
+        get_parse_mode(matches),
+        *matches.get_one::<f64>("pattern_value").unwrap(),
+    ).unwrap_or_else(|e| panic!("failed to read the stoichiometric matrix file: {e}"));
+    let nnz = s_matrix.nnz();
+    run_solve_lsqr(matches, &s_matrix, nnz, ("out of core", "streamed"));
+}
+
+/// This solves with LSQR against the matrix file loaded as a sparse matrix, for large models
+/// whose dense matrix would be mostly zeros.
+fn run_solve_sparse(matches: &ArgMatches) {
+    let matrix_path = get_lsqr_matrix_path(matches, "--sparse");
+    let s_matrix = smas::io::load_sparse_matrix(
+        matrix_path,
+        get_parse_mode(matches),
+        *matches.get_one::<f64>("pattern_value").unwrap(),
+    ).unwrap_or_else(|e| panic!("failed to load stoichiometric matrix file: {e}"));
+    let nnz = s_matrix.nnz();
+    run_solve_lsqr(matches, &s_matrix, nnz, ("sparse", "sparse"));
+}
+
+/// This decides whether a solve that did not ask for --sparse should use it anyway: the matrix
+/// file is a large coordinate file with few nonzero entries, and no option needs the dense matrix.
+fn prefer_sparse_solve(matches: &ArgMatches) -> bool {
+    #[cfg(feature = "rhai")]
+    if matches.contains_id("post_script_path") {
+        return false;
+    }
+    #[cfg(feature = "clipboard")]
+    if matches.contains_id("from_clipboard") || matches.contains_id("to_clipboard") {
+        return false;
+    }
+    if LSQR_CONFLICTS.iter().any(|id| matches.contains_id(id))
+        || !matches!(matches.get_one::<String>("solver").map(|s| s.as_str()), Some("pinv" | "lsqr" | "auto"))
+        || get_residual_norm(matches) != smas::solve::ResidualNorm::L2
+        || matches.get_many::<String>("accumulation_path").map_or(0, |paths| paths.len()) > 1
+    {
+        return false;
+    }
+    let Some((matrix_path, false)) = get_matrix_source(matches) else {
+        return false;
+    };
+    match smas::io::read_coordinate_shape(matrix_path) {
+        Ok(Some((nrows, ncols, nnz))) => smas::sparse::prefer_sparse(nrows, ncols, nnz),
+        _ => false,
+    }
+}
+
+/// This describes where the single accumulation vector of a solve came from, for the provenance.
+fn accumulation_input(matches: &ArgMatches) -> String {
+    match matches.get_one::<String>("accumulation_path") {
+        Some(path) => path.clone(),
+        None => String::from("-a (accumulation string)"),
+    }
+}
+
+/// This prints the residual norm of a solution, ||Sx - a||, for --report-residual, and warns
+/// when the solution does not reproduce the accumulation vector within epsilon.
+fn print_residual_norm(residual_norm: f64, acc_vector: &na::DVector<f64>, epsilon: f64) {
+    let acc_norm = acc_vector.norm();
+    let relative = if acc_norm == 0.0 { residual_norm } else { residual_norm / acc_norm };
+    eprintln!("% residual norm ||Sx - a||: {residual_norm:e} (relative to ||a||: {relative:e})");
+    if residual_norm > epsilon {
+        eprintln!("% warning: the solution does not reproduce the accumulation vector within epsilon; it may not be consistent with the matrix");
+    }
+}
+
+/// This solves a single accumulation vector exactly with fractions, for --exact, and writes the
+/// reaction vector as fractions or as floats.
+fn run_solve_exact(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let exact_format = matches.get_one::<String>("exact_format").unwrap();
+    if matches.get_many::<String>("accumulation_path").map_or(0, |paths| paths.len()) > 1 {
+        panic!("--exact solves a single accumulation vector");
+    }
+
+    let (acc_vector, orientation) = get_accumulation_vector(matches, parse_mode);
+    let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), orientation)));
+    smas::io::check_vector_length(acc_vector.nrows(), orientation, s_matrix.matrix.nrows())
+        .unwrap_or_else(|e| panic!("the accumulation vector does not fit the stoichiometric matrix: {e}"));
+    let solution = smas::exact::solve_exact(&acc_vector, &s_matrix.matrix)
+        .unwrap_or_else(|e| panic!("failed to solve exactly: {e}"));
+    if matches.contains_id("report") {
+        eprintln!(
+            "% method: exact\n% rank: {}\n% residual norm squared: {}",
+            solution.rank, solution.residual_norm_squared,
+        );
+    }
+    if matches.contains_id("report_residual") {
+        let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+        print_residual_norm(solution.residual_norm_squared.to_f64().sqrt(), &acc_vector, epsilon);
+    }
+
+    let mut provenance = smas::io::Provenance::new("smas reaction vector", "solve");
+    provenance.row_labels = s_matrix.col_labels;
+    provenance.parameters.push((String::from("method"), String::from("exact")));
+    provenance.inputs.push(accumulation_input(matches));
+    add_matrix_provenance(&mut provenance, matches);
 
-    // if let Some(reaction_vector_computed) = reaction_vector_computed {
-    //     let results: String = match reaction_vector_truth {
-    //         Some(reaction_vector_truth) => format_comparison_results(
-    //             &reaction_vector_computed,
-    //             &reaction_vector_truth,
-    //             float_format,
-    //             float_precision as usize,
-    //         ),
-    //         None => format_accumulation_results(
-    //             &reaction_vector_computed,
-    //             float_format,
-    //             float_precision as usize,
-    //         )
-    //     };
-    //     println!("{}", results);
-    // } else {
-    //     println!("failed to produce a reaction vector");
-    // }
-}
\ No newline at end of file
+    let results = match exact_format.as_str() {
+        "fraction" => smas::exact::format_fraction_vector(&solution.reactions, &provenance),
+        _ => get_formatter(matches).format_vector(
+            &na::DVector::from_iterator(solution.reactions.len(), solution.reactions.iter().map(|val| val.to_f64())),
+            float_format,
+            float_precision as usize,
+            &provenance,
+        ),
+    };
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+/// This solves with LSQR against a matrix that is not held densely, and writes the reaction
+/// vector like the default solver.
+///
+/// # Arguments
+/// * `matches` - the solve arguments
+/// * `s_matrix` - the stoichiometric matrix
+/// * `nnz` - the number of nonzero entries of the matrix, for the report
+/// * `storage` - the provenance parameter that records how the matrix was stored, and the word
+///   the report describes the matrix with
+///
+fn run_solve_lsqr(matches: &ArgMatches, s_matrix: &impl smas::solve::LinearOperator, nnz: usize, storage: (&str, &str)) {
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let tolerance = *matches.get_one::<f64>("lsqr_tolerance").unwrap();
+    let max_iterations = *matches.get_one::<usize>("max_iterations").unwrap();
+
+    let (m, n) = s_matrix.shape();
+    let (acc_vector, orientation) = get_accumulation_vector(matches, parse_mode);
+    smas::io::check_vector_length(acc_vector.nrows(), orientation, m)
+        .unwrap_or_else(|e| panic!("the accumulation vector does not fit the stoichiometric matrix: {e}"));
+    let reaction_labels = matches.get_one::<String>("reaction_labels_path").map(|path| {
+        smas::io::load_labels(path, n).unwrap_or_else(|e| panic!("failed to load reaction labels file: {e}"))
+    });
+
+    let solution = smas::solve::solve_lsqr(&acc_vector, s_matrix, tolerance, max_iterations)
+        .and_then(smas::solve::require_converged)
+        .unwrap_or_else(|e| panic!("failed to solve: {e}"));
+    if matches.contains_id("report") {
+        eprintln!("{}", solution.format_report());
+        eprintln!("% {} {m} x {n} matrix with {nnz} nonzero entries", storage.1);
+    }
+    if matches.contains_id("report_residual") || matches.contains_id("residuals_out_path") {
+        let residuals = s_matrix.apply(&solution.reactions).map(|ax| ax - &acc_vector)
+            .unwrap_or_else(|e| panic!("failed to compute the residuals: {e}"));
+        if matches.contains_id("report_residual") {
+            print_residual_norm(residuals.norm(), &acc_vector, epsilon);
+        }
+        if let Some(path) = matches.get_one::<String>("residuals_out_path") {
+            write_residuals(path, &residuals, None, accumulation_input(matches), matches);
+        }
+    }
+    let mut results_vector = solution.reactions;
+    smas::util::clamp_to_zero(&mut results_vector, epsilon);
+
+    let mut provenance = smas::io::Provenance::new("smas reaction vector", "solve");
+    provenance.row_labels = reaction_labels;
+    provenance.parameters.push((String::from("method"), String::from(solution.method)));
+    provenance.parameters.push((String::from(storage.0), String::from("true")));
+    provenance.parameters.push((String::from("tolerance"), format!("{tolerance:e}")));
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    let (_, preprocessing_descriptions) = get_preprocessing(matches, parse_mode);
+    if !preprocessing_descriptions.is_empty() {
+        provenance.parameters.push((String::from("preprocessing"), preprocessing_descriptions.join(", ")));
+    }
+    provenance.inputs.push(accumulation_input(matches));
+    add_matrix_provenance(&mut provenance, matches);
+
+    let results = get_formatter(matches).format_vector(
+        &results_vector,
+        float_format,
+        float_precision as usize,
+        &provenance,
+    );
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+/// This describes how solve would solve, for --dry-run.
+fn describe_solve_method(matches: &ArgMatches, sparse: bool) -> String {
+    let residual_norm = get_residual_norm(matches);
+    if matches.contains_id("exact") {
+        String::from("exact fractions")
+    } else if matches.contains_id("out_of_core") {
+        String::from("LSQR, streaming the matrix file without loading it (--out-of-core)")
+    } else if sparse {
+        String::from("LSQR on the sparse matrix")
+    } else if residual_norm != smas::solve::ResidualNorm::L2 {
+        format!("minimizing the {} norm of the residual by linear programming", residual_norm.name())
+    } else if matches.contains_id("bounds_path") || matches.contains_id("dg0_path") {
+        String::from("bounded least squares")
+    } else if matches.get_one::<String>("solver").is_some_and(|solver| solver == "auto") {
+        String::from("the least squares method --solver auto picks once the matrix is loaded")
+    } else {
+        format!("{} least squares", matches.get_one::<String>("solver").map_or("pinv", |s| s.as_str()))
+    }
+}
+
+/// This checks the inputs of a subcommand, and describes them and what would run, with an
+/// estimate of the memory of the solve, without computing anything. Inputs that fail to load
+/// fail the dry run the same way they would fail the run.
+fn run_dry_run(name: &str, matches: &ArgMatches) {
+    let parse_mode = get_parse_mode(matches);
+    let has = |id: &str| matches.try_contains_id(id).unwrap_or(false);
+    let mut lines = vec![format!("% dry run of smas {name}: the inputs were checked and nothing was computed")];
+
+    // ** a matrix that solve would solve as a sparse matrix is not loaded densely, which could exhaust memory
+    let sparse = name == "solve" && !has("exact") && !has("out_of_core") && (has("sparse") || prefer_sparse_solve(matches));
+    let coordinate_shape = get_matrix_source(matches)
+        .filter(|(_, equations)| !*equations)
+        .and_then(|(path, _)| smas::io::read_coordinate_shape(path).unwrap_or_else(|e| panic!("failed to read {path}: {e}")));
+    let (nrows, ncols, nnz) = match (coordinate_shape, sparse || has("out_of_core")) {
+        (Some(shape), true) => shape,
+        _ => {
+            let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+            let nnz = s_matrix.matrix.iter().filter(|value| **value != 0.0).count();
+            (s_matrix.matrix.nrows(), s_matrix.matrix.ncols(), nnz)
+        }
+    };
+    let format = match get_matrix_source(matches) {
+        None => String::from("the built-in default matrix"),
+        Some((path, true)) => {
+            let contents = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {path}: {e}"));
+            let kind = if smas::pathway::is_xml(&contents) { "pathway file" } else { "reaction equations" };
+            format!("{path} ({kind})")
+        }
+        Some((path, false)) if smas::arrow::is_arrow_path(path) => format!("{path} (Arrow IPC)"),
+        Some((path, false)) if coordinate_shape.is_some() => format!("{path} (Matrix Market coordinate)"),
+        Some((path, false)) => format!("{path} (Matrix Market array)"),
+    };
+    lines.push(format!("% matrix: {format}, {nrows} metabolites x {ncols} reactions, {nnz} nonzero entries"));
+    if let Some(version) = get_matrix_source(matches).and_then(|(path, _)| get_model_version(path)) {
+        lines.push(format!("% model: {version}"));
+    }
+
+    let accumulation_paths: Vec<&String> = matches.try_get_many::<String>("accumulation_path")
+        .ok()
+        .flatten()
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+    for path in &accumulation_paths {
+        let (acc_vector, orientation) = smas::io::load_oriented_vector(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load accumulation vector file: {e}"));
+        smas::io::check_vector_length(acc_vector.nrows(), orientation, nrows)
+            .unwrap_or_else(|e| panic!("the accumulation vector {path} does not fit the stoichiometric matrix: {e}"));
+        lines.push(format!("% accumulation vector: {path}, {} values", acc_vector.nrows()));
+    }
+    if let Some(vector_string) = matches.try_get_one::<String>("accumulation_string").ok().flatten() {
+        let acc_vector = parse_accumulation_string(vector_string, matches, parse_mode);
+        smas::io::check_vector_length(acc_vector.nrows(), smas::io::VectorOrientation::Column, nrows)
+            .unwrap_or_else(|e| panic!("the accumulation vector given with -a does not fit the stoichiometric matrix: {e}"));
+        lines.push(format!("% accumulation vector: -a, {} values", acc_vector.nrows()));
+    }
+    if let Some(path) = matches.try_get_one::<String>("bounds_path").ok().flatten() {
+        smas::io::load_bounds(path, ncols).unwrap_or_else(|e| panic!("failed to load bounds file: {e}"));
+        lines.push(format!("% bounds: {path}, {ncols} reactions"));
+    }
+
+    if name == "solve" {
+        let n_vectors = accumulation_paths.len().max(1);
+        lines.push(format!("% would solve {n_vectors} accumulation vectors with {}", describe_solve_method(matches, sparse)));
+    } else {
+        lines.push(format!("% would run smas {name}"));
+    }
+    let (estimate, storage) = if has("out_of_core") {
+        (smas::memory::estimate_sparse_solve(nrows, ncols, 0), "LSQR; the matrix is streamed")
+    } else if sparse {
+        (smas::memory::estimate_sparse_solve(nrows, ncols, nnz), "sparse matrix and LSQR")
+    } else {
+        (smas::memory::estimate_dense_solve(nrows, ncols), "dense matrix and its SVD")
+    };
+    lines.push(format!("% estimated peak memory of the solve: {} ({storage})", smas::memory::format_bytes(estimate as f64)));
+
+    // ** without a calibration from smas bench, the estimate assumes a typical machine
+    let calibration_path = smas::cost::calibration_path().filter(|path| path.exists());
+    let (calibration, source) = match &calibration_path {
+        Some(path) => (
+            smas::cost::load_calibration(path).unwrap_or_else(|e| panic!("failed to load calibration file {}: {e}", path.display())),
+            String::from("calibrated by smas bench on this machine"),
+        ),
+        None => (smas::cost::Calibration::default(), String::from("uncalibrated; run smas bench to calibrate it for this machine")),
+    };
+    let time = if has("exact") {
+        None
+    } else if sparse || has("out_of_core") {
+        let max_iterations = matches.try_get_one::<usize>("max_iterations").ok().flatten().copied().unwrap_or(nrows.min(ncols));
+        Some(format!("at most {} for {max_iterations} LSQR iterations", smas::cost::format_seconds(calibration.estimate_sparse(nrows, ncols, nnz, max_iterations))))
+    } else {
+        Some(smas::cost::format_seconds(calibration.estimate_dense(nrows, ncols)))
+    };
+    match time {
+        Some(time) => lines.push(format!("% estimated factorization time: {time} ({source})")),
+        None => lines.push(String::from("% estimated factorization time: none for exact fractions, whose cost depends on their size")),
+    }
+    eprintln!("{}", lines.join("\n"));
+}
+
+fn run_bench(matches: &ArgMatches) {
+    let path = matches.get_one::<String>("calibration_path").map(std::path::PathBuf::from)
+        .or_else(smas::cost::calibration_path)
+        .unwrap_or_else(|| panic!("no place to store the calibration: set {} or HOME, or give --out", smas::cost::CALIBRATION_VARIABLE));
+    let policy = get_overwrite_policy(matches);
+    // ** refuse before calibrating rather than after
+    if policy == smas::io::OverwritePolicy::Refuse && path.exists() {
+        panic!("failed to write calibration file: {}", smas::error::SmasError::OutputExists(path));
+    }
+
+    eprintln!("% calibrating the cost model, which takes about a second");
+    let calibration = smas::cost::calibrate();
+    smas::io::write_output_file(&path, format!("{}\n", smas::cost::format_calibration(&calibration)), policy)
+        .unwrap_or_else(|e| panic!("failed to write calibration file: {e}"));
+    eprintln!(
+        "% dense factorization: {:.2} GFLOP/s\n% sparse LSQR: {:.2} GFLOP/s\n% wrote {}",
+        1e-9 / calibration.dense_flop_seconds,
+        1e-9 / calibration.sparse_flop_seconds,
+        path.display(),
+    );
+}
+
+fn run_solve(matches: &ArgMatches) {
+    if matches.contains_id("out_of_core") {
+        return run_solve_out_of_core(matches);
+    }
+    if matches.contains_id("exact") {
+        return run_solve_exact(matches);
+    }
+    if matches.contains_id("sparse") || prefer_sparse_solve(matches) {
+        // ** auto takes the sparse path for the same matrices that choose_method gives to LSQR
+        if matches.contains_id("report") && matches.get_one::<String>("solver").is_some_and(|solver| solver == "auto") {
+            let choice = smas::solve::auto::MethodChoice {
+                method: smas::solve::auto::Method::Lsqr,
+                reasons: vec![String::from("the matrix file is a large coordinate file with few nonzero entries, so it is \
+                    loaded as a sparse matrix and solved with LSQR instead of a full SVD")],
+            };
+            eprintln!("{}", smas::solve::auto::format_choice(&choice));
+        }
+        return run_solve_sparse(matches);
+    }
+    check_solve_options(matches);
+    let problem = load_dense_problem(matches);
+    match matches.get_one::<f64>("fuse") {
+        Some(&fuse) => run_solve_joint(matches, problem, fuse),
+        None => run_solve_dense(matches, problem),
+    }
+}
+
+/// This checks the combinations of solve options that clap cannot check on its own, before
+/// anything is loaded.
+fn check_solve_options(matches: &ArgMatches) {
+    let n_paths = matches.get_many::<String>("accumulation_path").map_or(0, |paths| paths.len());
+    let fuse = matches.contains_id("fuse");
+    let replicates = matches.contains_id("replicates");
+    let solver = matches.get_one::<String>("solver").map(|s| s.as_str()).unwrap_or("pinv");
+    let lambda = matches.get_one::<f64>("lambda").copied();
+    let huber_delta = matches.get_one::<f64>("huber_delta").copied();
+
+    if n_paths > 1 && matches.contains_id("out_path") && !fuse {
+        panic!("-o can only be used with a single accumulation vector, use --out-template for several");
+    }
+    if let Some(template) = matches.get_one::<String>("out_template") {
+        smas::io::check_out_template(template, n_paths)
+            .unwrap_or_else(|e| panic!("invalid --out-template: {e}"));
+    }
+    if matches.contains_id("weight_by_spread") && !replicates {
+        panic!("--weight-by-spread needs the spread of --replicates");
+    }
+    if get_outlier_test(matches).is_some() && !replicates {
+        panic!("--reject-outliers can only be used with --replicates");
+    }
+    if n_paths > 1 && cfg!(feature = "clipboard") && matches.contains_id("to_clipboard") && !fuse {
+        panic!("--to-clipboard can only be used with a single accumulation vector, or with --fuse");
+    }
+    if n_paths > 1 && matches.contains_id("dump_intermediates_path") {
+        panic!("--dump-intermediates can only be used with a single accumulation vector");
+    }
+    if n_paths > 1 && matches.contains_id("residuals_out_path") {
+        panic!("--residuals-out can only be used with a single accumulation vector");
+    }
+    let least_squares_only = ["lsqr", "qr", "lasso", "huber"].contains(&solver);
+    if least_squares_only && (matches.contains_id("dg0_path") || matches.contains_id("bounds_path")) {
+        panic!("--solver {solver} cannot be combined with --dg0 or --bounds");
+    }
+    if least_squares_only && get_residual_norm(matches) != smas::solve::ResidualNorm::L2 {
+        panic!("--solver {solver} only minimizes the two-norm, use --residual-norm l2");
+    }
+
+    if lambda.is_some() && solver != "lasso" {
+        panic!("--lambda is only used by --solver lasso");
+    }
+    if lambda.is_some_and(|lambda| lambda < 0.0 || lambda.is_nan()) {
+        panic!("--lambda must be at least 0, found {}", lambda.unwrap());
+    }
+    if huber_delta.is_some() && solver != "huber" {
+        panic!("--huber-delta is only used by --solver huber");
+    }
+    if huber_delta.is_some_and(|delta| delta <= 0.0 || delta.is_nan()) {
+        panic!("--huber-delta must be above 0, found {}", huber_delta.unwrap());
+    }
+}
+
+/// This is what solve loads for the dense solvers: the stoichiometric matrix and the accumulation
+/// vectors, after unit conversion, preprocessing and merging rows.
+struct DenseProblem {
+    /// The stoichiometric matrix, S: (m x n)
+    s_matrix: na::DMatrix<f64>,
+    /// The label of each metabolite, if the matrix has them
+    metabolite_labels: Option<Vec<String>>,
+    /// The label of each reaction, if the matrix has them
+    reaction_labels: Option<Vec<String>>,
+    /// Each accumulation vector, with the path of the file it was read from, if there is one
+    inputs: Vec<(Option<PathBuf>, na::DVector<f64>)>,
+    /// The weight of each metabolite by the spread of the replicates, if there are replicates
+    spread_weights: Option<na::DVector<f64>>,
+    /// The preprocessing steps that were applied, for the provenance
+    preprocessing: Vec<String>,
+    /// Where an accumulation vector without a path came from, e.g. -a
+    string_input: &'static str,
+    /// The memory loading the matrix took
+    memory_usages: Vec<smas::memory::Usage>,
+}
+
+impl DenseProblem {
+    /// This names an input for the provenance, or returns None for combined replicates, whose
+    /// paths add_replicate_provenance lists.
+    fn input_name(&self, input_path: Option<&Path>, replicates: bool) -> Option<String> {
+        match input_path {
+            Some(path) => Some(path.display().to_string()),
+            None if replicates => None,
+            None => Some(String::from(self.string_input)),
+        }
+    }
+}
+
+/// This loads the stoichiometric matrix and the accumulation vectors of a dense solve, from paths,
+/// -a, the clipboard, replicates or the prompt, and prepares them to be solved: converting units,
+/// preprocessing, checking their consistency and merging rows.
+fn load_dense_problem(matches: &ArgMatches) -> DenseProblem {
+    let accumulation_paths: Vec<&String> = matches.get_many::<String>("accumulation_path")
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+    let accumulation_string = matches.get_one::<String>("accumulation_string");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let replicate_paths: Vec<&String> = matches.get_many::<String>("replicates")
+        .map(|paths| paths.collect())
+        .unwrap_or_default();
+    let aggregate = get_aggregate(matches);
+    let outlier_test = get_outlier_test(matches);
+    let mut memory_usages = vec!();
+
+    check_declared_units(matches);
+    let (preprocessing, mut preprocessing_descriptions) = get_preprocessing(matches, parse_mode);
+    let acc_scale = matches.get_one::<f64>("acc_scale").copied();
+    if let Some(acc_scale) = acc_scale {
+        preprocessing_descriptions.insert(0, format!("acc-scale {acc_scale}"));
+    }
+
+    let mut unit_conversions = vec!();
+    let mut load_accumulation = |path: &String| {
+        let (a_vector, orientation) = smas::io::load_oriented_vector(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load accumulation vector file {path}: {e}"));
+        let (a_vector, conversion) = convert_units(a_vector, path, matches);
+        if let Some(conversion) = conversion {
+            unit_conversions.push(format!("units {path} ({conversion})"));
+        }
+        (a_vector, orientation)
+    };
+    // ** replicates are combined into a single input, keeping the spread of each element
+    let mut aggregated = None;
+    // ** each input is an optional path paired with its accumulation vector and its orientation
+    #[cfg(feature = "clipboard")]
+    let pasted = matches.contains_id("from_clipboard").then(clipboard_accumulation_vector);
+    #[cfg(not(feature = "clipboard"))]
+    let pasted: Option<na::DVector<f64>> = None;
+    let prompted = (replicate_paths.is_empty() && accumulation_paths.is_empty() && accumulation_string.is_none() && pasted.is_none())
+        .then(|| prompt_accumulation_vector(matches, parse_mode))
+        .flatten();
+    let string_input = match (&pasted, &prompted) {
+        (Some(_), _) => "the clipboard",
+        (None, Some(_)) => "typed at the prompt",
+        (None, None) => "-a (accumulation string)",
+    };
+    let oriented_inputs: Vec<_> = if !replicate_paths.is_empty() {
+        let (replicates, orientations): (Vec<_>, Vec<_>) = replicate_paths.iter().map(|path| load_accumulation(path)).unzip();
+        let combined = smas::replicates::aggregate(&replicates, aggregate, outlier_test, get_summation(matches))
+            .unwrap_or_else(|e| panic!("failed to combine the replicates: {e}"));
+        let values = combined.values.clone();
+        aggregated = Some(combined);
+        vec![(None, (values, orientations[0]))]
+    } else if accumulation_paths.is_empty() {
+        match (accumulation_string, pasted, prompted) {
+            (Some(vector_string), _, _) => {
+                let vector = parse_accumulation_string(vector_string, matches, parse_mode);
+                vec![(None, (vector, smas::io::VectorOrientation::Column))]
+            }
+            (None, Some(vector), _) => vec![(None, (vector, smas::io::VectorOrientation::Column))],
+            (None, None, Some((path, vector))) => vec![(path.map(PathBuf::from), vector)],
+            (None, None, None) => panic!("an accumulation vector must be provided as a path or with -a"),
+        }
+    } else {
+        accumulation_paths.iter()
+            .map(|path| (Some(PathBuf::from(path)), load_accumulation(path)))
+            .collect()
+    };
+    // ** unit conversions happen as the inputs are loaded, before any other preprocessing
+    preprocessing_descriptions.splice(0..0, unit_conversions);
+
+    let (labeled_matrix, usage) = smas::memory::measure("matrix", || {
+        get_oriented_s_matrix(matches, parse_mode, oriented_inputs.first().map(|(_, (a, o))| (a.nrows(), *o)))
+    });
+    memory_usages.push(usage);
+    let smas::io::LabeledMatrix {
+        matrix: s_matrix,
+        row_labels: metabolite_labels,
+        col_labels: reaction_labels,
+    } = labeled_matrix;
+
+    let inputs: Vec<(Option<PathBuf>, na::DVector<f64>)> = oriented_inputs.into_iter()
+        .map(|(input_path, (a_vector, orientation))| {
+            let a_vector = preprocess_accumulation(a_vector * acc_scale.unwrap_or(1.0), &preprocessing);
+            smas::io::check_vector_length(a_vector.nrows(), orientation, s_matrix.nrows())
+                .unwrap_or_else(|e| panic!("the accumulation vector does not fit the stoichiometric matrix: {e}"));
+            (input_path, a_vector)
+        })
+        .collect();
+
+    // ** an inconsistent accumulation vector fails before anything is solved, rather than
+    // ** silently getting a least squares compromise
+    if matches.contains_id("check_consistency") {
+        let tolerance = *matches.get_one::<f64>("consistency_tolerance").unwrap();
+        for (input_path, a_vector) in &inputs {
+            let input = input_path.as_ref().map_or_else(|| String::from(string_input), |path| path.display().to_string());
+            let consistency = smas::audit::check_consistency(a_vector, &s_matrix)
+                .unwrap_or_else(|e| panic!("failed to check the consistency of {input}: {e}"));
+            eprintln!(
+                "% {input}: {:.6}% of the accumulation vector is in the column space of the matrix (unexplained norm {:e})",
+                100.0 * consistency.explained_fraction,
+                consistency.unexplained_norm,
+            );
+            if !consistency.is_consistent(tolerance) {
+                panic!(
+                    "the accumulation vector {input} is inconsistent with the matrix: {:e} of it cannot be produced by any \
+                    reaction vector, more than --consistency-tolerance {tolerance:e}",
+                    1.0 - consistency.explained_fraction,
+                );
+            }
+        }
+    }
+
+    // ** scaling the accumulation vector scales its spread too
+    let spread_weights = aggregated.map(|aggregated| {
+        let row_labels: Vec<String> = (0..s_matrix.nrows())
+            .map(|i| match &metabolite_labels {
+                Some(labels) => labels[i].clone(),
+                None => format!("M{}", i + 1),
+            })
+            .collect();
+        eprintln!(
+            "{}",
+            smas::replicates::format_spread(&aggregated, aggregate, &row_labels, float_format, float_precision as usize)
+        );
+        if outlier_test.is_some() {
+            let replicate_names: Vec<String> = replicate_paths.iter().map(|path| path.to_string()).collect();
+            eprintln!("{}", smas::replicates::format_rejections(
+                &aggregated.rejected,
+                &row_labels,
+                &replicate_names,
+                float_format,
+                float_precision as usize,
+            ));
+        }
+        let scale: f64 = preprocessing.iter()
+            .map(|step| match step {
+                smas::util::Preprocess::Scale(factor) => factor.abs(),
+                _ => 1.0,
+            })
+            .product();
+        smas::replicates::spread_weights(&(aggregated.spread * scale * acc_scale.unwrap_or(1.0).abs()))
+    });
+
+    // ** merged rows are summed, so the spread of a merged row adds in quadrature
+    let (s_matrix, metabolite_labels, inputs, spread_weights) = match matches.get_many::<String>("merge_suffix") {
+        Some(patterns) => {
+            let patterns: Vec<String> = patterns.cloned().collect();
+            let labels = metabolite_labels.unwrap_or_else(|| panic!("--merge-suffix needs metabolite labels, e.g. from --metabolite-labels"));
+            let groups = smas::util::group_rows_by_suffix(&labels, &patterns);
+            eprintln!("{}", smas::util::format_merged_rows(&groups, &labels));
+            preprocessing_descriptions.push(format!("merge-suffix {} ({} -> {} rows)", patterns.join(" "), labels.len(), groups.len()));
+            let merge_vector = |vector: &na::DVector<f64>| -> na::DVector<f64> {
+                smas::util::merge_rows(&na::DMatrix::from_column_slice(vector.nrows(), 1, vector.as_slice()), &groups).column(0).into_owned()
+            };
+            let inputs = inputs.into_iter().map(|(path, a_vector)| (path, merge_vector(&a_vector))).collect();
+            let spread_weights = spread_weights.map(|weights| {
+                merge_vector(&weights.map(|w| 1.0 / (w * w))).map(|variance| 1.0 / variance.sqrt())
+            });
+            let merged_labels = groups.iter().map(|group| group.label.clone()).collect();
+            (smas::util::merge_rows(&s_matrix, &groups), Some(merged_labels), inputs, spread_weights)
+        }
+        None => (s_matrix, metabolite_labels, inputs, spread_weights),
+    };
+
+    DenseProblem {
+        s_matrix,
+        metabolite_labels,
+        reaction_labels,
+        inputs,
+        spread_weights,
+        preprocessing: preprocessing_descriptions,
+        string_input,
+        memory_usages,
+    }
+}
+
+/// This solves every accumulation vector of a problem jointly, for --fuse, and writes the
+/// reactions x conditions matrix.
+fn run_solve_joint(matches: &ArgMatches, problem: DenseProblem, fuse: f64) {
+    let out_path = matches.get_one::<String>("out_path").map(|p| p.as_str());
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let overwrite_policy = get_overwrite_policy(matches);
+    let replicates = matches.contains_id("replicates");
+    let mut memory_usages = problem.memory_usages.clone();
+
+    let acc_vectors: Vec<na::DVector<f64>> = problem.inputs.iter().map(|(_, a)| a.clone()).collect();
+    let (mut results_matrix, usage) = smas::memory::measure("factorization", || {
+        smas::solve::solve_joint(&acc_vectors, &problem.s_matrix, fuse)
+    });
+    memory_usages.push(usage);
+    results_matrix.iter_mut()
+        .filter(|val| smas::util::epsilon_eq(**val, 0.0, epsilon))
+        .for_each(|val| *val = 0.0);
+
+    let mut provenance = smas::io::Provenance::new("smas reaction matrix (reactions x conditions)", "solve");
+    provenance.row_labels = problem.reaction_labels.clone();
+    provenance.parameters.push((String::from("method"), String::from("joint")));
+    provenance.parameters.push((String::from("fuse"), fuse.to_string()));
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    if !problem.preprocessing.is_empty() {
+        provenance.parameters.push((String::from("preprocessing"), problem.preprocessing.join(", ")));
+    }
+    add_matrix_provenance(&mut provenance, matches);
+    add_replicate_provenance(&mut provenance, matches);
+    for (input_path, _) in &problem.inputs {
+        provenance.inputs.extend(problem.input_name(input_path.as_deref(), replicates));
+    }
+
+    let conditions: Vec<String> = problem.inputs.iter().enumerate()
+        .map(|(j, (input_path, _))| match input_path {
+            Some(path) => path.display().to_string(),
+            None => format!("condition {}", j + 1),
+        })
+        .collect();
+    #[cfg(feature = "clipboard")]
+    if matches.contains_id("to_clipboard") {
+        let col_labels = conditions.clone();
+        copy_to_clipboard(&results_matrix, "reaction", "R", col_labels, &provenance, float_format, float_precision as usize);
+    }
+    if !write_arrow_output(&results_matrix, "reaction", "R", conditions, &provenance, out_path, overwrite_policy) {
+        let (results, usage) = smas::memory::measure("results", || get_formatter(matches).format_matrix(
+            &results_matrix,
+            float_format,
+            float_precision as usize,
+            &provenance,
+        ));
+        memory_usages.push(usage);
+        write_output(&results, out_path, overwrite_policy);
+    }
+    if get_mem_report(matches) {
+        eprintln!("{}", smas::memory::format_usage(&memory_usages));
+    }
+}
+
+/// This reads whether solve should report its memory usage, which needs the mem-report feature.
+fn get_mem_report(matches: &ArgMatches) -> bool {
+    cfg!(feature = "mem-report") && matches.contains_id("mem_report")
+}
+
+/// These are the dense solvers of solve, each of which solves an accumulation vector on its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DenseSolver {
+    Pinv,
+    Nnls,
+    Lsqr,
+    Qr,
+    Lasso,
+    Huber,
+}
+
+/// This reads the dense solver of --solver. auto picks its solver from the matrix, so it can only
+/// pick once the matrix is final, i.e. after merging rows.
+fn get_dense_solver(matches: &ArgMatches, s_matrix: &na::DMatrix<f64>) -> DenseSolver {
+    match matches.get_one::<String>("solver").map(|s| s.as_str()) {
+        Some("nnls") => DenseSolver::Nnls,
+        Some("lsqr") => DenseSolver::Lsqr,
+        Some("qr") => DenseSolver::Qr,
+        Some("lasso") => DenseSolver::Lasso,
+        Some("huber") => DenseSolver::Huber,
+        Some("auto") => {
+            let irreversible: Option<Vec<bool>> = get_matrix_source(matches)
+                .filter(|(_, equations)| *equations)
+                .map(|(path, _)| load_reaction_model(path).reversible.iter().map(|reversible| !reversible).collect())
+                .filter(|irreversible: &Vec<bool>| irreversible.len() == s_matrix.ncols());
+            let constrained = matches.contains_id("dg0_path") || matches.contains_id("bounds_path")
+                || get_residual_norm(matches) != smas::solve::ResidualNorm::L2;
+            let choice = smas::solve::auto::choose_method(s_matrix, irreversible.as_deref(), constrained);
+            if matches.contains_id("report") || matches.contains_id("explain") {
+                eprintln!("{}", smas::solve::auto::format_choice(&choice));
+            }
+            match choice.method {
+                smas::solve::auto::Method::Nnls => DenseSolver::Nnls,
+                smas::solve::auto::Method::Lsqr => DenseSolver::Lsqr,
+                smas::solve::auto::Method::Pinv => DenseSolver::Pinv,
+            }
+        }
+        _ => DenseSolver::Pinv,
+    }
+}
+
+/// This loads the flux bounds of a dense solve: the directions --dg0 makes irreversible, intersected
+/// with --bounds, and with every lower bound raised to 0 for nnls.
+fn get_solve_bounds(matches: &ArgMatches, s_matrix: &na::DMatrix<f64>, solver: DenseSolver) -> Option<smas::constraints::Bounds> {
+    let temperature = *matches.get_one::<f64>("temperature").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let nnls = solver == DenseSolver::Nnls;
+
+    let mut bounds = matches.get_one::<String>("dg0_path")
+        .map(|path| load_direction_bounds(path, s_matrix, temperature, parse_mode));
+    if let Some(path) = matches.get_one::<String>("bounds_path") {
+        let file_bounds = smas::io::load_bounds(path, s_matrix.ncols())
+            .unwrap_or_else(|e| panic!("failed to load bounds file: {e}"));
+        bounds = Some(match bounds {
+            Some(bounds) => bounds.intersect(&file_bounds),
+            None => file_bounds,
+        });
+    }
+    // ** nnls in another norm is a linear program with every lower bound at 0
+    if nnls && bounds.is_none() && get_residual_norm(matches) != smas::solve::ResidualNorm::L2 {
+        bounds = Some(smas::constraints::Bounds::unbounded(s_matrix.ncols()));
+    }
+    // ** nnls with bounds is a bounded solve with every lower bound raised to 0
+    if let Some(bounds) = bounds.as_mut().filter(|_| nnls) {
+        bounds.lower.iter_mut().for_each(|lower| *lower = lower.max(0.0));
+    }
+    bounds
+}
+
+/// This solves one system with a dense solver: as a linear program in the one- or infinity-norm,
+/// within the bounds, or with the solver of --solver. pinv reuses the Solver in `pinv_solver` while
+/// the matrix stays the same.
+fn solve_dense(
+    matches: &ArgMatches,
+    solver: DenseSolver,
+    pinv_solver: &mut Option<smas::solve::Solver>,
+    bounds: Option<&smas::constraints::Bounds>,
+    system_vector: &na::DVector<f64>,
+    system_matrix: &na::DMatrix<f64>,
+    options: &smas::solve::SolveOptions,
+) -> Result<smas::solve::Solution, smas::error::SmasError> {
+    let residual_norm = get_residual_norm(matches);
+    let lsqr_tolerance = *matches.get_one::<f64>("lsqr_tolerance").unwrap();
+    let max_iterations = *matches.get_one::<usize>("max_iterations").unwrap();
+
+    if residual_norm != smas::solve::ResidualNorm::L2 {
+        return smas::solve::solve_residual_norm(system_vector, system_matrix, residual_norm, bounds, options);
+    }
+    if let Some(bounds) = bounds {
+        return smas::solve::solve_bounded_with_options(
+            system_vector,
+            system_matrix,
+            bounds,
+            smas::solve::BOUNDED_MAX_ITERATIONS,
+            options,
+        );
+    }
+    match solver {
+        DenseSolver::Pinv => smas::solve::cached_solver(pinv_solver, system_matrix).solve(system_vector),
+        DenseSolver::Nnls => smas::solve::solve_nnls_with_options(
+            system_vector,
+            system_matrix,
+            smas::solve::BOUNDED_MAX_ITERATIONS,
+            options,
+        ),
+        DenseSolver::Lsqr => smas::solve::solve_lsqr_with_options(
+            system_vector,
+            system_matrix,
+            lsqr_tolerance,
+            max_iterations,
+            options,
+        ).and_then(smas::solve::require_converged),
+        DenseSolver::Qr => smas::solve::solve_qr(system_vector, system_matrix),
+        DenseSolver::Lasso => smas::solve::solve_lasso_with_options(
+            system_vector,
+            system_matrix,
+            *matches.get_one::<f64>("lambda").unwrap(),
+            max_iterations,
+            options,
+        ),
+        DenseSolver::Huber => smas::solve::solve_huber_with_options(
+            system_vector,
+            system_matrix,
+            *matches.get_one::<f64>("huber_delta").unwrap(),
+            max_iterations,
+            options,
+        ),
+    }
+}
+
+/// This prints the diagnostics of a dense solution that were asked for to stderr: the --report,
+/// --report-residual, --audit-numerics, --explain and --constraint-report.
+///
+/// # Arguments
+/// * `matches` - The arguments of solve.
+/// * `problem` - The problem that was solved.
+/// * `a_vector` - The accumulation vector that was solved for.
+/// * `system` - The vector and matrix the solver was given, with any weights, measurements and prior.
+/// * `bounds` - The flux bounds of the solve, if any.
+/// * `prior` - The prior reaction vector, if any.
+/// * `solution` - The solution.
+///
+fn report_dense_solution(
+    matches: &ArgMatches,
+    problem: &DenseProblem,
+    a_vector: &na::DVector<f64>,
+    (system_vector, system_matrix): (&na::DVector<f64>, &na::DMatrix<f64>),
+    bounds: Option<&smas::constraints::Bounds>,
+    prior: Option<&na::DVector<f64>>,
+    solution: &smas::solve::Solution,
+) {
+    let s_matrix = &problem.s_matrix;
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap() as usize;
+
+    if matches.contains_id("report") {
+        eprintln!("{}", solution.format_report_with_norm(get_residual_norm(matches), s_matrix, a_vector));
+        if system_matrix.nrows() != s_matrix.nrows() {
+            // ** the residual norm is the fit to the accumulation vector, the weighted
+            // ** system the solver was given is reported on its own line
+            eprintln!("% augmented system residual norm: {:e}", solution.residual_norm);
+        }
+        if let Some(duals) = &solution.duals {
+            eprintln!("{}", duals.format(float_format, float_precision));
+        }
+        if let Some(prior) = prior {
+            let (_, prior_residual) = smas::measurements::prior_residual_norms(
+                s_matrix,
+                a_vector,
+                prior,
+                &solution.reactions,
+            );
+            eprintln!("% prior residual norm: {prior_residual:e}");
+        }
+    }
+    if matches.contains_id("report_residual") {
+        let norm = smas::solve::residual_norm(s_matrix, &solution.reactions, a_vector, get_summation(matches));
+        print_residual_norm(norm, a_vector, epsilon);
+    }
+    if matches.contains_id("audit_numerics") {
+        let audit = smas::audit::audit_numerics(system_vector, system_matrix, &solution.reactions);
+        eprintln!("{}", smas::audit::format_audit(&audit));
+    }
+    if matches.contains_id("explain") {
+        // ** rows past the metabolites come from flux measurements and the prior
+        let row_labels: Vec<String> = (0..system_matrix.nrows())
+            .map(|i| match &problem.metabolite_labels {
+                Some(labels) if i < labels.len() => labels[i].clone(),
+                _ if i < s_matrix.nrows() => format!("M{}", i + 1),
+                _ => format!("constraint {}", i + 1 - s_matrix.nrows()),
+            })
+            .collect();
+        eprintln!("{}", smas::explain::explain_solve(system_vector, system_matrix, solution, &row_labels));
+    }
+    if matches.contains_id("constraint_report") {
+        let statuses = smas::constraints::constraint_report(
+            s_matrix,
+            a_vector,
+            &solution.reactions,
+            bounds,
+            epsilon,
+        );
+        eprintln!(
+            "{}",
+            smas::constraints::format_constraint_report(&statuses, float_format, float_precision)
+        );
+    }
+}
+
+/// This builds the provenance of a reaction vector of a dense solve.
+fn dense_solve_provenance(
+    matches: &ArgMatches,
+    problem: &DenseProblem,
+    input_path: Option<&Path>,
+    solver: DenseSolver,
+    solution: &smas::solve::Solution,
+) -> smas::io::Provenance {
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let residual_norm = get_residual_norm(matches);
+
+    let mut provenance = smas::io::Provenance::new("smas reaction vector", "solve");
+    provenance.row_labels = problem.reaction_labels.clone();
+    provenance.parameters.push((String::from("method"), String::from(solution.method)));
+    if solver == DenseSolver::Lsqr {
+        let lsqr_tolerance = *matches.get_one::<f64>("lsqr_tolerance").unwrap();
+        provenance.parameters.push((String::from("tolerance"), format!("{lsqr_tolerance:e}")));
+    }
+    if let Some(lambda) = matches.get_one::<f64>("lambda") {
+        provenance.parameters.push((String::from("lambda"), lambda.to_string()));
+    }
+    if let Some(delta) = matches.get_one::<f64>("huber_delta") {
+        provenance.parameters.push((String::from("huber delta"), delta.to_string()));
+    }
+    if residual_norm != smas::solve::ResidualNorm::L2 {
+        provenance.parameters.push((String::from("residual norm"), String::from(residual_norm.name())));
+    }
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    if !problem.preprocessing.is_empty() {
+        provenance.parameters.push((String::from("preprocessing"), problem.preprocessing.join(", ")));
+    }
+    if matches.contains_id("loopless") {
+        provenance.parameters.push((String::from("loopless"), String::from("true")));
+    }
+    if matches.contains_id("integerize") {
+        let denominator = *matches.get_one::<u32>("integerize_denominator").unwrap();
+        provenance.parameters.push((String::from("integerize"), format!("1/{denominator}")));
+    }
+    provenance.inputs.extend(problem.input_name(input_path, matches.contains_id("replicates")));
+    add_replicate_provenance(&mut provenance, matches);
+    add_matrix_provenance(&mut provenance, matches);
+    if let Some(path) = matches.get_one::<String>("dg0_path") {
+        let temperature = *matches.get_one::<f64>("temperature").unwrap();
+        provenance.parameters.push((String::from("temperature"), temperature.to_string()));
+        provenance.inputs.push(path.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("bounds_path") {
+        provenance.inputs.push(path.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("flux_measurements_path") {
+        provenance.inputs.push(path.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("prior_path") {
+        let prior_weight = *matches.get_one::<f64>("prior_weight").unwrap();
+        provenance.parameters.push((String::from("prior weight"), prior_weight.to_string()));
+        provenance.inputs.push(path.clone());
+    }
+    provenance
+}
+
+/// This solves each accumulation vector of a problem on its own with a dense solver, writing each
+/// reaction vector as soon as it is solved, so that an interrupted batch keeps those.
+fn run_solve_dense(matches: &ArgMatches, problem: DenseProblem) {
+    let out_path = matches.get_one::<String>("out_path");
+    let out_template = matches.get_one::<String>("out_template");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let dump_intermediates_path = matches.get_one::<String>("dump_intermediates_path");
+    let residuals_out_path = matches.get_one::<String>("residuals_out_path");
+    let loopless = matches.contains_id("loopless");
+    let integerize = matches.contains_id("integerize");
+    let integerize_denominator = *matches.get_one::<u32>("integerize_denominator").unwrap();
+    let integerize_tolerance = *matches.get_one::<f64>("integerize_tolerance").unwrap();
+    let weight_by_spread = matches.contains_id("weight_by_spread");
+    let prior_weight = *matches.get_one::<f64>("prior_weight").unwrap();
+    let mem_report = get_mem_report(matches);
+    #[cfg(feature = "rhai")]
+    let post_script = matches.get_one::<String>("post_script_path").map(|path| {
+        let script = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read the post-script: {e}"));
+        smas::post_script::PostScript::parse(&script)
+            .unwrap_or_else(|e| panic!("failed to parse the post-script {path}: {e}"))
+    });
+    let s_matrix = &problem.s_matrix;
+    let mut memory_usages = problem.memory_usages.clone();
+
+    let solver = get_dense_solver(matches, s_matrix);
+    let bounds = get_solve_bounds(matches, s_matrix, solver);
+    let prior = matches.get_one::<String>("prior_path").map(|path| {
+        smas::io::load_vector_with_mode(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load prior flux vector file: {e}"))
+    });
+    let flux_measurements = matches.get_one::<String>("flux_measurements_path").map(|path| {
+        smas::io::load_flux_measurements(path)
+            .unwrap_or_else(|e| panic!("failed to load flux measurements file: {e}"))
+    });
+
+    let n_inputs = problem.inputs.len();
+    let options = smas::solve::SolveOptions {
+        timeout: None,
+        cancel_token: (n_inputs > 1).then(smas::cancel::install_interrupt_handler),
+    };
+    let interrupt = |index: usize, memory_usages: &[smas::memory::Usage]| -> ! {
+        eprintln!("% interrupted after solving {index} of {n_inputs} accumulation vectors");
+        if mem_report {
+            eprintln!("{}", smas::memory::format_usage(memory_usages));
+        }
+        std::process::exit(smas::cancel::EXIT_INTERRUPTED);
+    };
+    // ** the inputs of a batch share their matrix, so the pseudoinverse is computed once
+    let mut pinv_solver = None;
+    for (index, (input_path, a_vector)) in problem.inputs.iter().enumerate() {
+        let input_path = input_path.as_deref();
+        if options.cancel_token.as_ref().is_some_and(|cancel| cancel.is_cancelled()) {
+            interrupt(index, &memory_usages);
+        }
+        let (mut system_matrix, mut system_vector) = match &problem.spread_weights {
+            Some(weights) if weight_by_spread => smas::measurements::weight_rows(s_matrix, a_vector, weights)
+                .unwrap_or_else(|e| panic!("failed to weight the metabolites by their spread: {e}")),
+            _ => (s_matrix.clone(), a_vector.clone()),
+        };
+        // ** flux measurements are appended to the system as weighted rows
+        if let Some(measurements) = &flux_measurements {
+            (system_matrix, system_vector) = smas::measurements::append_flux_measurements(&system_matrix, &system_vector, measurements)
+                .unwrap_or_else(|e| panic!("failed to apply flux measurements: {e}"));
+        }
+        if let Some(prior) = &prior {
+            (system_matrix, system_vector) = smas::measurements::append_prior(&system_matrix, &system_vector, prior, prior_weight)
+                .unwrap_or_else(|e| panic!("failed to apply the prior flux vector: {e}"));
+        }
+
+        let (solution, usage) = smas::memory::measure("factorization", || {
+            solve_dense(matches, solver, &mut pinv_solver, bounds.as_ref(), &system_vector, &system_matrix, &options)
+        });
+        memory_usages.push(usage);
+        let mut solution = match solution {
+            Ok(solution) => solution,
+            Err(smas::error::SmasError::Cancelled { .. }) => interrupt(index, &memory_usages),
+            Err(e) => panic!("failed to solve: {e}"),
+        };
+        if loopless {
+            let exchange = smas::solve::exchange_reactions(s_matrix);
+            solution.reactions = smas::solve::loopless(s_matrix, &solution.reactions, &exchange)
+                .unwrap_or_else(|e| panic!("failed to remove loops from the solution: {e}"));
+        }
+        if integerize {
+            let integerized = smas::solve::integerize::integerize(
+                &system_vector,
+                &system_matrix,
+                &solution.reactions,
+                integerize_denominator,
+                integerize_tolerance,
+                smas::solve::integerize::INTEGERIZE_MAX_NODES,
+            ).unwrap_or_else(|e| panic!("failed to round the solution to multiples of 1/{integerize_denominator}: {e}"));
+            if matches.contains_id("report") {
+                eprintln!(
+                    "% integerized: residual norm {:e} (least squares {:e}) after {} nodes",
+                    integerized.residual_norm, integerized.least_squares_norm, integerized.nodes,
+                );
+            }
+            solution.reactions = integerized.reactions;
+            solution.residual_norm = integerized.residual_norm;
+        }
+        report_dense_solution(
+            matches,
+            &problem,
+            a_vector,
+            (&system_vector, &system_matrix),
+            bounds.as_ref(),
+            prior.as_ref(),
+            &solution,
+        );
+        if let Some(dir) = dump_intermediates_path {
+            write_intermediates(Path::new(dir), &system_vector, &system_matrix, matches);
+        }
+        if let Some(path) = residuals_out_path {
+            let residuals = smas::solve::residuals(s_matrix, &solution.reactions, a_vector);
+            let input = match input_path {
+                Some(path) => path.display().to_string(),
+                None if matches.contains_id("replicates") => String::from("--replicates"),
+                None => String::from(problem.string_input),
+            };
+            write_residuals(path, &residuals, problem.metabolite_labels.clone(), input, matches);
+        }
+        #[cfg(feature = "rhai")]
+        if let Some(post_script) = &post_script {
+            let context = smas::post_script::Context::new(
+                s_matrix,
+                problem.metabolite_labels.as_deref(),
+                problem.reaction_labels.as_deref(),
+                a_vector,
+                &solution,
+            );
+            let outcome = post_script.run(&context)
+                .unwrap_or_else(|e| panic!("failed to run the post-script: {e}"));
+            eprintln!("{}", smas::post_script::format_outcome(&outcome, &context, float_format, float_precision as usize));
+        }
+        let provenance = dense_solve_provenance(matches, &problem, input_path, solver, &solution);
+        let mut results_vector = solution.reactions;
+        smas::util::clamp_to_zero(&mut results_vector, epsilon);
+
+        let rendered_path = out_template.map(|template| smas::io::render_out_template(
+            template,
+            &smas::io::OutTemplateVars {
+                input_path,
+                index,
+                method: solution.method,
+            },
+        ));
+        let out_path = rendered_path.as_deref().or(out_path.map(|p| p.as_str()));
+        let results_matrix = na::DMatrix::from_column_slice(results_vector.len(), 1, results_vector.as_slice());
+        #[cfg(feature = "clipboard")]
+        if matches.contains_id("to_clipboard") {
+            let col_labels = vec![String::from("flux")];
+            copy_to_clipboard(&results_matrix, "reaction", "R", col_labels, &provenance, float_format, float_precision as usize);
+        }
+        if write_arrow_output(&results_matrix, "reaction", "R", vec![String::from("flux")], &provenance, out_path, overwrite_policy) {
+            continue;
+        }
+
+        let (results, usage) = smas::memory::measure("results", || get_formatter(matches).format_vector(
+            &results_vector,
+            float_format,
+            float_precision as usize,
+            &provenance,
+        ));
+        memory_usages.push(usage);
+        write_output(&results, out_path, overwrite_policy);
+    }
+    if mem_report {
+        eprintln!("{}", smas::memory::format_usage(&memory_usages));
+    }
+}
+
+/// The environment variable rayon sizes its global thread pool with.
+const RAYON_NUM_THREADS: &str = "RAYON_NUM_THREADS";
+
+/// This reads how many threads smas may run on: --threads, or else RAYON_NUM_THREADS if it is a
+/// positive count, or else the number of CPUs.
+fn get_threads(matches: &ArgMatches) -> usize {
+    if let Some(threads) = matches.get_one::<usize>("threads") {
+        return *threads;
+    }
+    std::env::var(RAYON_NUM_THREADS).ok()
+        .and_then(|threads| threads.parse::<usize>().ok())
+        .filter(|threads| *threads > 0)
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+fn run_validate_pairs(matches: &ArgMatches, pairs_path: &str) {
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let jobs = matches.get_one::<usize>("jobs").copied().unwrap_or_else(|| get_threads(matches));
+
+    let pairs = smas::validation::load_pairs_manifest(pairs_path)
+        .unwrap_or_else(|e| panic!("failed to load the pairs manifest: {e}"));
+    let results = smas::validation::validate_pairs(&pairs, parse_mode, epsilon, get_summation(matches), jobs);
+    let output = smas::validation::format_pair_results(&pairs, &results, float_format, float_precision as usize);
+    write_output(&output, out_path.map(|p| p.as_str()), overwrite_policy);
+    // ** like a test runner, a failed or unreadable pair fails the run
+    if !results.iter().all(|result| result.as_ref().is_ok_and(|summary| summary.passed())) {
+        std::process::exit(1);
+    }
+}
+
+fn run_validate(matches: &ArgMatches) {
+    if let Some(pairs_path) = matches.get_one::<String>("pairs_path") {
+        run_validate_pairs(matches, pairs_path);
+        return;
+    }
+    let computed_path = matches.get_one::<String>("computed_path").unwrap();
+    let reactions_path = matches.get_one::<String>("reactions_path").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let mut compared = vec![computed_path, reactions_path];
+    compared.extend(matches.get_one::<String>("accumulation_path"));
+    compared.extend(get_matrix_source(matches).map(|(path, _)| path));
+    warn_model_versions(&compared);
+
+    let (reactions_computed, reactions_true) = match get_id_map(matches) {
+        Some((id_map, namespace)) => {
+            let pattern_value = *matches.get_one::<f64>("pattern_value").unwrap();
+            let load = |path: &String, what: &str| {
+                let mut vector = smas::io::load_labeled_matrix(path, parse_mode, pattern_value)
+                    .unwrap_or_else(|e| panic!("failed to load {what} reaction vector file: {e}"));
+                if vector.row_labels.is_none() || vector.matrix.ncols() != 1 {
+                    panic!("the {what} reaction vector file {path} must be a labeled vector to be aligned with --id-map");
+                }
+                translate_labels(&mut vector, &id_map, &namespace, path);
+                (vector.row_labels.unwrap(), vector.matrix.column(0).into_owned())
+            };
+            let (computed_labels, computed) = load(computed_path, "computed");
+            let (true_labels, reactions_true) = load(reactions_path, "true");
+            // ** the true vector is reordered to match the computed one, reaction by reaction
+            let aligned = computed_labels.iter()
+                .map(|label| match true_labels.iter().position(|true_label| true_label == label) {
+                    Some(i) => reactions_true[i],
+                    None => panic!("the reaction {label} of {computed_path} is not in {reactions_path}"),
+                })
+                .collect::<Vec<f64>>();
+            let ignored = true_labels.iter().filter(|label| !computed_labels.contains(label)).count();
+            if ignored > 0 {
+                eprintln!("% {ignored} reactions of {reactions_path} are not in {computed_path} and were ignored");
+            }
+            (computed, na::DVector::from_vec(aligned))
+        }
+        None => (
+            smas::io::load_vector_with_mode(computed_path, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to load computed reaction vector file: {e}")),
+            smas::io::load_vector_with_mode(reactions_path, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to load true reaction vector file: {e}")),
+        ),
+    };
+
+    let results = smas::io::format_comparison_results(
+        &reactions_computed,
+        &reactions_true,
+        float_format,
+        float_precision as usize,
+        epsilon,
+        get_summation(matches),
+    ).unwrap_or_else(|e| panic!("failed to compare reaction vectors: {e}"));
+
+    // ** with an accumulation vector, the true vector is also checked against the matrix, which
+    // ** catches a true vector that belongs to another version of the model
+    let acc_vector = match (matches.get_one::<String>("accumulation_path"), matches.get_one::<String>("accumulation_string")) {
+        (Some(path), _) => Some(
+            smas::io::load_vector_with_mode(path, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to load accumulation vector file: {e}")),
+        ),
+        (None, Some(vector_string)) => Some(parse_accumulation_string(vector_string, matches, parse_mode)),
+        (None, None) => None,
+    };
+    let results = match acc_vector {
+        Some(acc_vector) => {
+            let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), smas::io::VectorOrientation::Column)));
+            let consistency = smas::io::format_consistency_results(
+                &s_matrix,
+                &reactions_true,
+                &acc_vector,
+                float_format,
+                float_precision as usize,
+                epsilon,
+            ).unwrap_or_else(|e| panic!("failed to check the true reaction vector against the accumulation vector: {e}"));
+            format!("{results}\n{consistency}")
+        }
+        None => results,
+    };
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_jacobian(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+
+    let prior_weight = matches.get_one::<f64>("prior_weight").copied();
+    let jacobian = match prior_weight {
+        Some(weight) => smas::measurements::prior_jacobian(&s_matrix.matrix, weight),
+        None => smas::solve::jacobian(&s_matrix.matrix),
+    };
+
+    let mut provenance = match prior_weight {
+        Some(weight) => {
+            let mut provenance = smas::io::Provenance::new("smas jacobian dx/db of the prior weighted solution", "jacobian");
+            provenance.parameters.push((String::from("method"), String::from("prior")));
+            provenance.parameters.push((String::from("prior weight"), weight.to_string()));
+            provenance
+        }
+        None => {
+            let mut provenance = smas::io::Provenance::new("smas jacobian dx/db of the pinv solution", "jacobian");
+            provenance.parameters.push((String::from("method"), String::from("pinv")));
+            provenance
+        }
+    };
+    provenance.row_labels = s_matrix.col_labels;
+    provenance.col_labels = s_matrix.row_labels;
+    add_matrix_provenance(&mut provenance, matches);
+
+    let col_labels = provenance.col_labels.clone()
+        .unwrap_or_else(|| (1..=jacobian.ncols()).map(|i| format!("M{i}")).collect());
+    let out_path = out_path.map(|p| p.as_str());
+    if write_arrow_output(&jacobian, "reaction", "R", col_labels, &provenance, out_path, overwrite_policy) {
+        return;
+    }
+
+    let results = get_formatter(matches).format_matrix(
+        &jacobian,
+        float_format,
+        float_precision as usize,
+        &provenance,
+    );
+    write_output(&results, out_path, overwrite_policy);
+}
+
+fn run_timecourse(matches: &ArgMatches) {
+    let accumulation_path = matches.get_one::<String>("accumulation_path").unwrap();
+    let times_string = matches.get_one::<String>("times").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let smooth = *matches.get_one::<f64>("smooth").unwrap();
+    let mode = match matches.get_one::<String>("mode").map(|s| s.as_str()) {
+        Some("constant") => smas::timecourse::TimeMode::Constant,
+        _ => smas::timecourse::TimeMode::Piecewise,
+    };
+
+    let acc_matrix = smas::io::load_matrix_with_mode(accumulation_path, parse_mode)
+        .unwrap_or_else(|e| panic!("failed to load accumulation time course file: {e}"));
+    let times: Vec<f64> = smas::io::parse_vector(times_string).iter().copied().collect();
+
+    let smas::io::LabeledMatrix {
+        matrix: s_matrix,
+        col_labels: reaction_labels,
+        ..
+    } = get_oriented_s_matrix(matches, parse_mode, Some((acc_matrix.nrows(), smas::io::VectorOrientation::Column)));
+
+    let mut results_matrix = smas::timecourse::fit_timecourse(&acc_matrix, &times, &s_matrix, mode, smooth)
+        .unwrap_or_else(|e| panic!("failed to fit the time course: {e}"));
+    results_matrix.iter_mut()
+        .filter(|val| smas::util::epsilon_eq(**val, 0.0, epsilon))
+        .for_each(|val| *val = 0.0);
+
+    let mut provenance = smas::io::Provenance::new("smas reaction fluxes (reactions x time intervals)", "timecourse");
+    provenance.row_labels = reaction_labels;
+    provenance.parameters.push((String::from("mode"), format!("{mode:?}").to_lowercase()));
+    provenance.parameters.push((String::from("smooth"), smooth.to_string()));
+    provenance.parameters.push((String::from("times"), times_string.clone()));
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    provenance.inputs.push(accumulation_path.clone());
+    add_matrix_provenance(&mut provenance, matches);
+
+    let results = get_formatter(matches).format_matrix(
+        &results_matrix,
+        float_format,
+        float_precision as usize,
+        &provenance,
+    );
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_sensitivity(matches: &ArgMatches) {
+    let entries_string = matches.get_one::<String>("entries").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let (acc_vector, acc_orientation) = get_accumulation_vector(matches, parse_mode);
+
+    let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), acc_orientation))).matrix;
+
+    let entries = smas::sensitivity::parse_matrix_entries(entries_string)
+        .unwrap_or_else(|e| panic!("failed to parse --entries: {e}"));
+    let sensitivities = smas::sensitivity::matrix_sensitivity(
+        &acc_vector,
+        &s_matrix,
+        &entries,
+        smas::sensitivity::DEFAULT_RELATIVE_STEP,
+    )
+        .unwrap_or_else(|e| panic!("failed to compute the sensitivity: {e}"));
+
+    let results = smas::sensitivity::format_sensitivity(&sensitivities, float_format, float_precision as usize);
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_sample(matches: &ArgMatches) {
+    let accumulation_path = matches.get_one::<String>("accumulation_path");
+    let out_path = matches.get_one::<String>("out_path");
+    let samples_path = matches.get_one::<String>("samples_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let n_samples = *matches.get_one::<usize>("n").unwrap();
+    let burn_in = *matches.get_one::<usize>("burn_in").unwrap();
+    let thin = *matches.get_one::<usize>("thin").unwrap();
+    let seed = *matches.get_one::<u64>("seed").unwrap();
+    let max_flux = *matches.get_one::<f64>("max_flux").unwrap();
+    let dg0_path = matches.get_one::<String>("dg0_path");
+    let temperature = *matches.get_one::<f64>("temperature").unwrap();
+
+    let (acc_vector, acc_orientation) = get_accumulation_vector(matches, parse_mode);
+
+    let smas::io::LabeledMatrix {
+        matrix: s_matrix,
+        col_labels: reaction_labels,
+        ..
+    } = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), acc_orientation)));
+
+    // ** every flux is limited to [-max flux, max flux], and to its feasible direction with --dg0
+    let mut bounds = match dg0_path {
+        Some(path) => load_direction_bounds(path, &s_matrix, temperature, parse_mode),
+        None => smas::constraints::Bounds::unbounded(s_matrix.ncols()),
+    };
+    bounds.lower.iter_mut().for_each(|l| *l = l.max(-max_flux));
+    bounds.upper.iter_mut().for_each(|u| *u = u.min(max_flux));
+
+    let settings = smas::sample::HitAndRun { n_samples, burn_in, thin, seed };
+    let options = smas::solve::SolveOptions {
+        timeout: None,
+        cancel_token: Some(smas::cancel::install_interrupt_handler()),
+    };
+    let samples = match smas::sample::sample_fluxes(&acc_vector, &s_matrix, &bounds, &settings, &options) {
+        Ok(samples) => samples,
+        Err(smas::error::SmasError::Cancelled { .. }) => {
+            eprintln!("% interrupted before the first sample");
+            std::process::exit(smas::cancel::EXIT_INTERRUPTED);
+        }
+        Err(e) => panic!("failed to sample the solution space: {e}"),
+    };
+    // ** an interrupted run writes the samples it recorded, and says so in the provenance
+    let interrupted = samples.ncols() < n_samples;
+    if interrupted {
+        eprintln!("% interrupted after {} of {n_samples} samples", samples.ncols());
+    }
+
+    if let Some(path) = samples_path {
+        let mut provenance = smas::io::Provenance::new("smas flux samples (reactions x samples)", "sample");
+        provenance.row_labels = reaction_labels;
+        provenance.parameters.push((String::from("method"), String::from("hit-and-run")));
+        provenance.parameters.push((String::from("burn in"), burn_in.to_string()));
+        provenance.parameters.push((String::from("thin"), thin.to_string()));
+        provenance.parameters.push((String::from("seed"), seed.to_string()));
+        provenance.parameters.push((String::from("max flux"), max_flux.to_string()));
+        if interrupted {
+            provenance.parameters.push((String::from("interrupted"), format!("after {} of {n_samples} samples", samples.ncols())));
+        }
+        provenance.inputs.push(match accumulation_path {
+            Some(path) => path.clone(),
+            None => String::from("-a (accumulation string)"),
+        });
+        add_matrix_provenance(&mut provenance, matches);
+        if let Some(path) = dg0_path {
+            provenance.parameters.push((String::from("temperature"), temperature.to_string()));
+            provenance.inputs.push(path.clone());
+        }
+        let results = get_formatter(matches).format_matrix(&samples, float_format, float_precision as usize, &provenance);
+        write_output(&results, Some(path), overwrite_policy);
+    }
+
+    if interrupted && samples.ncols() == 0 {
+        std::process::exit(smas::cancel::EXIT_INTERRUPTED);
+    }
+    let marginals = smas::sample::marginals(&samples, get_summation(matches))
+        .unwrap_or_else(|e| panic!("failed to summarize the samples: {e}"));
+    let results = smas::sample::format_marginals(&marginals, float_format, float_precision as usize);
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+    if interrupted {
+        std::process::exit(smas::cancel::EXIT_INTERRUPTED);
+    }
+}
+
+fn run_solution_set(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let max_flux = matches.get_one::<f64>("max_flux").copied();
+    let dg0_path = matches.get_one::<String>("dg0_path");
+    let temperature = *matches.get_one::<f64>("temperature").unwrap();
+    let vertices = matches.contains_id("vertices");
+
+    let (acc_vector, acc_orientation) = get_accumulation_vector(matches, parse_mode);
+
+    let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), acc_orientation))).matrix;
+
+    let mut bounds = dg0_path.map(|path| load_direction_bounds(path, &s_matrix, temperature, parse_mode));
+    if let Some(max_flux) = max_flux {
+        let bounds = bounds.get_or_insert_with(|| smas::constraints::Bounds::unbounded(s_matrix.ncols()));
+        bounds.lower.iter_mut().for_each(|l| *l = l.max(-max_flux));
+        bounds.upper.iter_mut().for_each(|u| *u = u.min(max_flux));
+    }
+
+    let set = smas::solution_set::solution_set(&acc_vector, &s_matrix, bounds.as_ref())
+        .unwrap_or_else(|e| panic!("failed to describe the solution set: {e}"));
+    let vertex_matrix = if vertices {
+        let bounds = bounds.as_ref()
+            .unwrap_or_else(|| panic!("--vertices requires bounds from --max-flux or --dg0"));
+        Some(
+            smas::solution_set::enumerate_vertices(&set, bounds, smas::solution_set::MAX_VERTEX_COMBINATIONS)
+                .unwrap_or_else(|e| panic!("failed to enumerate vertices: {e}"))
+        )
+    } else {
+        None
+    };
+
+    let results = smas::solution_set::format_solution_set(
+        &set,
+        vertex_matrix.as_ref(),
+        float_format,
+        float_precision as usize,
+    );
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_generate_acc(matches: &ArgMatches) {
+    let reactions_path = matches.get_one::<String>("reactions_path").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let noise = *matches.get_one::<f64>("noise").unwrap();
+    let seed = *matches.get_one::<u64>("seed").unwrap();
+    let noise_kind = match matches.get_one::<String>("noise_kind").map(|s| s.as_str()) {
+        Some("absolute") => smas::generate::NoiseKind::Absolute,
+        _ => smas::generate::NoiseKind::Relative,
+    };
+
+    let reactions = smas::io::load_vector_with_mode(reactions_path, parse_mode)
+        .unwrap_or_else(|e| panic!("failed to load reaction vector file: {e}"));
+    let smas::io::LabeledMatrix {
+        matrix: s_matrix,
+        row_labels: metabolite_labels,
+        ..
+    } = get_labeled_s_matrix(matches, parse_mode);
+
+    let acc_vector = smas::generate::generate_accumulation(&s_matrix, &reactions, noise, noise_kind, seed)
+        .unwrap_or_else(|e| panic!("failed to generate the accumulation vector: {e}"));
+
+    let mut provenance = smas::io::Provenance::new("smas accumulation vector", "generate-acc");
+    provenance.row_labels = metabolite_labels;
+    provenance.parameters.push((String::from("noise"), noise.to_string()));
+    provenance.parameters.push((String::from("noise kind"), format!("{noise_kind:?}").to_lowercase()));
+    provenance.parameters.push((String::from("seed"), seed.to_string()));
+    provenance.inputs.push(reactions_path.clone());
+    add_matrix_provenance(&mut provenance, matches);
+
+    let results = get_formatter(matches).format_vector(
+        &acc_vector,
+        float_format,
+        float_precision as usize,
+        &provenance,
+    );
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_export_equations(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let output_format = matches.get_one::<String>("output_format").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    // ** only reaction equations record which reactions are reversible
+    let reversible = get_matrix_source(matches).filter(|(_, equations)| *equations).map(|(path, _)| {
+        load_reaction_model(path).reversible
+    });
+
+    let results = match output_format.as_str() {
+        // ** the canonical form has no provenance header, so that it only changes with the model
+        "canonical" => smas::io::format_reaction_equations_canonical(&s_matrix, reversible.as_deref()),
+        _ => {
+            let mut provenance = smas::io::Provenance::new("smas reaction equations", "export-equations");
+            add_matrix_provenance(&mut provenance, matches);
+            format!(
+                "{}{}",
+                provenance.format_header(),
+                smas::io::format_reaction_equations(&s_matrix, reversible.as_deref()),
+            )
+        }
+    };
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+/// This reads the flux bounds of fba and fva from --bounds. Without it, only reaction equations say
+/// which reactions cannot run backwards, and the reactions of a matrix file are unbounded.
+fn get_lp_bounds(matches: &ArgMatches, n_reactions: usize) -> smas::constraints::Bounds {
+    match matches.get_one::<String>("bounds_path") {
+        Some(path) => smas::io::load_bounds(path, n_reactions).unwrap_or_else(|e| panic!("failed to load bounds file: {e}")),
+        None => match get_matrix_source(matches).filter(|(_, equations)| *equations) {
+            Some((path, _)) => smas::constraints::Bounds::from_directions(&load_reaction_model(path).reversible),
+            None => smas::constraints::Bounds::unbounded(n_reactions),
+        },
+    }
+}
+
+/// This reads the accumulation vector of fba and fva from its positional path, or from -a, or
+/// returns None for a steady state if neither was given.
+fn get_optional_accumulation(matches: &ArgMatches, parse_mode: smas::io::ParseMode, n_metabolites: usize) -> Option<na::DVector<f64>> {
+    let acc_vector = match (matches.get_one::<String>("accumulation_path"), matches.get_one::<String>("accumulation_string")) {
+        (Some(path), _) => smas::io::load_vector_with_mode(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load accumulation vector file: {e}")),
+        (None, Some(vector_string)) => parse_accumulation_string(vector_string, matches, parse_mode),
+        (None, None) => return None,
+    };
+    if acc_vector.nrows() != n_metabolites {
+        panic!("the accumulation vector has {} values but the matrix has {n_metabolites} metabolites", acc_vector.nrows());
+    }
+    Some(acc_vector)
+}
+
+/// This adds the bounds and accumulation vector of fba and fva to their provenance.
+fn add_lp_provenance(provenance: &mut smas::io::Provenance, matches: &ArgMatches) {
+    if let Some(path) = matches.get_one::<String>("bounds_path") {
+        provenance.inputs.push(path.clone());
+    }
+    match (matches.get_one::<String>("accumulation_path"), matches.get_one::<String>("accumulation_string")) {
+        (Some(path), _) => provenance.inputs.push(path.clone()),
+        (None, Some(_)) => provenance.inputs.push(String::from("-a (accumulation string)")),
+        (None, None) => provenance.parameters.push((String::from("accumulation"), String::from("steady state"))),
+    }
+}
+
+fn run_fba(matches: &ArgMatches) {
+    let objective_arg = matches.get_one::<String>("objective").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let report = matches.contains_id("report");
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let (m, n) = s_matrix.matrix.shape();
+    // ** an objective that is not a file is reaction=weight pairs, e.g. "biomass=1"
+    let objective = if smas::io::is_keyed_vector(objective_arg) && !Path::new(objective_arg).is_file() {
+        let labels: Vec<String> = (0..n).map(|j| s_matrix.col_label(j)).collect();
+        smas::io::parse_keyed_vector(objective_arg, &labels)
+            .unwrap_or_else(|e| panic!("failed to parse the objective: {e}"))
+    } else {
+        smas::io::load_vector_with_mode(objective_arg, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load objective file: {e}"))
+    };
+    if objective.nrows() != n {
+        panic!("the objective has {} weights but the matrix has {n} reactions", objective.nrows());
+    }
+    let bounds = get_lp_bounds(matches, n);
+    let acc_vector = get_optional_accumulation(matches, parse_mode, m);
+
+    let solution = smas::solve::fba::fba(
+        acc_vector.as_ref(),
+        &s_matrix.matrix,
+        &objective,
+        &bounds,
+        &smas::solve::SolveOptions::default(),
+    ).unwrap_or_else(|e| match e {
+        smas::error::SmasError::Unbounded => panic!(
+            "failed to solve: {e}; bound the uptake reactions with --bounds, e.g. starting from smas init-bounds"
+        ),
+        e => panic!("failed to solve: {e}"),
+    });
+    let objective_value = objective.dot(&solution.reactions);
+    eprintln!("% objective value: {objective_value:e}");
+    if report {
+        eprintln!("{}", solution.format_report());
+        if let Some(duals) = &solution.duals {
+            eprintln!("{}", duals.format(float_format, float_precision as usize));
+        }
+    }
+
+    let mut results_vector = solution.reactions;
+    smas::util::clamp_to_zero(&mut results_vector, epsilon);
+    let mut provenance = smas::io::Provenance::new("smas flux balance analysis reaction vector", "fba");
+    provenance.row_labels = s_matrix.col_labels.clone();
+    provenance.parameters.push((String::from("method"), String::from(solution.method)));
+    provenance.parameters.push((String::from("objective value"), objective_value.to_string()));
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    provenance.inputs.push(objective_arg.clone());
+    add_lp_provenance(&mut provenance, matches);
+    add_matrix_provenance(&mut provenance, matches);
+    let out_path = out_path.map(|p| p.as_str());
+    let results_matrix = na::DMatrix::from_column_slice(n, 1, results_vector.as_slice());
+    if write_arrow_output(&results_matrix, "reaction", "R", vec![String::from("flux")], &provenance, out_path, overwrite_policy) {
+        return;
+    }
+    let results = get_formatter(matches).format_vector(&results_vector, float_format, float_precision as usize, &provenance);
+    write_output(&results, out_path, overwrite_policy);
+}
+
+fn run_fva(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let (m, n) = s_matrix.matrix.shape();
+    let bounds = get_lp_bounds(matches, n);
+    let acc_vector = get_optional_accumulation(matches, parse_mode, m);
+
+    let mut ranges = smas::solve::fva::fva(
+        acc_vector.as_ref(),
+        &s_matrix.matrix,
+        &bounds,
+        epsilon,
+        &smas::solve::SolveOptions::default(),
+    ).unwrap_or_else(|e| panic!("failed to solve: {e}"));
+    ranges.iter_mut()
+        .filter(|val| smas::util::epsilon_eq(**val, 0.0, epsilon))
+        .for_each(|val| *val = 0.0);
+    let unbounded = ranges.row_iter().filter(|range| range.iter().any(|val| val.is_infinite())).count();
+    if unbounded > 0 {
+        eprintln!("% {unbounded} of {n} reactions are unbounded; bound the uptake reactions with --bounds, e.g. starting from smas init-bounds");
+    }
+
+    let mut provenance = smas::io::Provenance::new("smas flux variability analysis (reactions x minimum, maximum)", "fva");
+    provenance.row_labels = s_matrix.col_labels.clone();
+    provenance.col_labels = Some(vec![String::from("minimum"), String::from("maximum")]);
+    provenance.parameters.push((String::from("epsilon"), epsilon.to_string()));
+    add_lp_provenance(&mut provenance, matches);
+    add_matrix_provenance(&mut provenance, matches);
+    let out_path = out_path.map(|p| p.as_str());
+    let col_labels = provenance.col_labels.clone().unwrap();
+    if write_arrow_output(&ranges, "reaction", "R", col_labels, &provenance, out_path, overwrite_policy) {
+        return;
+    }
+    let results = get_formatter(matches).format_matrix(&ranges, float_format, float_precision as usize, &provenance);
+    write_output(&results, out_path, overwrite_policy);
+}
+
+fn run_regress(matches: &ArgMatches) {
+    let corpus_dir = Path::new(matches.get_one::<String>("corpus_dir").unwrap());
+    let update = matches.contains_id("update");
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let cases = smas::regress::load_corpus(corpus_dir)
+        .unwrap_or_else(|e| panic!("failed to load the corpus manifest {}: {e}", corpus_dir.join(smas::regress::MANIFEST_NAME).display()));
+    if update {
+        for case in &cases {
+            smas::regress::update_case(corpus_dir, case, parse_mode)
+                .unwrap_or_else(|e| panic!("failed to update case {}: {e}", case.name));
+        }
+        eprintln!("% stored the expected output of {} cases in {}", cases.len(), corpus_dir.join(smas::regress::EXPECTED_DIR).display());
+        return;
+    }
+
+    let statuses: Vec<smas::regress::CaseStatus> = cases.iter()
+        .map(|case| smas::regress::check_case(corpus_dir, case, parse_mode, epsilon, get_summation(matches)))
+        .collect();
+    let results = smas::regress::format_regression_report(&cases, &statuses, float_format, float_precision as usize);
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+    if !statuses.iter().all(|status| status.passed()) {
+        std::process::exit(1);
+    }
+}
+
+fn run_analyze(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let analysis = smas::audit::analyze_matrix(&s_matrix.matrix);
+    let mut provenance = smas::io::Provenance::new("smas matrix analysis", "analyze");
+    add_matrix_provenance(&mut provenance, matches);
+    let results = format!("{}{}", provenance.format_header(), smas::audit::format_analysis(&analysis));
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_moieties(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let acc_vector = get_optional_accumulation(matches, parse_mode, s_matrix.matrix.nrows());
+    let pools = smas::moieties::conserved_pools(&s_matrix.matrix);
+    let mut provenance = smas::io::Provenance::new("smas conserved moieties", "moieties");
+    add_matrix_provenance(&mut provenance, matches);
+    match (matches.get_one::<String>("accumulation_path"), matches.get_one::<String>("accumulation_string")) {
+        (Some(path), _) => provenance.inputs.push(path.clone()),
+        (None, Some(_)) => provenance.inputs.push(String::from("-a (accumulation string)")),
+        (None, None) => {}
+    }
+    let output = smas::moieties::format_pools(&s_matrix, &pools, acc_vector.as_ref(), float_format, float_precision as usize);
+    write_output(&format!("{}{output}", provenance.format_header()), out_path.map(|p| p.as_str()), overwrite_policy);
+
+    // ** a measured accumulation that changes a conserved pool cannot be produced by the model
+    if let Some(acc_vector) = &acc_vector {
+        let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+        let leaking = pools.iter().filter(|pool| pool.total(acc_vector).abs() > epsilon).count();
+        if leaking > 0 {
+            eprintln!("% warning: the accumulation vector changes the total of {leaking} conserved pools");
+        }
+    }
+}
+
+fn run_grep(matches: &ArgMatches) {
+    let pattern = matches.get_one::<String>("pattern").unwrap();
+    let ignore_case = matches.contains_id("ignore_case");
+    let solution_path = matches.get_one::<String>("solution_path");
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let reversible = get_matrix_source(matches).filter(|(_, equations)| *equations).map(|(path, _)| {
+        load_reaction_model(path).reversible
+    });
+    let fluxes = solution_path.map(|path| {
+        let fluxes = smas::io::load_vector_with_mode(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load solution file: {e}"));
+        if fluxes.nrows() != s_matrix.matrix.ncols() {
+            panic!("the solution has {} fluxes but the matrix has {} reactions", fluxes.nrows(), s_matrix.matrix.ncols());
+        }
+        fluxes
+    });
+
+    let results = smas::search::search(&s_matrix, pattern, ignore_case);
+    let output = smas::search::format_search(
+        &s_matrix,
+        &results,
+        reversible.as_deref(),
+        fluxes.as_ref(),
+        float_format,
+        float_precision as usize,
+    );
+    write_output(&output, out_path.map(|p| p.as_str()), overwrite_policy);
+    // ** like grep, finding nothing is a failure, for scripts
+    if results.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn run_import_pathway(matches: &ArgMatches) {
+    let pathway_path = matches.get_one::<String>("pathway_path").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let output_format = matches.get_one::<String>("output_format").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let import = smas::pathway::load_pathway(pathway_path)
+        .unwrap_or_else(|e| panic!("failed to import pathway file {pathway_path}: {e}"));
+    eprintln!("{}", smas::pathway::format_import_report(&import));
+
+    let s_matrix = &import.model.s_matrix;
+    let title = match output_format.as_str() {
+        "matrix" => "smas stoichiometric matrix (metabolites x reactions)",
+        _ => "smas reaction equations",
+    };
+    let mut provenance = smas::io::Provenance::new(title, "import-pathway");
+    provenance.parameters.push((String::from("pathway format"), import.format.to_string()));
+    provenance.parameters.push((String::from("ambiguities"), import.ambiguities.len().to_string()));
+    provenance.inputs.push(pathway_path.clone());
+    let results = match output_format.as_str() {
+        "matrix" => {
+            provenance.row_labels = s_matrix.row_labels.clone();
+            provenance.col_labels = s_matrix.col_labels.clone();
+            get_formatter(matches).format_matrix(&s_matrix.matrix, float_format, float_precision as usize, &provenance)
+        }
+        _ => format!(
+            "{}{}",
+            provenance.format_header(),
+            smas::io::format_reaction_equations(s_matrix, Some(&import.model.reversible)),
+        ),
+    };
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_snap(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let tolerance = *matches.get_one::<f64>("tolerance").unwrap();
+    let max_denominator = if matches.contains_id("to_integers") {
+        1
+    } else {
+        *matches.get_one::<u32>("max_denominator").unwrap()
+    };
+
+    let mut s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let report = smas::snap::snap_matrix(&mut s_matrix.matrix, max_denominator, tolerance);
+    eprintln!("{}", smas::snap::format_snap_report(&report, &s_matrix, float_format, float_precision as usize));
+
+    let mut provenance = smas::io::Provenance::new("smas stoichiometric matrix (metabolites x reactions)", "snap");
+    provenance.parameters.push((String::from("tolerance"), tolerance.to_string()));
+    provenance.parameters.push((String::from("max denominator"), max_denominator.to_string()));
+    add_matrix_provenance(&mut provenance, matches);
+    provenance.row_labels = s_matrix.row_labels;
+    provenance.col_labels = s_matrix.col_labels;
+
+    let results = get_formatter(matches).format_matrix(
+        &s_matrix.matrix,
+        float_format,
+        float_precision as usize,
+        &provenance,
+    );
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_init_bounds(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let n = s_matrix.matrix.ncols();
+    // ** only reaction equations record which reactions are reversible
+    let reversible = get_matrix_source(matches).filter(|(_, equations)| *equations).map(|(path, _)| {
+        load_reaction_model(path).reversible
+    });
+    let bounds = match &reversible {
+        Some(reversible) => smas::constraints::Bounds::from_directions(reversible),
+        None => smas::constraints::Bounds::unbounded(n),
+    };
+    let exchange = smas::solve::exchange_reactions(&s_matrix.matrix);
+    let comments: Vec<String> = (0..n)
+        .map(|j| if exchange[j] {
+            format!("{} exchange", s_matrix.col_label(j))
+        } else {
+            s_matrix.col_label(j)
+        })
+        .collect();
+
+    let n_exchange = exchange.iter().filter(|exchange| **exchange).count();
+    eprintln!("% {n} reactions, {n_exchange} flagged as exchange reactions");
+    match &reversible {
+        Some(reversible) => eprintln!(
+            "% {} irreversible reactions bounded below by 0",
+            reversible.iter().filter(|reversible| !**reversible).count(),
+        ),
+        None => eprintln!("% the matrix does not record reaction directions, so every reaction is unbounded; \
+            use --equations to bound irreversible reactions below by 0"),
+    }
+
+    let mut provenance = smas::io::Provenance::new("smas bounds template (lower upper per reaction)", "init-bounds");
+    add_matrix_provenance(&mut provenance, matches);
+    let results = format!("{}{}", provenance.format_header(), smas::io::format_bounds(&bounds, &comments));
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_model_diff(matches: &ArgMatches) {
+    let old_path = matches.get_one::<String>("old_path").unwrap();
+    let new_path = matches.get_one::<String>("new_path").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap();
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let pattern_value = *matches.get_one::<f64>("pattern_value").unwrap();
+
+    let mut old = smas::io::load_labeled_matrix(old_path, parse_mode, pattern_value)
+        .unwrap_or_else(|e| panic!("failed to load old stoichiometric matrix file: {e}"));
+    let mut new = smas::io::load_labeled_matrix(new_path, parse_mode, pattern_value)
+        .unwrap_or_else(|e| panic!("failed to load new stoichiometric matrix file: {e}"));
+    if let Some((id_map, namespace)) = get_id_map(matches) {
+        translate_labels(&mut old, &id_map, &namespace, old_path);
+        translate_labels(&mut new, &id_map, &namespace, new_path);
+    }
+
+    let diff = smas::model_diff::diff_models(&old, &new, epsilon);
+    // ** two versions of a model are expected to differ, but two copies of one version are not
+    match (get_model_version(old_path), get_model_version(new_path)) {
+        (Some(old_version), Some(new_version)) if old_version != new_version => {
+            eprintln!("% comparing model {old_version} with model {new_version}");
+        }
+        (Some(version), Some(_)) if !diff.is_empty() => {
+            eprintln!("% warning: {old_path} and {new_path} both declare model {version}, but differ");
+        }
+        _ => (),
+    }
+    let results = smas::model_diff::format_model_diff(&diff, float_format, float_precision as usize);
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+fn run_heatmap(matches: &ArgMatches) {
+    let out_path = matches.get_one::<String>("out_path");
+    let parse_mode = get_parse_mode(matches);
+    let overwrite_policy = get_overwrite_policy(matches);
+    let width = matches.get_one::<usize>("width").copied();
+
+    // ** colors are escape sequences, so they are only written to the terminal
+    let style = smas::heatmap::HeatmapStyle {
+        magnitude: matches.contains_id("magnitude"),
+        color: out_path.is_none() && !matches.contains_id("no_color"),
+        ascii: matches.contains_id("ascii"),
+    };
+    let s_matrix = get_labeled_s_matrix(matches, parse_mode);
+    let results = smas::heatmap::format_heatmap(&s_matrix, style, width);
+    write_output(&results, out_path.map(|p| p.as_str()), overwrite_policy);
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(matches: &ArgMatches) {
+    let reactions_path = matches.get_one::<String>("reactions_path");
+    let epsilon = *matches.get_one::<f64>("epsilon").unwrap();
+    let parse_mode = get_parse_mode(matches);
+
+    let (acc_vector, acc_orientation) = get_accumulation_vector(matches, parse_mode);
+    let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), acc_orientation)));
+    let truth = reactions_path.map(|path| {
+        smas::io::load_vector_with_mode(path, parse_mode)
+            .unwrap_or_else(|e| panic!("failed to load true reaction vector file: {e}"))
+    });
+    let solution = smas::solve::solve_pinv(&acc_vector, &s_matrix.matrix)
+        .unwrap_or_else(|e| panic!("failed to solve: {e}"));
+
+    let mut dashboard = smas::tui::Dashboard::new(s_matrix, acc_vector, solution.reactions, truth, epsilon);
+    smas::tui::run(&mut dashboard)
+        .unwrap_or_else(|e| panic!("failed to run the dashboard: {e}"));
+}
+
+/// This builds the REPL session shared by repl and script: from a saved session, or from the
+/// stoichiometric matrix and accumulation vector.
+fn get_repl(matches: &ArgMatches) -> smas::repl::Repl {
+    let float_format = get_float_format(matches);
+    let float_precision = *matches.get_one::<u8>("float_precision").unwrap() as usize;
+    let parse_mode = get_parse_mode(matches);
+
+    match matches.get_one::<String>("session_path") {
+        Some(path) => {
+            let session = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read the session file: {e}"));
+            smas::repl::Repl::parse_session(&session)
+                .unwrap_or_else(|e| panic!("failed to load the session file: {e}"))
+        }
+        None => {
+            let (acc_vector, acc_orientation) = get_accumulation_vector(matches, parse_mode);
+            let s_matrix = get_oriented_s_matrix(matches, parse_mode, Some((acc_vector.nrows(), acc_orientation)));
+            let model = smas::repl::Model::new(s_matrix, acc_vector)
+                .unwrap_or_else(|e| panic!("failed to build the model: {e}"));
+            smas::repl::Repl::new(model, float_format, float_precision)
+        }
+    }
+}
+
+fn run_repl(matches: &ArgMatches) {
+    let mut repl = get_repl(matches);
+    smas::repl::run(&mut repl, std::io::stdin().lock(), std::io::stdout())
+        .unwrap_or_else(|e| panic!("failed to run the REPL: {e}"));
+}
+
+fn run_script(matches: &ArgMatches) {
+    let script_path = matches.get_one::<String>("script_path").unwrap();
+    let out_path = matches.get_one::<String>("out_path");
+    let overwrite_policy = get_overwrite_policy(matches);
+
+    let script = std::fs::read_to_string(script_path)
+        .unwrap_or_else(|e| panic!("failed to read the script file: {e}"));
+    let mut repl = get_repl(matches);
+    let mut output = vec!();
+    let result = smas::repl::run_script(&mut repl, &script, &mut output, matches.contains_id("echo"));
+    // ** the replies of the commands that ran are written even when a later one fails
+    let output = String::from_utf8(output).expect("replies are UTF-8");
+    write_output(output.trim_end(), out_path.map(|p| p.as_str()), overwrite_policy);
+    result.unwrap_or_else(|e| panic!("failed to run {script_path}: {e}"));
+}
+
+fn run_json_rpc() {
+    let mut request = String::new();
+    std::io::Read::read_to_string(&mut std::io::stdin(), &mut request)
+        .unwrap_or_else(|e| panic!("failed to read the request: {e}"));
+    let (response, ok) = smas::json_rpc::handle_request(&request);
+    println!("{response}");
+    if !ok {
+        std::process::exit(1);
+    }
+}
+
+fn run_selftest() {
+    let results = smas::selftest::run_selftest();
+    println!("{}", smas::selftest::format_selftest_results(&results));
+    if results.iter().any(|r| !r.passed) {
+        std::process::exit(1);
+    }
+}
+
+fn run_examples(matches: &ArgMatches) {
+    if matches.contains_id("list") {
+        println!("{}", smas::matrices::format_datasets());
+        return;
+    }
+    match matches.get_one::<String>("extract") {
+        Some(dir) => {
+            let paths = smas::examples::extract_examples(dir)
+                .expect("failed to extract example files");
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        None => {
+            for (name, _) in smas::examples::EXAMPLE_FILES {
+                println!("{name}");
+            }
+        }
+    }
+}
+
+fn solve_command() -> App<'static> {
+    let mut command = Command::new("solve")
+        .about("Solve for a reaction vector given an accumulation vector")
+        .arg(
+            arg!([accumulation_path] ... "The paths to one or more stoichiometric accumulation vector files in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(--"out-template" <template> "A template for naming the output file of each input, \
+                e.g. \"results/{stem}_solution.{ext}\". The placeholders {stem}, {ext}, {index}, {date} \
+                and {method} are replaced with the input file stem and extension, the input's position, \
+                the current date, and the solver method.")
+                .id("out_template")
+                .required(false)
+        )
+        .arg(
+            arg!(--report "Print solver diagnostics (method, iterations, convergence, residual norm, and dual values for linear programming methods) to stderr.")
+        )
+        .arg(
+            arg!(--"report-residual" "Print the residual norm of the solution, ||Sx - a||, to stderr, with a warning if it is larger \
+                than epsilon, i.e. the accumulation vector is not consistent with the matrix.")
+                .id("report_residual")
+        )
+        .arg(
+            arg!(--"check-consistency" "Before solving, project the accumulation vector onto the column space of the matrix, \
+                report the fraction of it the matrix can explain, and fail if more than --consistency-tolerance of it cannot \
+                be produced by any reaction vector, rather than returning a least squares compromise.")
+                .id("check_consistency")
+        )
+        .arg(
+            arg!(--"consistency-tolerance" <fraction> "The largest fraction of the squared norm of the accumulation vector \
+                that --check-consistency allows outside the column space of the matrix.")
+                .id("consistency_tolerance")
+                .required(false)
+                .default_value("1e-6")
+                .requires("check_consistency")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"audit-numerics" "Check the singular values, condition number, and the ranges of the matrix, accumulation vector \
+                and solution for values where roundoff may dominate the answer, and print the findings to stderr.")
+                .id("audit_numerics")
+        )
+        .arg(
+            arg!(--"dump-intermediates" <dir> "Write the intermediate results of the pseudoinverse solve to a directory for \
+                teaching: U.txt, sigma.txt and V.txt (the SVD S = U Sigma V^T), pinv.txt (the pseudoinverse) and \
+                projection.txt (the projection of B onto the column space of S).")
+                .id("dump_intermediates_path")
+                .required(false)
+                .conflicts_with("fuse")
+        )
+        .arg(
+            arg!(--"residuals-out" <path> "Write the residual of each metabolite, Sx - a, to a file with the metabolite \
+                labels, to see which measurements the model fails to explain. Flux measurement and prior rows are left out.")
+                .id("residuals_out_path")
+                .required(false)
+                .conflicts_with("fuse")
+        )
+        .arg(
+            arg!(--explain "Narrate the solve to stderr step by step: the dimensions, the scaling, the singular values kept \
+                and discarded, the size of the null space component of the solution, and the residual of each metabolite.")
+        )
+        .arg(
+            arg!(--"constraint-report" "Print each constraint with its slack at the solution, and whether it is binding, to stderr.")
+                .id("constraint_report")
+        )
+        .arg(
+            arg!(--loopless "Remove flux around internal loops from the solution while keeping exchange fluxes and the fit to the accumulation vector.")
+        )
+        .arg(
+            arg!(--integerize "Round the solution to a nearby one whose fluxes are whole numbers, or multiples of \
+                1/--integerize-denominator, for counts of discrete reaction events rather than rates. Each flux is rounded \
+                in turn and the others are refit, so that the residual grows by at most --integerize-tolerance.")
+                .conflicts_with_all(&["bounds_path", "dg0_path", "residual_norm", "fuse"])
+        )
+        .arg(
+            arg!(--"integerize-denominator" <denominator> "The denominator of the fluxes --integerize rounds to, e.g. 2 for halves.")
+                .id("integerize_denominator")
+                .required(false)
+                .default_value("1")
+                .value_parser(value_parser!(u32).range(1..))
+        )
+        .arg(
+            arg!(--"integerize-tolerance" <tolerance> "How much larger the residual norm of the rounded solution may be than \
+                that of the least squares solution.")
+                .id("integerize_tolerance")
+                .required(false)
+                .default_value("1e-6")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--bounds <bounds_path> "The path to a file with a lower and an upper bound on each reaction, one \"lower upper\" \
+                line per reaction in column order; \"inf\" and \"-inf\" leave a side unbounded. The solve is the least squares \
+                solution within the bounds. With --dg0, both sets of bounds apply.")
+                .id("bounds_path")
+                .required(false)
+                .conflicts_with("fuse")
+        )
+        .arg(
+            arg!(--solver <solver> "Solve with the minimum norm pseudoinverse (pinv), which may give negative fluxes, with \
+                non-negative least squares (nnls), for models where every reaction is irreversible, with LSQR (lsqr), which \
+                iterates to the minimum norm solution without a full SVD, for large matrices, or with a QR decomposition (qr), \
+                which is faster than pinv for matrices with independent reactions and an error otherwise, or with the lasso \
+                (lasso), which penalizes the one-norm of the fluxes by --lambda for a sparse solution, with most fluxes \
+                exactly 0, or with the Huber loss (huber), which fits residuals beyond --huber-delta linearly so that a few \
+                outlying metabolites do not dominate the fit. With --dg0 or --bounds, nnls also applies those bounds. \
+                auto picks nnls if the equations make every reaction irreversible, LSQR for a large sparse matrix, and pinv \
+                otherwise, and gives its reasons with --explain or --report. --method is another name for this option.")
+                .required(false)
+                .alias("method")
+                .default_value("pinv")
+                .value_parser(["pinv", "nnls", "lsqr", "qr", "lasso", "huber", "auto"])
+                .conflicts_with_all(&["fuse", "out_of_core", "sparse"])
+        )
+        .arg(
+            arg!(--"residual-norm" <norm> "The norm the residual ||Sx - a|| is measured with, in the --report and as the \
+                objective of the solve: l2 (least squares), l1 (least absolute deviations, which tolerates a few outlying \
+                metabolites) or linf (the smallest worst-case residual). l1 and linf are solved as linear programs, \
+                within the bounds of --solver nnls, --dg0 and --bounds.")
+                .id("residual_norm")
+                .required(false)
+                .default_value("l2")
+                .value_parser(["l1", "l2", "linf"])
+                .conflicts_with_all(&["fuse", "out_of_core", "sparse"])
+        )
+        .arg(
+            arg!(--"flux-measurements" <flux_measurements_path> "The path to a CSV file of measured fluxes with one reaction,value,std_dev row per measurement \
+                (reactions numbered from 1). The measurements are fitted together with the accumulation vector, weighted by 1/std_dev.")
+                .id("flux_measurements_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--prior <prior_path> "The path to a prior reaction vector file in the Matrix Market array format. \
+                The solution minimizes ||Sx - a||^2 + w^2 ||x - prior||^2, where w is --prior-weight.")
+                .id("prior_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--"prior-weight" <weight> "The weight w of the prior reaction vector relative to the accumulation vector.")
+                .id("prior_weight")
+                .required(false)
+                .default_value("1.0")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--fuse <lambda> "Solve all accumulation vectors jointly as experimental conditions, penalizing differences between \
+                the reaction vectors of each pair of conditions by lambda^2 ||x_k - x_l||^2. The penalty is quadratic, not the one-norm \
+                of a fused lasso: it pulls the conditions towards each other but does not make their fluxes exactly equal. The output \
+                is a reactions x conditions matrix.")
+                .required(false)
+                .value_parser(value_parser!(f64))
+                .conflicts_with_all(&[
+                    "report", "report_residual", "explain", "audit_numerics", "constraint_report", "loopless", "dg0_path",
+                    "flux_measurements_path", "prior_path", "out_template",
+                ])
+        )
+        .arg(
+            arg!(--replicates <paths> ... "The paths to replicate accumulation vector files, which are combined element by element \
+                with --aggregate and solved as one accumulation vector. The spread of each element is reported to stderr.")
+                .required(false)
+                .multiple_values(true)
+                .conflicts_with_all(&["accumulation_path", "accumulation_string"])
+        )
+        .arg(
+            arg!(--aggregate <aggregate> "How replicates are combined: the mean, with the standard deviation as the spread, \
+                or the median, with the scaled median absolute deviation as the spread.")
+                .required(false)
+                .value_parser(["mean", "median"])
+                .default_value("mean")
+        )
+        .arg(
+            arg!(--"reject-outliers" <test> "Leave outlying replicate values of each metabolite out of the aggregate, and report \
+                them to stderr: mad rejects values with a modified z-score above --mad-threshold, and grubbs repeats the \
+                two-sided Grubbs test at significance --grubbs-alpha, which assumes normally distributed replicates.")
+                .id("reject_outliers")
+                .required(false)
+                .value_parser(["mad", "grubbs"])
+        )
+        .arg(
+            arg!(--"mad-threshold" <threshold> "The modified z-score above which --reject-outliers mad rejects a value.")
+                .id("mad_threshold")
+                .required(false)
+                .value_parser(value_parser!(f64))
+                .default_value("3.5")
+        )
+        .arg(
+            arg!(--"grubbs-alpha" <alpha> "The significance level of --reject-outliers grubbs.")
+                .id("grubbs_alpha")
+                .required(false)
+                .value_parser(value_parser!(f64))
+                .default_value("0.05")
+        )
+        .arg(
+            arg!(--"out-of-core" "Solve with LSQR against the matrix file (-s) without loading it, for matrices that do not fit \
+                in memory. The file is parsed once into a binary cache of its nonzero entries in the temporary directory, which is \
+                memory mapped and read twice per iteration. Slower than the default solver.")
+                .id("out_of_core")
+                .conflicts_with_all(LSQR_CONFLICTS)
+        )
+        .arg(
+            arg!(--sparse "Solve with LSQR against the matrix file (-s) loaded as a sparse matrix, which needs memory for \
+                its nonzero entries only. Large coordinate files with at most 5% nonzero entries are solved this way \
+                automatically when no option needs the dense matrix.")
+                .conflicts_with_all(LSQR_CONFLICTS)
+                .conflicts_with("out_of_core")
+        )
+        .arg(
+            arg!(--exact "Solve with exact fractions rather than floats, for matrices of small integers: the same minimum \
+                norm solution as the default solver, without tiny nonzero artifacts of rounding. Decimal entries are read as \
+                the fractions they stand for, e.g. 0.1 as 1/10; large matrices with many decimals can overflow.")
+                .conflicts_with_all(&[
+                    "out_of_core", "sparse", "solver", "residual_norm", "replicates", "fuse", "dg0_path", "bounds_path",
+                    "flux_measurements_path", "prior_path", "loopless", "explain", "dump_intermediates_path", "constraint_report",
+                    "audit_numerics", "out_template", "merge_suffix", "weight_by_spread", "residuals_out_path",
+                    "check_consistency", "integerize",
+                ])
+        )
+        .arg(
+            arg!(--"exact-format" <format> "How --exact writes the reaction vector: as exact fractions, e.g. 3/2, or as floats.")
+                .id("exact_format")
+                .required(false)
+                .default_value("fraction")
+                .value_parser(["fraction", "float"])
+        )
+        .arg(
+            arg!(--lambda <lambda> "The weight of the one-norm penalty of --solver lasso: the larger, the fewer reactions carry \
+                flux and the larger the residual. At least the largest |S'a| makes every flux 0.")
+                .required(false)
+                .required_if_eq("solver", "lasso")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"huber-delta" <delta> "The residual at which the loss of --solver huber turns from quadratic to linear, \
+                about the size of the measurement noise of the accumulation vector: metabolites with larger residuals are \
+                treated as outliers and weighed less.")
+                .id("huber_delta")
+                .required(false)
+                .required_if_eq("solver", "huber")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"lsqr-tolerance" <tolerance> "The relative tolerance at which --solver lsqr, --out-of-core and --sparse stop iterating.")
+                .id("lsqr_tolerance")
+                .required(false)
+                .value_parser(value_parser!(f64))
+                .default_value("1e-10")
+        )
+        .arg(
+            arg!(--"max-iterations" <count> "The most iterations --solver lsqr, --out-of-core and --sparse run, sweeps \
+                --solver lasso runs, or reweightings --solver huber runs, before giving up.")
+                .id("max_iterations")
+                .required(false)
+                .value_parser(value_parser!(usize))
+                .default_value("10000")
+        )
+        .arg(
+            arg!(--"weight-by-spread" "Solve the weighted least squares problem with each metabolite weighted by 1 / spread \
+                over the replicates, so that reproducible metabolites pull harder on the fit.")
+                .id("weight_by_spread")
+                .conflicts_with("fuse")
+        )
+        .arg(
+            arg!(--"merge-suffix" <pattern> "Merge the metabolites whose labels differ only by a suffix matching this \
+                pattern, e.g. compartment tags (\"_?\" merges glc__D_c and glc__D_e into glc__D) or protonation states, \
+                summing their rows of the stoichiometric matrix and their accumulation values. '*' matches any run of \
+                characters and '?' any one character. May be given more than once.")
+                .id("merge_suffix")
+                .required(false)
+                .action(ArgAction::Append)
+        );
+
+    command = add_preprocessing_args(add_common_args(command));
+    #[cfg(feature = "mem-report")]
+    {
+        command = command.arg(
+            arg!(--"mem-report" "Report to stderr how much memory loading the matrix, the factorization and formatting \
+                the results took at their peak and kept, and the peak of the whole run, to size machines for larger models.")
+                .id("mem_report")
+        );
+    }
+    #[cfg(feature = "clipboard")]
+    {
+        command = command
+            .arg(
+                arg!(--"from-clipboard" "Read the accumulation vector from the system clipboard, e.g. a column copied from \
+                    a spreadsheet: values separated by whitespace, tabs, commas or new lines.")
+                    .id("from_clipboard")
+                    .conflicts_with_all(&["accumulation_path", "accumulation_string", "replicates", "out_of_core", "sparse", "exact"])
+            )
+            .arg(
+                arg!(--"to-clipboard" "Also copy the reaction vector to the system clipboard, as tab separated rows of labels \
+                    and fluxes that paste into spreadsheet cells.")
+                    .id("to_clipboard")
+                    .conflicts_with_all(&["out_of_core", "sparse", "exact"])
+            );
+    }
+    #[cfg(feature = "rhai")]
+    {
+        command = command.arg(
+            arg!(--"post-script" <path> "The path to a rhai script of derived quantities and reaction filters to run on each \
+                solution, ending with a map of its results, e.g. #{ yield: flux(\"biomass\") / flux(\"glc_in\") }; the results \
+                are reported to stderr.")
+                .id("post_script_path")
+                .required(false)
+                .conflicts_with_all(&["out_of_core", "sparse", "fuse"])
+        );
+    }
+    add_thermo_args(add_accumulation_args(command))
+}
+
+fn validate_command() -> App<'static> {
+    let command = Command::new("validate")
+        .about("A set of utilities designed to help validate computed results using ground truth data")
+        .arg(
+            arg!(<computed_path> "The path to a computed reaction vector file in the Matrix Market array format.")
+                .required(false)
+                .required_unless_present("pairs_path")
+        )
+        .arg(
+            arg!(-r <reactions_path> "The path to a true stoichiometric reaction vector file in the Matrix Market array format. \
+                        smas will compare the computed vector to this vector.")
+                .required(false)
+                .required_unless_present("pairs_path")
+        )
+        .arg(
+            arg!(--pairs <pairs_path> "The path to a manifest of \"computed,truth\" rows of paths, relative to the manifest, \
+                to compare in one run, e.g. to regression test a solver change across a corpus. Writes one summary row per \
+                pair and a summary over every pair, and exits with 1 if any pair fails.")
+                .id("pairs_path")
+                .required(false)
+                .conflicts_with_all(&["computed_path", "reactions_path", "accumulation_path", "accumulation_string", "id_map_path"])
+        )
+        .arg(
+            arg!(--jobs <jobs> "The number of pairs of --pairs to compare at once (--threads by default).")
+                .required(false)
+                .requires("pairs_path")
+                .value_parser(value_parser!(usize))
+        )
+        .arg(
+            arg!(--accumulation <accumulation_path> "Optionally, the path to the accumulation vector file the computed vector was solved for. \
+                smas also checks, metabolite by metabolite, that the true vector reproduces it with the stoichiometric matrix.")
+                .id("accumulation_path")
+                .required(false)
+        )
+        .arg(
+            arg!(-a <accumulation_string> "Optionally, provide the accumulation vector to check the true vector against via stdin. \
+                The vector should be enclosed in quotes and whitespace delimited, \
+                e.g. \"0.0 1e5 0.5 0.3 0.0 ...\", or give metabolites by label, \
+                e.g. \"glc=-1.2 o2=-0.8 co2=2.3\", with the others 0.")
+                .required(false)
+                .conflicts_with("accumulation_path")
+        );
+    add_id_map_args(add_common_args(command))
+}
+
+fn jacobian_command() -> App<'static> {
+    let command = Command::new("jacobian")
+        .about("Export the Jacobian dx/db of the solution operator (the pseudoinverse of the stoichiometric matrix)")
+        .arg(
+            arg!(--"prior-weight" <weight> "Export the Jacobian of the prior weighted fit of solve --prior with this \
+                --prior-weight w instead, (S^T S + w^2 I)^-1 S^T, which does not depend on the prior itself.")
+                .id("prior_weight")
+                .required(false)
+                .value_parser(value_parser!(f64))
+        );
+    add_common_args(command)
+}
+
+fn timecourse_command() -> App<'static> {
+    let command = Command::new("timecourse")
+        .about("Fit reaction fluxes over time to accumulation measured at several time points")
+        .arg(
+            arg!(<accumulation_path> "The path to a Matrix Market array file with one column of accumulation per time point, \
+                each relative to a common starting point.")
+        )
+        .arg(
+            arg!(--times <times> "The whitespace delimited time of each column, e.g. \"0 1 2 4\".")
+        )
+        .arg(
+            arg!(--mode <mode> "Fit one constant flux vector, or a piecewise constant flux vector per interval.")
+                .required(false)
+                .default_value("piecewise")
+                .value_parser(["constant", "piecewise"])
+        )
+        .arg(
+            arg!(--smooth <lambda> "In piecewise mode, penalize changes between consecutive intervals by lambda^2 ||x_j+1 - x_j||^2.")
+                .required(false)
+                .default_value("0.0")
+                .value_parser(value_parser!(f64))
+        );
+    add_common_args(command)
+}
+
+fn sensitivity_command() -> App<'static> {
+    let command = Command::new("sensitivity")
+        .about("Estimate how the solution changes with chosen stoichiometric coefficients by finite differences")
+        .arg(
+            arg!([accumulation_path] "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(--wrt <input> "The input whose entries are perturbed.")
+                .required(false)
+                .default_value("matrix")
+                .value_parser(["matrix"])
+        )
+        .arg(
+            arg!(--entries <entries> "A comma separated list of matrix coefficients to perturb, written as R<reaction>:M<metabolite> \
+                and numbered from 1, e.g. \"R3:M7,R5:M2\".")
+        );
+    add_accumulation_args(add_preprocessing_args(add_common_args(command)))
+}
+
+fn sample_command() -> App<'static> {
+    let command = Command::new("sample")
+        .about("Sample the reaction vectors that fit an accumulation vector within flux bounds with hit-and-run")
+        .arg(
+            arg!([accumulation_path] "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(--n <count> "The number of samples.")
+                .id("n")
+                .required(false)
+                .default_value("10000")
+                .value_parser(value_parser!(usize))
+        )
+        .arg(
+            arg!(--"burn-in" <steps> "The number of hit-and-run steps discarded before the first sample.")
+                .id("burn_in")
+                .required(false)
+                .default_value("1000")
+                .value_parser(value_parser!(usize))
+        )
+        .arg(
+            arg!(--thin <steps> "The number of hit-and-run steps between samples.")
+                .required(false)
+                .default_value("10")
+                .value_parser(value_parser!(usize))
+        )
+        .arg(
+            arg!(--seed <seed> "The seed of the random number generator.")
+                .required(false)
+                .default_value("0")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            arg!(--"max-flux" <flux> "Every reaction flux is limited to [-flux, flux] so that the solution space is bounded.")
+                .id("max_flux")
+                .required(false)
+                .default_value("1000")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"samples-out" <samples_path> "Also write every sample to this path as a reactions x samples Matrix Market array.")
+                .id("samples_path")
+                .required(false)
+        );
+    add_thermo_args(add_accumulation_args(add_preprocessing_args(add_common_args(command))))
+}
+
+fn solution_set_command() -> App<'static> {
+    let command = Command::new("solution-set")
+        .about("Describe the set of reaction vectors that fit an accumulation vector: its dimension, a particular solution and a null space basis")
+        .arg(
+            arg!([accumulation_path] "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(--"max-flux" <flux> "Limit every reaction flux to [-flux, flux].")
+                .id("max_flux")
+                .required(false)
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--vertices "Enumerate the vertices of the solution set within the bounds. This is only practical for small systems.")
+        );
+    add_thermo_args(add_accumulation_args(add_preprocessing_args(add_common_args(command))))
+}
+
+fn generate_acc_command() -> App<'static> {
+    let command = Command::new("generate-acc")
+        .about("Generate an accumulation vector a = S r from a known reaction vector, with optional noise")
+        .arg(
+            arg!(-r <reactions_path> "The path to a known stoichiometric reaction vector file in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(--noise <level> "The standard deviation of the Gaussian noise added to each value, relative to the value (see --noise-kind).")
+                .required(false)
+                .default_value("0.0")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--"noise-kind" <kind> "Whether the noise level is relative to the magnitude of each value or absolute.")
+                .id("noise_kind")
+                .required(false)
+                .default_value("relative")
+                .value_parser(["relative", "absolute"])
+        )
+        .arg(
+            arg!(--seed <seed> "The seed of the random number generator.")
+                .required(false)
+                .default_value("0")
+                .value_parser(value_parser!(u64))
+        );
+    add_common_args(command)
+}
+
+fn export_equations_command() -> App<'static> {
+    let command = Command::new("export-equations")
+        .about("Write each reaction (column) of the stoichiometric matrix as a human readable reaction equation")
+        .arg(
+            arg!(--"output-format" <format>)
+                .help("Write the reactions in matrix order with a provenance header (equations), or sorted by name with \
+                    fixed precision coefficients and no header, for storing models in version control (canonical).")
+                .id("output_format")
+                .required(false)
+                .default_value("equations")
+                .value_parser(["equations", "canonical"])
+        );
+    add_common_args(command)
+}
+
+fn fba_command() -> App<'static> {
+    let command = Command::new("fba")
+        .about("Flux balance analysis: find the fluxes that maximize a linear objective, e.g. a biomass reaction, among \
+            those that balance every metabolite and stay within their bounds")
+        .arg(
+            arg!([accumulation_path] "The path to an accumulation vector file the fluxes must produce; without it, or -a, \
+                every metabolite is at a steady state.")
+        )
+        .arg(
+            arg!(-a <accumulation_string> "Optionally, provide the accumulation vector via stdin, whitespace delimited, \
+                e.g. \"0.0 1e5 0.5 ...\", or by metabolite label, e.g. \"glc=-1.2 co2=2.3\", with the others 0.")
+                .required(false)
+                .conflicts_with("accumulation_path")
+        )
+        .arg(
+            arg!(--objective <objective> "The weight of each reaction in the objective to maximize: the path to a vector \
+                file, or reaction=weight pairs, e.g. \"biomass=1\", with the other reactions 0.")
+        )
+        .arg(
+            arg!(--bounds <bounds_path> "The path to a file with a lower and an upper bound on each reaction, one \"lower upper\" \
+                line per reaction in column order, e.g. written by init-bounds. Without it, only the irreversible reactions \
+                of --equations are bounded, below by 0.")
+                .id("bounds_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--report "Print the solver diagnostics and the dual values: the shadow price of each metabolite and the \
+                reduced cost of each reaction, to stderr.")
+        );
+    add_common_args(command)
+}
+
+fn fva_command() -> App<'static> {
+    let command = Command::new("fva")
+        .about("Flux variability analysis: find the smallest and largest flux of each reaction among those that balance \
+            every metabolite within epsilon and stay within their bounds, to see how well the data constrain it")
+        .arg(
+            arg!([accumulation_path] "The path to an accumulation vector file the fluxes must produce; without it, or -a, \
+                every metabolite is at a steady state.")
+        )
+        .arg(
+            arg!(-a <accumulation_string> "Optionally, provide the accumulation vector via stdin, whitespace delimited, \
+                e.g. \"0.0 1e5 0.5 ...\", or by metabolite label, e.g. \"glc=-1.2 co2=2.3\", with the others 0.")
+                .required(false)
+                .conflicts_with("accumulation_path")
+        )
+        .arg(
+            arg!(--bounds <bounds_path> "The path to a file with a lower and an upper bound on each reaction, one \"lower upper\" \
+                line per reaction in column order, e.g. written by init-bounds. Without it, only the irreversible reactions \
+                of --equations are bounded, below by 0.")
+                .id("bounds_path")
+                .required(false)
+        );
+    add_common_args(command)
+}
+
+fn regress_command() -> App<'static> {
+    let command = Command::new("regress")
+        .about("Check that this version of smas reproduces the stored expected output of every case of a corpus, \
+            reporting the drift of each case")
+        .arg(
+            arg!(--corpus <corpus_dir> "The corpus directory. Its cases.csv lists one \"name,matrix,accumulation\" row per \
+                case, with paths relative to the directory, and the expected output of each case is stored in its expected \
+                directory.")
+                .id("corpus_dir")
+        )
+        .arg(
+            arg!(--update "Solve every case and store its output, with hashes of its inputs and output, as the expected \
+                output, instead of checking it.")
+        );
+    add_common_args(command)
+}
+
+fn analyze_command() -> App<'static> {
+    let command = Command::new("analyze")
+        .about("Report the rank, nullity, singular values and condition number of the stoichiometric matrix, and \
+            whether solving with it is under- or overdetermined, to see why a solve gives unexpected results");
+    add_common_args(command)
+}
+
+fn moieties_command() -> App<'static> {
+    let command = Command::new("moieties")
+        .about("Find the conserved metabolite pools (conserved moieties) of the stoichiometric matrix: weighted sums of \
+            metabolites, e.g. atp + adp, whose total no reaction changes, from the left null space of the matrix")
+        .arg(
+            arg!([accumulation_path] "Optionally, the path to an accumulation vector file, to report how much it changes \
+                the total of each pool; a pool that changes cannot be explained by the model.")
+        )
+        .arg(
+            arg!(-a <accumulation_string> "Optionally, provide the accumulation vector via stdin, whitespace delimited, \
+                e.g. \"0.0 1e5 0.5 ...\", or by metabolite label, e.g. \"glc=-1.2 co2=2.3\", with the others 0.")
+                .required(false)
+                .conflicts_with("accumulation_path")
+        );
+    add_common_args(command)
+}
+
+fn grep_command() -> App<'static> {
+    let command = Command::new("grep")
+        .about("List the reactions and metabolites whose labels match a pattern, with their stoichiometry and, given a \
+            solution, their fluxes")
+        .arg(
+            arg!(<pattern> "The pattern to search the labels for: a part of a label, e.g. glc, or a glob over the whole \
+                label, where * matches any run of characters and ? any one character, e.g. \"glc*\".")
+        )
+        .arg(
+            arg!(-i --"ignore-case" "Match upper and lower case letters to each other.")
+                .id("ignore_case")
+        )
+        .arg(
+            arg!(--solution <solution_path> "The path to a reaction vector file solved for the model, to list the flux \
+                of each matching reaction and the net production of each matching metabolite.")
+                .id("solution_path")
+                .required(false)
+        );
+    add_common_args(command)
+}
+
+fn import_pathway_command() -> App<'static> {
+    let command = Command::new("import-pathway")
+        .about("Build a labeled stoichiometric matrix from a KEGG KGML or BioPAX Level 3 pathway file, reporting what was ambiguous")
+        .arg(
+            arg!(<pathway_path> "The path to the KGML or BioPAX pathway file.")
+        )
+        .arg(
+            arg!(--"output-format" <format>)
+                .help("Write reaction equations, which keep which reactions are reversible and can be read with \
+                    --equations (equations), or a labeled Matrix Market matrix (matrix).")
+                .id("output_format")
+                .required(false)
+                .default_value("equations")
+                .value_parser(["equations", "matrix"])
+        );
+    add_common_args(command)
+}
+
+fn snap_command() -> App<'static> {
+    let command = Command::new("snap")
+        .about("Round coefficients of the stoichiometric matrix that are within a tolerance of an integer or simple fraction, \
+            e.g. 0.9999999 or 0.5000001, reporting those that could not be snapped")
+        .arg(
+            arg!(--"to-integers" "Only snap coefficients to integers.")
+                .id("to_integers")
+        )
+        .arg(
+            arg!(--"max-denominator" <n> "Snap coefficients to fractions with at most this denominator, e.g. 2 for halves; \
+                smaller denominators are preferred.")
+                .id("max_denominator")
+                .required(false)
+                .default_value("4")
+                .value_parser(value_parser!(u32).range(1..))
+                .conflicts_with("to_integers")
+        )
+        .arg(
+            arg!(--tolerance <tolerance> "How far a coefficient may be from the integer or fraction it is snapped to.")
+                .required(false)
+                .default_value("1e-6")
+                .value_parser(value_parser!(f64))
+        );
+    add_common_args(command)
+}
+
+fn init_bounds_command() -> App<'static> {
+    let command = Command::new("init-bounds")
+        .about("Write a template bounds file for solve --bounds with a \"lower upper\" line per reaction: unbounded, \
+            except irreversible reactions of --equations, which are bounded below by 0, and with exchange reactions flagged");
+    add_common_args(command)
+}
+
+fn model_diff_command() -> App<'static> {
+    let command = Command::new("model-diff")
+        .about("Compare two stoichiometric matrices by metabolite and reaction name: added and removed rows and columns, changed coefficients, and the change in rank")
+        .arg(
+            arg!(<old_path> "The path to the old stoichiometric matrix file.")
+        )
+        .arg(
+            arg!(<new_path> "The path to the new stoichiometric matrix file.")
+        );
+    add_id_map_args(add_common_args(command))
+}
+
+fn repl_command() -> App<'static> {
+    add_repl_args(add_preprocessing_args(add_common_args(Command::new("repl")
+        .about("Curate a model interactively: set coefficients, add and remove reactions, re-solve, and undo edits"))))
+}
+
+fn script_command() -> App<'static> {
+    add_repl_args(add_preprocessing_args(add_common_args(Command::new("script")
+        .about("Run a file of REPL commands, one per line, stopping at the first command that fails")
+        .arg(
+            arg!(<script_path> "The path to the file of REPL commands.")
+        )
+        .arg(
+            arg!(--echo "Print each command before its reply.")
+                .required(false)
+        ))))
+}
+
+#[cfg(feature = "tui")]
+fn tui_command() -> App<'static> {
+    add_preprocessing_args(add_accumulation_args(add_common_args(Command::new("tui")
+        .about("Explore a solve in a terminal dashboard: the matrix heatmap, the solution and residuals as bar charts, \
+            and a validation table")
+        .arg(
+            arg!([accumulation_path] "The path to a stoichiometric accumulation vector file in the Matrix Market array format.")
+        )
+        .arg(
+            arg!(-r <reactions_path> "The path to a true stoichiometric reaction vector file for the validation pane.")
+                .required(false)
+        ))))
+}
+
+fn heatmap_command() -> App<'static> {
+    let command = Command::new("heatmap")
+        .about("Draw the sign pattern of the stoichiometric matrix in the terminal, one line per metabolite and one character per reaction")
+        .arg(
+            arg!(--magnitude "Shade each entry by its magnitude, with green for positive and red for negative entries.")
+        )
+        .arg(
+            arg!(--ascii "Draw with ASCII characters only.")
+        )
+        .arg(
+            arg!(--"no-color" "Do not color the entries. Colors are never written to a file (-o).")
+                .id("no_color")
+        )
+        .arg(
+            arg!(--width <columns> "Leave out the reactions that do not fit in this many columns.")
+                .required(false)
+                .value_parser(value_parser!(usize))
+        );
+    add_common_args(command)
+}
+
+fn json_rpc_command() -> App<'static> {
+    Command::new("json-rpc")
+        .about("Read one JSON request from stdin, e.g. {\"matrix\": [[1, -1], [0, 1]], \"vector\": [1, 2], \"options\": {}}, \
+            solve it, and write a JSON response to stdout; the exit status is 1 if the response is an error")
+}
+
+fn bench_command() -> App<'static> {
+    Command::new("bench")
+        .about("Time the dense and sparse solvers on this machine, and store the result, so that --dry-run can estimate \
+            how long a solve will take")
+        .arg(
+            arg!(-o --out <path> "The path to store the calibration at. By default, and where --dry-run reads it, this is \
+                the path in SMAS_CALIBRATION, or else smas/calibration.txt in $XDG_CONFIG_HOME or ~/.config.")
+                .id("calibration_path")
+                .required(false)
+        )
+        .arg(
+            arg!(--force "Overwrite the calibration if it already exists, e.g. to recalibrate.")
+                .conflicts_with("backup")
+        )
+        .arg(
+            arg!(--backup "Rename the calibration to <name>.bak if it already exists before writing.")
+        )
+}
+
+fn selftest_command() -> App<'static> {
+    Command::new("selftest")
+        .about("Run the solver against built-in reference problems to verify the installation")
+}
+
+fn examples_command() -> App<'static> {
+    Command::new("examples")
+        .about("List or extract the example data files bundled with smas")
+        .arg(
+            arg!(--extract <dir> "Write the example files into this directory.")
+                .required(false)
+        )
+        .arg(
+            arg!(--list "List the reference datasets: each model with its example accumulation and reaction vectors, \
+                its shape, files and a description.")
+                .conflicts_with("extract")
+        )
+}
+
+/// This builds the command line interface: the subcommands and their arguments.
+fn build_app() -> App<'static> {
+    let app = App::new("smas")
+        .version("0.1.0")
+        .author("Jack Roddy <jack.w.roddy@gmail.com>")
+        .about("A simple tool to help with finding a solution to a particular stoichiometric matrix equation")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .set_term_width(80)
+        .arg(
+            arg!(--threads <count> "The most threads smas runs on: the default of validate --jobs, and the size of any \
+                rayon thread pool, as RAYON_NUM_THREADS is set to it. The solvers run each factorization on a single \
+                thread, so parallel jobs do not oversubscribe the CPUs. By default, RAYON_NUM_THREADS if it is set, \
+                otherwise the number of CPUs.")
+                .required(false)
+                .global(true)
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+        )
+        .subcommand(solve_command())
+        .subcommand(validate_command())
+        .subcommand(jacobian_command())
+        .subcommand(export_equations_command())
+        .subcommand(grep_command())
+        .subcommand(analyze_command())
+        .subcommand(moieties_command())
+        .subcommand(fba_command())
+        .subcommand(fva_command())
+        .subcommand(regress_command())
+        .subcommand(import_pathway_command())
+        .subcommand(snap_command())
+        .subcommand(init_bounds_command())
+        .subcommand(model_diff_command())
+        .subcommand(heatmap_command())
+        .subcommand(timecourse_command())
+        .subcommand(sensitivity_command())
+        .subcommand(sample_command())
+        .subcommand(solution_set_command())
+        .subcommand(generate_acc_command())
+        .subcommand(json_rpc_command())
+        .subcommand(bench_command())
+        .subcommand(selftest_command())
+        .subcommand(examples_command())
+        .subcommand(repl_command())
+        .subcommand(script_command());
+    #[cfg(feature = "tui")]
+    let app = app.subcommand(tui_command());
+    app
+}
+
+fn main() {
+    let matches = build_app().get_matches();
+
+    // ** set before any thread starts, so that a rayon pool sizes itself the same as --jobs
+    if let Some((_, matches)) = matches.subcommand().filter(|(_, matches)| matches.contains_id("threads")) {
+        std::env::set_var(RAYON_NUM_THREADS, get_threads(matches).to_string());
+    }
+
+    // ** json-rpc, selftest and examples have no common arguments, so no --profile-json
+    let profile = matches.subcommand()
+        .and_then(|(_, matches)| matches.try_get_one::<String>("profile_json").ok().flatten().cloned())
+        .map(|path| (path, get_overwrite_policy(matches.subcommand().unwrap().1)));
+    let trace = profile.as_ref().map(|_| {
+        let (subscriber, trace) = smas::profile::chrome_trace();
+        tracing::subscriber::set_global_default(subscriber)
+            .unwrap_or_else(|e| panic!("failed to start profiling: {e}"));
+        trace
+    });
+
+    if let Some((name, matches)) = matches.subcommand().filter(|(_, matches)| matches.try_contains_id("dry_run").unwrap_or(false)) {
+        return run_dry_run(name, matches);
+    }
+
+    match matches.subcommand() {
+        Some(("solve", matches)) => run_solve(matches),
+        Some(("validate", matches)) => run_validate(matches),
+        Some(("jacobian", matches)) => run_jacobian(matches),
+        Some(("export-equations", matches)) => run_export_equations(matches),
+        Some(("grep", matches)) => run_grep(matches),
+        Some(("analyze", matches)) => run_analyze(matches),
+        Some(("moieties", matches)) => run_moieties(matches),
+        Some(("fba", matches)) => run_fba(matches),
+        Some(("fva", matches)) => run_fva(matches),
+        Some(("regress", matches)) => run_regress(matches),
+        Some(("import-pathway", matches)) => run_import_pathway(matches),
+        Some(("snap", matches)) => run_snap(matches),
+        Some(("init-bounds", matches)) => run_init_bounds(matches),
+        Some(("model-diff", matches)) => run_model_diff(matches),
+        Some(("heatmap", matches)) => run_heatmap(matches),
+        Some(("timecourse", matches)) => run_timecourse(matches),
+        Some(("sensitivity", matches)) => run_sensitivity(matches),
+        Some(("sample", matches)) => run_sample(matches),
+        Some(("solution-set", matches)) => run_solution_set(matches),
+        Some(("generate-acc", matches)) => run_generate_acc(matches),
+        Some(("json-rpc", _)) => run_json_rpc(),
+        Some(("bench", matches)) => run_bench(matches),
+        Some(("selftest", _)) => run_selftest(),
+        Some(("examples", matches)) => run_examples(matches),
+        Some(("repl", matches)) => run_repl(matches),
+        Some(("script", matches)) => run_script(matches),
+        #[cfg(feature = "tui")]
+        Some(("tui", matches)) => run_tui(matches),
+        _ => unreachable!()
+    }
+
+    if let (Some((path, policy)), Some(trace)) = (profile, trace) {
+        smas::io::write_output_file(&path, trace.finish(), policy)
+            .unwrap_or_else(|e| panic!("failed to write the profile: {e}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulation_vector_without_replicates() {
+        // ** sample has no --replicates, so checking the declared units must not look it up
+        let matches = build_app().get_matches_from(["smas", "sample", "resources/astd015.txt", "--n", "2"]);
+        let (_, matches) = matches.subcommand().unwrap();
+        let (acc_vector, _) = get_accumulation_vector(matches, smas::io::ParseMode::Lenient);
+        assert_eq!(acc_vector.nrows(), 39);
+    }
+
+    #[test]
+    fn test_threads_sets_the_default_jobs() {
+        let matches = build_app().get_matches_from(["smas", "validate", "--pairs", "pairs.csv", "--threads", "3"]);
+        let (_, matches) = matches.subcommand().unwrap();
+        assert_eq!(get_threads(matches), 3);
+
+        // ** --threads is global, so it may also come before the subcommand
+        let matches = build_app().get_matches_from(["smas", "--threads", "2", "validate", "--pairs", "pairs.csv"]);
+        let (_, matches) = matches.subcommand().unwrap();
+        assert_eq!(get_threads(matches), 2);
+        assert!(build_app().try_get_matches_from(["smas", "validate", "--threads", "0"]).is_err());
+    }
+}
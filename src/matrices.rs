@@ -1,4 +1,4 @@
-pub static S_MAT: &'static [f64; 1092] = &[
+pub static S_MAT: &[f64; 1092] = &[
     0.0, 2.0, -2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
     0.0, 0.0, 2.0, -2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
     0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, -3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
@@ -40,7 +40,7 @@ pub static S_MAT: &'static [f64; 1092] = &[
     0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, -2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0
 ];
 
-pub static A_STD_015: &'static [f64; 39] = &[
+pub static A_STD_015: &[f64; 39] = &[
     -2.00000e2,
     -1.22400e3,
     9.78030e-7,
@@ -82,7 +82,7 @@ pub static A_STD_015: &'static [f64; 39] = &[
     -4.84120e3
 ];
 
-pub static R_STD_015: &'static [f64; 28] = &[
+pub static R_STD_015: &[f64; 28] = &[
     5.4416e-07,
     13086.0,
     13186.0,
@@ -111,4 +111,156 @@ pub static R_STD_015: &'static [f64; 28] = &[
     3.6711e+05,
     8901.3,
     2.7438e+05
-];
\ No newline at end of file
+];
+/// This is a reference dataset bundled into smas: a stoichiometric matrix with an example
+/// accumulation vector and the reaction vector it should be solved to, for tests and demos.
+/// The data are Matrix Market files, so labels ride along in their "%rows:" and "%cols:" comments.
+pub struct Dataset {
+    /// The name the dataset is looked up by, e.g. "std015"
+    pub name: &'static str,
+    /// A one line description
+    pub description: &'static str,
+    /// The file name and contents of the stoichiometric matrix
+    pub s_matrix: (&'static str, &'static str),
+    /// The file name and contents of the example accumulation vector
+    pub accumulation: (&'static str, &'static str),
+    /// The file name and contents of the reference reaction vector, if there is one
+    pub reactions: Option<(&'static str, &'static str)>,
+}
+
+impl Dataset {
+    /// This parses a bundled file, which is checked by the tests to be valid.
+    fn parse(&self, (name, contents): (&str, &str)) -> crate::io::LabeledMatrix {
+        crate::io::parse_labeled_matrix(contents, crate::io::ParseMode::Lenient, crate::io::DEFAULT_PATTERN_VALUE)
+            .unwrap_or_else(|e| panic!("the bundled {name} of the {} dataset is malformed: {e}", self.name))
+    }
+
+    /// This returns the stoichiometric matrix, with its metabolite and reaction labels if it has any.
+    pub fn labeled_s_matrix(&self) -> crate::io::LabeledMatrix {
+        self.parse(self.s_matrix)
+    }
+
+    /// This returns the example accumulation vector.
+    pub fn accumulation(&self) -> nalgebra::DVector<f64> {
+        self.parse(self.accumulation).matrix.column(0).into_owned()
+    }
+
+    /// This returns the reference reaction vector, if there is one.
+    pub fn reactions(&self) -> Option<nalgebra::DVector<f64>> {
+        self.reactions.map(|file| self.parse(file).matrix.column(0).into_owned())
+    }
+
+    /// This returns the bundled files of the dataset, as (file name, contents) pairs.
+    pub fn files(&self) -> Vec<(&'static str, &'static str)> {
+        [Some(self.s_matrix), Some(self.accumulation), self.reactions].into_iter().flatten().collect()
+    }
+}
+
+/// The reference datasets bundled into smas; see `dataset` to look one up by name.
+pub static DATASETS: &[Dataset] = &[
+    Dataset {
+        name: "std015",
+        description: "the default model with a standard accumulation vector and its reference reaction vector",
+        s_matrix: ("smat.txt", include_str!("../resources/smat.txt")),
+        accumulation: ("astd015.txt", include_str!("../resources/astd015.txt")),
+        reactions: Some(("rstd015.txt", include_str!("../resources/rstd015.txt"))),
+    },
+    Dataset {
+        name: "med015",
+        description: "the default model with a medium accumulation vector and its reference reaction vector",
+        s_matrix: ("smat.txt", include_str!("../resources/smat.txt")),
+        accumulation: ("amed015.txt", include_str!("../resources/amed015.txt")),
+        reactions: Some(("rmed015.txt", include_str!("../resources/rmed015.txt"))),
+    },
+    Dataset {
+        name: "glycolysis",
+        description: "a toy model of upper glycolysis whose accumulation vector solves exactly",
+        s_matrix: ("glycolysis_smat.txt", include_str!("../resources/glycolysis_smat.txt")),
+        accumulation: ("glycolysis_acc.txt", include_str!("../resources/glycolysis_acc.txt")),
+        reactions: Some(("glycolysis_flux.txt", include_str!("../resources/glycolysis_flux.txt"))),
+    },
+];
+
+/// This looks up a bundled reference dataset by name.
+///
+/// # Arguments
+/// * `name` - the name of the dataset, e.g. "glycolysis"
+///
+pub fn dataset(name: &str) -> Option<&'static Dataset> {
+    DATASETS.iter().find(|dataset| dataset.name == name)
+}
+
+/// This formats the bundled datasets, one per line, with their shape, files and description.
+pub fn format_datasets() -> String {
+    DATASETS.iter()
+        .map(|dataset| {
+            let s_matrix = dataset.labeled_s_matrix();
+            let files: Vec<&str> = dataset.files().iter().map(|(name, _)| *name).collect();
+            format!(
+                "{}\t{} x {}{}\t{}\t{}",
+                dataset.name,
+                s_matrix.matrix.nrows(),
+                s_matrix.matrix.ncols(),
+                if s_matrix.row_labels.is_some() { " labeled" } else { "" },
+                files.join(" "),
+                dataset.description,
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// This returns a matrix with the shape and sparsity pattern of S_MAT, but random coefficients,
+/// for stress-testing solvers against the structure of a real model. Each nonzero keeps its sign,
+/// so substrates stay substrates and products stay products, and its magnitude is a random integer
+/// from 1 to 4, like the coefficients of real reactions. The same seed always gives the same
+/// matrix.
+///
+/// # Arguments
+/// * `seed` - the seed of the random number generator
+///
+pub fn random_like_default(seed: u64) -> nalgebra::DMatrix<f64> {
+    let mut rng = crate::util::SplitMix64::new(seed);
+    let values: Vec<f64> = S_MAT.iter()
+        .map(|value| match *value {
+            0.0 => 0.0,
+            value => value.signum() * (1 + rng.next_u64() % 4) as f64,
+        })
+        .collect();
+    nalgebra::DMatrix::from_row_slice(39, 28, &values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_random_like_default() {
+        let default = util::default_s_matrix();
+        let random = matrices::random_like_default(7);
+        assert_eq!(random.shape(), default.shape());
+        assert!(random.iter().zip(default.iter()).all(|(r, d)| r.signum() == d.signum() || (*r == 0.0 && *d == 0.0)));
+        assert!(random.iter().all(|r| [0.0, 1.0, 2.0, 3.0, 4.0].contains(&r.abs())));
+        assert_eq!(random, matrices::random_like_default(7));
+        assert_ne!(random, matrices::random_like_default(8));
+    }
+
+    #[test]
+    fn test_datasets() {
+        for dataset in matrices::DATASETS {
+            let s_matrix = dataset.labeled_s_matrix();
+            let accumulation = dataset.accumulation();
+            assert_eq!(accumulation.nrows(), s_matrix.matrix.nrows(), "{}", dataset.name);
+            assert_eq!(dataset.reactions().unwrap().nrows(), s_matrix.matrix.ncols(), "{}", dataset.name);
+        }
+        assert!(matrices::dataset("std015").unwrap().labeled_s_matrix().matrix == util::default_s_matrix());
+
+        let glycolysis = matrices::dataset("glycolysis").unwrap();
+        let s_matrix = glycolysis.labeled_s_matrix();
+        assert_eq!(s_matrix.col_labels.as_deref().unwrap(), ["hk", "pgi", "pfk", "ald"]);
+        let solution = solve::solve(glycolysis.accumulation(), s_matrix.matrix.clone());
+        assert!(util::vectors_eq(&solution, &glycolysis.reactions().unwrap(), 1e-12, 1e-12));
+        assert!(matrices::dataset("missing").is_none());
+        assert!(matrices::format_datasets().contains("glycolysis\t5 x 4 labeled\tglycolysis_smat.txt"));
+    }
+}
@@ -0,0 +1,273 @@
+use crate::error::SmasError;
+use crate::solve;
+use crate::util;
+
+use nalgebra as na;
+
+/// This is a direct measurement of a single reaction flux, e.g. from 13C metabolic flux analysis.
+pub struct FluxMeasurement {
+    /// The index of the measured reaction, i.e. its column in the matrix (0-based)
+    pub reaction: usize,
+    /// The measured flux
+    pub value: f64,
+    /// The standard deviation of the measurement
+    pub std_dev: f64,
+}
+
+/// This appends weighted rows to a system Ax = B, returning the stacked matrix and vector.
+/// Each extra row is scaled by its weight so that it contributes weight² times its squared
+/// residual to the least squares objective.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `rows` - the extra rows; (k x n)
+/// * `rhs` - the right hand side of the extra rows; (k x 1)
+/// * `weights` - the weight of each extra row; (k x 1)
+///
+pub fn append_weighted_rows(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    rows: &na::DMatrix<f64>,
+    rhs: &na::DVector<f64>,
+    weights: &na::DVector<f64>,
+) -> (na::DMatrix<f64>, na::DVector<f64>) {
+    let m = s_matrix.nrows();
+    let k = rows.nrows();
+    let mut matrix = s_matrix.clone().insert_rows(m, k, 0.0);
+    let mut vector = acc_vector.clone().insert_rows(m, k, 0.0);
+    for i in 0..k {
+        matrix.row_mut(m + i).copy_from(&(rows.row(i) * weights[i]));
+        vector[m + i] = rhs[i] * weights[i];
+    }
+    (matrix, vector)
+}
+
+/// This scales each row of a system Ax = B by its weight, so that the least squares fit of the
+/// returned system is the weighted least squares fit of the original, e.g. with weights of
+/// 1 / spread for replicate accumulation data.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `weights` - the weight of each row; (m x 1)
+///
+pub fn weight_rows(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    weights: &na::DVector<f64>,
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>), SmasError> {
+    if weights.nrows() != s_matrix.nrows() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.nrows(),
+            found: weights.nrows(),
+        });
+    }
+    let mut matrix = s_matrix.clone();
+    for (i, mut row) in matrix.row_iter_mut().enumerate() {
+        row *= weights[i];
+    }
+    Ok((matrix, acc_vector.component_mul(weights)))
+}
+
+/// This appends flux measurements to a system Ax = B as soft constraints: one row per measurement,
+/// x_r = value, weighted by 1 / std_dev so that precise measurements pull harder on the fit.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `measurements` - the flux measurements
+///
+pub fn append_flux_measurements(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    measurements: &[FluxMeasurement],
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>), SmasError> {
+    let n = s_matrix.ncols();
+    let k = measurements.len();
+    let mut rows = na::DMatrix::zeros(k, n);
+    let mut rhs = na::DVector::zeros(k);
+    let mut weights = na::DVector::zeros(k);
+    for (i, measurement) in measurements.iter().enumerate() {
+        if measurement.reaction >= n {
+            return Err(SmasError::InvalidArgument(format!(
+                "flux measurement for reaction {} but the matrix has {n} reactions",
+                measurement.reaction + 1
+            )));
+        }
+        rows[(i, measurement.reaction)] = 1.0;
+        rhs[i] = measurement.value;
+        weights[i] = 1.0 / measurement.std_dev;
+    }
+    Ok(append_weighted_rows(s_matrix, acc_vector, &rows, &rhs, &weights))
+}
+
+/// This appends a prior flux vector to a system Ax = B as soft constraints, so that solving the
+/// stacked system minimizes ||Ax - B||² + weight² ||x - prior||².
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `prior` - the prior flux vector; (n x 1)
+/// * `weight` - the tradeoff between fitting the prior and fitting the accumulation vector
+///
+pub fn append_prior(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    prior: &na::DVector<f64>,
+    weight: f64,
+) -> Result<(na::DMatrix<f64>, na::DVector<f64>), SmasError> {
+    let n = s_matrix.ncols();
+    if prior.nrows() != n {
+        return Err(SmasError::Shape {
+            expected: n,
+            found: prior.nrows(),
+        });
+    }
+    let weights = na::DVector::from_element(n, weight);
+    Ok(append_weighted_rows(s_matrix, acc_vector, &na::DMatrix::identity(n, n), prior, &weights))
+}
+
+/// This computes the Jacobian of the prior weighted fit of `append_prior` with respect to the
+/// accumulation vector, dx/dB. The fit is x = (AᵀA + weight² I)⁻¹(AᵀB + weight² prior), so the
+/// Jacobian is (AᵀA + weight² I)⁻¹Aᵀ; (n x m). It does not depend on the prior, and with a weight
+/// of 0 it is the pseudoinverse A⁺.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `weight` - the weight of the prior
+///
+pub fn prior_jacobian(s_matrix: &na::DMatrix<f64>, weight: f64) -> na::DMatrix<f64> {
+    let (m, n) = s_matrix.shape();
+    // ** the columns of the stacked system's pseudoinverse that multiply B, as the solver computes them
+    let mut stacked = na::DMatrix::zeros(m + n, n);
+    stacked.rows_mut(0, m).copy_from(s_matrix);
+    stacked.rows_mut(m, n).fill_diagonal(weight);
+    solve::jacobian(&stacked).columns(0, m).into_owned()
+}
+
+/// This computes the two residual components of a prior weighted fit separately: the
+/// accumulation residual ||Ax - B|| and the prior residual ||x - prior||, with compensated
+/// summation.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `prior` - the prior flux vector; (n x 1)
+/// * `reactions` - the solution vector, x; (n x 1)
+///
+pub fn prior_residual_norms(
+    s_matrix: &na::DMatrix<f64>,
+    acc_vector: &na::DVector<f64>,
+    prior: &na::DVector<f64>,
+    reactions: &na::DVector<f64>,
+) -> (f64, f64) {
+    (
+        solve::residual_norm(s_matrix, reactions, acc_vector, util::Summation::Compensated),
+        util::sum_squared_delta_with(reactions.as_slice(), prior.as_slice(), util::Summation::Compensated).sqrt(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_append_flux_measurements() {
+        // ** x1 + x2 = 2 is underdetermined; a precise measurement of x1 pins the solution
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[2.0]);
+        let measurements = vec![measurements::FluxMeasurement {
+            reaction: 0,
+            value: 1.5,
+            std_dev: 1e-3,
+        }];
+        let (matrix, vector) = measurements::append_flux_measurements(&s_matrix, &acc_vector, &measurements).unwrap();
+        assert_eq!(matrix.shape(), (2, 2));
+        assert_eq!(vector[1], 1500.0);
+
+        let r_vector = solve::solve(vector, matrix);
+        assert!(util::epsilon_eq(r_vector[0], 1.5, 1e-6));
+        assert!(util::epsilon_eq(r_vector[1], 0.5, 1e-6));
+    }
+
+    #[test]
+    fn test_flux_measurement_report() {
+        // ** measurements of 3 for both fluxes disagree with x1 + x2 = 2, so the fit is x = 5/3
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[2.0]);
+        let measurements: Vec<_> = (0..2)
+            .map(|reaction| measurements::FluxMeasurement { reaction, value: 3.0, std_dev: 1.0 })
+            .collect();
+        let (matrix, vector) = measurements::append_flux_measurements(&s_matrix, &acc_vector, &measurements).unwrap();
+        let solution = solve::solve_pinv(&vector, &matrix).unwrap();
+        assert!(util::epsilon_eq(solution.reactions[0], 5.0 / 3.0, 1e-9));
+        assert!(util::epsilon_eq(solution.residual_norm, 4.0 / 3.0 * 3f64.sqrt(), 1e-9));
+
+        // ** the report measures the fit to the accumulation vector alone
+        let report = solution.format_report_against(&s_matrix, &acc_vector);
+        let reported: f64 = report.rsplit("% residual norm: ").next().unwrap().parse().unwrap();
+        assert!(util::epsilon_eq(reported, 4.0 / 3.0, 1e-9), "{report}");
+
+        let unknown = vec![measurements::FluxMeasurement { reaction: 2, value: 1.0, std_dev: 1.0 }];
+        assert!(matches!(
+            measurements::append_flux_measurements(&s_matrix, &acc_vector, &unknown),
+            Err(error::SmasError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_append_prior() {
+        // ** x = 2 with a prior of 0: the weighted fit lands at 2 / (1 + weight²)
+        let s_matrix = na::DMatrix::from_row_slice(1, 1, &[1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[2.0]);
+        let prior = na::DVector::from_row_slice(&[0.0]);
+        let (matrix, vector) = measurements::append_prior(&s_matrix, &acc_vector, &prior, 1.0).unwrap();
+        let r_vector = solve::solve(vector, matrix);
+        assert!(util::epsilon_eq(r_vector[0], 1.0, 1e-12));
+
+        let (acc_residual, prior_residual) = measurements::prior_residual_norms(&s_matrix, &acc_vector, &prior, &r_vector);
+        assert!(util::epsilon_eq(acc_residual, 1.0, 1e-12));
+        assert!(util::epsilon_eq(prior_residual, 1.0, 1e-12));
+    }
+
+    #[test]
+    fn test_prior_jacobian() {
+        let s_matrix = na::DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 0.0, 0.0, 1.0, -1.0]);
+        let weight = 0.5;
+        let jacobian = measurements::prior_jacobian(&s_matrix, weight);
+        let normal = s_matrix.transpose() * &s_matrix + na::DMatrix::identity(3, 3) * weight * weight;
+        let expected = normal.try_inverse().unwrap() * s_matrix.transpose();
+        for (c, t) in jacobian.iter().zip(expected.iter()) {
+            assert!(util::epsilon_eq(*c, *t, 1e-9));
+        }
+
+        // ** the fit moves with B exactly as the Jacobian says, whatever the prior
+        let acc_vector = na::DVector::from_row_slice(&[2.0, -1.0]);
+        let prior = na::DVector::from_row_slice(&[1.0, 3.0, -2.0]);
+        let (matrix, vector) = measurements::append_prior(&s_matrix, &acc_vector, &prior, weight).unwrap();
+        let (_, prior_vector) = measurements::append_prior(&s_matrix, &na::DVector::zeros(2), &prior, weight).unwrap();
+        let delta = solve::solve(vector, matrix.clone()) - solve::solve(prior_vector, matrix);
+        for (c, t) in delta.iter().zip((&jacobian * &acc_vector).iter()) {
+            assert!(util::epsilon_eq(*c, *t, 1e-9));
+        }
+
+        let pinv = solve::jacobian(&s_matrix);
+        for (c, t) in measurements::prior_jacobian(&s_matrix, 0.0).iter().zip(pinv.iter()) {
+            assert!(util::epsilon_eq(*c, *t, 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_weight_rows() {
+        // ** x = 1 and x = 3 disagree; weighting the second by 3 pulls the fit to (1 + 9 * 3) / 10
+        let s_matrix = na::DMatrix::from_row_slice(2, 1, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 3.0]);
+        let weights = na::DVector::from_row_slice(&[1.0, 3.0]);
+        let (matrix, vector) = measurements::weight_rows(&s_matrix, &acc_vector, &weights).unwrap();
+        let r_vector = solve::solve(vector, matrix);
+        assert!(util::epsilon_eq(r_vector[0], 2.8, 1e-12));
+        assert!(measurements::weight_rows(&s_matrix, &acc_vector, &na::DVector::zeros(3)).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of bytes allocated and not yet freed.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+/// The largest number of bytes allocated at once since the peak was last reset.
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// This is a global allocator that counts the bytes allocated through the system allocator. It
+/// only counts once a program installs it with #[global_allocator], which the smas binary does
+/// with the mem-report feature.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size >= layout.size() {
+                let allocated = ALLOCATED.fetch_add(new_size - layout.size(), Ordering::Relaxed) + new_size - layout.size();
+                PEAK.fetch_max(allocated, Ordering::Relaxed);
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// This returns the number of bytes allocated and not yet freed, or 0 if the counting allocator
+/// is not installed.
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// This returns the largest number of bytes allocated at once so far, or 0 if the counting
+/// allocator is not installed.
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// This is the memory used by one phase of a computation, e.g. the factorization.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Usage {
+    /// The name of the phase
+    pub name: &'static str,
+    /// The bytes the phase left allocated, e.g. the matrix it loaded; negative if it freed more
+    pub retained: isize,
+    /// The most bytes the phase had allocated at once on top of what was allocated before it
+    pub peak: usize,
+}
+
+/// This runs one phase of a computation and measures its memory. Allocations made by other
+/// threads meanwhile are counted as the phase's own. The overall peak is kept.
+///
+/// # Arguments
+/// * `name` - the name of the phase, e.g. "factorization"
+/// * `phase` - the phase to run
+///
+pub fn measure<T>(name: &'static str, phase: impl FnOnce() -> T) -> (T, Usage) {
+    let before = allocated();
+    let overall_peak = PEAK.swap(before, Ordering::Relaxed);
+    let result = phase();
+    let phase_peak = PEAK.fetch_max(overall_peak, Ordering::Relaxed);
+    let usage = Usage {
+        name,
+        retained: allocated() as isize - before as isize,
+        peak: phase_peak.saturating_sub(before),
+    };
+    (result, usage)
+}
+
+/// This formats a number of bytes with a binary unit, e.g. "1.50 MiB".
+///
+/// # Arguments
+/// * `bytes` - the number of bytes
+///
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
+
+/// This estimates the peak memory in bytes of solving with a dense matrix by its SVD, as the
+/// default solver does: the matrix, the working copy the SVD overwrites, and its factors U (m x k),
+/// the k singular values and Vᵀ (k x n), with k = min(m, n).
+///
+/// # Arguments
+/// * `nrows` - the number of rows (metabolites), m
+/// * `ncols` - the number of columns (reactions), n
+///
+pub fn estimate_dense_solve(nrows: usize, ncols: usize) -> usize {
+    let k = nrows.min(ncols);
+    let floats = [nrows.saturating_mul(ncols).saturating_mul(2), nrows.saturating_mul(k), k, k.saturating_mul(ncols)]
+        .iter()
+        .fold(0usize, |total, count| total.saturating_add(*count));
+    floats.saturating_mul(std::mem::size_of::<f64>())
+}
+
+/// This estimates the peak memory in bytes of solving with a sparse matrix by LSQR: a value and a
+/// row index per nonzero entry, a column offset per column, and the handful of vectors of length
+/// m and n that LSQR iterates with.
+///
+/// # Arguments
+/// * `nrows` - the number of rows (metabolites), m
+/// * `ncols` - the number of columns (reactions), n
+/// * `nnz` - the number of nonzero entries
+///
+pub fn estimate_sparse_solve(nrows: usize, ncols: usize, nnz: usize) -> usize {
+    let entries = nnz.saturating_mul(std::mem::size_of::<f64>() + std::mem::size_of::<usize>());
+    let offsets = ncols.saturating_add(1).saturating_mul(std::mem::size_of::<usize>());
+    let vectors = nrows.saturating_add(ncols).saturating_mul(4 * std::mem::size_of::<f64>());
+    entries.saturating_add(offsets).saturating_add(vectors)
+}
+
+/// This formats the memory used by each phase, and the peak of the whole run, as '%' comment
+/// lines, for sizing machines for larger models.
+///
+/// # Arguments
+/// * `usages` - the memory used by each phase, in the order they ran
+///
+pub fn format_usage(usages: &[Usage]) -> String {
+    let mut lines = vec![format!("% {:<16}{:>14}{:>14}", "phase", "peak", "retained")];
+    for usage in usages {
+        lines.push(format!(
+            "% {:<16}{:>14}{:>14}",
+            usage.name,
+            format_bytes(usage.peak as f64),
+            format_bytes(usage.retained as f64)
+        ));
+    }
+    lines.push(format!("% peak allocated over the whole run: {}", format_bytes(peak() as f64)));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_estimate_solve() {
+        // ** 2 x 3: the matrix twice, U 2 x 2, 2 singular values and Vᵀ 2 x 3
+        assert_eq!(memory::estimate_dense_solve(2, 3), 8 * (12 + 4 + 2 + 6));
+        assert_eq!(memory::estimate_sparse_solve(2, 3, 4), 16 * 4 + 8 * 4 + 32 * 5);
+        // ** genome-scale shapes do not overflow
+        assert_eq!(memory::estimate_dense_solve(usize::MAX, 2), usize::MAX);
+        // ** sparse storage is far smaller for a genome-scale model with few nonzeros
+        assert!(memory::estimate_sparse_solve(8000, 13000, 60000) * 100 < memory::estimate_dense_solve(8000, 13000));
+    }
+
+    #[test]
+    fn test_format_usage() {
+        assert_eq!(memory::format_bytes(512.0), "512 B");
+        assert_eq!(memory::format_bytes(1536.0), "1.50 KiB");
+        assert_eq!(memory::format_bytes(-3.0 * 1024.0 * 1024.0), "-3.00 MiB");
+
+        // ** the tests do not install the allocator, so the phase measures nothing
+        let (value, usage) = memory::measure("factorization", || vec![0u8; 4096].len());
+        assert_eq!(value, 4096);
+        assert_eq!(usage.name, "factorization");
+        let report = memory::format_usage(&[usage]);
+        assert!(report.lines().nth(1).unwrap().starts_with("% factorization"), "{report}");
+        assert!(report.ends_with("peak allocated over the whole run: 0 B"), "{report}");
+    }
+}
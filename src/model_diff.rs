@@ -0,0 +1,171 @@
+use crate::io::{self, FloatFormat, LabeledMatrix};
+use crate::solve;
+
+/// This is a coefficient that differs between two versions of a stoichiometric matrix.
+pub struct CoefficientChange {
+    /// The name of the metabolite (row)
+    pub metabolite: String,
+    /// The name of the reaction (column)
+    pub reaction: String,
+    /// The coefficient in the old matrix
+    pub old: f64,
+    /// The coefficient in the new matrix
+    pub new: f64,
+}
+
+/// This describes how a stoichiometric matrix changed between two versions of a model.
+pub struct ModelDiff {
+    /// The metabolites that are only in the new matrix
+    pub added_metabolites: Vec<String>,
+    /// The metabolites that are only in the old matrix
+    pub removed_metabolites: Vec<String>,
+    /// The reactions that are only in the new matrix
+    pub added_reactions: Vec<String>,
+    /// The reactions that are only in the old matrix
+    pub removed_reactions: Vec<String>,
+    /// The coefficients of shared metabolites and reactions that changed
+    pub changed: Vec<CoefficientChange>,
+    /// The shape of the old and new matrices
+    pub shapes: ((usize, usize), (usize, usize)),
+    /// The rank of the old and new matrices
+    pub ranks: (usize, usize),
+}
+
+impl ModelDiff {
+    /// This returns the null space dimension of the old and new matrices, i.e. the number of
+    /// independent flux modes that leave every metabolite unchanged.
+    pub fn null_space_dimensions(&self) -> (usize, usize) {
+        (self.shapes.0.1 - self.ranks.0, self.shapes.1.1 - self.ranks.1)
+    }
+
+    /// This returns whether the two matrices have the same metabolites, reactions and coefficients.
+    pub fn is_empty(&self) -> bool {
+        self.added_metabolites.is_empty()
+            && self.removed_metabolites.is_empty()
+            && self.added_reactions.is_empty()
+            && self.removed_reactions.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// This lists the names of the rows or columns of a matrix, numbering unlabeled ones.
+fn labels(count: usize, label: impl Fn(usize) -> String) -> Vec<String> {
+    (0..count).map(label).collect()
+}
+
+/// This compares two stoichiometric matrices. Metabolites and reactions are matched by name, so
+/// reordered rows and columns are not reported as changes; unlabeled rows and columns are named
+/// by position (M1, M2, ... and R1, R2, ...).
+///
+/// # Arguments
+/// * `old` - the old labeled stoichiometric matrix
+/// * `new` - the new labeled stoichiometric matrix
+/// * `epsilon` - coefficients that differ by at most this much are considered unchanged
+///
+pub fn diff_models(old: &LabeledMatrix, new: &LabeledMatrix, epsilon: f64) -> ModelDiff {
+    let old_metabolites = labels(old.matrix.nrows(), |i| old.row_label(i));
+    let new_metabolites = labels(new.matrix.nrows(), |i| new.row_label(i));
+    let old_reactions = labels(old.matrix.ncols(), |j| old.col_label(j));
+    let new_reactions = labels(new.matrix.ncols(), |j| new.col_label(j));
+
+    let only_in = |a: &[String], b: &[String]| -> Vec<String> {
+        a.iter().filter(|name| !b.contains(name)).cloned().collect()
+    };
+
+    let mut changed = vec!();
+    for (j_old, reaction) in old_reactions.iter().enumerate() {
+        let Some(j_new) = new_reactions.iter().position(|r| r == reaction) else {
+            continue;
+        };
+        for (i_old, metabolite) in old_metabolites.iter().enumerate() {
+            let Some(i_new) = new_metabolites.iter().position(|m| m == metabolite) else {
+                continue;
+            };
+            let (old_value, new_value) = (old.matrix[(i_old, j_old)], new.matrix[(i_new, j_new)]);
+            if (old_value - new_value).abs() > epsilon {
+                changed.push(CoefficientChange {
+                    metabolite: metabolite.clone(),
+                    reaction: reaction.clone(),
+                    old: old_value,
+                    new: new_value,
+                });
+            }
+        }
+    }
+
+    ModelDiff {
+        added_metabolites: only_in(&new_metabolites, &old_metabolites),
+        removed_metabolites: only_in(&old_metabolites, &new_metabolites),
+        added_reactions: only_in(&new_reactions, &old_reactions),
+        removed_reactions: only_in(&old_reactions, &new_reactions),
+        changed,
+        shapes: (old.matrix.shape(), new.matrix.shape()),
+        ranks: (solve::rank(&old.matrix), solve::rank(&new.matrix)),
+    }
+}
+
+/// This formats a model diff as a report in the style of a unified diff: a summary of the shape,
+/// rank and null space dimension, then one line per added (+) or removed (-) metabolite or
+/// reaction, then a tab delimited table of changed coefficients.
+///
+/// # Arguments
+/// * `diff` - the model diff
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_model_diff(diff: &ModelDiff, float_format: FloatFormat, float_precision: usize) -> String {
+    let ((old_m, old_n), (new_m, new_n)) = diff.shapes;
+    let (old_null, new_null) = diff.null_space_dimensions();
+    let mut result_string = format!(
+        "% metabolites: {old_m} -> {new_m}\n% reactions: {old_n} -> {new_n}\n% rank: {} -> {}\n% null space dimension: {old_null} -> {new_null}",
+        diff.ranks.0, diff.ranks.1,
+    );
+    for (sign, kind, names) in [
+        ('-', "metabolite", &diff.removed_metabolites),
+        ('+', "metabolite", &diff.added_metabolites),
+        ('-', "reaction", &diff.removed_reactions),
+        ('+', "reaction", &diff.added_reactions),
+    ] {
+        for name in names {
+            result_string.push_str(&format!("\n{sign} {kind} {name}"));
+        }
+    }
+    result_string.push_str("\n% reaction \t metabolite \t old \t new");
+    for change in &diff.changed {
+        result_string.push_str(&format!(
+            "\n~ {}\t{}\t{}\t{}",
+            change.reaction,
+            change.metabolite,
+            io::format_float(change.old, float_format, float_precision),
+            io::format_float(change.new, float_format, float_precision),
+        ));
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_diff_models() {
+        let old = io::parse_reaction_equations("hk: glc + atp -> g6p + adp\npgi: g6p -> f6p").unwrap();
+        let new = io::parse_reaction_equations("pgi: g6p -> 2 f6p\nhk: glc -> g6p\npfk: f6p -> fbp").unwrap();
+        let diff = model_diff::diff_models(&old.s_matrix, &new.s_matrix, 1e-9);
+
+        assert_eq!(diff.removed_metabolites, vec![String::from("atp"), String::from("adp")]);
+        assert_eq!(diff.added_metabolites, vec![String::from("fbp")]);
+        assert!(diff.removed_reactions.is_empty());
+        assert_eq!(diff.added_reactions, vec![String::from("pfk")]);
+
+        // ** reordering the reactions is not a change, but the new f6p coefficient is
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!((diff.changed[0].reaction.as_str(), diff.changed[0].metabolite.as_str()), ("pgi", "f6p"));
+        assert_eq!((diff.changed[0].old, diff.changed[0].new), (1.0, 2.0));
+
+        assert_eq!(diff.ranks, (2, 3));
+        assert_eq!(diff.null_space_dimensions(), (0, 0));
+        assert!(!diff.is_empty());
+        assert!(model_diff::diff_models(&old.s_matrix, &old.s_matrix, 1e-9).is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::io::{self, FloatFormat, LabeledMatrix};
+use crate::solve;
+
+use nalgebra as na;
+
+/// Coefficients closer than this to 0 after reduction are dropped from a pool.
+const POOL_EPSILON: f64 = 1e-9;
+
+/// The largest multiplier tried to turn the coefficients of a pool into whole numbers.
+const MAX_DENOMINATOR: u32 = 12;
+
+/// This is a conserved metabolite pool: a weighted sum of metabolites that no reaction changes,
+/// e.g. atp + adp + amp.
+pub struct ConservedPool {
+    /// The index (row) and weight of each metabolite in the pool, in row order
+    pub terms: Vec<(usize, f64)>,
+}
+
+impl ConservedPool {
+    /// This returns the weighted total of the pool for a vector of metabolite amounts or rates,
+    /// e.g. 0 for an accumulation vector that the model can produce.
+    ///
+    /// # Arguments
+    /// * `values` - one value per metabolite: (m x 1)
+    ///
+    pub fn total(&self, values: &na::DVector<f64>) -> f64 {
+        self.terms.iter().map(|(i, weight)| weight * values[*i]).sum()
+    }
+}
+
+/// This brings the rows of a matrix to reduced row echelon form in place, with partial pivoting.
+fn reduce_rows(rows: &mut na::DMatrix<f64>) {
+    let (k, m) = rows.shape();
+    let mut pivot_row = 0;
+    for col in 0..m {
+        if pivot_row == k {
+            break;
+        }
+        let (best, best_value) = (pivot_row..k)
+            .map(|r| (r, rows[(r, col)].abs()))
+            .fold((pivot_row, 0.0), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+        if best_value <= POOL_EPSILON {
+            continue;
+        }
+        rows.swap_rows(pivot_row, best);
+        let pivot = rows[(pivot_row, col)];
+        rows.row_mut(pivot_row).scale_mut(1.0 / pivot);
+        let reduced = rows.row(pivot_row).into_owned();
+        for r in (0..k).filter(|r| *r != pivot_row) {
+            let factor = rows[(r, col)];
+            if factor != 0.0 {
+                let updated = rows.row(r) - &reduced * factor;
+                rows.set_row(r, &updated);
+            }
+        }
+        pivot_row += 1;
+    }
+}
+
+/// This scales the weights of a pool to whole numbers if a small multiplier does so.
+fn to_whole_numbers(weights: &mut [f64]) {
+    let Some(multiplier) = (1..=MAX_DENOMINATOR).map(f64::from).find(|multiplier| {
+        weights.iter().all(|weight| ((weight * multiplier) - (weight * multiplier).round()).abs() <= 1e-6)
+    }) else {
+        return;
+    };
+    for weight in weights.iter_mut() {
+        *weight = (*weight * multiplier).round();
+    }
+}
+
+/// This finds the conserved metabolite pools (conserved moieties) of a stoichiometric matrix:
+/// weighted sums of metabolites whose total is invariant under every reaction. They span the left
+/// null space of the matrix, and there are as many as its left nullity, m - rank. The basis is
+/// brought to reduced row echelon form so that each pool involves few metabolites and has weight 1
+/// for the first metabolite that no earlier pool has, and weights are scaled to whole numbers
+/// where a small multiplier does so, e.g. atp + adp rather than 0.707 atp + 0.707 adp.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn conserved_pools(s_matrix: &na::DMatrix<f64>) -> Vec<ConservedPool> {
+    let basis = solve::left_null_space(s_matrix);
+    let mut rows = basis.transpose();
+    reduce_rows(&mut rows);
+    rows.row_iter()
+        .filter_map(|row| {
+            let mut weights: Vec<f64> = row.iter()
+                .map(|weight| if weight.abs() <= POOL_EPSILON { 0.0 } else { *weight })
+                .collect();
+            to_whole_numbers(&mut weights);
+            let terms: Vec<(usize, f64)> = weights.into_iter()
+                .enumerate()
+                .filter(|(_, weight)| *weight != 0.0)
+                .collect();
+            (!terms.is_empty()).then_some(ConservedPool { terms })
+        })
+        .collect()
+}
+
+/// This formats the conserved pools of a stoichiometric matrix, one per line, as a weighted sum of
+/// metabolite names, e.g. "pool1: atp + adp", with negative weights written as differences.
+/// Given an accumulation vector, each line also has the weighted total of the accumulation of the
+/// pool, which is 0 if the measurements are consistent with the pool being conserved.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `pools` - the conserved pools of the matrix
+/// * `acc_vector` - the accumulation vector, B: (m x 1), if any
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_pools(
+    s_matrix: &LabeledMatrix,
+    pools: &[ConservedPool],
+    acc_vector: Option<&na::DVector<f64>>,
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let format_weight = |weight: f64| {
+        if weight.fract() == 0.0 {
+            format!("{weight}")
+        } else {
+            io::format_float(weight, float_format, float_precision)
+        }
+    };
+    let mut result_string = format!("% conserved pools: {}", pools.len());
+    if acc_vector.is_some() {
+        result_string.push_str("\n% pool \t metabolites \t accumulation");
+    }
+    for (k, pool) in pools.iter().enumerate() {
+        let mut sum = String::new();
+        for (position, (i, weight)) in pool.terms.iter().enumerate() {
+            let sign = match (position, *weight < 0.0) {
+                (0, true) => "-",
+                (0, false) => "",
+                (_, true) => " - ",
+                (_, false) => " + ",
+            };
+            let metabolite = s_matrix.row_label(*i);
+            match format_weight(weight.abs()).as_str() {
+                "1" => sum.push_str(&format!("{sign}{metabolite}")),
+                coefficient => sum.push_str(&format!("{sign}{coefficient} {metabolite}")),
+            }
+        }
+        result_string.push_str(&format!("\npool{}: {sum}", k + 1));
+        if let Some(acc_vector) = acc_vector {
+            result_string.push_str(&format!("\t{}", io::format_float(pool.total(acc_vector), float_format, float_precision)));
+        }
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_conserved_pools() {
+        // ** atp and adp are only interconverted, and so are nad and nadh, so both pools are conserved
+        let model = io::parse_reaction_equations(
+            "hk: glc + atp -> g6p + adp\npk: pep + adp -> pyr + atp\ngapdh: g3p + nad -> bpg + nadh\nldh: pyr + nadh -> lac + nad"
+        ).unwrap();
+        let s_matrix = &model.s_matrix;
+        let pools = moieties::conserved_pools(&s_matrix.matrix);
+        assert_eq!(pools.len(), audit::analyze_matrix(&s_matrix.matrix).left_nullity);
+        for pool in &pools {
+            let weights = na::DVector::from_fn(s_matrix.matrix.nrows(), |i, _| {
+                pool.terms.iter().find(|(j, _)| *j == i).map_or(0.0, |(_, weight)| *weight)
+            });
+            assert!((weights.transpose() * &s_matrix.matrix).amax() < 1e-9);
+        }
+
+        let output = moieties::format_pools(s_matrix, &pools, None, io::FloatFormat::Decimal, 3);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.iter().any(|line| line.ends_with(": atp + adp")), "{output}");
+        assert!(lines.iter().any(|line| line.ends_with(": nad + nadh")), "{output}");
+
+        // ** the totals of an accumulation vector show whether it leaks a conserved pool
+        let atp = (0..s_matrix.matrix.nrows()).find(|i| s_matrix.row_label(*i) == "atp").unwrap();
+        let mut acc_vector = na::DVector::zeros(s_matrix.matrix.nrows());
+        acc_vector[atp] = 2.0;
+        let leaked: Vec<f64> = pools.iter().map(|pool| pool.total(&acc_vector)).filter(|total| *total != 0.0).collect();
+        assert_eq!(leaked, vec![2.0]);
+
+        // ** a matrix of full row rank conserves nothing
+        assert!(moieties::conserved_pools(&na::DMatrix::identity(3, 3)).is_empty());
+    }
+}
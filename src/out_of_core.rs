@@ -0,0 +1,178 @@
+use crate::error::SmasError;
+use crate::io::{self, ParseMode};
+use crate::solve::LinearOperator;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use memmap2::Mmap;
+use nalgebra as na;
+
+/// The size of an entry of the cache: the row and column as little endian u64s, then the value as
+/// a little endian f64.
+const ENTRY_SIZE: usize = 24;
+
+/// The number given to the next cache file, so that matrices opened at the same time do not share one.
+static NEXT_CACHE: AtomicUsize = AtomicUsize::new(0);
+
+/// This is a stoichiometric matrix that stays on disk. Opening it parses the Matrix Market file
+/// once into a binary cache of its nonzero entries in the temporary directory, which is mapped
+/// into memory; every product with the matrix then reads the mapped entries, which the operating
+/// system pages in from disk as needed, so only the vectors are held in memory. This trades speed
+/// for the ability to solve systems whose matrix does not fit in memory at all. The cache is
+/// deleted when the matrix is dropped.
+pub struct StreamedMatrix {
+    /// The path to the binary cache of the nonzero entries
+    cache_path: PathBuf,
+    /// The cache, mapped into memory
+    cache: Mmap,
+    /// The number of rows (metabolites)
+    nrows: usize,
+    /// The number of columns (reactions)
+    ncols: usize,
+}
+
+impl StreamedMatrix {
+    /// This opens a matrix file for streaming, reading it through once to check it, find its shape
+    /// and write its nonzero entries to the cache. Returns the errors of `io::stream_matrix_entries`,
+    /// or SmasError::Io if the cache cannot be written or mapped.
+    ///
+    /// # Arguments
+    /// * `path` - the path to the matrix file
+    /// * `mode` - whether deviations from the format are errors (strict) or accepted (lenient)
+    /// * `pattern_value` - the value of every entry listed in a pattern file
+    ///
+    pub fn open<R: AsRef<Path>>(path: R, mode: ParseMode, pattern_value: f64) -> Result<Self, SmasError> {
+        let _span = tracing::info_span!("load", path = %path.as_ref().display()).entered();
+        let cache_path = std::env::temp_dir().join(format!(
+            "smas_streamed_matrix_{}_{}.coo",
+            std::process::id(),
+            NEXT_CACHE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (nrows, ncols) = match write_cache(path, mode, pattern_value, &cache_path) {
+            Ok(shape) => shape,
+            Err(e) => {
+                let _ = fs::remove_file(&cache_path);
+                return Err(e);
+            }
+        };
+        // SAFETY: the cache is a file of our own in the temporary directory, which nothing else
+        // writes to or truncates while it is mapped
+        let cache = File::open(&cache_path).and_then(|file| unsafe { Mmap::map(&file) });
+        match cache {
+            Ok(cache) => Ok(StreamedMatrix { cache_path, cache, nrows, ncols }),
+            Err(e) => {
+                let _ = fs::remove_file(&cache_path);
+                Err(SmasError::Io(e))
+            }
+        }
+    }
+
+    /// This returns the number of nonzero entries of the matrix.
+    pub fn nnz(&self) -> usize {
+        self.cache.len() / ENTRY_SIZE
+    }
+
+    /// This returns the (0-based) row, column and value of each nonzero entry.
+    fn entries(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.cache.chunks_exact(ENTRY_SIZE).map(|entry| {
+            let row = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+            let col = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+            let value = f64::from_le_bytes(entry[16..24].try_into().unwrap());
+            (row as usize, col as usize, value)
+        })
+    }
+}
+
+/// This parses a matrix file and writes its nonzero entries to a binary cache. Returns the shape of
+/// the matrix.
+fn write_cache<R: AsRef<Path>>(
+    path: R,
+    mode: ParseMode,
+    pattern_value: f64,
+    cache_path: &Path,
+) -> Result<(usize, usize), SmasError> {
+    let mut writer = BufWriter::new(File::create(cache_path)?);
+    let mut result = Ok(());
+    let shape = io::stream_matrix_entries(path, mode, pattern_value, |i, j, value| {
+        if result.is_ok() {
+            result = writer.write_all(&(i as u64).to_le_bytes())
+                .and_then(|_| writer.write_all(&(j as u64).to_le_bytes()))
+                .and_then(|_| writer.write_all(&value.to_le_bytes()));
+        }
+    })?;
+    result?;
+    writer.flush()?;
+    Ok(shape)
+}
+
+impl Drop for StreamedMatrix {
+    fn drop(&mut self) {
+        // ** the cache is only a copy of the matrix file, so failing to delete it loses nothing
+        let _ = fs::remove_file(&self.cache_path);
+    }
+}
+
+impl LinearOperator for StreamedMatrix {
+    fn shape(&self) -> (usize, usize) {
+        (self.nrows, self.ncols)
+    }
+
+    fn apply(&self, x: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        let _span = tracing::info_span!("multiply", transpose = false).entered();
+        let mut y = na::DVector::zeros(self.nrows);
+        for (i, j, value) in self.entries() {
+            y[i] += value * x[j];
+        }
+        Ok(y)
+    }
+
+    fn apply_transpose(&self, y: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        let _span = tracing::info_span!("multiply", transpose = true).entered();
+        let mut x = na::DVector::zeros(self.ncols);
+        for (i, j, value) in self.entries() {
+            x[j] += value * y[i];
+        }
+        Ok(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::solve::LinearOperator;
+    use nalgebra as na;
+
+    #[test]
+    fn test_streamed_matrix() {
+        // ** a symmetric coordinate file streams its mirrored entries too
+        let path = std::env::temp_dir().join(format!("smas_streamed_matrix_test_{}.mtx", std::process::id()));
+        std::fs::write(&path, "%%MatrixMarket matrix coordinate real symmetric\n% a comment\n3 3 3\n1 1 2.0\n3 1 -1.0\n2 2 4.0\n").unwrap();
+        let streamed = out_of_core::StreamedMatrix::open(&path, io::ParseMode::Strict, 1.0).unwrap();
+        let dense = io::load_matrix(&path).unwrap();
+        assert_eq!(streamed.shape(), (3, 3));
+        assert_eq!(streamed.nnz(), 4);
+
+        let x = na::DVector::from_row_slice(&[1.0, 2.0, 3.0]);
+        assert_eq!(streamed.apply(&x).unwrap(), &dense * &x);
+        assert_eq!(streamed.apply_transpose(&x).unwrap(), dense.tr_mul(&x));
+
+        // ** solving against the file gives the in-memory answer
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 8.0, -1.0]);
+        let solution = solve::solve_lsqr(&acc_vector, &streamed, solve::LSQR_TOLERANCE, 100).unwrap();
+        let expected = solve::solve(acc_vector, dense);
+        assert!(util::vectors_eq(&solution.reactions, &expected, 1e-9, 1e-9));
+
+        // ** the cache is deleted with the matrix
+        let cache_path = streamed.cache_path.clone();
+        assert!(cache_path.exists());
+        drop(streamed);
+        assert!(!cache_path.exists());
+
+        std::fs::write(&path, "3 3\n1 0 0\n0 1\n").unwrap();
+        assert!(matches!(out_of_core::StreamedMatrix::open(&path, io::ParseMode::Lenient, 1.0), Err(error::SmasError::Shape { .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,742 @@
+use crate::error::SmasError;
+use crate::io::{LabeledMatrix, ReactionModel};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use nalgebra as na;
+
+/// The BioPAX Level 3 classes that convert participants on their left into those on their right.
+const CONVERSIONS: [&str; 6] = [
+    "BiochemicalReaction",
+    "TransportWithBiochemicalReaction",
+    "Transport",
+    "ComplexAssembly",
+    "Degradation",
+    "Conversion",
+];
+
+/// The deepest elements may nest. Elements are parsed without recursion, but dropping or
+/// comparing a tree recurses, so deeper documents could overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// This is an element of an XML document.
+#[derive(Clone, PartialEq, Debug)]
+pub struct XmlElement {
+    /// The qualified name, e.g. "bp:left"
+    pub name: String,
+    /// The attributes, in document order, with their entities decoded
+    pub attributes: Vec<(String, String)>,
+    /// The child elements
+    pub children: Vec<XmlElement>,
+    /// The text directly inside the element, with its entities decoded
+    pub text: String,
+    /// The line the element starts on
+    pub line: usize,
+}
+
+impl XmlElement {
+    /// This returns the name without its namespace prefix, e.g. "left" for "bp:left".
+    pub fn local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+
+    /// This returns the value of an attribute, looked up by its qualified name or, failing that,
+    /// its local name.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(key, _)| key == name)
+            .or_else(|| self.attributes.iter().find(|(key, _)| local_name(key) == local_name(name)))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// This returns the children with the given local name.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> + 'a {
+        self.children.iter().filter(move |child| child.local_name() == name)
+    }
+
+    /// This returns the trimmed text of the first child with the given local name, if it has any.
+    fn child_text<'a>(&'a self, name: &'a str) -> Option<&'a str> {
+        self.children_named(name).map(|child| child.text.trim()).find(|text| !text.is_empty())
+    }
+
+    /// This returns the element and all of its descendants, parents before children.
+    pub fn descendants(&self) -> Vec<&XmlElement> {
+        let mut elements = vec!();
+        let mut stack = vec![self];
+        while let Some(element) = stack.pop() {
+            elements.push(element);
+            stack.extend(element.children.iter().rev());
+        }
+        elements
+    }
+}
+
+/// This strips the namespace prefix from a qualified name.
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// This decodes the entities of XML text, e.g. "&amp;" and "&#945;".
+fn decode_entities(text: &str, line: usize) -> Result<String, SmasError> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(';') else {
+            return Err(SmasError::Parse { line, message: String::from("an XML entity is missing its ';'") });
+        };
+        let entity = &rest[start + 1..start + end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|decimal| decimal.parse::<u32>()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        let Some(character) = character else {
+            return Err(SmasError::Parse { line, message: format!("unknown XML entity \"&{entity};\"") });
+        };
+        decoded.push(character);
+        rest = &rest[start + end + 1..];
+    }
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+/// This parses an XML document and returns its root element. This reads the subset of XML that
+/// pathway databases export: elements, attributes, text, CDATA sections and the predefined and
+/// numeric entities. Declarations, processing instructions, comments and the document type are
+/// skipped; entities declared in the document type are not supported. Elements may nest at most
+/// 256 deep.
+///
+/// # Arguments
+/// * `text` - the document
+///
+pub fn parse_xml(text: &str) -> Result<XmlElement, SmasError> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_at = |position: usize| line_starts.partition_point(|start| *start <= position);
+    let error = |position: usize, message: String| SmasError::Parse { line: line_at(position), message };
+    let find = |position: usize, pattern: &str| -> Result<usize, SmasError> {
+        text[position..].find(pattern)
+            .map(|offset| position + offset)
+            .ok_or_else(|| error(position, format!("expected \"{pattern}\" before the end of the document")))
+    };
+
+    // ** elements are kept on an explicit stack rather than parsed recursively, so deeply nested
+    // ** documents cannot overflow the call stack
+    let mut stack: Vec<XmlElement> = vec!();
+    let mut root: Option<XmlElement> = None;
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        if rest.starts_with("<?") {
+            position = find(position, "?>")? + 2;
+        } else if rest.starts_with("<!--") {
+            position = find(position, "-->")? + 3;
+        } else if rest.starts_with("<![CDATA[") {
+            let end = find(position, "]]>")?;
+            let Some(element) = stack.last_mut() else {
+                return Err(error(position, String::from("text outside of the root element")));
+            };
+            element.text.push_str(&text[position + 9..end]);
+            position = end + 3;
+        } else if rest.starts_with("<!") {
+            // ** a document type may hold declarations in brackets, which may hold '>'
+            let close = find(position, ">")?;
+            position = match text[position..close].find('[') {
+                Some(_) => find(position, "]")?,
+                None => close,
+            };
+            position = find(position, ">")? + 1;
+        } else if let Some(rest) = rest.strip_prefix("</") {
+            let end = find(position, ">")?;
+            let name = rest[..end - position - 2].trim();
+            let Some(element) = stack.pop() else {
+                return Err(error(position, format!("unexpected closing tag </{name}>")));
+            };
+            if element.name != name {
+                return Err(error(position, format!("expected </{}> to close the element on line {}, found </{name}>", element.name, element.line)));
+            }
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => root = Some(element),
+            }
+            position = end + 1;
+        } else if rest.starts_with('<') {
+            if root.is_some() {
+                return Err(error(position, String::from("the document has more than one root element")));
+            }
+            if stack.len() >= MAX_DEPTH {
+                return Err(error(position, format!("elements nest more than {MAX_DEPTH} deep")));
+            }
+            let line = line_at(position);
+            let tag_end = tag_end(text, position).ok_or_else(|| error(position, String::from("an element's tag is not closed")))?;
+            let self_closing = text[..tag_end].ends_with('/');
+            let tag = &text[position + 1..if self_closing { tag_end - 1 } else { tag_end }];
+            let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+            let name = &tag[..name_end];
+            if name.is_empty() {
+                return Err(error(position, String::from("an element has no name")));
+            }
+            let element = XmlElement {
+                name: name.to_string(),
+                attributes: parse_attributes(&tag[name_end..], line)?,
+                children: vec!(),
+                text: String::new(),
+                line,
+            };
+            match (self_closing, stack.last_mut()) {
+                (false, _) => stack.push(element),
+                (true, Some(parent)) => parent.children.push(element),
+                (true, None) => root = Some(element),
+            }
+            position = tag_end + 1;
+        } else {
+            let end = rest.find('<').map_or(text.len(), |offset| position + offset);
+            let content = &text[position..end];
+            match stack.last_mut() {
+                Some(element) => element.text.push_str(&decode_entities(content, line_at(position))?),
+                None if content.trim().is_empty() => {}
+                None => return Err(error(position, String::from("text outside of the root element"))),
+            }
+            position = end;
+        }
+    }
+    if let Some(element) = stack.last() {
+        return Err(error(text.len(), format!("the element <{}> on line {} is not closed", element.name, element.line)));
+    }
+    root.ok_or_else(|| error(text.len(), String::from("the document has no root element")))
+}
+
+/// This finds the '>' that ends the tag starting at `position`, skipping quoted attribute values.
+fn tag_end(text: &str, position: usize) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (offset, c) in text[position..].char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(position + offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// This parses the attributes of a tag, e.g. ` id="1" name='a'`.
+fn parse_attributes(mut rest: &str, line: usize) -> Result<Vec<(String, String)>, SmasError> {
+    let mut attributes = vec!();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(attributes);
+        }
+        let malformed = || SmasError::Parse { line, message: format!("malformed attribute \"{}\"", rest.split_whitespace().next().unwrap_or(rest)) };
+        let (name, value) = rest.split_once('=').ok_or_else(malformed)?;
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'').ok_or_else(malformed)?;
+        let end = value[1..].find(quote).ok_or_else(malformed)? + 1;
+        attributes.push((name.trim().to_string(), decode_entities(&value[1..end], line)?));
+        rest = &value[end + 1..];
+    }
+}
+
+/// This is the format of a pathway file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PathwayFormat {
+    /// KEGG Markup Language, the format of KEGG pathway maps
+    Kgml,
+    /// BioPAX Level 3, the RDF/XML format of e.g. Reactome and WikiPathways
+    Biopax,
+}
+
+impl std::fmt::Display for PathwayFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathwayFormat::Kgml => write!(f, "KGML"),
+            PathwayFormat::Biopax => write!(f, "BioPAX"),
+        }
+    }
+}
+
+/// This is a stoichiometric matrix imported from a pathway file, with what the import had to guess.
+pub struct PathwayImport {
+    /// The format of the pathway file
+    pub format: PathwayFormat,
+    /// The labeled stoichiometric matrix and the reversibility of each reaction
+    pub model: ReactionModel,
+    /// Each place where the pathway was ambiguous, and how it was read
+    pub ambiguities: Vec<String>,
+}
+
+/// This returns whether a file's contents look like XML, and so may be a pathway file rather
+/// than reaction equations.
+///
+/// # Arguments
+/// * `contents` - the contents of the file
+///
+pub fn is_xml(contents: &str) -> bool {
+    contents.trim_start_matches('\u{feff}').trim_start().starts_with('<')
+}
+
+/// This imports the reactions of a KEGG KGML or BioPAX Level 3 pathway as a labeled
+/// stoichiometric matrix. The format is chosen by the root element. Pathway files are often
+/// incomplete, so the import is best-effort: e.g. KGML has no stoichiometry, so every coefficient
+/// is 1, and a reaction with no direction is read as irreversible, left to right. Each such guess
+/// is listed in the ambiguities of the result.
+///
+/// # Arguments
+/// * `contents` - the contents of the pathway file
+///
+pub fn import_pathway(contents: &str) -> Result<PathwayImport, SmasError> {
+    let root = parse_xml(contents.trim_start_matches('\u{feff}'))?;
+    let mut ambiguities = vec!();
+    let (format, reactions) = match root.local_name() {
+        "pathway" => (PathwayFormat::Kgml, kgml_reactions(&root, &mut ambiguities)),
+        "RDF" => (PathwayFormat::Biopax, biopax_reactions(&root, &mut ambiguities)?),
+        name => {
+            return Err(SmasError::Parse {
+                line: root.line,
+                message: format!("expected a KGML <pathway> or BioPAX <rdf:RDF> root element, found <{name}>"),
+            })
+        }
+    };
+    if reactions.is_empty() {
+        return Err(SmasError::Parse { line: root.line, message: format!("the {format} pathway has no reactions") });
+    }
+    Ok(PathwayImport { format, model: build_model(reactions, &mut ambiguities), ambiguities })
+}
+
+/// This reads a pathway file and imports it; see `import_pathway`.
+///
+/// # Arguments
+/// * `path` - the path to the file
+///
+pub fn load_pathway<R: AsRef<Path>>(path: R) -> Result<PathwayImport, SmasError> {
+    import_pathway(&fs::read_to_string(path)?)
+}
+
+/// This is a reaction read from a pathway: its name, whether it is reversible, and its
+/// (metabolite, coefficient) terms, negative for substrates.
+type PathwayReaction = (String, bool, Vec<(String, f64)>);
+
+/// This turns a database identifier or display name into a label: whitespace becomes '_', and
+/// ':' too, since the reaction equations format uses it to end the reaction name.
+fn sanitize_label(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("_").replace(':', "_")
+}
+
+/// This reads the reactions of a KGML pathway. The entries of a reaction list KEGG identifiers,
+/// e.g. "rn:R00299" or "cpd:C00031", which are labeled without their database prefix.
+fn kgml_reactions(root: &XmlElement, ambiguities: &mut Vec<String>) -> Vec<PathwayReaction> {
+    let first_id = |element: &XmlElement, what: &str, ambiguities: &mut Vec<String>| -> Option<String> {
+        let ids: Vec<&str> = element.attribute("name").unwrap_or("").split_whitespace().collect();
+        if ids.len() > 1 {
+            ambiguities.push(format!("line {}: the {what} lists {} KEGG ids ({}); using the first", element.line, ids.len(), ids.join(" ")));
+        }
+        if element.children_named("alt").next().is_some() {
+            ambiguities.push(format!("line {}: the {what} has alternatives; using the first", element.line));
+        }
+        ids.first().map(|id| sanitize_label(id.split_once(':').map_or(id, |(_, id)| id)))
+    };
+
+    let mut reactions = vec!();
+    for reaction in root.children_named("reaction") {
+        let name = first_id(reaction, "reaction", ambiguities)
+            .or_else(|| reaction.attribute("id").map(|id| format!("R{}", sanitize_label(id))))
+            .unwrap_or_else(|| format!("R{}", reactions.len() + 1));
+        let reversible = match reaction.attribute("type") {
+            Some("reversible") => true,
+            Some("irreversible") => false,
+            other => {
+                ambiguities.push(format!(
+                    "line {}: reaction {name} has type {}; read as irreversible",
+                    reaction.line,
+                    other.map_or(String::from("(none)"), |other| format!("\"{other}\"")),
+                ));
+                false
+            }
+        };
+        let mut terms = vec!();
+        for (side, sign) in [("substrate", -1.0), ("product", 1.0)] {
+            for participant in reaction.children_named(side) {
+                match first_id(participant, side, ambiguities) {
+                    Some(metabolite) => terms.push((metabolite, sign)),
+                    None => ambiguities.push(format!("line {}: a {side} of reaction {name} has no KEGG id; skipped", participant.line)),
+                }
+            }
+        }
+        reactions.push((name, reversible, terms));
+    }
+    if !reactions.is_empty() {
+        ambiguities.push(String::from("KGML has no stoichiometry; every coefficient is 1"));
+    }
+    reactions
+}
+
+/// This returns the key an RDF resource is referred to by: "#id" for rdf:ID, the URI for rdf:about.
+fn rdf_key(element: &XmlElement) -> Option<String> {
+    element.attribute("rdf:ID").map(|id| format!("#{id}"))
+        .or_else(|| element.attribute("rdf:about").map(String::from))
+}
+
+/// This returns the resource a reference refers to, with its key: the rdf:resource it names, or
+/// the resource defined inside it.
+fn resolve_resource<'a>(reference: &'a XmlElement, resources: &HashMap<String, &'a XmlElement>) -> Option<(String, &'a XmlElement)> {
+    match reference.attribute("rdf:resource") {
+        Some(key) => resources.get(key).map(|element| (key.to_string(), *element)),
+        None => reference.children.first().and_then(|element| rdf_key(element).map(|key| (key, element))),
+    }
+}
+
+/// This reads the reactions of a BioPAX Level 3 document: every conversion, e.g. a
+/// BiochemicalReaction, with its left and right participants. Participants may be referred to
+/// with rdf:resource or defined inline. Metabolites are labeled with their display name.
+fn biopax_reactions(root: &XmlElement, ambiguities: &mut Vec<String>) -> Result<Vec<PathwayReaction>, SmasError> {
+    let elements = root.descendants();
+    let resources: HashMap<String, &XmlElement> = elements.iter()
+        .filter_map(|element| rdf_key(element).map(|key| (key, *element)))
+        .collect();
+    let resolve = |reference| resolve_resource(reference, &resources);
+    let display_name = |element: &XmlElement| -> String {
+        ["displayName", "standardName", "name"].iter()
+            .find_map(|name| element.child_text(name))
+            .map(sanitize_label)
+            .or_else(|| rdf_key(element).map(|key| sanitize_label(key.rsplit(['#', '/']).next().unwrap_or(&key))))
+            .unwrap_or_default()
+    };
+
+    // ** distinct entities with the same name, e.g. glucose outside and inside the cell, get distinct labels
+    let mut labels: HashMap<String, String> = HashMap::new();
+    let mut label_count: HashMap<String, usize> = HashMap::new();
+    let mut label = |key: &str, element: &XmlElement, ambiguities: &mut Vec<String>| -> String {
+        if let Some(label) = labels.get(key) {
+            return label.clone();
+        }
+        let name = display_name(element);
+        let count = label_count.entry(name.clone()).or_insert(0);
+        *count += 1;
+        let label = match *count {
+            1 => name,
+            n => {
+                ambiguities.push(format!("line {}: another entity is also named {name}; labeled {name}_{n}", element.line));
+                format!("{name}_{n}")
+            }
+        };
+        labels.insert(key.to_string(), label.clone());
+        label
+    };
+
+    let mut reactions = vec!();
+    for conversion in elements.iter().filter(|element| CONVERSIONS.contains(&element.local_name())) {
+        let name = display_name(conversion);
+        let coefficients: HashMap<String, f64> = conversion.children_named("participantStoichiometry")
+            .filter_map(&resolve)
+            .filter_map(|(_, stoichiometry)| {
+                let entity = stoichiometry.children_named("physicalEntity").next().and_then(&resolve)?;
+                let coefficient = stoichiometry.child_text("stoichiometricCoefficient")?.parse::<f64>().ok()?;
+                Some((entity.0, coefficient))
+            })
+            .collect();
+        let (reversible, swap) = match conversion.child_text("conversionDirection") {
+            Some("REVERSIBLE") => (true, false),
+            Some("LEFT-TO-RIGHT") => (false, false),
+            Some("RIGHT-TO-LEFT") => (false, true),
+            other => {
+                ambiguities.push(format!(
+                    "line {}: reaction {name} has conversionDirection {}; read as left to right",
+                    conversion.line,
+                    other.unwrap_or("(none)"),
+                ));
+                (false, false)
+            }
+        };
+
+        let mut terms = vec!();
+        for (side, sign) in [("left", -1.0), ("right", 1.0)] {
+            let sign = if swap { -sign } else { sign };
+            for participant in conversion.children_named(side) {
+                let Some((key, entity)) = resolve(participant) else {
+                    return Err(SmasError::Parse {
+                        line: participant.line,
+                        message: format!("a participant of reaction {name} refers to a resource that is not defined"),
+                    });
+                };
+                let metabolite = label(&key, entity, ambiguities);
+                let coefficient = coefficients.get(&key).copied().unwrap_or_else(|| {
+                    ambiguities.push(format!("line {}: reaction {name} gives no stoichiometry for {metabolite}; using 1", participant.line));
+                    1.0
+                });
+                terms.push((metabolite, sign * coefficient));
+            }
+        }
+        reactions.push((name, reversible, terms));
+    }
+    Ok(reactions)
+}
+
+/// This builds the labeled stoichiometric matrix of the imported reactions. Metabolites are
+/// numbered in order of first appearance, and reactions with the same name are given distinct
+/// labels.
+fn build_model(reactions: Vec<PathwayReaction>, ambiguities: &mut Vec<String>) -> ReactionModel {
+    let mut metabolites: Vec<String> = vec!();
+    let mut reaction_labels: Vec<String> = vec!();
+    let mut reversible: Vec<bool> = vec!();
+    let mut entries: Vec<(usize, usize, f64)> = vec!();
+    for (name, is_reversible, terms) in reactions {
+        let mut label = name.clone();
+        let mut n = 1;
+        while reaction_labels.contains(&label) {
+            n += 1;
+            label = format!("{name}_{n}");
+        }
+        if n > 1 {
+            ambiguities.push(format!("reaction {name} appears {n} times; labeled {label}"));
+        }
+        let j = reaction_labels.len();
+        let mut column: Vec<(usize, f64)> = vec!();
+        for (metabolite, coefficient) in terms {
+            let i = metabolites.iter().position(|m| *m == metabolite).unwrap_or_else(|| {
+                metabolites.push(metabolite);
+                metabolites.len() - 1
+            });
+            match column.iter_mut().find(|(row, _)| *row == i) {
+                Some((_, value)) => *value += coefficient,
+                None => column.push((i, coefficient)),
+            }
+        }
+        for (i, value) in &column {
+            if *value == 0.0 {
+                ambiguities.push(format!("reaction {label} consumes and produces {} equally; its coefficient is 0", metabolites[*i]));
+            }
+        }
+        entries.extend(column.into_iter().map(|(i, value)| (i, j, value)));
+        reaction_labels.push(label);
+        reversible.push(is_reversible);
+    }
+
+    let mut matrix = na::DMatrix::zeros(metabolites.len(), reaction_labels.len());
+    for (i, j, value) in entries {
+        matrix[(i, j)] = value;
+    }
+    ReactionModel {
+        s_matrix: LabeledMatrix {
+            matrix,
+            row_labels: Some(metabolites),
+            col_labels: Some(reaction_labels),
+        },
+        reversible,
+    }
+}
+
+/// This formats the summary and ambiguities of a pathway import as '%' comment lines.
+///
+/// # Arguments
+/// * `import` - the imported pathway
+///
+pub fn format_import_report(import: &PathwayImport) -> String {
+    let mut report = format!(
+        "% imported {} reactions and {} metabolites from a {} pathway, with {} ambiguities",
+        import.model.s_matrix.matrix.ncols(),
+        import.model.s_matrix.matrix.nrows(),
+        import.format,
+        import.ambiguities.len(),
+    );
+    for ambiguity in &import.ambiguities {
+        report.push_str(&format!("\n%   {ambiguity}"));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_import_pathway() {
+        let kgml = r#"<?xml version="1.0"?>
+<!DOCTYPE pathway SYSTEM "https://www.kegg.jp/kegg/xml/KGML_v0.7.2_.dtd">
+<!-- glycolysis, abridged -->
+<pathway name="path:map00010" org="map" title="Glycolysis &amp; gluconeogenesis">
+    <entry id="1" name="cpd:C00031" type="compound"/>
+    <reaction id="10" name="rn:R01786 rn:R00299" type="irreversible">
+        <substrate id="1" name="cpd:C00031"/>
+        <product id="2" name="cpd:C00668"/>
+    </reaction>
+    <reaction id="11" name="rn:R02740" type="reversible">
+        <substrate id="2" name="cpd:C00668"/>
+        <product id="3" name="cpd:C05345"/>
+    </reaction>
+</pathway>"#;
+        let import = pathway::import_pathway(kgml).unwrap();
+        assert_eq!(import.format, pathway::PathwayFormat::Kgml);
+        assert_eq!(import.model.s_matrix.matrix, na::DMatrix::from_row_slice(3, 2, &[-1.0, 0.0, 1.0, -1.0, 0.0, 1.0]));
+        assert_eq!(import.model.s_matrix.col_labels.as_deref().unwrap(), ["R01786", "R02740"]);
+        assert_eq!(import.model.s_matrix.row_labels.as_deref().unwrap(), ["C00031", "C00668", "C05345"]);
+        assert_eq!(import.model.reversible, [false, true]);
+        assert_eq!(import.ambiguities.len(), 2, "{:?}", import.ambiguities);
+
+        let biopax = r##"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:bp="http://www.biopax.org/release/biopax-level3.owl#">
+  <bp:SmallMolecule rdf:ID="atp"><bp:displayName>ATP</bp:displayName></bp:SmallMolecule>
+  <bp:SmallMolecule rdf:ID="adp"><bp:displayName>ADP</bp:displayName></bp:SmallMolecule>
+  <bp:SmallMolecule rdf:ID="glc_out"><bp:displayName>glucose</bp:displayName></bp:SmallMolecule>
+  <bp:SmallMolecule rdf:ID="glc_in"><bp:displayName>glucose</bp:displayName></bp:SmallMolecule>
+  <bp:Stoichiometry rdf:ID="s1">
+    <bp:physicalEntity rdf:resource="#atp"/>
+    <bp:stoichiometricCoefficient rdf:datatype="http://www.w3.org/2001/XMLSchema#float">2.0</bp:stoichiometricCoefficient>
+  </bp:Stoichiometry>
+  <bp:Transport rdf:ID="uptake">
+    <bp:displayName>glucose uptake</bp:displayName>
+    <bp:left rdf:resource="#glc_out"/>
+    <bp:left rdf:resource="#atp"/>
+    <bp:right rdf:resource="#glc_in"/>
+    <bp:right><bp:SmallMolecule rdf:about="http://example.org/pi"><bp:name>P&lt;i&gt;</bp:name></bp:SmallMolecule></bp:right>
+    <bp:participantStoichiometry rdf:resource="#s1"/>
+    <bp:conversionDirection>RIGHT-TO-LEFT</bp:conversionDirection>
+  </bp:Transport>
+</rdf:RDF>"##;
+        let import = pathway::import_pathway(biopax).unwrap();
+        assert_eq!(import.format, pathway::PathwayFormat::Biopax);
+        let s_matrix = &import.model.s_matrix;
+        assert_eq!(s_matrix.col_labels.as_deref().unwrap(), ["glucose_uptake"]);
+        assert_eq!(s_matrix.row_labels.as_deref().unwrap(), ["glucose", "ATP", "glucose_2", "P<i>"]);
+        assert_eq!(s_matrix.matrix.column(0).as_slice(), [1.0, 2.0, -1.0, -1.0]);
+        assert_eq!(import.ambiguities.len(), 4, "{:?}", import.ambiguities);
+        assert!(pathway::format_import_report(&import).starts_with("% imported 1 reactions and 4 metabolites from a BioPAX pathway"));
+
+        let Err(error) = pathway::import_pathway("<pathway>\n<reaction>\n</pathway>") else {
+            panic!("a mismatched closing tag should fail");
+        };
+        assert_eq!(error.to_string(), "line 3: expected </reaction> to close the element on line 2, found </pathway>");
+        assert!(pathway::import_pathway("<sbml/>").is_err());
+        assert!(pathway::is_xml("\u{feff}  <pathway/>") && !pathway::is_xml("hk: glc -> g6p"));
+    }
+
+    #[test]
+    fn test_import_kgml_ambiguities() {
+        let kgml = r#"<pathway name="path:map00010">
+    <reaction id="10" name="rn:R00299 rn:R01786">
+        <substrate id="1" name="cpd:C00031 cpd:C00267"/>
+        <product id="2" name="cpd:C00668"><alt name="cpd:C01172"/></product>
+        <product id="3"/>
+    </reaction>
+    <reaction id="11" type="maybe">
+        <substrate id="2" name="cpd:C00668"/>
+        <product id="2" name="cpd:C00668"/>
+    </reaction>
+    <reaction id="12" name="rn:R00299" type="reversible">
+        <substrate id="2" name="cpd:C00668"/>
+    </reaction>
+</pathway>"#;
+        let import = pathway::import_pathway(kgml).unwrap();
+        let s_matrix = &import.model.s_matrix;
+        assert_eq!(s_matrix.col_labels.as_deref().unwrap(), ["R00299", "R11", "R00299_2"]);
+        assert_eq!(s_matrix.row_labels.as_deref().unwrap(), ["C00031", "C00668"]);
+        assert_eq!(s_matrix.matrix, na::DMatrix::from_row_slice(2, 3, &[-1.0, 0.0, 0.0, 1.0, 0.0, -1.0]));
+        assert_eq!(import.model.reversible, [false, false, true]);
+        assert_eq!(import.ambiguities, [
+            "line 2: the reaction lists 2 KEGG ids (rn:R00299 rn:R01786); using the first",
+            "line 2: reaction R00299 has type (none); read as irreversible",
+            "line 3: the substrate lists 2 KEGG ids (cpd:C00031 cpd:C00267); using the first",
+            "line 4: the product has alternatives; using the first",
+            "line 5: a product of reaction R00299 has no KEGG id; skipped",
+            "line 7: reaction R11 has type \"maybe\"; read as irreversible",
+            "KGML has no stoichiometry; every coefficient is 1",
+            "reaction R11 consumes and produces C00668 equally; its coefficient is 0",
+            "reaction R00299 appears 2 times; labeled R00299_2",
+        ]);
+
+        let Err(error) = pathway::import_pathway("<pathway name=\"path:map00010\">\n</pathway>") else {
+            panic!("a pathway without reactions should fail");
+        };
+        assert_eq!(error.to_string(), "line 1: the KGML pathway has no reactions");
+    }
+
+    #[test]
+    fn test_import_biopax_ambiguities() {
+        let biopax = r##"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:bp="http://www.biopax.org/release/biopax-level3.owl#">
+  <bp:SmallMolecule rdf:ID="glc"><bp:displayName>glucose</bp:displayName></bp:SmallMolecule>
+  <bp:SmallMolecule rdf:ID="g6p"><bp:standardName>glucose 6-phosphate</bp:standardName></bp:SmallMolecule>
+  <bp:SmallMolecule rdf:about="http://example.org/compound/atp"/>
+  <bp:BiochemicalReaction rdf:ID="hk">
+    <bp:left rdf:resource="#glc"/>
+    <bp:left rdf:resource="http://example.org/compound/atp"/>
+    <bp:right rdf:resource="#g6p"/>
+  </bp:BiochemicalReaction>
+  <bp:BiochemicalReaction rdf:ID="pgi">
+    <bp:displayName>pgi</bp:displayName>
+    <bp:left rdf:resource="#g6p"/>
+    <bp:conversionDirection>REVERSIBLE</bp:conversionDirection>
+  </bp:BiochemicalReaction>
+</rdf:RDF>"##;
+        let import = pathway::import_pathway(biopax).unwrap();
+        let s_matrix = &import.model.s_matrix;
+        // ** names fall back from displayName to standardName to the resource's id
+        assert_eq!(s_matrix.col_labels.as_deref().unwrap(), ["hk", "pgi"]);
+        assert_eq!(s_matrix.row_labels.as_deref().unwrap(), ["glucose", "atp", "glucose_6-phosphate"]);
+        assert_eq!(s_matrix.matrix, na::DMatrix::from_row_slice(3, 2, &[-1.0, 0.0, -1.0, 0.0, 1.0, -1.0]));
+        assert_eq!(import.model.reversible, [false, true]);
+        assert_eq!(import.ambiguities, [
+            "line 5: reaction hk has conversionDirection (none); read as left to right",
+            "line 6: reaction hk gives no stoichiometry for glucose; using 1",
+            "line 7: reaction hk gives no stoichiometry for atp; using 1",
+            "line 8: reaction hk gives no stoichiometry for glucose_6-phosphate; using 1",
+            "line 12: reaction pgi gives no stoichiometry for glucose_6-phosphate; using 1",
+        ]);
+
+        let dangling = r##"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:bp="http://www.biopax.org/release/biopax-level3.owl#">
+  <bp:Conversion rdf:ID="r1">
+    <bp:left rdf:resource="#missing"/>
+  </bp:Conversion>
+</rdf:RDF>"##;
+        let Err(error) = pathway::import_pathway(dangling) else {
+            panic!("a participant referring to an undefined resource should fail");
+        };
+        assert_eq!(error.to_string(), "line 3: a participant of reaction r1 refers to a resource that is not defined");
+    }
+
+    #[test]
+    fn test_parse_xml_errors() {
+        let error = |text: &str| pathway::parse_xml(text).unwrap_err().to_string();
+        assert_eq!(error("<a>\n<b>\n</a>"), "line 3: expected </b> to close the element on line 2, found </a>");
+        assert_eq!(error("<a>\n<b>"), "line 2: the element <b> on line 2 is not closed");
+        assert_eq!(error("<a/></a>"), "line 1: unexpected closing tag </a>");
+        assert_eq!(error("<a/>\n<b/>"), "line 2: the document has more than one root element");
+        assert_eq!(error("text <a/>"), "line 1: text outside of the root element");
+        assert_eq!(error("<!-- only a comment -->"), "line 1: the document has no root element");
+        assert_eq!(error("<a>\n<!-- not closed"), "line 2: expected \"-->\" before the end of the document");
+        assert_eq!(error("<a id=\"1>"), "line 1: an element's tag is not closed");
+        assert_eq!(error("<a id=1/>"), "line 1: malformed attribute \"id=1\"");
+        assert_eq!(error("<a checked/>"), "line 1: malformed attribute \"checked\"");
+        assert_eq!(error("<a>&amp</a>"), "line 1: an XML entity is missing its ';'");
+        assert_eq!(error("<>"), "line 1: an element has no name");
+
+        // ** entities declared in the document type are not expanded
+        let declared = "<!DOCTYPE a [<!ENTITY glc \"glucose\">]>\n<a>&glc;</a>";
+        assert_eq!(error(declared), "line 2: unknown XML entity \"&glc;\"");
+        let root = pathway::parse_xml("<!DOCTYPE a [<!ENTITY gt \">\">]>\n<a t='&#945;&#x3B2;'><![CDATA[<b>&amp;]]></a>").unwrap();
+        assert_eq!(root.attribute("t"), Some("\u{3b1}\u{3b2}"));
+        assert_eq!(root.text, "<b>&amp;");
+
+        // ** documents nesting deeper than the limit are rejected before they are built
+        let nested = |depth: usize| format!("{}<b/>{}", "<a>".repeat(depth), "</a>".repeat(depth));
+        let mut element = &pathway::parse_xml(&nested(255)).unwrap();
+        let mut levels = 1;
+        while let Some(child) = element.children.first() {
+            element = child;
+            levels += 1;
+        }
+        assert_eq!(levels, 256);
+        assert_eq!(error(&nested(256)), "line 1: elements nest more than 256 deep");
+        assert_eq!(error(&nested(100_000)), "line 1: elements nest more than 256 deep");
+    }
+}
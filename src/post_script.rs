@@ -0,0 +1,337 @@
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat};
+use crate::solve::Solution;
+
+use std::rc::Rc;
+
+use nalgebra as na;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+
+/// The most operations a script may run, so that a runaway loop stops with an error.
+const MAX_OPERATIONS: u64 = 10_000_000;
+/// The deepest expressions may nest, at the top level and inside functions, so that a script
+/// cannot overflow the stack.
+const MAX_EXPR_DEPTH: usize = 64;
+const MAX_FUNCTION_EXPR_DEPTH: usize = 32;
+/// The deepest function calls may nest.
+const MAX_CALL_LEVELS: usize = 32;
+/// The longest string and the largest array and map a script may build.
+const MAX_STRING_SIZE: usize = 1 << 16;
+const MAX_ARRAY_SIZE: usize = 1 << 24;
+const MAX_MAP_SIZE: usize = 1 << 16;
+
+/// This creates a rhai engine that can only compute: modules cannot be imported, eval is
+/// disabled, and the operations, nesting depth and sizes a script may use are limited. Printing
+/// goes to stderr, so that it does not mix with the results on stdout.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new())
+        .disable_symbol("eval")
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_FUNCTION_EXPR_DEPTH)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_ARRAY_SIZE)
+        .set_max_map_size(MAX_MAP_SIZE)
+        .on_print(|text| eprintln!("{text}"))
+        .on_debug(|text, _, position| eprintln!("% debug at {position}: {text}"));
+    engine
+}
+
+/// This converts a rhai error to a SmasError::Parse with the line of the script it occurred on, or
+/// line 0 if it has none.
+fn script_error(mut error: EvalAltResult) -> SmasError {
+    // ** errors raised inside a function or closure are wrapped in the call, and report the line
+    // they were raised on
+    while let EvalAltResult::ErrorInFunctionCall(_, _, inner, _) = error {
+        error = *inner;
+    }
+    let position = error.take_position();
+    let message = match &error {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        _ => error.to_string(),
+    };
+    SmasError::Parse { line: position.line().unwrap_or(0), message }
+}
+
+/// This is a post-script: a rhai script that runs on each solution and computes derived
+/// quantities and reaction filters. The script ends with a map of its results, in which each
+/// number is a derived quantity and each array a filter, listing the reactions it kept by name or
+/// 0-based index. The results are reported in key order. For example
+///
+/// ```text
+/// // the net uptake, and the reactions carrying flux that consume ATP
+/// let uptake = flux("glc_in") - flux("glc_out");
+/// #{
+///     uptake: uptake,
+///     yield: flux("biomass") / uptake,
+///     atp_consumers: reactions.filter(|r| flux(r).abs() > 1e-6 && S("atp", r) < 0.0),
+/// }
+/// ```
+///
+/// Scripts see the constants `reactions` and `metabolites` (the names), `fluxes` (the solution),
+/// `accumulation` (B) and `residual_norm`, and the functions flux(reaction), S(metabolite,
+/// reaction) and B(metabolite), which take names or 0-based indices. Scripts are sandboxed: they
+/// can only compute, with no files or modules, and the operations and nesting depth they may use
+/// are limited.
+pub struct PostScript {
+    /// The compiled script
+    ast: AST,
+}
+
+impl PostScript {
+    /// This compiles a post-script. Returns SmasError::Parse with the line of the first syntax
+    /// error.
+    ///
+    /// # Arguments
+    /// * `script` - the text of the script
+    ///
+    pub fn parse(script: &str) -> Result<Self, SmasError> {
+        let ast = sandboxed_engine().compile(script).map_err(|e| SmasError::Parse {
+            line: e.position().line().unwrap_or(0),
+            message: e.err_type().to_string(),
+        })?;
+        Ok(PostScript { ast })
+    }
+
+    /// This runs the script on a solution. Returns SmasError::Parse with the line of the script
+    /// that fails, e.g. by naming an unknown reaction or running too many operations, or
+    /// SmasError::InvalidArgument if the script does not end with a map of numbers and arrays of
+    /// reactions.
+    ///
+    /// # Arguments
+    /// * `context` - the labeled solution and matrix
+    ///
+    pub fn run(&self, context: &Context) -> Result<Outcome, SmasError> {
+        let mut engine = sandboxed_engine();
+        let data = Rc::new(ScriptData {
+            s_matrix: context.s_matrix.clone(),
+            acc_vector: context.acc_vector.clone(),
+            fluxes: context.solution.reactions.clone(),
+            metabolites: context.metabolites.clone(),
+            reactions: context.reactions.clone(),
+        });
+        let flux = Rc::clone(&data);
+        engine.register_fn("flux", move |reaction: Dynamic| -> Result<f64, Box<EvalAltResult>> {
+            Ok(flux.fluxes[find(&reaction, &flux.reactions, "reaction")?])
+        });
+        let coefficient = Rc::clone(&data);
+        engine.register_fn("S", move |metabolite: Dynamic, reaction: Dynamic| -> Result<f64, Box<EvalAltResult>> {
+            let i = find(&metabolite, &coefficient.metabolites, "metabolite")?;
+            let j = find(&reaction, &coefficient.reactions, "reaction")?;
+            Ok(coefficient.s_matrix[(i, j)])
+        });
+        let accumulation = Rc::clone(&data);
+        engine.register_fn("B", move |metabolite: Dynamic| -> Result<f64, Box<EvalAltResult>> {
+            Ok(accumulation.acc_vector[find(&metabolite, &accumulation.metabolites, "metabolite")?])
+        });
+
+        let names = |names: &[String]| names.iter().cloned().map(Dynamic::from).collect::<Array>();
+        let numbers = |values: &na::DVector<f64>| values.iter().copied().map(Dynamic::from).collect::<Array>();
+        let mut scope = Scope::new();
+        scope.push_constant("reactions", names(&data.reactions))
+            .push_constant("metabolites", names(&data.metabolites))
+            .push_constant("fluxes", numbers(&data.fluxes))
+            .push_constant("accumulation", numbers(&data.acc_vector))
+            .push_constant("residual_norm", context.solution.residual_norm);
+        let result = engine.eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| script_error(*e))?;
+
+        let Some(results) = result.clone().try_cast::<Map>() else {
+            return Err(SmasError::InvalidArgument(format!(
+                "a post-script must end with a map of its results, e.g. #{{ yield: ... }}, but it ended with a {}",
+                result.type_name()
+            )));
+        };
+        let mut outcome = Outcome { derived: vec!(), filters: vec!() };
+        for (name, value) in results {
+            let name = name.to_string();
+            if let Some(value) = value.as_float().ok().or_else(|| value.as_int().ok().map(|value| value as f64)) {
+                outcome.derived.push((name, value));
+            } else if let Some(kept) = value.clone().try_cast::<Array>() {
+                let kept = kept.iter()
+                    .map(|reaction| find(reaction, &data.reactions, "reaction"))
+                    .collect::<Result<Vec<usize>, String>>()
+                    .map_err(|e| SmasError::InvalidArgument(format!("the filter \"{name}\" lists {e}")))?;
+                outcome.filters.push((name, kept));
+            } else {
+                return Err(SmasError::InvalidArgument(format!(
+                    "the result \"{name}\" must be a number or an array of reactions, but it is a {}",
+                    value.type_name()
+                )));
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+/// This is the data the functions of a script read, shared between them.
+struct ScriptData {
+    s_matrix: na::DMatrix<f64>,
+    acc_vector: na::DVector<f64>,
+    fluxes: na::DVector<f64>,
+    metabolites: Vec<String>,
+    reactions: Vec<String>,
+}
+
+/// This finds a metabolite or reaction by name or 0-based index.
+fn find(key: &Dynamic, names: &[String], kind: &str) -> Result<usize, String> {
+    if let Ok(index) = key.as_int() {
+        return usize::try_from(index).ok()
+            .filter(|index| *index < names.len())
+            .ok_or_else(|| format!("no {kind} at index {index} (0-{})", names.len().saturating_sub(1)));
+    }
+    match key.clone().into_immutable_string() {
+        Ok(name) => names.iter()
+            .position(|candidate| candidate.as_str() == name.as_str())
+            .ok_or_else(|| format!("no {kind} named \"{name}\"")),
+        Err(type_name) => Err(format!("expected a {kind} name or index, found a {type_name}")),
+    }
+}
+
+/// This is what a post-script sees: a solution labeled with its metabolite and reaction names,
+/// and the system it solves.
+pub struct Context<'a> {
+    /// The stoichiometric matrix, A: (m x n)
+    pub s_matrix: &'a na::DMatrix<f64>,
+    /// The name of each metabolite (row)
+    pub metabolites: Vec<String>,
+    /// The name of each reaction (column)
+    pub reactions: Vec<String>,
+    /// The accumulation vector, B; (m x 1)
+    pub acc_vector: &'a na::DVector<f64>,
+    /// The solution
+    pub solution: &'a Solution,
+}
+
+impl<'a> Context<'a> {
+    /// This creates a Context, naming unlabeled metabolites M1, M2, ... and reactions R1, R2, ....
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    /// * `metabolite_labels` - the name of each metabolite, if the rows are labeled
+    /// * `reaction_labels` - the name of each reaction, if the columns are labeled
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    /// * `solution` - the solution
+    ///
+    pub fn new(
+        s_matrix: &'a na::DMatrix<f64>,
+        metabolite_labels: Option<&[String]>,
+        reaction_labels: Option<&[String]>,
+        acc_vector: &'a na::DVector<f64>,
+        solution: &'a Solution,
+    ) -> Self {
+        let names = |labels: Option<&[String]>, n: usize, prefix: &str| match labels {
+            Some(labels) => labels.to_vec(),
+            None => (1..=n).map(|i| format!("{prefix}{i}")).collect(),
+        };
+        Context {
+            s_matrix,
+            metabolites: names(metabolite_labels, s_matrix.nrows(), "M"),
+            reactions: names(reaction_labels, s_matrix.ncols(), "R"),
+            acc_vector,
+            solution,
+        }
+    }
+}
+
+/// This is what a post-script computed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Outcome {
+    /// The derived quantities, in key order
+    pub derived: Vec<(String, f64)>,
+    /// The filters, by key, with the indices of the reactions each kept
+    pub filters: Vec<(String, Vec<usize>)>,
+}
+
+/// This formats what a post-script computed as '%' comment lines: each derived quantity, and
+/// the reactions each filter kept with their fluxes.
+///
+/// # Arguments
+/// * `outcome` - what the script computed
+/// * `context` - the labeled solution the script ran on
+/// * `float_format` - how to format the floats
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_outcome(outcome: &Outcome, context: &Context, float_format: FloatFormat, float_precision: usize) -> String {
+    let mut lines = vec!();
+    for (name, value) in &outcome.derived {
+        lines.push(format!("% {name} = {}", io::format_float(*value, float_format, float_precision)));
+    }
+    for (name, kept) in &outcome.filters {
+        lines.push(format!("% {name}: {} of {} reactions", kept.len(), context.reactions.len()));
+        for j in kept {
+            let value = io::format_float(context.solution.reactions[*j], float_format, float_precision);
+            lines.push(format!("%   {}\t{value}", context.reactions[*j]));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_post_script() {
+        let s_matrix = na::DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 0.0, 0.0, 1.0, -1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[0.5, 2.0]);
+        let solution = solve::solve_pinv(&acc_vector, &s_matrix).unwrap();
+        let reactions = [String::from("glc_in"), String::from("hk"), String::from("atp use")];
+        let context = post_script::Context::new(&s_matrix, None, Some(&reactions), &acc_vector, &solution);
+
+        let script = "// derived quantities\n\
+            let net = flux(\"glc_in\") - flux(\"atp use\");\n\
+            #{\n\
+                net: net,\n\
+                ratio: -(net ** 2) / max(1.0, max(B(\"M2\"), 3.0)) + S(0, \"hk\") * 2,\n\
+                n_reactions: reactions.len(),\n\
+                carrying: reactions.filter(|r| flux(r) > 0.0 && S(\"M2\", r) != 0.0),\n\
+                first_two: [0, \"hk\"],\n\
+            }\n";
+        let outcome = post_script::PostScript::parse(script).unwrap().run(&context).unwrap();
+        let fluxes = &solution.reactions;
+        let net = fluxes[0] - fluxes[2];
+        assert_eq!(outcome.derived, [
+            (String::from("n_reactions"), 3.0),
+            (String::from("net"), net),
+            (String::from("ratio"), -(net * net) / 3.0 - 2.0),
+        ]);
+        let carrying: Vec<usize> = (1..3).filter(|j| fluxes[*j] > 0.0).collect();
+        assert_eq!(outcome.filters, [(String::from("carrying"), carrying), (String::from("first_two"), vec![0, 1])]);
+
+        let formatted = post_script::format_outcome(&outcome, &context, io::FloatFormat::Decimal, 2);
+        assert!(formatted.starts_with("% n_reactions = 3.00\n"), "{formatted}");
+        assert!(formatted.contains("% carrying: "), "{formatted}");
+    }
+
+    #[test]
+    fn test_post_script_sandbox() {
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, -1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0]);
+        let solution = solve::solve_pinv(&acc_vector, &s_matrix).unwrap();
+        let context = post_script::Context::new(&s_matrix, None, None, &acc_vector, &solution);
+        let parse_error = |script: &str| post_script::PostScript::parse(script).err().unwrap().to_string();
+        let run_error = |script: &str| post_script::PostScript::parse(script).unwrap().run(&context).unwrap_err().to_string();
+
+        // ** errors name the line of the script
+        assert!(parse_error("let x = 1;\nlet y = (x +;\n").starts_with("line 2: "));
+        assert_eq!(run_error("\nlet x = flux(\"pgi\");"), "line 2: no reaction named \"pgi\"");
+        assert_eq!(run_error("#{ x: S(\"M1\", 2) }"), "line 1: no reaction at index 2 (0-1)");
+        assert_eq!(run_error("#{ x: B(1.5) }"), "line 1: expected a metabolite name or index, found a f64");
+
+        // ** runaway loops, deep nesting, modules and eval are stopped
+        assert!(run_error("loop { }").contains("Too many operations"));
+        let nested = format!("let x = {}1{};", "(".repeat(100), ")".repeat(100));
+        assert_eq!(parse_error(&nested), "line 1: Expression exceeds maximum complexity");
+        assert!(run_error("import \"std\" as s;").contains("Module not found"));
+        assert!(parse_error("eval(\"1\")").starts_with("line 1: "));
+
+        // ** the results are a map of numbers and arrays of reactions
+        assert!(run_error("1 + 1").contains("must end with a map of its results"));
+        assert!(run_error("#{ x: \"text\" }").contains("must be a number or an array of reactions"));
+        assert!(run_error("#{ x: [\"R3\"] }").contains("the filter \"x\" lists no reaction named \"R3\""));
+    }
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::layer::SubscriberExt;
+
+/// This is the in-memory file a Chrome trace is written to, so that it can be written out with
+/// the same overwrite policy as the other output files once the trace is finished.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// This is a Chrome trace being recorded from the tracing spans of smas: load (matrix and vector
+/// files), factorize (the SVD behind the pseudoinverse), solve, format, and write.
+pub struct ChromeTrace {
+    /// The trace written so far
+    buffer: SharedBuffer,
+    /// Stops the thread that writes the trace when dropped, after writing what is left
+    guard: FlushGuard,
+}
+
+impl ChromeTrace {
+    /// This stops recording and returns the trace in the Chrome trace event format, which
+    /// chrome://tracing, Perfetto and Speedscope open directly. Spans that have not ended yet
+    /// are left out.
+    pub fn finish(self) -> Vec<u8> {
+        drop(self.guard);
+        std::mem::take(&mut *self.buffer.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// This creates a subscriber that records every span, with its fields, into a Chrome trace. The
+/// subscriber records nothing until it is installed, e.g. with tracing::subscriber::set_global_default.
+pub fn chrome_trace() -> (impl tracing::Subscriber + Send + Sync, ChromeTrace) {
+    let buffer = SharedBuffer::default();
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .writer(buffer.clone())
+        .include_args(true)
+        .build();
+    (tracing_subscriber::registry().with(layer), ChromeTrace { buffer, guard })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_chrome_trace() {
+        let (subscriber, trace) = profile::chrome_trace();
+        tracing::subscriber::with_default(subscriber, || {
+            let s_matrix = na::DMatrix::from_row_slice(2, 3, &[1.0, -1.0, 0.0, 0.0, 1.0, -1.0]);
+            solve::solve(na::DVector::from_row_slice(&[1.0, 2.0]), s_matrix);
+        });
+        let trace = String::from_utf8(trace.finish()).unwrap();
+        let solve = trace.find("\"name\":\"solve\"").unwrap_or_else(|| panic!("{trace}"));
+        let factorize = trace.find("\"name\":\"factorize\"").unwrap_or_else(|| panic!("{trace}"));
+
+        // ** the solve begins before the factorization it contains
+        assert!(trace.trim_start().starts_with('['), "{trace}");
+        assert!(solve < factorize, "{trace}");
+        assert!(trace.contains("\"method\":\"pinv\""), "{trace}");
+        assert!(trace.contains("\"rows\":\"2\""), "{trace}");
+    }
+}
@@ -0,0 +1,358 @@
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat, LabeledMatrix, ParseMode};
+use crate::pathway;
+use crate::solve;
+use crate::util;
+use crate::validation::{self, PairSummary};
+
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+
+/// The name of the manifest of a corpus, in the corpus directory.
+pub const MANIFEST_NAME: &str = "cases.csv";
+
+/// The name of the directory of a corpus that holds the expected output of each case.
+pub const EXPECTED_DIR: &str = "expected";
+
+/// This is one case of a regression corpus: a model and an accumulation vector to solve with it.
+pub struct RegressionCase {
+    /// The name of the case, which names its expected output file
+    pub name: String,
+    /// The path of the stoichiometric matrix file, or of a reaction equation or pathway file
+    pub matrix: PathBuf,
+    /// The path of the accumulation vector file
+    pub accumulation: PathBuf,
+}
+
+/// This is the outcome of checking one case of a regression corpus against its expected output.
+pub enum CaseStatus {
+    /// Every flux is within the tolerance of its expected value
+    Passed(PairSummary),
+    /// Some fluxes drifted from their expected values by more than the tolerance
+    Drifted(PairSummary),
+    /// The model or accumulation vector changed since the expected output was stored
+    InputsChanged,
+    /// No expected output has been stored for the case
+    Missing,
+    /// The expected output file does not match its own hash, e.g. because it was edited by hand
+    Corrupt,
+    /// The case could not be loaded or solved
+    Failed(SmasError),
+}
+
+impl CaseStatus {
+    /// This returns whether the case reproduced its expected output.
+    pub fn passed(&self) -> bool {
+        matches!(self, CaseStatus::Passed(_))
+    }
+
+    /// This returns a one word description of the status.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            CaseStatus::Passed(_) => "passed",
+            CaseStatus::Drifted(_) => "drifted",
+            CaseStatus::InputsChanged => "inputs-changed",
+            CaseStatus::Missing => "missing",
+            CaseStatus::Corrupt => "corrupt",
+            CaseStatus::Failed(_) => "failed",
+        }
+    }
+}
+
+/// This returns the 64 bit FNV-1a hash of some bytes, which is stable across platforms and
+/// versions of Rust, unlike the hasher of the standard library.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3))
+}
+
+/// This parses the manifest of a regression corpus: one "name,matrix,accumulation" row of comma
+/// separated fields per case. A first row of "name,matrix,accumulation" is a header, and blank lines
+/// and lines starting with '#' are skipped. Relative paths are relative to the corpus directory.
+///
+/// Returns SmasError::Parse if a row does not have three fields, or a name is used twice or
+/// cannot name a file.
+///
+/// # Arguments
+/// * `contents` - the contents of the manifest
+/// * `corpus_dir` - the corpus directory
+///
+pub fn parse_corpus_manifest(contents: &str, corpus_dir: &Path) -> Result<Vec<RegressionCase>, SmasError> {
+    let mut cases: Vec<RegressionCase> = vec!();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| SmasError::Parse { line: i + 1, message };
+        let fields: Vec<&str> = trimmed.split(',').map(|field| field.trim().trim_matches('"')).collect();
+        if fields.len() != 3 || fields.iter().any(|field| field.is_empty()) {
+            return Err(error(format!("expected a name, a matrix path and an accumulation path separated by commas, found \"{trimmed}\"")));
+        }
+        if cases.is_empty() && fields == ["name", "matrix", "accumulation"] {
+            continue;
+        }
+        let name = fields[0];
+        if name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(error(format!("the case name \"{name}\" cannot name a file")));
+        }
+        if cases.iter().any(|case| case.name == name) {
+            return Err(error(format!("the case name \"{name}\" is used twice")));
+        }
+        cases.push(RegressionCase {
+            name: name.to_string(),
+            matrix: corpus_dir.join(fields[1]),
+            accumulation: corpus_dir.join(fields[2]),
+        });
+    }
+    Ok(cases)
+}
+
+/// This reads the manifest of a regression corpus; see `parse_corpus_manifest`.
+///
+/// # Arguments
+/// * `corpus_dir` - the corpus directory, which holds the manifest
+///
+pub fn load_corpus<R: AsRef<Path>>(corpus_dir: R) -> Result<Vec<RegressionCase>, SmasError> {
+    let corpus_dir = corpus_dir.as_ref();
+    let contents = std::fs::read_to_string(corpus_dir.join(MANIFEST_NAME)).map_err(SmasError::Io)?;
+    parse_corpus_manifest(&contents, corpus_dir)
+}
+
+/// This returns the path of the expected output file of a case.
+pub fn expected_path(corpus_dir: &Path, case: &RegressionCase) -> PathBuf {
+    corpus_dir.join(EXPECTED_DIR).join(format!("{}.mtx", case.name))
+}
+
+/// This reads the model of a case: a Matrix Market file, whose first line that is not a comment is
+/// its size, or else reaction equations or a pathway file.
+fn parse_model(contents: &str, mode: ParseMode) -> Result<LabeledMatrix, SmasError> {
+    if pathway::is_xml(contents) {
+        return Ok(pathway::import_pathway(contents)?.model.s_matrix);
+    }
+    let first_line = contents.lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('%') && !line.starts_with('#'));
+    let is_size_line = first_line.is_some_and(|line| line.split_ascii_whitespace().all(|entry| entry.parse::<usize>().is_ok()));
+    if is_size_line {
+        io::parse_labeled_matrix(contents, mode, io::DEFAULT_PATTERN_VALUE)
+    } else {
+        Ok(io::parse_reaction_equations(contents)?.s_matrix)
+    }
+}
+
+/// This solves a case with the pseudoinverse, returning the labeled reaction vector and the hash
+/// of the inputs it was solved from.
+fn solve_case(case: &RegressionCase, mode: ParseMode) -> Result<(LabeledMatrix, u64), SmasError> {
+    let matrix_bytes = std::fs::read(&case.matrix).map_err(SmasError::Io)?;
+    let acc_bytes = std::fs::read(&case.accumulation).map_err(SmasError::Io)?;
+    let s_matrix = parse_model(&String::from_utf8_lossy(&matrix_bytes), mode)?;
+    let acc_vector = io::load_vector_with_mode(&case.accumulation, mode)?;
+    let solution = solve::solve_pinv(&acc_vector, &s_matrix.matrix)?;
+
+    let mut input_bytes = matrix_bytes;
+    input_bytes.extend_from_slice(&acc_bytes);
+    let n = solution.reactions.nrows();
+    let reactions = LabeledMatrix {
+        matrix: na::DMatrix::from_column_slice(n, 1, solution.reactions.as_slice()),
+        row_labels: s_matrix.col_labels,
+        col_labels: None,
+    };
+    Ok((reactions, fnv1a_hash(&input_bytes)))
+}
+
+/// This formats the values of an expected output, one per line, in the shortest form that reads
+/// back as the same float, so that the output hash does not depend on formatting options.
+fn format_expected_values(values: &na::DMatrix<f64>) -> String {
+    values.iter().map(|value| format!("{value:e}\n")).collect()
+}
+
+/// This reads a "% name: value" comment from the header of an expected output file.
+fn header_value<'a>(contents: &'a str, name: &str) -> Option<&'a str> {
+    contents.lines()
+        .take_while(|line| line.starts_with('%'))
+        .find_map(|line| line.trim_start_matches('%').trim().strip_prefix(name)?.strip_prefix(':'))
+        .map(|value| value.trim())
+}
+
+/// This solves a case and stores its reaction vector as the expected output, with the hash of
+/// the inputs it was solved from and the hash of the output itself.
+///
+/// # Arguments
+/// * `corpus_dir` - the corpus directory
+/// * `case` - the case to solve
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+///
+pub fn update_case(corpus_dir: &Path, case: &RegressionCase, mode: ParseMode) -> Result<(), SmasError> {
+    let (reactions, input_hash) = solve_case(case, mode)?;
+    let values = format_expected_values(&reactions.matrix);
+    let mut provenance = io::Provenance::new("smas regression expected output", "regress");
+    provenance.parameters.push((String::from("case"), case.name.clone()));
+    provenance.parameters.push((String::from("method"), String::from("pinv")));
+    provenance.parameters.push((String::from("input hash"), format!("{input_hash:016x}")));
+    provenance.parameters.push((String::from("output hash"), format!("{:016x}", fnv1a_hash(values.as_bytes()))));
+    provenance.row_labels = reactions.row_labels;
+    let contents = format!(
+        "%%MatrixMarket matrix array real general\n{}{} 1\n{values}",
+        provenance.format_header(),
+        reactions.matrix.nrows()
+    );
+    io::write_atomic(expected_path(corpus_dir, case), contents).map_err(SmasError::Io)
+}
+
+/// This solves a case and compares its reaction vector with the stored expected output. Cases
+/// whose inputs changed since the expected output was stored are not compared, since the
+/// expected output no longer applies to them.
+///
+/// # Arguments
+/// * `corpus_dir` - the corpus directory
+/// * `case` - the case to check
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+/// * `tolerance` - the largest |delta| of a flux that has not drifted
+/// * `summation` - how the squared deltas are accumulated
+///
+pub fn check_case(
+    corpus_dir: &Path,
+    case: &RegressionCase,
+    mode: ParseMode,
+    tolerance: f64,
+    summation: util::Summation,
+) -> CaseStatus {
+    let path = expected_path(corpus_dir, case);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return CaseStatus::Missing,
+        Err(e) => return CaseStatus::Failed(SmasError::Io(e)),
+    };
+    let expected = match io::parse_labeled_matrix(&contents, ParseMode::Strict, io::DEFAULT_PATTERN_VALUE) {
+        Ok(expected) if expected.matrix.ncols() == 1 => expected,
+        _ => return CaseStatus::Corrupt,
+    };
+    let output_hash = format!("{:016x}", fnv1a_hash(format_expected_values(&expected.matrix).as_bytes()));
+    if header_value(&contents, "output hash") != Some(output_hash.as_str()) {
+        return CaseStatus::Corrupt;
+    }
+
+    let (reactions, input_hash) = match solve_case(case, mode) {
+        Ok(solved) => solved,
+        Err(e) => return CaseStatus::Failed(e),
+    };
+    if header_value(&contents, "input hash") != Some(format!("{input_hash:016x}").as_str()) {
+        return CaseStatus::InputsChanged;
+    }
+    let computed = reactions.matrix.column(0).into_owned();
+    match validation::compare_vectors(&computed, &expected.matrix.column(0).into_owned(), tolerance, summation) {
+        Ok(summary) if summary.passed() => CaseStatus::Passed(summary),
+        Ok(summary) => CaseStatus::Drifted(summary),
+        Err(e) => CaseStatus::Failed(e),
+    }
+}
+
+/// This formats the results of checking a regression corpus: one row per case with its status
+/// and, for compared cases, the number of fluxes within the tolerance and the largest and root
+/// mean square drift, followed by a count of the cases with each status.
+///
+/// # Arguments
+/// * `cases` - the cases of the corpus
+/// * `statuses` - the status of each case
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_regression_report(
+    cases: &[RegressionCase],
+    statuses: &[CaseStatus],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let format_float = |value: f64| io::format_float(value, float_format, float_precision);
+    let mut result_string = String::from("% case \t status \t within tolerance \t max drift \t rms drift");
+    for (case, status) in cases.iter().zip(statuses) {
+        let details = match status {
+            CaseStatus::Passed(summary) | CaseStatus::Drifted(summary) => format!(
+                "\t{}/{}\t{}\t{}",
+                summary.n_within,
+                summary.n_reactions,
+                format_float(summary.max_delta),
+                format_float(summary.rms_delta())
+            ),
+            CaseStatus::InputsChanged => String::from("\t% rerun with --update if the change is intended"),
+            CaseStatus::Missing => String::from("\t% run with --update to store its expected output"),
+            CaseStatus::Corrupt => String::from("\t% the expected output does not match its hash"),
+            CaseStatus::Failed(e) => format!("\t% {e}"),
+        };
+        result_string.push_str(&format!("\n  {}\t{}{details}", case.name, status.describe()));
+    }
+    let count = |describe: &str| statuses.iter().filter(|status| status.describe() == describe).count();
+    result_string.push_str(&format!(
+        "\n% {}/{} cases passed, {} drifted, {} inputs changed, {} missing, {} corrupt, {} failed",
+        count("passed"),
+        statuses.len(),
+        count("drifted"),
+        count("inputs-changed"),
+        count("missing"),
+        count("corrupt"),
+        count("failed")
+    ));
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_regress() {
+        assert_eq!(regress::fnv1a_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(regress::fnv1a_hash(b"a"), 0xaf63dc4c8601ec8c);
+
+        let dir = std::env::temp_dir().join(format!("smas_regress_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("model.txt"), "up: -> glc\nhk: glc -> g6p\n").unwrap();
+        std::fs::write(dir.join("acc.txt"), "2 1\n1\n2\n").unwrap();
+        std::fs::write(dir.join("matrix.mtx"), "2 1\n1\n1\n").unwrap();
+        std::fs::write(dir.join(regress::MANIFEST_NAME), "name,matrix,accumulation\nequations,model.txt,acc.txt\nmatrix,matrix.mtx,acc.txt\n").unwrap();
+        let cases = regress::load_corpus(&dir).unwrap();
+        assert_eq!(cases.len(), 2);
+        let check = |case| regress::check_case(&dir, case, io::ParseMode::Lenient, 1e-6, util::Summation::default());
+
+        assert!(matches!(check(&cases[0]), regress::CaseStatus::Missing));
+        for case in &cases {
+            regress::update_case(&dir, case, io::ParseMode::Lenient).unwrap();
+        }
+        assert!(check(&cases[0]).passed());
+        assert!(check(&cases[1]).passed());
+
+        // ** replaces the values of an expected output, and its output hash if rehash is set
+        let rewrite = |path: &std::path::Path, values: &str, rehash: bool| {
+            let expected = std::fs::read_to_string(path).unwrap();
+            let lines: Vec<&str> = expected.lines().collect();
+            let header_len = lines.iter().position(|line| !line.starts_with('%')).unwrap() + 1;
+            let header: String = lines[..header_len].iter()
+                .map(|line| if line.starts_with("% output hash:") && rehash {
+                    format!("% output hash: {:016x}\n", regress::fnv1a_hash(values.as_bytes()))
+                } else {
+                    format!("{line}\n")
+                })
+                .collect();
+            std::fs::write(path, header + values).unwrap();
+        };
+
+        // ** an expected output edited by hand no longer matches its hash
+        rewrite(&regress::expected_path(&dir, &cases[1]), "1.6e0\n", false);
+        assert!(matches!(check(&cases[1]), regress::CaseStatus::Corrupt));
+
+        // ** a different solution from the same inputs is drift
+        rewrite(&regress::expected_path(&dir, &cases[0]), "3e0\n2.5e0\n", true);
+        match check(&cases[0]) {
+            regress::CaseStatus::Drifted(summary) => assert_eq!(summary.n_within, 1),
+            status => panic!("expected drift, found {}", status.describe()),
+        }
+
+        // ** a changed accumulation vector is reported rather than compared
+        std::fs::write(dir.join("acc.txt"), "2 1\n1\n3\n").unwrap();
+        let statuses: Vec<regress::CaseStatus> = cases.iter().map(check).collect();
+        assert!(matches!(statuses[0], regress::CaseStatus::InputsChanged));
+        let report = regress::format_regression_report(&cases, &statuses, io::FloatFormat::Decimal, 1);
+        assert!(report.ends_with("% 0/2 cases passed, 0 drifted, 1 inputs changed, 0 missing, 1 corrupt, 0 failed"), "{report}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,687 @@
+use crate::error::SmasError;
+use crate::heatmap::{self, HeatmapStyle};
+use crate::io::{self, FloatFormat, LabeledMatrix};
+use crate::solve;
+
+use std::io::{BufRead, Write};
+
+use nalgebra as na;
+
+/// The first line of a session file, naming its format and version.
+const SESSION_HEADER: &str = "%%smas session 1";
+
+/// The prompt printed before each command is read.
+const PROMPT: &str = "smas> ";
+
+/// The commands the REPL understands, printed by "help".
+const HELP: &str = "\
+set S[i][j] v            set a coefficient; i and j are 1-based indices or names
+add-reaction \"A + 2B -> C\" add a reaction, named with a \"name:\" prefix or R<n> after its column
+remove-reaction R7       remove a reaction by name
+save-session file.smas   save the model, options and last solution
+load-session file.smas   load a saved session, undoably
+resolve                  solve the model with the pseudoinverse
+show                     print the model as a heatmap
+undo                     undo the last edit
+help                     print this help
+quit                     leave the REPL";
+
+/// This is a model being curated: a stoichiometric matrix whose rows and columns are always
+/// labeled, so that names stay attached to their rows and columns as reactions come and go, and
+/// the accumulation vector it is solved against.
+#[derive(Clone)]
+pub struct Model {
+    /// The stoichiometric matrix, labeled with the metabolite and reaction names
+    pub s_matrix: LabeledMatrix,
+    /// The accumulation vector, B; one entry per metabolite
+    pub acc_vector: na::DVector<f64>,
+}
+
+impl Model {
+    /// This creates a Model, naming unlabeled rows M1, M2, ... and columns R1, R2, ....
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    ///
+    pub fn new(s_matrix: LabeledMatrix, acc_vector: na::DVector<f64>) -> Result<Self, SmasError> {
+        if acc_vector.nrows() != s_matrix.matrix.nrows() {
+            return Err(SmasError::Shape {
+                expected: s_matrix.matrix.nrows(),
+                found: acc_vector.nrows(),
+            });
+        }
+        let row_labels = (0..s_matrix.matrix.nrows()).map(|i| s_matrix.row_label(i)).collect();
+        let col_labels = (0..s_matrix.matrix.ncols()).map(|j| s_matrix.col_label(j)).collect();
+        Ok(Model {
+            s_matrix: LabeledMatrix {
+                matrix: s_matrix.matrix,
+                row_labels: Some(row_labels),
+                col_labels: Some(col_labels),
+            },
+            acc_vector,
+        })
+    }
+
+    /// This returns the metabolite names, one per row.
+    pub fn metabolites(&self) -> &[String] {
+        self.s_matrix.row_labels.as_deref().unwrap_or_default()
+    }
+
+    /// This returns the reaction names, one per column.
+    pub fn reactions(&self) -> &[String] {
+        self.s_matrix.col_labels.as_deref().unwrap_or_default()
+    }
+}
+
+/// This is a REPL command, parsed from one line of input.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    /// Set the coefficient of a metabolite (row) in a reaction (column), each given by 1-based
+    /// index or name
+    Set {
+        /// The metabolite
+        row: String,
+        /// The reaction
+        col: String,
+        /// The new coefficient
+        value: f64,
+    },
+    /// Add a reaction from its equation, e.g. "hk: glc + atp -> g6p + adp"
+    AddReaction(String),
+    /// Remove a reaction by name
+    RemoveReaction(String),
+    /// Solve the model with the pseudoinverse
+    Resolve,
+    /// Save the session to a file
+    SaveSession(String),
+    /// Load a session from a file
+    LoadSession(String),
+    /// Print the model as a heatmap
+    Show,
+    /// Undo the last edit
+    Undo,
+    /// Print the commands
+    Help,
+    /// Leave the REPL
+    Quit,
+}
+
+/// This parses one line of input into a command. Blank lines and lines starting with '#' have
+/// no command.
+///
+/// # Arguments
+/// * `line` - the line, e.g. "set S[1][2] -1"
+/// * `line_number` - the 1-based number of the line, for errors
+///
+pub fn parse_command(line: &str, line_number: usize) -> Result<Option<Command>, SmasError> {
+    let error = |message: String| SmasError::Parse { line: line_number, message };
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+    let (name, rest) = trimmed.split_once(char::is_whitespace).unwrap_or((trimmed, ""));
+    let rest = rest.trim();
+    let no_arguments = |command: Command| match rest.is_empty() {
+        true => Ok(Some(command)),
+        false => Err(error(format!("{name} takes no arguments, found \"{rest}\""))),
+    };
+    match name {
+        "set" => {
+            let usage = || error(format!("expected \"set S[i][j] v\", found \"{trimmed}\""));
+            let (target, value) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+            let (row, col) = target.strip_prefix("S[")
+                .and_then(|target| target.strip_suffix(']'))
+                .and_then(|target| target.split_once("]["))
+                .ok_or_else(usage)?;
+            let value = value.trim().parse::<f64>()
+                .map_err(|_| error(format!("expected a number, found \"{}\"", value.trim())))?;
+            Ok(Some(Command::Set { row: row.to_string(), col: col.to_string(), value }))
+        }
+        "add-reaction" => {
+            let equation = rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')).unwrap_or(rest);
+            if equation.trim().is_empty() {
+                return Err(error(String::from("add-reaction expects an equation, e.g. \"A + 2B -> C\"")));
+            }
+            Ok(Some(Command::AddReaction(equation.trim().to_string())))
+        }
+        "remove-reaction" => match rest.split_ascii_whitespace().collect::<Vec<&str>>()[..] {
+            [reaction] => Ok(Some(Command::RemoveReaction(reaction.to_string()))),
+            _ => Err(error(format!("expected \"remove-reaction <name>\", found \"{trimmed}\""))),
+        },
+        "save-session" | "load-session" => match rest.split_ascii_whitespace().collect::<Vec<&str>>()[..] {
+            [path] if name == "save-session" => Ok(Some(Command::SaveSession(path.to_string()))),
+            [path] => Ok(Some(Command::LoadSession(path.to_string()))),
+            _ => Err(error(format!("expected \"{name} <path>\", found \"{trimmed}\""))),
+        },
+        "resolve" => no_arguments(Command::Resolve),
+        "show" => no_arguments(Command::Show),
+        "undo" => no_arguments(Command::Undo),
+        "help" => no_arguments(Command::Help),
+        "quit" | "exit" => no_arguments(Command::Quit),
+        _ => Err(error(format!("unknown command \"{name}\", try \"help\""))),
+    }
+}
+
+/// This finds a row or column by its 1-based index or its name.
+fn find_index(key: &str, labels: &[String], kind: &str, line_number: usize) -> Result<usize, SmasError> {
+    if let Some(index) = labels.iter().position(|label| label == key) {
+        return Ok(index);
+    }
+    match key.parse::<usize>() {
+        Ok(index) if (1..=labels.len()).contains(&index) => Ok(index - 1),
+        _ => Err(SmasError::Parse {
+            line: line_number,
+            message: format!("no {kind} named \"{key}\" and no {kind} at that index (1-{})", labels.len()),
+        }),
+    }
+}
+
+/// This splits terms written without a space between coefficient and metabolite, e.g. "2B", into
+/// "2 B" for the equation parser. A term naming a metabolite already in the model, e.g. "2pg", is
+/// left as it is.
+fn split_coefficients(equation: &str, metabolites: &[String]) -> String {
+    let tokens: Vec<String> = equation.split_ascii_whitespace()
+        .map(|token| {
+            let digits = token.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(token.len());
+            let name = &token[digits..];
+            let splits = digits > 0
+                && name.starts_with(char::is_alphabetic)
+                && token[..digits].parse::<f64>().is_ok()
+                && !metabolites.iter().any(|m| m == token);
+            match splits {
+                true => format!("{} {name}", &token[..digits]),
+                false => token.to_string(),
+            }
+        })
+        .collect();
+    tokens.join(" ")
+}
+
+/// This is the state of a REPL session: the model, the edits that can be undone, and the last
+/// solution.
+pub struct Repl {
+    /// The model being curated
+    pub model: Model,
+    /// The model before each edit, with the command that made the edit, most recent last
+    history: Vec<(String, Model)>,
+    /// The reaction vector from the last "resolve", if the model has not been edited since
+    pub solution: Option<na::DVector<f64>>,
+    /// How to format the floats of solutions
+    float_format: FloatFormat,
+    /// How many positions the floats of solutions have past the decimal point
+    float_precision: usize,
+    /// The Solver of the last "resolve", reused while the matrix is unchanged
+    solver: Option<solve::Solver>,
+}
+
+impl Repl {
+    /// This creates a REPL session for a model.
+    ///
+    /// # Arguments
+    /// * `model` - the model to curate
+    /// * `float_format` - how to format the floats of solutions
+    /// * `float_precision` - how many positions the floats of solutions have past the decimal point
+    ///
+    pub fn new(model: Model, float_format: FloatFormat, float_precision: usize) -> Self {
+        Repl {
+            model,
+            history: vec!(),
+            solution: None,
+            float_format,
+            float_precision,
+            solver: None,
+        }
+    }
+
+    /// This returns the number of edits that can be undone.
+    pub fn undo_depth(&self) -> usize {
+        self.history.len()
+    }
+
+    /// This formats the session for a session file: the model, the float options, and the last
+    /// solution. The undo history is not saved. Floats are written in their shortest form that
+    /// reads back exactly, and names one per line, so any name survives.
+    pub fn format_session(&self) -> String {
+        let float_format = match self.float_format {
+            FloatFormat::Decimal => "decimal",
+            FloatFormat::Scientific => "scientific",
+            FloatFormat::Canonical => "canonical",
+        };
+        let values = |values: &mut dyn Iterator<Item = &f64>| {
+            values.map(|value| format!("{value:e}")).collect::<Vec<String>>().join(" ")
+        };
+        let matrix = &self.model.s_matrix.matrix;
+        let mut lines = vec![
+            String::from(SESSION_HEADER),
+            format!("float_format {float_format}"),
+            format!("float_precision {}", self.float_precision),
+            format!("metabolites {}", matrix.nrows()),
+        ];
+        lines.extend(self.model.metabolites().iter().cloned());
+        lines.push(format!("reactions {}", matrix.ncols()));
+        lines.extend(self.model.reactions().iter().cloned());
+        lines.push(String::from("matrix"));
+        lines.extend(matrix.row_iter().map(|row| values(&mut row.iter())));
+        lines.push(String::from("accumulation"));
+        lines.push(values(&mut self.model.acc_vector.iter()));
+        match &self.solution {
+            Some(solution) => {
+                lines.push(String::from("solution"));
+                lines.push(values(&mut solution.iter()));
+            }
+            None => lines.push(String::from("no solution")),
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// This reads a session from a session file written by `format_session`, with an empty undo
+    /// history. Returns SmasError::Parse with the line of the file that is malformed.
+    ///
+    /// # Arguments
+    /// * `session` - the contents of the session file
+    ///
+    pub fn parse_session(session: &str) -> Result<Self, SmasError> {
+        let mut lines = session.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let mut line_number = 0;
+        let mut next = |expected: &str| match lines.next() {
+            Some((number, line)) => {
+                line_number = number;
+                Ok((number, line))
+            }
+            None => Err(SmasError::Parse { line: line_number + 1, message: format!("expected {expected}, found the end of the file") }),
+        };
+        let error = |line: usize, message: String| SmasError::Parse { line, message };
+        let field = |(line, text): (usize, &str), key: &str| match text.strip_prefix(key).and_then(|rest| rest.strip_prefix(' ')) {
+            Some(value) => Ok(value.to_string()),
+            None => Err(error(line, format!("expected \"{key} ...\", found \"{text}\""))),
+        };
+        let count = |(line, value): (usize, String)| value.parse::<usize>()
+            .map_err(|_| error(line, format!("expected a count, found \"{value}\"")));
+        let values = |(line, text): (usize, &str), expected: usize| {
+            let values = text.split_ascii_whitespace()
+                .map(|value| value.parse::<f64>().map_err(|_| error(line, format!("expected a number, found \"{value}\""))))
+                .collect::<Result<Vec<f64>, SmasError>>()?;
+            match values.len() == expected {
+                true => Ok(values),
+                false => Err(error(line, format!("expected {expected} values, found {}", values.len()))),
+            }
+        };
+
+        let (line, header) = next("the session header")?;
+        if header != SESSION_HEADER {
+            return Err(error(line, format!("expected \"{SESSION_HEADER}\", found \"{header}\"")));
+        }
+        let line = next("the float format")?;
+        let float_format = match field(line, "float_format")?.as_str() {
+            "decimal" => FloatFormat::Decimal,
+            "scientific" => FloatFormat::Scientific,
+            "canonical" => FloatFormat::Canonical,
+            other => return Err(error(line.0, format!("unknown float format \"{other}\""))),
+        };
+        let line = next("the float precision")?;
+        let float_precision = count((line.0, field(line, "float_precision")?))?;
+
+        let line = next("the metabolites")?;
+        let m = count((line.0, field(line, "metabolites")?))?;
+        let metabolites = (0..m).map(|_| next("a metabolite").map(|(_, name)| name.to_string()))
+            .collect::<Result<Vec<String>, SmasError>>()?;
+        let line = next("the reactions")?;
+        let n = count((line.0, field(line, "reactions")?))?;
+        let reactions = (0..n).map(|_| next("a reaction").map(|(_, name)| name.to_string()))
+            .collect::<Result<Vec<String>, SmasError>>()?;
+
+        let (line, text) = next("the matrix")?;
+        if text != "matrix" {
+            return Err(error(line, format!("expected \"matrix\", found \"{text}\"")));
+        }
+        // ** the counts are not trusted until the rows are read, so the entries grow row by row
+        let mut entries = vec!();
+        for _ in 0..m {
+            entries.extend(values(next("a matrix row")?, n)?);
+        }
+        let (line, text) = next("the accumulation vector")?;
+        if text != "accumulation" {
+            return Err(error(line, format!("expected \"accumulation\", found \"{text}\"")));
+        }
+        let acc_vector = na::DVector::from_vec(values(next("the accumulation vector")?, m)?);
+        let solution = match next("the solution")? {
+            (_, "no solution") => None,
+            (_, "solution") => Some(na::DVector::from_vec(values(next("the solution")?, n)?)),
+            (line, text) => return Err(error(line, format!("expected \"solution\" or \"no solution\", found \"{text}\""))),
+        };
+
+        let s_matrix = LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(m, n, &entries),
+            row_labels: Some(metabolites),
+            col_labels: Some(reactions),
+        };
+        let mut repl = Repl::new(Model::new(s_matrix, acc_vector)?, float_format, float_precision);
+        repl.solution = solution;
+        Ok(repl)
+    }
+
+    /// This saves the model before an edit, so that the edit can be undone.
+    fn checkpoint(&mut self, description: String) {
+        self.history.push((description, self.model.clone()));
+        self.solution = None;
+    }
+
+    /// This runs a command and returns what it prints. Edits leave the model unchanged when they
+    /// fail.
+    ///
+    /// # Arguments
+    /// * `command` - the command to run; Quit prints nothing, leaving is up to the caller
+    /// * `line_number` - the 1-based number of the line the command was read from, for errors
+    ///
+    pub fn execute(&mut self, command: &Command, line_number: usize) -> Result<String, SmasError> {
+        let error = |message: String| SmasError::Parse { line: line_number, message };
+        match command {
+            Command::Set { row, col, value } => {
+                let i = find_index(row, self.model.metabolites(), "metabolite", line_number)?;
+                let j = find_index(col, self.model.reactions(), "reaction", line_number)?;
+                let old = self.model.s_matrix.matrix[(i, j)];
+                let (metabolite, reaction) = (self.model.s_matrix.row_label(i), self.model.s_matrix.col_label(j));
+                self.checkpoint(format!("set S[{metabolite}][{reaction}] {value} (was {old})"));
+                self.model.s_matrix.matrix[(i, j)] = *value;
+                Ok(format!("% S[{metabolite}][{reaction}]: {old} -> {value}"))
+            }
+            Command::AddReaction(equation) => {
+                let equation = split_coefficients(equation, self.model.metabolites());
+                let parsed = io::parse_reaction_equations(&equation)
+                    .map_err(|e| error(format!("failed to parse \"{equation}\": {e}")))?;
+                if parsed.s_matrix.matrix.ncols() != 1 {
+                    return Err(error(format!("expected one reaction, found {}", parsed.s_matrix.matrix.ncols())));
+                }
+                let reactions = self.model.reactions();
+                let name = match equation.contains(':') {
+                    true => parsed.s_matrix.col_label(0),
+                    false => (reactions.len() + 1..)
+                        .map(|n| format!("R{n}"))
+                        .find(|name| !reactions.contains(name))
+                        .unwrap(),
+                };
+                if reactions.contains(&name) {
+                    return Err(error(format!("a reaction named \"{name}\" already exists")));
+                }
+
+                self.checkpoint(format!("add-reaction {name}"));
+                let (m, n) = self.model.s_matrix.matrix.shape();
+                let new_metabolites: Vec<String> = parsed.s_matrix.row_labels.iter().flatten()
+                    .filter(|metabolite| !self.model.metabolites().contains(metabolite))
+                    .cloned()
+                    .collect();
+                let model = &mut self.model;
+                let matrix = std::mem::replace(&mut model.s_matrix.matrix, na::DMatrix::zeros(0, 0));
+                model.s_matrix.matrix = matrix.insert_rows(m, new_metabolites.len(), 0.0).insert_column(n, 0.0);
+                let acc_vector = std::mem::replace(&mut model.acc_vector, na::DVector::zeros(0));
+                model.acc_vector = acc_vector.insert_rows(m, new_metabolites.len(), 0.0);
+                model.s_matrix.row_labels.get_or_insert_with(Vec::new).extend(new_metabolites.iter().cloned());
+                model.s_matrix.col_labels.get_or_insert_with(Vec::new).push(name.clone());
+                for (k, metabolite) in parsed.s_matrix.row_labels.iter().flatten().enumerate() {
+                    let i = model.metabolites().iter().position(|m| m == metabolite).unwrap();
+                    model.s_matrix.matrix[(i, n)] = parsed.s_matrix.matrix[(k, 0)];
+                }
+
+                let mut reply = format!("% added {name} as reaction {}", n + 1);
+                if !new_metabolites.is_empty() {
+                    reply += &format!("\n% new metabolites, with accumulation 0: {}", new_metabolites.join(", "));
+                }
+                Ok(reply)
+            }
+            Command::RemoveReaction(reaction) => {
+                let Some(j) = self.model.reactions().iter().position(|r| r == reaction) else {
+                    return Err(error(format!("no reaction named \"{reaction}\"")));
+                };
+                self.checkpoint(format!("remove-reaction {reaction}"));
+                let matrix = std::mem::replace(&mut self.model.s_matrix.matrix, na::DMatrix::zeros(0, 0));
+                self.model.s_matrix.matrix = matrix.remove_column(j);
+                if let Some(labels) = self.model.s_matrix.col_labels.as_mut() {
+                    labels.remove(j);
+                }
+                Ok(format!("% removed {reaction}"))
+            }
+            Command::Resolve => {
+                let solution = solve::cached_solver(&mut self.solver, &self.model.s_matrix.matrix).solve(&self.model.acc_vector)?;
+                let mut lines: Vec<String> = solution.reactions.iter().enumerate()
+                    .map(|(j, value)| {
+                        let value = io::format_float(*value, self.float_format, self.float_precision);
+                        format!("{}\t{value}", self.model.s_matrix.col_label(j))
+                    })
+                    .collect();
+                lines.push(format!(
+                    "% residual norm: {}",
+                    io::format_float(solution.residual_norm, self.float_format, self.float_precision)
+                ));
+                self.solution = Some(solution.reactions);
+                Ok(lines.join("\n"))
+            }
+            Command::SaveSession(path) => {
+                io::write_atomic(path, self.format_session())?;
+                Ok(format!("% saved the session to {path}"))
+            }
+            Command::LoadSession(path) => {
+                let session = std::fs::read_to_string(path)?;
+                let loaded = Repl::parse_session(&session)
+                    .map_err(|e| error(format!("failed to load the session {path}: {e}")))?;
+                self.checkpoint(format!("load-session {path}"));
+                self.model = loaded.model;
+                self.solution = loaded.solution;
+                self.float_format = loaded.float_format;
+                self.float_precision = loaded.float_precision;
+                let (m, n) = self.model.s_matrix.matrix.shape();
+                let solved = match self.solution {
+                    Some(_) => ", with its last solution",
+                    None => "",
+                };
+                Ok(format!("% loaded the session {path}: {m} metabolites x {n} reactions{solved}"))
+            }
+            Command::Show => {
+                let (m, n) = self.model.s_matrix.matrix.shape();
+                let style = HeatmapStyle { magnitude: false, color: false, ascii: false };
+                Ok(format!("% {m} metabolites x {n} reactions\n{}", heatmap::format_heatmap(&self.model.s_matrix, style, None)))
+            }
+            Command::Undo => {
+                let (description, model) = self.history.pop().ok_or(SmasError::Empty("the undo history"))?;
+                self.model = model;
+                self.solution = None;
+                Ok(format!("% undid {description}"))
+            }
+            Command::Help => Ok(String::from(HELP)),
+            Command::Quit => Ok(String::new()),
+        }
+    }
+}
+
+/// This runs the REPL, reading one command per line until "quit" or the end of the input.
+/// Errors are printed and the session goes on.
+///
+/// # Arguments
+/// * `repl` - the session
+/// * `input` - where the commands are read from, e.g. stdin
+/// * `output` - where the replies are printed, e.g. stdout
+///
+pub fn run(repl: &mut Repl, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    write!(output, "{PROMPT}")?;
+    output.flush()?;
+    for (i, line) in input.lines().enumerate() {
+        let reply = parse_command(&line?, i + 1)
+            .and_then(|command| match command {
+                Some(Command::Quit) => Ok(None),
+                Some(command) => repl.execute(&command, i + 1).map(Some),
+                None => Ok(Some(String::new())),
+            });
+        match reply {
+            Ok(None) => return Ok(()),
+            Ok(Some(reply)) if reply.is_empty() => {}
+            Ok(Some(reply)) => writeln!(output, "{reply}")?,
+            Err(e) => writeln!(output, "error: {e}")?,
+        }
+        write!(output, "{PROMPT}")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// This runs a script of REPL commands, one per line, without prompts. The script stops at
+/// "quit", and fails fast: the first command that fails stops it, and its error is returned with
+/// the line of the script it was on. Edits made before the failure are kept.
+///
+/// # Arguments
+/// * `repl` - the session
+/// * `script` - the commands
+/// * `output` - where the replies are printed
+/// * `echo` - whether each command is printed, after the prompt, before its reply
+///
+pub fn run_script(repl: &mut Repl, script: &str, mut output: impl Write, echo: bool) -> Result<(), SmasError> {
+    for (i, line) in script.lines().enumerate() {
+        let Some(command) = parse_command(line, i + 1)? else {
+            continue;
+        };
+        if echo {
+            writeln!(output, "{PROMPT}{}", line.trim())?;
+        }
+        if command == Command::Quit {
+            break;
+        }
+        let reply = repl.execute(&command, i + 1)?;
+        if !reply.is_empty() {
+            writeln!(output, "{reply}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_repl() {
+        let s_matrix = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 0.0, 1.0]),
+            row_labels: Some(vec![String::from("A"), String::from("B")]),
+            col_labels: None,
+        };
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let model = repl::Model::new(s_matrix, acc_vector).unwrap();
+        let mut session = repl::Repl::new(model, io::FloatFormat::Decimal, 3);
+
+        assert_eq!(
+            repl::parse_command("set S[A][2] 3", 1).unwrap(),
+            Some(repl::Command::Set { row: String::from("A"), col: String::from("2"), value: 3.0 })
+        );
+        assert!(repl::parse_command("set S[1] 3", 4).unwrap_err().to_string().starts_with("line 4:"));
+        assert_eq!(repl::parse_command("  # a comment", 1).unwrap(), None);
+
+        let input = "set S[A][R2] 0\n\
+            add-reaction \"A + 2B -> C\"\n\
+            remove-reaction R1\n\
+            remove-reaction R9\n\
+            resolve\n\
+            undo\n\
+            quit\n\
+            show\n";
+        let mut output = vec!();
+        repl::run(&mut session, input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("% S[A][R2]: -1 -> 0"), "{output}");
+        assert!(output.contains("% added R3 as reaction 3\n% new metabolites, with accumulation 0: C"), "{output}");
+        assert!(output.contains("error: line 4: no reaction named \"R9\""), "{output}");
+        assert!(output.contains("R2\t1.000\nR3\t-0.500\n% residual norm: 0.707"), "{output}");
+        assert!(output.contains("% undid remove-reaction R1"), "{output}");
+        // ** quit stops the session before show
+        assert!(!output.contains("metabolites x"), "{output}");
+
+        let model = &session.model;
+        assert_eq!(model.reactions(), ["R1", "R2", "R3"]);
+        assert_eq!(model.metabolites(), ["A", "B", "C"]);
+        assert_eq!(model.acc_vector, na::DVector::from_row_slice(&[1.0, 2.0, 0.0]));
+        assert_eq!(model.s_matrix.matrix.column(2).as_slice(), [-1.0, -2.0, 1.0]);
+        assert_eq!(session.undo_depth(), 2);
+
+        session.execute(&repl::Command::Undo, 1).unwrap();
+        session.execute(&repl::Command::Undo, 1).unwrap();
+        assert_eq!(session.model.s_matrix.matrix, na::DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 0.0, 1.0]));
+        let error = session.execute(&repl::Command::Undo, 1).unwrap_err();
+        assert_eq!(error.to_string(), "the undo history is empty");
+    }
+
+    #[test]
+    fn test_session() {
+        let s_matrix = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 2, &[0.1, -1.0, 0.0, 1.0 / 3.0]),
+            row_labels: Some(vec![String::from("A"), String::from("fructose 6-phosphate")]),
+            col_labels: None,
+        };
+        let acc_vector = na::DVector::from_row_slice(&[1e-9, 2.0]);
+        let model = repl::Model::new(s_matrix, acc_vector).unwrap();
+        let mut session = repl::Repl::new(model, io::FloatFormat::Decimal, 3);
+        session.execute(&repl::Command::Resolve, 1).unwrap();
+
+        // ** the session reads back exactly, including names with spaces and the solution
+        let formatted = session.format_session();
+        let restored = repl::Repl::parse_session(&formatted).unwrap();
+        assert_eq!(restored.model.s_matrix.matrix, session.model.s_matrix.matrix);
+        assert_eq!(restored.model.metabolites(), session.model.metabolites());
+        assert_eq!(restored.model.reactions(), ["R1", "R2"]);
+        assert_eq!(restored.model.acc_vector, session.model.acc_vector);
+        assert_eq!(restored.solution, session.solution);
+        assert_eq!(restored.format_session(), formatted);
+
+        let truncated: String = formatted.lines().take(10).collect::<Vec<&str>>().join("\n");
+        let Err(error) = repl::Repl::parse_session(&truncated) else {
+            panic!("a truncated session should not load");
+        };
+        assert_eq!(error.to_string(), "line 11: expected a matrix row, found the end of the file");
+
+        // ** large counts fail on the missing lines, not by reserving memory for them
+        let names: String = (0..50_000).map(|i| format!("M{i}\n")).collect();
+        let header: String = formatted.lines().take(3).map(|line| format!("{line}\n")).collect();
+        let oversized = format!("{header}metabolites 50000\n{names}reactions 50000\n{names}matrix\n1 2\n");
+        let Err(error) = repl::Repl::parse_session(&oversized) else {
+            panic!("a session with too few values should not load");
+        };
+        assert_eq!(error.to_string(), "line 100007: expected 50000 values, found 2");
+
+        // ** loading a session is an edit that can be undone
+        let path = std::env::temp_dir().join(format!("smas_session_test_{}.smas", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        session.execute(&repl::Command::SaveSession(path.clone()), 1).unwrap();
+        session.execute(&repl::Command::RemoveReaction(String::from("R1")), 2).unwrap();
+        let reply = session.execute(&repl::Command::LoadSession(path.clone()), 3).unwrap();
+        assert!(reply.ends_with("2 metabolites x 2 reactions, with its last solution"), "{reply}");
+        assert_eq!(session.model.reactions(), ["R1", "R2"]);
+        assert!(session.solution.is_some());
+        session.execute(&repl::Command::Undo, 4).unwrap();
+        assert_eq!(session.model.reactions(), ["R2"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_run_script() {
+        let s_matrix = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 0.0, 1.0]),
+            row_labels: None,
+            col_labels: None,
+        };
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let mut session = repl::Repl::new(repl::Model::new(s_matrix, acc_vector).unwrap(), io::FloatFormat::Decimal, 1);
+
+        // ** comments are skipped, and the first failing command stops the script
+        let script = "# curate\nset S[M1][R2] 0\n\nremove-reaction R9\nremove-reaction R1\n";
+        let mut output = vec!();
+        let error = repl::run_script(&mut session, script, &mut output, true).unwrap_err();
+        assert_eq!(error.to_string(), "line 4: no reaction named \"R9\"");
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "smas> set S[M1][R2] 0\n% S[M1][R2]: -1 -> 0\nsmas> remove-reaction R9\n");
+        assert_eq!(session.model.reactions(), ["R1", "R2"]);
+        assert_eq!(session.undo_depth(), 1);
+
+        let mut output = vec!();
+        repl::run_script(&mut session, "resolve\nquit\nundo\n", &mut output, false).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "R1\t1.0\nR2\t2.0\n% residual norm: 0.0\n");
+        assert_eq!(session.undo_depth(), 1);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::error::SmasError;
+use crate::io::{format_float, FloatFormat};
+use crate::util;
+
+use nalgebra as na;
+
+/// The scale factor that makes the median absolute deviation estimate the standard deviation of
+/// normally distributed values.
+const MAD_SCALE: f64 = 1.4826;
+
+/// This is how replicate accumulation vectors are combined into one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Aggregate {
+    /// The mean of each element, with the sample standard deviation as its spread
+    #[default]
+    Mean,
+    /// The median of each element, with the scaled median absolute deviation as its spread, which
+    /// a single bad replicate cannot drag away
+    Median,
+}
+
+impl Aggregate {
+    /// This returns the name of the aggregate, as given on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+        }
+    }
+}
+
+/// This is a test that rejects outlying replicate values of a metabolite before aggregation.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutlierTest {
+    /// Reject values whose modified z-score, 0.6745 |x - median| / MAD, is above the threshold;
+    /// 3.5 is the usual choice. Nothing is rejected where the MAD is zero.
+    Mad { threshold: f64 },
+    /// Repeat the two-sided Grubbs test at significance alpha, rejecting the value farthest from
+    /// the mean while it is significant and at least three values remain. This assumes the values
+    /// are normally distributed.
+    Grubbs { alpha: f64 },
+}
+
+/// This is a replicate value that an outlier test rejected.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rejection {
+    /// The index of the metabolite, i.e. the element of the accumulation vector (0-based)
+    pub metabolite: usize,
+    /// The index of the replicate (0-based)
+    pub replicate: usize,
+    /// The rejected value
+    pub value: f64,
+    /// Why the value was rejected, e.g. "modified z-score 12.1 > 3.5"
+    pub reason: String,
+}
+
+/// These are replicate accumulation vectors combined into one, with the spread of each element.
+pub struct Aggregated {
+    /// The combined accumulation vector, B; (m x 1)
+    pub values: na::DVector<f64>,
+    /// The spread of each element over the replicates, on the scale of a standard deviation; (m x 1)
+    pub spread: na::DVector<f64>,
+    /// The replicate values left out of the aggregate by the outlier test, by metabolite
+    pub rejected: Vec<Rejection>,
+}
+
+/// This returns the median of sorted values.
+fn median(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    }
+}
+
+/// This returns ln Γ(x) for x > 0, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS.iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (j, c)| sum + c / (x + 1.0 + j as f64));
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// This returns the regularized incomplete beta function I_x(a, b), by its continued fraction.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    // ** the continued fraction converges quickly only below the mean, so use the symmetry otherwise
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - incomplete_beta(1.0 - x, b, a);
+    }
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp() / a;
+    let tiny = 1e-300;
+    let (mut c, mut d) = (1.0, 1.0 - (a + b) * x / (a + 1.0));
+    d = 1.0 / if d.abs() < tiny { tiny } else { d };
+    let mut fraction = d;
+    for m in 1..=300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            d = 1.0 / if d.abs() < tiny { tiny } else { d };
+            c = 1.0 + numerator / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            fraction *= c * d;
+        }
+        if (c * d - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    front * fraction
+}
+
+/// This returns the t such that a Student's t variable with the given degrees of freedom exceeds
+/// it with probability p, for 0 < p < 0.5, by bisection of the upper tail probability.
+fn student_t_upper_quantile(p: f64, degrees_of_freedom: f64) -> f64 {
+    let upper_tail = |t: f64| 0.5 * incomplete_beta(degrees_of_freedom / (degrees_of_freedom + t * t), degrees_of_freedom / 2.0, 0.5);
+    let (mut low, mut high) = (0.0, 1e8);
+    for _ in 0..200 {
+        let middle = (low + high) / 2.0;
+        if upper_tail(middle) > p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// This returns the critical value of the two-sided Grubbs test for n values at significance alpha.
+fn grubbs_critical_value(n: usize, alpha: f64) -> f64 {
+    let n = n as f64;
+    let t = student_t_upper_quantile(alpha / (2.0 * n), n - 2.0);
+    (n - 1.0) / n.sqrt() * (t * t / (n - 2.0 + t * t)).sqrt()
+}
+
+/// This returns the indices of the values an outlier test rejects, with the reason for each.
+fn find_outliers(values: &[f64], test: OutlierTest) -> Vec<(usize, String)> {
+    match test {
+        OutlierTest::Mad { threshold } => {
+            let mut sorted = values.to_vec();
+            sorted.sort_by(f64::total_cmp);
+            let center = median(&sorted);
+            let mut deviations: Vec<f64> = values.iter().map(|v| (v - center).abs()).collect();
+            deviations.sort_by(f64::total_cmp);
+            let mad = median(&deviations);
+            if mad == 0.0 {
+                return vec!();
+            }
+            values.iter()
+                .enumerate()
+                .filter_map(|(j, v)| {
+                    let score = 0.6745 * (v - center).abs() / mad;
+                    (score > threshold).then(|| (j, format!("modified z-score {score:.3} > {threshold}")))
+                })
+                .collect()
+        }
+        OutlierTest::Grubbs { alpha } => {
+            let mut kept: Vec<usize> = (0..values.len()).collect();
+            let mut rejected = vec!();
+            while kept.len() >= 3 {
+                let n = kept.len() as f64;
+                let mean = kept.iter().map(|j| values[*j]).sum::<f64>() / n;
+                let std_dev = (kept.iter().map(|j| (values[*j] - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+                if std_dev == 0.0 {
+                    break;
+                }
+                let (position, farthest) = kept.iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| (values[**a] - mean).abs().total_cmp(&(values[**b] - mean).abs()))
+                    .map(|(position, j)| (position, *j))
+                    .unwrap();
+                let statistic = (values[farthest] - mean).abs() / std_dev;
+                let critical = grubbs_critical_value(kept.len(), alpha);
+                if statistic <= critical {
+                    break;
+                }
+                rejected.push((farthest, format!("Grubbs G {statistic:.3} > {critical:.3} at alpha {alpha}, n = {}", kept.len())));
+                kept.remove(position);
+            }
+            rejected
+        }
+    }
+}
+
+/// This combines replicate accumulation vectors element by element, after leaving out the values
+/// the outlier test rejects, if there is one. The spread of a single replicate is zero. Returns
+/// SmasError::Empty if there are no replicates, or SmasError::Shape if the replicates do not all
+/// have the length of the first.
+///
+/// # Arguments
+/// * `replicates` - the replicate accumulation vectors; (m x 1) each
+/// * `aggregate` - how the replicates are combined
+/// * `outliers` - the test that rejects outlying values of each metabolite, if any
+/// * `summation` - how the sums of the mean and variance are accumulated
+///
+pub fn aggregate(
+    replicates: &[na::DVector<f64>],
+    aggregate: Aggregate,
+    outliers: Option<OutlierTest>,
+    summation: util::Summation,
+) -> Result<Aggregated, SmasError> {
+    let Some(first) = replicates.first() else {
+        return Err(SmasError::Empty("the set of replicates"));
+    };
+    let m = first.nrows();
+    if let Some(replicate) = replicates.iter().find(|replicate| replicate.nrows() != m) {
+        return Err(SmasError::Shape {
+            expected: m,
+            found: replicate.nrows(),
+        });
+    }
+
+    let mut values = na::DVector::zeros(m);
+    let mut spread = na::DVector::zeros(m);
+    let mut rejected = vec!();
+    for i in 0..m {
+        let mut elements: Vec<f64> = replicates.iter().map(|replicate| replicate[i]).collect();
+        if let Some(test) = outliers {
+            let mut outlying = find_outliers(&elements, test);
+            outlying.sort_by_key(|(j, _)| *j);
+            for (j, reason) in outlying.into_iter().rev() {
+                rejected.push(Rejection {
+                    metabolite: i,
+                    replicate: j,
+                    value: elements.remove(j),
+                    reason,
+                });
+            }
+        }
+        let k = elements.len();
+        (values[i], spread[i]) = match aggregate {
+            Aggregate::Mean => {
+                let mean = util::sum_with(&elements, summation) / k as f64;
+                let squares: Vec<f64> = elements.iter().map(|v| (v - mean).powi(2)).collect();
+                let variance = if k > 1 { util::sum_with(&squares, summation) / (k - 1) as f64 } else { 0.0 };
+                (mean, variance.sqrt())
+            }
+            Aggregate::Median => {
+                elements.sort_by(f64::total_cmp);
+                let center = median(&elements);
+                let mut deviations: Vec<f64> = elements.iter().map(|v| (v - center).abs()).collect();
+                deviations.sort_by(f64::total_cmp);
+                (center, MAD_SCALE * median(&deviations))
+            }
+        };
+    }
+    rejected.sort_by_key(|rejection| (rejection.metabolite, rejection.replicate));
+    Ok(Aggregated { values, spread, rejected })
+}
+
+/// This returns the weight of each row of a weighted least squares fit from the spread of its
+/// accumulation value: 1 / spread, so that reproducible metabolites pull harder on the fit.
+/// Elements without spread are given the largest weight of the others rather than an infinite
+/// one, and if no element has any spread every weight is 1.
+///
+/// # Arguments
+/// * `spread` - the spread of each element of the accumulation vector; (m x 1)
+///
+pub fn spread_weights(spread: &na::DVector<f64>) -> na::DVector<f64> {
+    let smallest = spread.iter().copied().filter(|s| *s > 0.0).fold(f64::INFINITY, f64::min);
+    if smallest.is_infinite() {
+        return na::DVector::from_element(spread.nrows(), 1.0);
+    }
+    spread.map(|s| 1.0 / if s > 0.0 { s } else { smallest })
+}
+
+/// This formats the combined value and spread of each element as '%' comment lines, one per
+/// metabolite.
+///
+/// # Arguments
+/// * `aggregated` - the combined replicates
+/// * `aggregate` - how the replicates were combined
+/// * `row_labels` - the name of each element, e.g. its metabolite; (m x 1)
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_spread(
+    aggregated: &Aggregated,
+    aggregate: Aggregate,
+    row_labels: &[String],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let spread_name = match aggregate {
+        Aggregate::Mean => "std dev",
+        Aggregate::Median => "scaled MAD",
+    };
+    let mut lines = vec![format!("% metabolite\t{}\t{spread_name}", aggregate.name())];
+    for (i, label) in row_labels.iter().enumerate() {
+        lines.push(format!(
+            "% {label}\t{}\t{}",
+            format_float(aggregated.values[i], float_format, float_precision),
+            format_float(aggregated.spread[i], float_format, float_precision),
+        ));
+    }
+    lines.join("\n")
+}
+
+/// This formats the replicate values an outlier test rejected as '%' comment lines, one per value.
+///
+/// # Arguments
+/// * `rejected` - the rejected replicate values
+/// * `row_labels` - the name of each element, e.g. its metabolite; (m x 1)
+/// * `replicate_names` - the name of each replicate, e.g. its path
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_rejections(
+    rejected: &[Rejection],
+    row_labels: &[String],
+    replicate_names: &[String],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    if rejected.is_empty() {
+        return String::from("% no replicate values were rejected as outliers");
+    }
+    rejected.iter()
+        .map(|rejection| format!(
+            "% rejected {} of {} from {}: {}",
+            format_float(rejection.value, float_format, float_precision),
+            row_labels[rejection.metabolite],
+            replicate_names[rejection.replicate],
+            rejection.reason
+        ))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_aggregate() {
+        let replicates = vec![
+            na::DVector::from_row_slice(&[1.0, 2.0, 5.0]),
+            na::DVector::from_row_slice(&[3.0, 2.0, 5.0]),
+            na::DVector::from_row_slice(&[2.0, 2.0, 50.0]),
+        ];
+        let mean = replicates::aggregate(&replicates, replicates::Aggregate::Mean, None, util::Summation::Compensated).unwrap();
+        assert_eq!(mean.values, na::DVector::from_row_slice(&[2.0, 2.0, 20.0]));
+        assert_eq!(mean.spread[0], 1.0);
+        assert_eq!(mean.spread[1], 0.0);
+
+        // ** the median ignores the bad third replicate of the last element
+        let median = replicates::aggregate(&replicates, replicates::Aggregate::Median, None, util::Summation::Compensated).unwrap();
+        assert_eq!(median.values, na::DVector::from_row_slice(&[2.0, 2.0, 5.0]));
+        assert_eq!(median.spread, na::DVector::from_row_slice(&[1.4826, 0.0, 0.0]));
+
+        // ** elements without spread get the largest of the other weights
+        let weights = replicates::spread_weights(&mean.spread);
+        assert_eq!(weights[0], 1.0);
+        assert_eq!(weights[1], 1.0);
+        assert!(weights[2] < 0.1);
+        assert_eq!(replicates::spread_weights(&median.spread)[1], 1.0 / 1.4826);
+
+        assert!(matches!(
+            replicates::aggregate(&[], replicates::Aggregate::Mean, None, util::Summation::Compensated),
+            Err(error::SmasError::Empty(_))
+        ));
+        let ragged = vec![na::DVector::zeros(3), na::DVector::zeros(2)];
+        assert!(matches!(
+            replicates::aggregate(&ragged, replicates::Aggregate::Median, None, util::Summation::Compensated),
+            Err(error::SmasError::Shape { expected: 3, found: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_reject_outliers() {
+        // ** the critical values match the published tables
+        assert!((replicates::student_t_upper_quantile(0.025, 10.0) - 2.2281).abs() < 1e-4);
+        assert!((replicates::grubbs_critical_value(10, 0.05) - 2.2900).abs() < 1e-3);
+
+        // ** the fifth replicate of the first metabolite is far off; the second metabolite is clean
+        let values = [[10.0, 1.0], [10.2, 1.1], [9.9, 0.9], [10.1, 1.0], [30.0, 1.05], [9.8, 0.95]];
+        let replicates: Vec<na::DVector<f64>> = values.iter().map(|v| na::DVector::from_row_slice(v)).collect();
+        for test in [replicates::OutlierTest::Mad { threshold: 3.5 }, replicates::OutlierTest::Grubbs { alpha: 0.05 }] {
+            let aggregated = replicates::aggregate(&replicates, replicates::Aggregate::Mean, Some(test), util::Summation::Compensated).unwrap();
+            assert_eq!(aggregated.rejected.len(), 1, "{test:?}");
+            assert_eq!((aggregated.rejected[0].metabolite, aggregated.rejected[0].replicate), (0, 4));
+            assert!(util::epsilon_eq(aggregated.values[0], 10.0, 1e-12));
+            assert!(util::epsilon_eq(aggregated.values[1], 1.0, 1e-12));
+        }
+
+        let labels = vec![String::from("g6p"), String::from("f6p")];
+        let names: Vec<String> = (1..=6).map(|j| format!("a{j}.txt")).collect();
+        let aggregated = replicates::aggregate(
+            &replicates,
+            replicates::Aggregate::Median,
+            Some(replicates::OutlierTest::Mad { threshold: 3.5 }),
+            util::Summation::Compensated,
+        ).unwrap();
+        let report = replicates::format_rejections(&aggregated.rejected, &labels, &names, io::FloatFormat::Decimal, 1);
+        assert!(report.starts_with("% rejected 30.0 of g6p from a5.txt: modified z-score"), "{report}");
+    }
+}
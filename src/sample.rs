@@ -0,0 +1,226 @@
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat};
+use crate::solve::{self, SolveOptions};
+use crate::util;
+
+use nalgebra as na;
+
+/// The default number of hit-and-run steps discarded before the first sample is recorded.
+pub const DEFAULT_BURN_IN: usize = 1000;
+
+/// The default number of hit-and-run steps taken between recorded samples.
+pub const DEFAULT_THIN: usize = 10;
+
+/// These are the settings of a hit-and-run sampler.
+#[derive(Clone, Copy)]
+pub struct HitAndRun {
+    /// The number of samples to record
+    pub n_samples: usize,
+    /// The number of steps discarded before the first sample
+    pub burn_in: usize,
+    /// The number of steps between recorded samples
+    pub thin: usize,
+    /// The seed of the random number generator
+    pub seed: u64,
+}
+
+/// This is the marginal distribution of one reaction's flux over the samples.
+pub struct Marginal {
+    /// The mean flux
+    pub mean: f64,
+    /// The standard deviation of the flux
+    pub std_dev: f64,
+    /// The smallest sampled flux
+    pub min: f64,
+    /// The 5th percentile of the flux
+    pub lower: f64,
+    /// The median flux
+    pub median: f64,
+    /// The 95th percentile of the flux
+    pub upper: f64,
+    /// The largest sampled flux
+    pub max: f64,
+}
+
+/// This samples the set of reaction vectors that fit the accumulation vector as well as possible
+/// within the bounds, using hit-and-run.
+///
+/// The bounded least squares solution is the starting point. Every least squares solution within
+/// the bounds produces the same accumulation, so the sampled set is that solution plus the null
+/// space of A, cut by the bounds. Each step draws a uniformly random direction in the null space
+/// and moves to a uniformly random point on the chord through the current point in that direction.
+///
+/// If the cancellation token of the options is cancelled, or their timeout runs out, while
+/// sampling, sampling stops and the samples recorded so far are returned, so the result may have
+/// fewer than `settings.n_samples` columns. If that happens while finding the starting point, the
+/// SmasError::Cancelled or SmasError::TimedOut of the bounded solve is returned.
+///
+/// Returns SmasError::Unbounded if the bounds do not limit some direction of the null space.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `bounds` - the lower and upper bound of each reaction
+/// * `settings` - the number of samples, burn in, thinning and seed
+/// * `options` - the cancellation token and timeout that stop sampling early
+///
+pub fn sample_fluxes(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    bounds: &Bounds,
+    settings: &HitAndRun,
+    options: &SolveOptions,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    let HitAndRun { n_samples, burn_in, thin, seed } = *settings;
+    let deadline = options.start();
+    let start = solve::solve_bounded_with_options(acc_vector, s_matrix, bounds, solve::BOUNDED_MAX_ITERATIONS, options)
+        .and_then(solve::require_converged)?;
+    let basis = solve::null_space(s_matrix);
+    let n = s_matrix.ncols();
+    let thin = thin.max(1);
+
+    let mut samples = na::DMatrix::zeros(n, n_samples);
+    let mut x = start.reactions;
+    if basis.ncols() == 0 {
+        // ** the solution is unique, so every sample is the same
+        for mut column in samples.column_iter_mut() {
+            column.copy_from(&x);
+        }
+        return Ok(samples);
+    }
+
+    let mut rng = util::SplitMix64::new(seed);
+    let mut recorded = 0;
+    for step in 0..burn_in + n_samples * thin {
+        if deadline.check("hit-and-run", step).is_err() {
+            return Ok(samples.columns(0, recorded).into_owned());
+        }
+        let direction = &basis * na::DVector::from_fn(basis.ncols(), |_, _| rng.next_gaussian());
+        let (t_min, t_max) = bounds.chord(&x, &direction);
+        if !t_min.is_finite() || !t_max.is_finite() {
+            return Err(SmasError::Unbounded);
+        }
+        x += direction * (t_min + (t_max - t_min) * rng.next_f64());
+
+        if step >= burn_in && (step - burn_in + 1).is_multiple_of(thin) {
+            samples.column_mut(recorded).copy_from(&x);
+            recorded += 1;
+        }
+    }
+    Ok(samples)
+}
+
+/// This linearly interpolates the q-th quantile of sorted values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+/// This summarizes the marginal distribution of each reaction over the samples. Returns
+/// SmasError::Empty if there are no samples, since no statistic is defined over zero samples.
+///
+/// # Arguments
+/// * `samples` - one sample per column; (n x samples)
+/// * `summation` - how the sums of the mean and variance are accumulated
+///
+pub fn marginals(samples: &na::DMatrix<f64>, summation: util::Summation) -> Result<Vec<Marginal>, SmasError> {
+    if samples.ncols() == 0 {
+        return Err(SmasError::Empty("the set of samples"));
+    }
+    Ok(samples.row_iter()
+        .map(|row| {
+            let values: Vec<f64> = row.iter().copied().collect();
+            let mean = util::sum_with(&values, summation) / values.len() as f64;
+            let squares: Vec<f64> = values.iter().map(|v| (v - mean).powi(2)).collect();
+            let variance = util::sum_with(&squares, summation) / values.len() as f64;
+            let mut sorted = values;
+            sorted.sort_by(f64::total_cmp);
+            Marginal {
+                mean,
+                std_dev: variance.sqrt(),
+                min: sorted[0],
+                lower: quantile(&sorted, 0.05),
+                median: quantile(&sorted, 0.5),
+                upper: quantile(&sorted, 0.95),
+                max: sorted[sorted.len() - 1],
+            }
+        })
+        .collect())
+}
+
+/// This formats the marginal distributions as a tab delimited table with one row per reaction.
+///
+/// # Arguments
+/// * `marginals` - the marginal distribution of each reaction
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_marginals(
+    marginals: &[Marginal],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let mut result_string = String::from("% reaction \t mean \t std dev \t min \t 5% \t median \t 95% \t max");
+    for (i, marginal) in marginals.iter().enumerate() {
+        result_string.push_str(&format!("\n  {}", i + 1));
+        for value in [
+            marginal.mean,
+            marginal.std_dev,
+            marginal.min,
+            marginal.lower,
+            marginal.median,
+            marginal.upper,
+            marginal.max,
+        ] {
+            result_string.push('\t');
+            result_string.push_str(&io::format_float(value, float_format, float_precision));
+        }
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_sample_fluxes() {
+        // ** x1 + x2 = 1 with both fluxes in [0, 1]: the samples cover the segment uniformly
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0]);
+        let bounds = constraints::Bounds {
+            lower: na::DVector::from_element(2, 0.0),
+            upper: na::DVector::from_element(2, 1.0),
+        };
+        let settings = sample::HitAndRun { n_samples: 2000, burn_in: 100, thin: 1, seed: 42 };
+        let cancel = cancel::CancellationToken::new();
+        let options = solve::SolveOptions { timeout: None, cancel_token: Some(cancel.clone()) };
+        let samples = sample::sample_fluxes(&acc_vector, &s_matrix, &bounds, &settings, &options).unwrap();
+        assert_eq!(samples.ncols(), 2000);
+        for column in samples.column_iter() {
+            assert!(util::epsilon_eq(column[0] + column[1], 1.0, 1e-9));
+            assert!(column.iter().all(|v| *v >= -1e-9 && *v <= 1.0 + 1e-9));
+        }
+
+        let marginals = sample::marginals(&samples, util::Summation::default()).unwrap();
+        assert!(util::epsilon_eq(marginals[0].mean, 0.5, 0.05));
+        assert!(util::epsilon_eq(marginals[0].median, 0.5, 0.05));
+        assert!(matches!(sample::marginals(&samples.columns(0, 0).into_owned(), util::Summation::default()), Err(error::SmasError::Empty(_))));
+
+        // ** without bounds the segment is a line, which cannot be sampled
+        let unbounded = sample::sample_fluxes(&acc_vector, &s_matrix, &constraints::Bounds::unbounded(2), &settings, &options);
+        assert!(matches!(unbounded, Err(error::SmasError::Unbounded)));
+
+        // ** a run stopped before it finds the starting point has no samples to keep
+        let timed_out = solve::SolveOptions { timeout: Some(std::time::Duration::ZERO), cancel_token: None };
+        let samples = sample::sample_fluxes(&acc_vector, &s_matrix, &bounds, &settings, &timed_out);
+        assert!(matches!(samples, Err(error::SmasError::TimedOut { .. })));
+        cancel.cancel();
+        let cancelled = sample::sample_fluxes(&acc_vector, &s_matrix, &bounds, &settings, &options);
+        assert!(matches!(cancelled, Err(error::SmasError::Cancelled { method: "bounded", .. })));
+    }
+}
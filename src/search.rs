@@ -0,0 +1,140 @@
+use crate::io::{self, FloatFormat, LabeledMatrix};
+use crate::util;
+
+use nalgebra as na;
+
+/// These are the reactions and metabolites of a model whose labels match a search pattern.
+pub struct SearchResults {
+    /// The matching reactions (columns), in matrix order
+    pub reactions: Vec<usize>,
+    /// The matching metabolites (rows), in matrix order
+    pub metabolites: Vec<usize>,
+}
+
+impl SearchResults {
+    /// This returns whether nothing matched.
+    pub fn is_empty(&self) -> bool {
+        self.reactions.is_empty() && self.metabolites.is_empty()
+    }
+}
+
+/// This returns whether a label matches a search pattern. A pattern with '*' or '?' is a glob that
+/// must match the whole label, e.g. "glc*"; any other pattern matches labels that contain it,
+/// e.g. "glc" matches "glc__D_c".
+///
+/// # Arguments
+/// * `label` - the label of a reaction or metabolite
+/// * `pattern` - the search pattern
+/// * `ignore_case` - whether upper and lower case letters match each other
+///
+pub fn label_matches(label: &str, pattern: &str, ignore_case: bool) -> bool {
+    let (label, pattern) = if ignore_case {
+        (label.to_lowercase(), pattern.to_lowercase())
+    } else {
+        (label.to_string(), pattern.to_string())
+    };
+    if !pattern.contains(['*', '?']) {
+        return label.contains(&pattern);
+    }
+    let label: Vec<char> = label.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    util::glob_match(&label, &pattern)
+}
+
+/// This finds the reactions and metabolites whose labels match a search pattern; see
+/// `label_matches`. Unlabeled reactions and metabolites are named R1, R2, ... and M1, M2, ...
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `pattern` - the search pattern
+/// * `ignore_case` - whether upper and lower case letters match each other
+///
+pub fn search(s_matrix: &LabeledMatrix, pattern: &str, ignore_case: bool) -> SearchResults {
+    SearchResults {
+        reactions: (0..s_matrix.matrix.ncols())
+            .filter(|j| label_matches(&s_matrix.col_label(*j), pattern, ignore_case))
+            .collect(),
+        metabolites: (0..s_matrix.matrix.nrows())
+            .filter(|i| label_matches(&s_matrix.row_label(*i), pattern, ignore_case))
+            .collect(),
+    }
+}
+
+/// This formats search results: each matching reaction as its equation, and each matching
+/// metabolite with the coefficient of every reaction it takes part in, e.g. "glc: -1 hk, 1 glc_in".
+/// With a solution, each reaction is followed by its flux, and each metabolite by its net
+/// production, Σ_j A_ij x_j.
+///
+/// # Arguments
+/// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+/// * `results` - the matching reactions and metabolites
+/// * `reversible` - whether each reaction is reversible, if known
+/// * `fluxes` - the solved reaction vector, x, if there is one; (n x 1)
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_search(
+    s_matrix: &LabeledMatrix,
+    results: &SearchResults,
+    reversible: Option<&[bool]>,
+    fluxes: Option<&na::DVector<f64>>,
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let format_float = |value: f64| io::format_float(value, float_format, float_precision);
+    let mut lines = vec![format!("% reactions: {}", results.reactions.len())];
+    for j in &results.reactions {
+        let equation = io::format_reaction_equation(s_matrix, *j, reversible);
+        lines.push(match fluxes {
+            Some(fluxes) => format!("{equation}\tflux {}", format_float(fluxes[*j])),
+            None => equation,
+        });
+    }
+    lines.push(format!("% metabolites: {}", results.metabolites.len()));
+    for i in &results.metabolites {
+        let row = s_matrix.matrix.row(*i);
+        let terms: Vec<String> = row.iter()
+            .enumerate()
+            .filter(|(_, v)| **v != 0.0)
+            .map(|(j, v)| format!("{v} {}", s_matrix.col_label(j)))
+            .collect();
+        let line = format!("{}: {}", s_matrix.row_label(*i), terms.join(", "));
+        lines.push(match fluxes {
+            Some(fluxes) => format!("{line}\tnet {}", format_float(row.dot(&fluxes.transpose()))),
+            None => line,
+        });
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_search() {
+        let model = io::parse_reaction_equations("glc_in: -> glc\nhk: glc + atp -> g6p + adp\npgi: g6p <=> f6p").unwrap();
+        assert!(search::label_matches("glc__D_c", "glc", false));
+        assert!(search::label_matches("GLC", "glc", true));
+        assert!(!search::label_matches("GLC", "glc", false));
+        assert!(search::label_matches("g6p", "g?p", false));
+        assert!(!search::label_matches("g6p_c", "g?p", false));
+
+        let results = search::search(&model.s_matrix, "glc", false);
+        assert_eq!(results.reactions, vec![0]);
+        assert_eq!(results.metabolites, vec![0]);
+        assert!(search::search(&model.s_matrix, "nadh", false).is_empty());
+
+        let fluxes = na::DVector::from_row_slice(&[2.0, 1.5, 1.0]);
+        let output = search::format_search(
+            &model.s_matrix,
+            &search::search(&model.s_matrix, "g*", false),
+            Some(&model.reversible),
+            Some(&fluxes),
+            io::FloatFormat::Decimal,
+            1,
+        );
+        assert_eq!(output, "% reactions: 1\nglc_in: -> glc\tflux 2.0\n% metabolites: 2\nglc: 1 glc_in, -1 hk\tnet 0.5\ng6p: 1 hk, -1 pgi\tnet 0.5");
+    }
+}
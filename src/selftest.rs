@@ -0,0 +1,126 @@
+use crate::io;
+use crate::matrices;
+use crate::solve;
+use crate::util;
+
+use nalgebra as na;
+
+/// The tolerance used to compare computed and expected values in the self test cases.
+pub const SELFTEST_EPSILON: f64 = 1e-4;
+
+/// This describes the outcome of a single self test case.
+pub struct SelfTestResult {
+    /// A short, human readable name for the case
+    pub name: &'static str,
+    /// Whether every computed value was within `SELFTEST_EPSILON` of the expected value
+    pub passed: bool,
+    /// The largest absolute difference between a computed and an expected value
+    pub max_delta: f64,
+}
+
+/// This compares a computed vector to the expected vector and builds a SelfTestResult.
+fn check(name: &'static str, computed: &na::DVector<f64>, expected: &na::DVector<f64>) -> SelfTestResult {
+    let passed = util::vectors_eq(computed, expected, SELFTEST_EPSILON, 0.0);
+    let max_delta = util::max_norm_distance(computed, expected);
+
+    SelfTestResult {
+        name,
+        passed,
+        max_delta,
+    }
+}
+
+/// This runs the solver against the embedded reference problem and a handful of small synthetic
+/// problems with known solutions, returning one SelfTestResult per case.
+pub fn run_selftest() -> Vec<SelfTestResult> {
+    let mut results = vec!();
+
+    // ** the embedded reference problem: astd015 solved against the default matrix
+    let acc_vector = na::DVector::from_row_slice(matrices::A_STD_015);
+    let r_vector_truth = na::DVector::from_row_slice(matrices::R_STD_015);
+    let r_vector = solve::solve(acc_vector, util::default_s_matrix());
+    results.push(check("reference astd015 -> rstd015", &r_vector, &r_vector_truth));
+
+    // ** a zero accumulation vector should produce a zero reaction vector
+    let r_vector = solve::solve(na::DVector::zeros(39), util::default_s_matrix());
+    results.push(check("zero accumulation vector", &r_vector, &na::DVector::zeros(28)));
+
+    // ** the identity matrix should return the accumulation vector unchanged
+    let acc_vector = na::DVector::from_row_slice(&[1.5, -2.0, 3.25]);
+    let r_vector = solve::solve(acc_vector.clone(), na::DMatrix::identity(3, 3));
+    results.push(check("identity matrix", &r_vector, &acc_vector));
+
+    // ** an overdetermined but consistent system has an exact solution
+    let s_matrix = na::DMatrix::from_row_slice(3, 2, &[
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+    ]);
+    let r_vector_truth = na::DVector::from_row_slice(&[2.0, -3.0]);
+    let r_vector = solve::solve(&s_matrix * &r_vector_truth, s_matrix);
+    results.push(check("overdetermined consistent system", &r_vector, &r_vector_truth));
+
+    // ** an underdetermined system should produce the minimum norm solution
+    let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+    let r_vector = solve::solve(na::DVector::from_row_slice(&[2.0]), s_matrix);
+    results.push(check(
+        "underdetermined minimum norm solution",
+        &r_vector,
+        &na::DVector::from_row_slice(&[1.0, 1.0]),
+    ));
+
+    // ** a rank deficient system should also produce the minimum norm solution
+    let s_matrix = na::DMatrix::from_row_slice(2, 2, &[
+        1.0, 2.0,
+        2.0, 4.0,
+    ]);
+    let r_vector = solve::solve(na::DVector::from_row_slice(&[1.0, 2.0]), s_matrix);
+    results.push(check(
+        "rank deficient minimum norm solution",
+        &r_vector,
+        &na::DVector::from_row_slice(&[0.2, 0.4]),
+    ));
+
+    // ** formatting then parsing a vector should round trip
+    let r_vector_truth = na::DVector::from_row_slice(matrices::R_STD_015);
+    let r_vector = io::parse_vector(
+        &io::format_vector_flat(&r_vector_truth, io::FloatFormat::Scientific, 16)
+    );
+    results.push(check("format/parse round trip", &r_vector, &r_vector_truth));
+
+    results
+}
+
+/// This formats a list of SelfTestResults as a human readable report.
+///
+/// # Arguments
+/// * `results` - the results of the self test cases
+///
+pub fn format_selftest_results(results: &[SelfTestResult]) -> String {
+    let mut result_string = String::new();
+    for result in results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        result_string.push_str(&format!(
+            "{status}\t{}\t(max |delta|: {:.3e})\n",
+            result.name, result.max_delta
+        ));
+    }
+    let n_passed = results.iter().filter(|r| r.passed).count();
+    result_string.push_str(&format!("{n_passed}/{} self tests passed", results.len()));
+
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_run_selftest() {
+        let results = selftest::run_selftest();
+        assert!(!results.is_empty());
+        for result in results {
+            assert!(result.passed, "self test case failed: {}", result.name);
+        }
+    }
+}
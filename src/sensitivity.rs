@@ -0,0 +1,169 @@
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat};
+use crate::solve;
+
+use std::str::FromStr;
+
+use nalgebra as na;
+
+/// The default finite difference step, relative to the magnitude of the perturbed coefficient.
+pub const DEFAULT_RELATIVE_STEP: f64 = 1e-6;
+
+/// This identifies a single coefficient of the stoichiometric matrix.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MatrixEntry {
+    /// The row of the coefficient (0-based)
+    pub metabolite: usize,
+    /// The column of the coefficient (0-based)
+    pub reaction: usize,
+}
+
+/// This is the numerically estimated effect of one matrix coefficient on the solution.
+pub struct EntrySensitivity {
+    /// The perturbed coefficient
+    pub entry: MatrixEntry,
+    /// The value of the coefficient in the unperturbed matrix
+    pub coefficient: f64,
+    /// The finite difference step that was used
+    pub step: f64,
+    /// The derivative of each reaction flux with respect to the coefficient, dx/ds; (n x 1)
+    pub derivative: na::DVector<f64>,
+}
+
+/// This parses a comma separated list of matrix entries written as `R<reaction>:M<metabolite>`,
+/// e.g. "R3:M7,R5:M2". Reactions and metabolites are numbered from 1.
+///
+/// # Arguments
+/// * `spec` - the list of entries
+///
+pub fn parse_matrix_entries(spec: &str) -> Result<Vec<MatrixEntry>, SmasError> {
+    let parse_error = |entry: &str| SmasError::InvalidArgument(
+        format!("expected a matrix entry like R3:M7, found \"{entry}\""),
+    );
+
+    spec.split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (reaction, metabolite) = entry.split_once(':').ok_or_else(|| parse_error(entry))?;
+            let reaction = reaction.strip_prefix('R')
+                .and_then(|r| usize::from_str(r).ok())
+                .filter(|r| *r > 0)
+                .ok_or_else(|| parse_error(entry))?;
+            let metabolite = metabolite.strip_prefix('M')
+                .and_then(|m| usize::from_str(m).ok())
+                .filter(|m| *m > 0)
+                .ok_or_else(|| parse_error(entry))?;
+            Ok(MatrixEntry {
+                metabolite: metabolite - 1,
+                reaction: reaction - 1,
+            })
+        })
+        .collect()
+}
+
+/// This estimates how the pseudoinverse solution changes with each of the given matrix
+/// coefficients, using central finite differences.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `entries` - the coefficients to perturb
+/// * `relative_step` - the finite difference step relative to max(|coefficient|, 1)
+///
+pub fn matrix_sensitivity(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    entries: &[MatrixEntry],
+    relative_step: f64,
+) -> Result<Vec<EntrySensitivity>, SmasError> {
+    let mut sensitivities = vec!();
+    for entry in entries {
+        if entry.metabolite >= s_matrix.nrows() || entry.reaction >= s_matrix.ncols() {
+            return Err(SmasError::InvalidArgument(format!(
+                "R{}:M{} is outside of the {}x{} matrix",
+                entry.reaction + 1, entry.metabolite + 1, s_matrix.nrows(), s_matrix.ncols()
+            )));
+        }
+
+        let coefficient = s_matrix[(entry.metabolite, entry.reaction)];
+        let step = relative_step * coefficient.abs().max(1.0);
+
+        let mut forward = s_matrix.clone();
+        forward[(entry.metabolite, entry.reaction)] += step;
+        let mut backward = s_matrix.clone();
+        backward[(entry.metabolite, entry.reaction)] -= step;
+
+        let derivative = (solve::solve(acc_vector.clone(), forward)
+            - solve::solve(acc_vector.clone(), backward)) / (2.0 * step);
+
+        sensitivities.push(EntrySensitivity {
+            entry: *entry,
+            coefficient,
+            step,
+            derivative,
+        });
+    }
+    Ok(sensitivities)
+}
+
+/// This formats sensitivities as a tab delimited table with one row per coefficient: the norm of
+/// the derivative of the solution, and the reaction whose flux is most sensitive.
+///
+/// # Arguments
+/// * `sensitivities` - the sensitivities to be formatted
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_sensitivity(
+    sensitivities: &[EntrySensitivity],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let mut result_string = String::from("% entry \t coefficient \t step \t ||dx/ds|| \t most sensitive reaction \t dx/ds");
+    for sensitivity in sensitivities {
+        let (max_index, max_value) = sensitivity.derivative.iter()
+            .enumerate()
+            .fold((0, 0.0), |(bi, bv), (i, v)| if v.abs() > f64::abs(bv) { (i, *v) } else { (bi, bv) });
+        result_string.push_str(&format!(
+            "\n  R{}:M{}\t{}\t{}\t{}\tR{}\t{}",
+            sensitivity.entry.reaction + 1,
+            sensitivity.entry.metabolite + 1,
+            io::format_float(sensitivity.coefficient, float_format, float_precision),
+            io::format_float(sensitivity.step, float_format, float_precision),
+            io::format_float(sensitivity.derivative.norm(), float_format, float_precision),
+            max_index + 1,
+            io::format_float(max_value, float_format, float_precision),
+        ));
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_parse_matrix_entries() {
+        let entries = sensitivity::parse_matrix_entries("R3:M7, R5:M2").unwrap();
+        assert_eq!(entries, vec![
+            sensitivity::MatrixEntry { metabolite: 6, reaction: 2 },
+            sensitivity::MatrixEntry { metabolite: 1, reaction: 4 },
+        ]);
+        assert!(sensitivity::parse_matrix_entries("M7:R3").is_err());
+        assert!(sensitivity::parse_matrix_entries("R0:M1").is_err());
+    }
+
+    #[test]
+    fn test_matrix_sensitivity() {
+        // ** s x = b gives x = b / s, so dx/ds = -b / s²
+        let s_matrix = na::DMatrix::from_row_slice(1, 1, &[2.0]);
+        let acc_vector = na::DVector::from_row_slice(&[4.0]);
+        let entries = sensitivity::parse_matrix_entries("R1:M1").unwrap();
+        let sensitivities = sensitivity::matrix_sensitivity(
+            &acc_vector, &s_matrix, &entries, sensitivity::DEFAULT_RELATIVE_STEP,
+        ).unwrap();
+        assert!(util::epsilon_eq(sensitivities[0].derivative[0], -1.0, 1e-6));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::io::{self, FloatFormat, LabeledMatrix};
+
+use nalgebra as na;
+
+/// This is a coefficient that is not within the tolerance of any integer or simple fraction.
+pub struct Unsnapped {
+    /// The row of the coefficient
+    pub row: usize,
+    /// The column of the coefficient
+    pub col: usize,
+    /// The coefficient
+    pub value: f64,
+    /// The nearest value it could have been snapped to, and how far it is from it
+    pub nearest: (f64, f64),
+}
+
+/// This describes what snapping did to a matrix.
+pub struct SnapReport {
+    /// The number of coefficients that were changed
+    pub snapped: usize,
+    /// The largest change made to a coefficient
+    pub max_change: f64,
+    /// The coefficients that were left as they were
+    pub unsnapped: Vec<Unsnapped>,
+}
+
+/// This returns the fraction with a denominator of at most `max_denominator` nearest to a value,
+/// preferring smaller denominators, e.g. 0.5 for 0.4999999, along with its distance from the
+/// value.
+fn nearest_fraction(value: f64, max_denominator: u32) -> (f64, f64) {
+    (1..=max_denominator.max(1))
+        .map(|denominator| {
+            let fraction = (value * denominator as f64).round() / denominator as f64;
+            (fraction, (value - fraction).abs())
+        })
+        .fold((f64::NAN, f64::INFINITY), |best, candidate| if candidate.1 < best.1 { candidate } else { best })
+}
+
+/// This snaps a value to the integer or fraction with a denominator of at most `max_denominator`
+/// within `tolerance` of it, preferring smaller denominators; with a `max_denominator` of 1 only
+/// integers are snapped to. Returns None if there is no such fraction.
+///
+/// # Arguments
+/// * `value` - the value
+/// * `max_denominator` - the largest denominator of the fractions snapped to, e.g. 1 for integers
+/// * `tolerance` - how far a value may be from the fraction it is snapped to
+///
+pub fn snap_value(value: f64, max_denominator: u32, tolerance: f64) -> Option<f64> {
+    // ** adding 0 turns -0, e.g. from snapping -1e-12, into 0
+    (1..=max_denominator.max(1))
+        .map(|denominator| (value * denominator as f64).round() / denominator as f64 + 0.0)
+        .find(|fraction| (value - fraction).abs() <= tolerance)
+}
+
+/// This snaps every coefficient of a matrix exported from a floating point pipeline, e.g.
+/// 0.9999999 or 0.50000001, to the integer or simple fraction within `tolerance` of it; see
+/// `snap_value`. Coefficients that are not near one are left as they are and reported.
+///
+/// # Arguments
+/// * `matrix` - the matrix, snapped in place
+/// * `max_denominator` - the largest denominator of the fractions snapped to, e.g. 1 for integers
+/// * `tolerance` - how far a coefficient may be from the fraction it is snapped to
+///
+pub fn snap_matrix(matrix: &mut na::DMatrix<f64>, max_denominator: u32, tolerance: f64) -> SnapReport {
+    let mut report = SnapReport { snapped: 0, max_change: 0.0, unsnapped: vec!() };
+    // ** column major, like the matrix, so that coefficients are reported reaction by reaction
+    for col in 0..matrix.ncols() {
+        for row in 0..matrix.nrows() {
+            let value = matrix[(row, col)];
+            match snap_value(value, max_denominator, tolerance) {
+                Some(snapped) if snapped == value => {}
+                Some(snapped) => {
+                    report.snapped += 1;
+                    report.max_change = report.max_change.max((snapped - value).abs());
+                    matrix[(row, col)] = snapped;
+                }
+                None => report.unsnapped.push(Unsnapped {
+                    row,
+                    col,
+                    value,
+                    nearest: nearest_fraction(value, max_denominator),
+                }),
+            }
+        }
+    }
+    report
+}
+
+/// This formats a snap report as '%' comment lines: a summary, then one line per coefficient
+/// that could not be snapped, named by its metabolite and reaction.
+///
+/// # Arguments
+/// * `report` - the snap report
+/// * `s_matrix` - the labeled matrix that was snapped, for its labels
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many decimal places the floats have
+///
+pub fn format_snap_report(report: &SnapReport, s_matrix: &LabeledMatrix, float_format: FloatFormat, float_precision: usize) -> String {
+    let mut lines = vec![format!(
+        "% snapped {} coefficients (largest change {}); {} could not be snapped",
+        report.snapped,
+        io::format_float(report.max_change, float_format, float_precision),
+        report.unsnapped.len(),
+    )];
+    for unsnapped in &report.unsnapped {
+        lines.push(format!(
+            "% could not snap {} of {} in {}: nearest {} is {} away",
+            io::format_float(unsnapped.value, float_format, float_precision),
+            s_matrix.row_label(unsnapped.row),
+            s_matrix.col_label(unsnapped.col),
+            io::format_float(unsnapped.nearest.0, float_format, float_precision),
+            io::format_float(unsnapped.nearest.1, float_format, float_precision),
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_snap_matrix() {
+        assert_eq!(snap::snap_value(0.9999999, 1, 1e-6), Some(1.0));
+        assert_eq!(snap::snap_value(0.5000001, 1, 1e-6), None);
+        assert_eq!(snap::snap_value(0.5000001, 2, 1e-6), Some(0.5));
+        assert_eq!(snap::snap_value(-1.0 / 3.0 + 1e-9, 4, 1e-6), Some(-1.0 / 3.0));
+        assert_eq!(snap::snap_value(-1e-12, 1, 1e-6), Some(0.0));
+
+        let mut matrix = na::DMatrix::from_row_slice(2, 3, &[-0.9999999, 2.0000004, 0.3, 1e-9, 0.5000002, -3.0]);
+        let report = snap::snap_matrix(&mut matrix, 2, 1e-6);
+        assert_eq!(matrix, na::DMatrix::from_row_slice(2, 3, &[-1.0, 2.0, 0.3, 0.0, 0.5, -3.0]));
+        assert_eq!(report.snapped, 4);
+        assert!((report.max_change - 4e-7).abs() < 1e-12);
+        assert_eq!(report.unsnapped.len(), 1);
+        assert_eq!((report.unsnapped[0].row, report.unsnapped[0].col), (0, 2));
+
+        let s_matrix = io::LabeledMatrix {
+            matrix,
+            row_labels: Some(vec![String::from("glc"), String::from("g6p")]),
+            col_labels: None,
+        };
+        assert_eq!(
+            snap::format_snap_report(&report, &s_matrix, io::FloatFormat::Decimal, 2),
+            "% snapped 4 coefficients (largest change 0.00); 1 could not be snapped\n\
+            % could not snap 0.30 of glc in R3: nearest 0.50 is 0.20 away"
+        );
+    }
+}
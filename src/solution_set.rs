@@ -0,0 +1,245 @@
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat};
+use crate::solve;
+
+use nalgebra as na;
+
+/// The largest number of constraint combinations vertex enumeration examines.
+pub const MAX_VERTEX_COMBINATIONS: usize = 1_000_000;
+
+/// Constraint violations below this (relative to the size of the bound) are ignored.
+const VERTEX_EPSILON: f64 = 1e-9;
+
+/// This describes the set of reaction vectors that fit an accumulation vector as well as possible:
+/// a particular solution plus any combination of the null space basis, within the bounds.
+pub struct SolutionSet {
+    /// A particular solution; the bounded least squares solution if there are bounds; (n x 1)
+    pub particular: na::DVector<f64>,
+    /// An orthonormal basis of the null space of the matrix; (n x dimension)
+    pub basis: na::DMatrix<f64>,
+    /// The range of steps from the particular solution along each basis vector that stays
+    /// within the bounds; None without bounds
+    pub ranges: Option<Vec<(f64, f64)>>,
+}
+
+impl SolutionSet {
+    /// This returns the dimension of the affine solution space.
+    pub fn dimension(&self) -> usize {
+        self.basis.ncols()
+    }
+}
+
+/// This describes the solution set of Ax = B, optionally restricted to the given bounds.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `bounds` - the reaction bounds, if any
+///
+pub fn solution_set(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    bounds: Option<&Bounds>,
+) -> Result<SolutionSet, SmasError> {
+    let particular = match bounds {
+        Some(bounds) => solve::solve_bounded(acc_vector, s_matrix, bounds, solve::BOUNDED_MAX_ITERATIONS)
+            .and_then(solve::require_converged)?
+            .reactions,
+        None => solve::solve(acc_vector.clone(), s_matrix.clone()),
+    };
+    let basis = solve::null_space(s_matrix);
+    let ranges = bounds.map(|bounds| {
+        basis.column_iter()
+            .map(|direction| bounds.chord(&particular, &direction.into_owned()))
+            .collect()
+    });
+    Ok(SolutionSet {
+        particular,
+        basis,
+        ranges,
+    })
+}
+
+/// This enumerates the vertices of the solution set within the bounds, by solving for every
+/// point where `dimension` bound constraints are active at once and keeping the feasible ones.
+///
+/// The number of combinations grows quickly, so this is meant for small systems; it returns
+/// SmasError::TooLarge if more than `max_combinations` would be examined.
+///
+/// # Arguments
+/// * `set` - the solution set
+/// * `bounds` - the reaction bounds
+/// * `max_combinations` - the largest number of constraint combinations to examine
+///
+pub fn enumerate_vertices(
+    set: &SolutionSet,
+    bounds: &Bounds,
+    max_combinations: usize,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    let k = set.dimension();
+    let n = set.particular.nrows();
+    if k == 0 {
+        // ** the solution set is a single point, which is its own vertex if it is feasible
+        let inside = (0..n).all(|i| {
+            set.particular[i] >= bounds.lower[i] - VERTEX_EPSILON * (1.0 + bounds.lower[i].abs())
+                && set.particular[i] <= bounds.upper[i] + VERTEX_EPSILON * (1.0 + bounds.upper[i].abs())
+        });
+        return Ok(if inside {
+            na::DMatrix::from_column_slice(n, 1, set.particular.as_slice())
+        } else {
+            na::DMatrix::zeros(n, 0)
+        });
+    }
+
+    // ** each bound becomes a constraint a·z <= b on the null space coordinates z
+    let mut rows: Vec<na::DVector<f64>> = vec!();
+    let mut rhs: Vec<f64> = vec!();
+    for i in 0..n {
+        let row = set.basis.row(i).transpose();
+        if row.norm() <= VERTEX_EPSILON {
+            continue;
+        }
+        if bounds.upper[i].is_finite() {
+            rows.push(row.clone());
+            rhs.push(bounds.upper[i] - set.particular[i]);
+        }
+        if bounds.lower[i].is_finite() {
+            rows.push(-row);
+            rhs.push(set.particular[i] - bounds.lower[i]);
+        }
+    }
+
+    let c = rows.len();
+    if k > c {
+        return Ok(na::DMatrix::zeros(n, 0));
+    }
+    let combinations = (0..k).fold(1.0, |acc: f64, i| acc * (c - i) as f64 / (i + 1) as f64);
+    if combinations.round() > max_combinations as f64 {
+        return Err(SmasError::TooLarge {
+            task: "vertex enumeration",
+            size: combinations.round() as usize,
+            limit: max_combinations,
+        });
+    }
+
+    let feasible = |z: &na::DVector<f64>| rows.iter()
+        .zip(rhs.iter())
+        .all(|(row, b)| row.dot(z) <= b + VERTEX_EPSILON * (1.0 + b.abs()));
+
+    let mut vertices: Vec<na::DVector<f64>> = vec!();
+    let mut combination: Vec<usize> = (0..k).collect();
+    loop {
+        let system = na::DMatrix::from_fn(k, k, |r, col| rows[combination[r]][col]);
+        let system_rhs = na::DVector::from_fn(k, |r, _| rhs[combination[r]]);
+        if let Some(z) = system.lu().solve(&system_rhs) {
+            let duplicate = vertices.iter()
+                .any(|v| (v - &z).amax() <= VERTEX_EPSILON * (1.0 + z.amax()));
+            if z.iter().all(|v| v.is_finite()) && feasible(&z) && !duplicate {
+                vertices.push(z);
+            }
+        }
+
+        // ** advance to the next combination in lexicographic order
+        let Some(position) = (0..k).rev().find(|&i| combination[i] < c - k + i) else {
+            break;
+        };
+        combination[position] += 1;
+        for i in position + 1..k {
+            combination[i] = combination[i - 1] + 1;
+        }
+    }
+
+    let points: Vec<na::DVector<f64>> = vertices.iter()
+        .map(|z| &set.particular + &set.basis * z)
+        .collect();
+    if points.is_empty() {
+        Ok(na::DMatrix::zeros(n, 0))
+    } else {
+        Ok(na::DMatrix::from_columns(&points))
+    }
+}
+
+/// This formats a matrix as rows of whitespace delimited values, one row per line.
+fn format_rows(matrix: &na::DMatrix<f64>, float_format: FloatFormat, float_precision: usize) -> String {
+    let mut result_string = String::new();
+    for row in matrix.row_iter() {
+        result_string.push_str("\n ");
+        for val in row.iter() {
+            result_string.push(' ');
+            result_string.push_str(&io::format_float(*val, float_format, float_precision));
+        }
+    }
+    result_string
+}
+
+/// This formats a solution set as a report: its dimension, the particular solution, the null space
+/// basis, the feasible range along each basis vector, and the vertices, if they were enumerated.
+///
+/// # Arguments
+/// * `set` - the solution set
+/// * `vertices` - the vertices of the solution set, if any; (n x vertices)
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_solution_set(
+    set: &SolutionSet,
+    vertices: Option<&na::DMatrix<f64>>,
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let mut result_string = format!("% dimension: {}", set.dimension());
+    result_string.push_str("\n% particular solution (reactions x 1)");
+    let particular = na::DMatrix::from_column_slice(set.particular.nrows(), 1, set.particular.as_slice());
+    result_string.push_str(&format_rows(&particular, float_format, float_precision));
+    result_string.push_str(&format!("\n% null space basis (reactions x {})", set.dimension()));
+    result_string.push_str(&format_rows(&set.basis, float_format, float_precision));
+    if let Some(ranges) = &set.ranges {
+        result_string.push_str("\n% feasible range along each basis vector \t min step \t max step");
+        for (i, (t_min, t_max)) in ranges.iter().enumerate() {
+            result_string.push_str(&format!(
+                "\n  {}\t{}\t{}",
+                i + 1,
+                io::format_float(*t_min, float_format, float_precision),
+                io::format_float(*t_max, float_format, float_precision),
+            ));
+        }
+    }
+    if let Some(vertices) = vertices {
+        result_string.push_str(&format!("\n% vertices (reactions x {})", vertices.ncols()));
+        result_string.push_str(&format_rows(vertices, float_format, float_precision));
+    }
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_solution_set() {
+        // ** x1 + x2 = 1 with both fluxes in [0, 1] is the segment from (1, 0) to (0, 1)
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0]);
+        let bounds = constraints::Bounds {
+            lower: na::DVector::from_element(2, 0.0),
+            upper: na::DVector::from_element(2, 1.0),
+        };
+        let set = solution_set::solution_set(&acc_vector, &s_matrix, Some(&bounds)).unwrap();
+        assert_eq!(set.dimension(), 1);
+        assert!(util::epsilon_eq(set.particular[0] + set.particular[1], 1.0, 1e-12));
+        let (t_min, t_max) = set.ranges.as_ref().unwrap()[0];
+        assert!(util::epsilon_eq(t_max - t_min, 2.0_f64.sqrt(), 1e-9));
+
+        let vertices = solution_set::enumerate_vertices(&set, &bounds, solution_set::MAX_VERTEX_COMBINATIONS).unwrap();
+        assert_eq!(vertices.ncols(), 2);
+        let mut firsts: Vec<f64> = vertices.row(0).iter().copied().collect();
+        firsts.sort_by(f64::total_cmp);
+        assert!(util::epsilon_eq(firsts[0], 0.0, 1e-9));
+        assert!(util::epsilon_eq(firsts[1], 1.0, 1e-9));
+
+        let too_large = solution_set::enumerate_vertices(&set, &bounds, 1);
+        assert!(matches!(too_large, Err(error::SmasError::TooLarge { .. })));
+    }
+}
@@ -1,22 +1,1257 @@
+use crate::cancel::CancellationToken;
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::io;
+use crate::lp;
+use crate::util;
+
+use std::time::{Duration, Instant};
+
 use nalgebra as na;
+
+pub mod auto;
+pub mod fba;
+pub mod fva;
+pub mod integerize;
+
 pub const SVD_EPSILON: f64 = 1e-9;
 
+/// This is the result of a solve, along with diagnostics describing how it was computed.
+pub struct Solution {
+    /// The solution vector x, i.e. the reaction vector
+    pub reactions: na::DVector<f64>,
+    /// The name of the method that computed the solution
+    pub method: &'static str,
+    /// The number of iterations an iterative method ran for; None for direct methods
+    pub iterations: Option<usize>,
+    /// Whether the method converged; always true for direct methods
+    pub converged: bool,
+    /// The two-norm of the residual, ||Ax - B||
+    pub residual_norm: f64,
+    /// The dual values of the solution; only linear programming methods produce these
+    pub duals: Option<Duals>,
+}
+
+/// These are the dual values of a linear programming solution.
+pub struct Duals {
+    /// The shadow price of each metabolite, i.e. the dual value of each row of Ax = B; (m x 1)
+    pub shadow_prices: na::DVector<f64>,
+    /// The reduced cost of each reaction, i.e. the dual value of each bound on x; (n x 1)
+    pub reduced_costs: na::DVector<f64>,
+}
+
+impl Duals {
+    /// This formats the dual values as two tab delimited tables: shadow prices per metabolite,
+    /// then reduced costs per reaction.
+    ///
+    /// # Arguments
+    /// * `float_format` - how to format the floats: scientific or decimal
+    /// * `float_precision` - how many positions the floats have past the decimal point
+    ///
+    pub fn format(&self, float_format: io::FloatFormat, float_precision: usize) -> String {
+        let mut result_string = String::from("% metabolite \t shadow price");
+        for (i, val) in self.shadow_prices.iter().enumerate() {
+            result_string.push_str(&format!(
+                "\n  {}\t{}", i + 1, io::format_float(*val, float_format, float_precision)
+            ));
+        }
+        result_string.push_str("\n% reaction \t reduced cost");
+        for (i, val) in self.reduced_costs.iter().enumerate() {
+            result_string.push_str(&format!(
+                "\n  {}\t{}", i + 1, io::format_float(*val, float_format, float_precision)
+            ));
+        }
+        result_string
+    }
+}
+
+impl Solution {
+    /// This formats the solver diagnostics as comment lines, one per diagnostic.
+    pub fn format_report(&self) -> String {
+        self.format_report_with_residual("residual norm", self.residual_norm)
+    }
+
+    /// This formats the solver diagnostics like `format_report`, with the residual norm
+    /// recomputed from the system given rather than taken from the solver. This reports the fit
+    /// to the accumulation vector when the solver was given extra rows, e.g. flux measurements.
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    ///
+    pub fn format_report_against(&self, s_matrix: &na::DMatrix<f64>, acc_vector: &na::DVector<f64>) -> String {
+        self.format_report_with_residual("residual norm", compute_residual_norm(s_matrix, &self.reactions, acc_vector))
+    }
+
+    /// This formats the solver diagnostics like `format_report_against`, with the residual
+    /// measured in the chosen norm. The two-norm report is the same as `format_report_against`.
+    ///
+    /// # Arguments
+    /// * `norm` - the norm to measure the residual with
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    ///
+    pub fn format_report_with_norm(&self, norm: ResidualNorm, s_matrix: &na::DMatrix<f64>, acc_vector: &na::DVector<f64>) -> String {
+        match norm {
+            ResidualNorm::L2 => self.format_report_against(s_matrix, acc_vector),
+            norm => self.format_report_with_residual(
+                &format!("residual norm ({})", norm.name()),
+                norm.compute(s_matrix, &self.reactions, acc_vector),
+            ),
+        }
+    }
+
+    /// This formats the solver diagnostics with the residual line given.
+    fn format_report_with_residual(&self, label: &str, residual: f64) -> String {
+        let iterations = match self.iterations {
+            Some(iterations) => iterations.to_string(),
+            None => String::from("n/a (direct method)"),
+        };
+        format!(
+            "% method: {}\n% iterations: {}\n% converged: {}\n% {label}: {residual:e}",
+            self.method, iterations, self.converged
+        )
+    }
+}
+
 /// This function solves the linear equation Ax = B, where A is a stoichiometric matrix and B is an
 /// accumulation vector. The return value is the solution vector x.
 ///
-/// The solution is computed using the Moore-Penrose inverse (i.e. pseudoinverse) of A.
+/// The solution is computed using the Moore-Penrose inverse (i.e. pseudoinverse) of A. The
+/// pseudoinverse of an empty matrix is the (n x m) zero matrix, so a system without metabolites
+/// gives a zero reaction vector and a system without reactions gives an empty one.
 ///
 /// # Arguments
 /// * `acc_vector` - the accumulation vector, B; (m x n)
 /// * `s_matrix` - the stoichiometric matrix, A: (m x 1)
 ///
 pub fn solve(acc_vector: na::DVector<f64>, s_matrix: na::DMatrix<f64>) -> na::DVector<f64> {
-    let s_pseudo_inverse = s_matrix.pseudo_inverse(SVD_EPSILON)
-        .expect("failed to compute pseudo-inverse of stoichiometric matrix");
+    let _span = tracing::info_span!("solve", method = %"pinv").entered();
+    let s_pseudo_inverse = pseudo_inverse(s_matrix);
 
     s_pseudo_inverse * acc_vector
 }
 
+/// This computes the pseudoinverse of a matrix, which is the transposed zero matrix if the
+/// matrix is empty (the SVD of an empty matrix is undefined).
+fn pseudo_inverse(s_matrix: na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let _span = tracing::info_span!("factorize", method = %"svd", rows = s_matrix.nrows(), cols = s_matrix.ncols()).entered();
+    if s_matrix.is_empty() {
+        return na::DMatrix::zeros(s_matrix.ncols(), s_matrix.nrows());
+    }
+    s_matrix.pseudo_inverse(SVD_EPSILON)
+        .expect("failed to compute pseudo-inverse of stoichiometric matrix")
+}
+
+/// This checks that the accumulation vector has one entry per row of the stoichiometric matrix.
+fn check_shape(acc_vector: &na::DVector<f64>, s_matrix: &na::DMatrix<f64>) -> Result<(), SmasError> {
+    if acc_vector.nrows() != s_matrix.nrows() {
+        return Err(SmasError::Shape {
+            expected: s_matrix.nrows(),
+            found: acc_vector.nrows(),
+        });
+    }
+    Ok(())
+}
+
+/// This solves Ax = B with the pseudoinverse, like `solve`, but returns a Solution with
+/// diagnostics rather than only the solution vector. Returns SmasError::Shape if B does not have
+/// one entry per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn solve_pinv(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+) -> Result<Solution, SmasError> {
+    check_shape(acc_vector, s_matrix)?;
+    let reactions = solve(acc_vector.clone(), s_matrix.clone());
+    let residual_norm = compute_residual_norm(s_matrix, &reactions, acc_vector);
+
+    Ok(Solution {
+        reactions,
+        method: "pinv",
+        iterations: None,
+        converged: true,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// This is a pseudoinverse solver for a fixed stoichiometric matrix. The pseudoinverse is computed
+/// once on construction and never modified afterwards, so a Solver is Send + Sync and one instance
+/// can serve concurrent `solve` calls from many threads, e.g. behind an Arc in a service.
+pub struct Solver {
+    /// The stoichiometric matrix, A: (m x n)
+    s_matrix: na::DMatrix<f64>,
+    /// The pseudoinverse of the stoichiometric matrix; (n x m)
+    pseudo_inverse: na::DMatrix<f64>,
+}
+
+impl Solver {
+    /// This creates a Solver, computing the pseudoinverse of the stoichiometric matrix.
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    ///
+    pub fn new(s_matrix: na::DMatrix<f64>) -> Self {
+        let pseudo_inverse = jacobian(&s_matrix);
+        Solver {
+            s_matrix,
+            pseudo_inverse,
+        }
+    }
+
+    /// This returns the stoichiometric matrix the Solver was created with.
+    pub fn s_matrix(&self) -> &na::DMatrix<f64> {
+        &self.s_matrix
+    }
+
+    /// This solves Ax = B with the precomputed pseudoinverse.
+    ///
+    /// # Arguments
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    ///
+    pub fn solve(&self, acc_vector: &na::DVector<f64>) -> Result<Solution, SmasError> {
+        let _span = tracing::info_span!("solve", method = %"pinv", precomputed = true).entered();
+        check_shape(acc_vector, &self.s_matrix)?;
+        let reactions = &self.pseudo_inverse * acc_vector;
+        let residual_norm = compute_residual_norm(&self.s_matrix, &reactions, acc_vector);
+
+        Ok(Solution {
+            reactions,
+            method: "pinv",
+            iterations: None,
+            converged: true,
+            residual_norm,
+            duals: None,
+        })
+    }
+}
+
+/// This returns a Solver for a stoichiometric matrix, reusing the one in `cached` if it was
+/// created for an equal matrix and replacing it otherwise, so that solving many accumulation
+/// vectors, e.g. a batch or the edits of a session, computes the pseudoinverse once per matrix.
+///
+/// # Arguments
+/// * `cached` - the Solver of the previous solve, if any
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn cached_solver<'a>(cached: &'a mut Option<Solver>, s_matrix: &na::DMatrix<f64>) -> &'a Solver {
+    if cached.as_ref().is_none_or(|solver| solver.s_matrix() != s_matrix) {
+        *cached = Some(Solver::new(s_matrix.clone()));
+    }
+    cached.as_ref().unwrap()
+}
+
+/// These are the options an application embedding smas, e.g. a server or a GUI, uses to abandon
+/// an iterative solve that runs too long. The default never abandons a solve.
+#[derive(Clone, Default)]
+pub struct SolveOptions {
+    /// How long the solve may run before it is abandoned; None for no limit
+    pub timeout: Option<Duration>,
+    /// A token that abandons the solve when it is cancelled; None if it cannot be cancelled
+    pub cancel_token: Option<CancellationToken>,
+}
+
+impl SolveOptions {
+    /// This starts the clock of the timeout. The clock is only read if there is a timeout.
+    pub fn start(&self) -> Deadline<'_> {
+        Deadline {
+            options: self,
+            at: self.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+}
+
+/// This is a started SolveOptions, which iterative methods check between iterations.
+pub struct Deadline<'a> {
+    /// The options that were started
+    options: &'a SolveOptions,
+    /// When the timeout runs out, if there is one
+    at: Option<Instant>,
+}
+
+impl Deadline<'_> {
+    /// This returns SmasError::Cancelled if the cancellation token has been cancelled, or
+    /// SmasError::TimedOut if the timeout has run out.
+    ///
+    /// # Arguments
+    /// * `method` - the name of the method, for the error
+    /// * `iterations` - the number of iterations run so far, for the error
+    ///
+    pub fn check(&self, method: &'static str, iterations: usize) -> Result<(), SmasError> {
+        if self.options.cancel_token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return Err(SmasError::Cancelled { method, iterations });
+        }
+        if self.at.is_some_and(|at| Instant::now() >= at) {
+            return Err(SmasError::TimedOut { method, iterations });
+        }
+        Ok(())
+    }
+}
+
+/// The default maximum number of active set changes for the bounded least squares solver.
+pub const BOUNDED_MAX_ITERATIONS: usize = 10_000;
+
+/// This describes where a variable sits in the bounded least squares active set method.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum VarState {
+    Free,
+    AtLower,
+    AtUpper,
+}
+
+/// This solves min ||Ax - B|| subject to lower <= x <= upper with an active set method that
+/// generalizes the Lawson-Hanson NNLS algorithm to arbitrary (possibly infinite) bounds.
+///
+/// Variables are either free or held at one of their bounds. Each iteration frees the held
+/// variable whose gradient most strongly points into the feasible region, then solves the
+/// unconstrained problem over the free variables (with the pseudoinverse, so rank deficient
+/// subproblems get the minimum norm solution), stepping back to the boundary whenever the
+/// unconstrained solution leaves the feasible region.
+///
+/// Returns SmasError::NotConverged if `max_iterations` active set changes are not enough, and
+/// SmasError::Shape if B or the bounds do not match the shape of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `bounds` - the lower and upper bound of each reaction
+/// * `max_iterations` - the maximum number of active set changes
+///
+pub fn solve_bounded(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    bounds: &Bounds,
+    max_iterations: usize,
+) -> Result<Solution, SmasError> {
+    solve_bounded_with_options(acc_vector, s_matrix, bounds, max_iterations, &SolveOptions::default())
+}
+
+/// This solves min ||Ax - B|| within bounds like `solve_bounded`, checking the cancellation
+/// token and timeout of the options between active set changes.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `bounds` - the lower and upper bound of each reaction
+/// * `max_iterations` - the maximum number of active set changes
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_bounded_with_options(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    bounds: &Bounds,
+    max_iterations: usize,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let deadline = options.start();
+    let _span = tracing::info_span!("solve", method = %"bounded").entered();
+    check_shape(acc_vector, s_matrix)?;
+    let n = s_matrix.ncols();
+    if bounds.lower.nrows() != n || bounds.upper.nrows() != n {
+        return Err(SmasError::Shape {
+            expected: n,
+            found: bounds.lower.nrows().min(bounds.upper.nrows()),
+        });
+    }
+    let mut x = na::DVector::zeros(n);
+    let mut state = vec![VarState::Free; n];
+    for i in 0..n {
+        if bounds.lower[i] > bounds.upper[i] {
+            return Err(SmasError::Infeasible);
+        }
+        if bounds.lower[i].is_finite() {
+            state[i] = VarState::AtLower;
+            x[i] = bounds.lower[i];
+        } else if bounds.upper[i].is_finite() {
+            state[i] = VarState::AtUpper;
+            x[i] = bounds.upper[i];
+        }
+    }
+
+    let gradient_tolerance = 1e-10 * (s_matrix.transpose() * acc_vector).amax().max(1.0);
+    let bound_tolerance = |bound: f64| 1e-12 * (1.0 + bound.abs());
+    let mut iterations = 0;
+    let mut converged = false;
+
+    'outer: while iterations < max_iterations {
+        // ** solve over the free variables, stepping back whenever a bound is crossed
+        loop {
+            deadline.check("bounded", iterations)?;
+            let free: Vec<usize> = (0..n).filter(|i| state[*i] == VarState::Free).collect();
+            if free.is_empty() {
+                break;
+            }
+
+            let mut x_held = x.clone();
+            for i in &free {
+                x_held[*i] = 0.0;
+            }
+            let rhs = acc_vector - s_matrix * x_held;
+            let z = solve(rhs, s_matrix.select_columns(&free));
+
+            let mut alpha: f64 = 1.0;
+            for (k, i) in free.iter().enumerate() {
+                let (lower, upper) = (bounds.lower[*i], bounds.upper[*i]);
+                if z[k] < lower - bound_tolerance(lower) {
+                    alpha = alpha.min((x[*i] - lower) / (x[*i] - z[k]));
+                } else if z[k] > upper + bound_tolerance(upper) {
+                    alpha = alpha.min((upper - x[*i]) / (z[k] - x[*i]));
+                }
+            }
+
+            for (k, i) in free.iter().enumerate() {
+                x[*i] += alpha * (z[k] - x[*i]);
+            }
+            if alpha >= 1.0 {
+                break;
+            }
+
+            for i in free {
+                let (lower, upper) = (bounds.lower[i], bounds.upper[i]);
+                if x[i] <= lower + bound_tolerance(lower) {
+                    state[i] = VarState::AtLower;
+                    x[i] = lower;
+                } else if x[i] >= upper - bound_tolerance(upper) {
+                    state[i] = VarState::AtUpper;
+                    x[i] = upper;
+                }
+            }
+            iterations += 1;
+            if iterations >= max_iterations {
+                break 'outer;
+            }
+        }
+
+        // ** free the held variable whose gradient most strongly points into the feasible region
+        let gradient = s_matrix.transpose() * (acc_vector - s_matrix * &x);
+        let mut best: Option<(usize, f64)> = None;
+        for i in 0..n {
+            let improvement = match state[i] {
+                VarState::AtLower if bounds.upper[i] > bounds.lower[i] => gradient[i],
+                VarState::AtUpper if bounds.upper[i] > bounds.lower[i] => -gradient[i],
+                _ => continue,
+            };
+            if improvement > gradient_tolerance && best.is_none_or(|(_, b)| improvement > b) {
+                best = Some((i, improvement));
+            }
+        }
+        match best {
+            Some((i, _)) => state[i] = VarState::Free,
+            None => {
+                converged = true;
+                break;
+            }
+        }
+        iterations += 1;
+    }
+
+    let residual_norm = compute_residual_norm(s_matrix, &x, acc_vector);
+    require_converged(Solution {
+        reactions: x,
+        method: "bounded",
+        iterations: Some(iterations),
+        converged,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// This solves min ||Ax - B|| subject to x >= 0, i.e. non-negative least squares (NNLS), for
+/// systems where every reaction is irreversible and a negative flux is meaningless. This is the
+/// Lawson-Hanson algorithm, run as `solve_bounded` with a lower bound of 0 and no upper bound.
+///
+/// Returns SmasError::NotConverged if `max_iterations` active set changes are not enough, and
+/// SmasError::Shape if B does not have one entry per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `max_iterations` - the maximum number of active set changes
+///
+pub fn solve_nnls(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    max_iterations: usize,
+) -> Result<Solution, SmasError> {
+    solve_nnls_with_options(acc_vector, s_matrix, max_iterations, &SolveOptions::default())
+}
+
+/// This solves min ||Ax - B|| subject to x >= 0 like `solve_nnls`, checking the cancellation
+/// token and timeout of the options between active set changes.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `max_iterations` - the maximum number of active set changes
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_nnls_with_options(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    max_iterations: usize,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let mut bounds = Bounds::unbounded(s_matrix.ncols());
+    bounds.lower.fill(0.0);
+    let mut solution = solve_bounded_with_options(acc_vector, s_matrix, &bounds, max_iterations, options)
+        .map_err(|error| match error {
+            SmasError::NotConverged { iterations, residual_norm, .. } => {
+                SmasError::NotConverged { method: "nnls", iterations, residual_norm }
+            }
+            SmasError::Cancelled { iterations, .. } => SmasError::Cancelled { method: "nnls", iterations },
+            SmasError::TimedOut { iterations, .. } => SmasError::TimedOut { method: "nnls", iterations },
+            error => error,
+        })?;
+    solution.method = "nnls";
+    Ok(solution)
+}
+
+/// This is the norm the residual, Ax - B, is measured with: in the report, and as the objective
+/// of `solve_residual_norm`. The two-norm weighs large residuals most, the one-norm tolerates a
+/// few outlying metabolites, and the inf-norm bounds the worst metabolite.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ResidualNorm {
+    /// The sum of absolute values, ||Ax - B||₁
+    L1,
+    /// The square root of the sum of squares, ||Ax - B||₂
+    #[default]
+    L2,
+    /// The largest absolute value, ||Ax - B||∞
+    LInf,
+}
+
+impl ResidualNorm {
+    /// This returns the name of the norm, as given to --residual-norm.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResidualNorm::L1 => "l1",
+            ResidualNorm::L2 => "l2",
+            ResidualNorm::LInf => "linf",
+        }
+    }
+
+    /// This computes the norm of the residual, Ax - B. The two-norm is the same as
+    /// `Solution::residual_norm`.
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+    /// * `reactions` - the solution vector, x; (n x 1)
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    ///
+    pub fn compute(&self, s_matrix: &na::DMatrix<f64>, reactions: &na::DVector<f64>, acc_vector: &na::DVector<f64>) -> f64 {
+        let residual = || residuals(s_matrix, reactions, acc_vector).into_iter().map(|val| val.abs()).collect::<Vec<f64>>();
+        match self {
+            ResidualNorm::L1 => util::sum_with(&residual(), util::Summation::default()),
+            ResidualNorm::L2 => compute_residual_norm(s_matrix, reactions, acc_vector),
+            ResidualNorm::LInf => residual().into_iter().fold(0.0, f64::max),
+        }
+    }
+}
+
+/// This solves min ||Ax - B|| in the chosen norm, within bounds if any are given. The two-norm is
+/// solved with the pseudoinverse, or with `solve_bounded` within bounds. The one-norm and the
+/// inf-norm are solved as linear programs, with the residual split into non-negative parts:
+///
+/// l1: minimize Σ (p_i + q_i) subject to Ax - p + q = B, p, q >= 0
+///
+/// linf: minimize t subject to Ax + u - t = B and Ax - w + t = B, u, w, t >= 0
+///
+/// Their solutions need not be unique, and are not the minimum norm solution. Solution::residual_norm
+/// is the two-norm for every norm; use `ResidualNorm::compute` for the others. Returns
+/// SmasError::Shape if B or the bounds do not match the shape of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `norm` - the norm of the residual to minimize
+/// * `bounds` - the lower and upper bound of each reaction, if any
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_residual_norm(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    norm: ResidualNorm,
+    bounds: Option<&Bounds>,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    check_shape(acc_vector, s_matrix)?;
+    let (m, n) = s_matrix.shape();
+    if let Some(bounds) = bounds.filter(|bounds| bounds.lower.nrows() != n || bounds.upper.nrows() != n) {
+        return Err(SmasError::Shape {
+            expected: n,
+            found: bounds.lower.nrows(),
+        });
+    }
+    match (norm, bounds) {
+        (ResidualNorm::L2, Some(bounds)) => {
+            return solve_bounded_with_options(acc_vector, s_matrix, bounds, BOUNDED_MAX_ITERATIONS, options);
+        }
+        (ResidualNorm::L2, None) => return solve_pinv(acc_vector, s_matrix),
+        _ => {}
+    }
+    let _span = tracing::info_span!("solve", method = %norm.name()).entered();
+
+    // ** the columns are x, then the two non-negative parts of the residual, then t for linf
+    let n_vars = match norm {
+        ResidualNorm::LInf => n + 2 * m + 1,
+        _ => n + 2 * m,
+    };
+    let n_rows = match norm {
+        ResidualNorm::LInf => 2 * m,
+        _ => m,
+    };
+    let mut eq_matrix = na::DMatrix::zeros(n_rows, n_vars);
+    let mut eq_rhs = na::DVector::zeros(n_rows);
+    let mut objective = na::DVector::zeros(n_vars);
+    for i in 0..m {
+        eq_matrix.slice_mut((i, 0), (1, n)).copy_from(&s_matrix.row(i));
+        eq_rhs[i] = acc_vector[i];
+        match norm {
+            ResidualNorm::LInf => {
+                eq_matrix.slice_mut((m + i, 0), (1, n)).copy_from(&s_matrix.row(i));
+                eq_rhs[m + i] = acc_vector[i];
+                eq_matrix[(i, n + i)] = 1.0;
+                eq_matrix[(i, n_vars - 1)] = -1.0;
+                eq_matrix[(m + i, n + m + i)] = -1.0;
+                eq_matrix[(m + i, n_vars - 1)] = 1.0;
+            }
+            _ => {
+                eq_matrix[(i, n + i)] = -1.0;
+                eq_matrix[(i, n + m + i)] = 1.0;
+                objective[n + i] = 1.0;
+                objective[n + m + i] = 1.0;
+            }
+        }
+    }
+    if norm == ResidualNorm::LInf {
+        objective[n_vars - 1] = 1.0;
+    }
+    let mut lower = na::DVector::zeros(n_vars);
+    let mut upper = na::DVector::from_element(n_vars, f64::INFINITY);
+    if let Some(bounds) = bounds {
+        lower.rows_mut(0, n).copy_from(&bounds.lower);
+        upper.rows_mut(0, n).copy_from(&bounds.upper);
+    } else {
+        lower.rows_mut(0, n).fill(f64::NEG_INFINITY);
+    }
+
+    let program = lp::LinearProgram {
+        objective,
+        eq_matrix,
+        eq_rhs,
+        lower,
+        upper,
+    };
+    let solution = lp::solve_lp_with_options(&program, lp::LP_MAX_ITERATIONS, options)?;
+    let reactions = solution.x.rows(0, n).into_owned();
+    let residual_norm = compute_residual_norm(s_matrix, &reactions, acc_vector);
+    Ok(Solution {
+        reactions,
+        method: norm.name(),
+        iterations: Some(solution.iterations),
+        converged: true,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// This solves min ||Ax - B|| with a QR decomposition, A = QR, as x = R⁻¹QᵀB. This is faster than
+/// the pseudoinverse and as accurate, but it needs A to have full column rank, i.e. at least as
+/// many metabolites as reactions and no reaction that is a combination of others; the solution is
+/// then unique, and the same as the pseudoinverse's. Returns SmasError::RankDeficient otherwise,
+/// judging R's diagonal against SVD_EPSILON like the pseudoinverse, and SmasError::Shape if B
+/// does not have one entry per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn solve_qr(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+) -> Result<Solution, SmasError> {
+    let _span = tracing::info_span!("solve", method = %"qr").entered();
+    check_shape(acc_vector, s_matrix)?;
+    let (m, n) = s_matrix.shape();
+    let rank_deficient = || SmasError::RankDeficient {
+        method: "qr",
+        rank: rank(s_matrix),
+        columns: n,
+    };
+    if m < n {
+        return Err(rank_deficient());
+    }
+
+    let qr = s_matrix.clone().qr();
+    let r = qr.r();
+    let largest = r.diagonal().amax();
+    if r.diagonal().iter().any(|val| val.abs() <= SVD_EPSILON * largest) {
+        return Err(rank_deficient());
+    }
+    let reactions = r.solve_upper_triangular(&qr.q().tr_mul(acc_vector))
+        .ok_or_else(rank_deficient)?;
+    let residual_norm = compute_residual_norm(s_matrix, &reactions, acc_vector);
+
+    Ok(Solution {
+        reactions,
+        method: "qr",
+        iterations: None,
+        converged: true,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// The relative change in the fluxes below which `solve_lasso` stops sweeping.
+pub const LASSO_TOLERANCE: f64 = 1e-10;
+
+/// This solves the lasso, min ½||Ax - B||² + lambda ||x||₁, for the sparsest set of reactions that
+/// explains the accumulation vector: the larger lambda, the more fluxes are exactly zero, at the
+/// cost of a larger residual. With lambda = 0 this is least squares, and with lambda at least
+/// the largest |A'B| every flux is zero. The columns of A are not rescaled, so reactions with
+/// large stoichiometric coefficients are penalized less per unit of residual.
+///
+/// This is cyclic coordinate descent: each sweep sets every flux in turn to the soft-thresholded
+/// minimizer along its coordinate, which is exactly zero when its correlation with the residual
+/// is within lambda. Sweeps stop when no flux changes by more than LASSO_TOLERANCE relative to the
+/// largest flux. Returns SmasError::NotConverged if `max_iterations` sweeps are not enough, and
+/// SmasError::Shape if B does not have one entry per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `lambda` - the weight of the one-norm penalty, at least 0
+/// * `max_iterations` - the maximum number of sweeps
+///
+pub fn solve_lasso(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    lambda: f64,
+    max_iterations: usize,
+) -> Result<Solution, SmasError> {
+    solve_lasso_with_options(acc_vector, s_matrix, lambda, max_iterations, &SolveOptions::default())
+}
+
+/// This solves the lasso like `solve_lasso`, checking the cancellation token and timeout of the
+/// options between sweeps.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `lambda` - the weight of the one-norm penalty, at least 0
+/// * `max_iterations` - the maximum number of sweeps
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_lasso_with_options(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    lambda: f64,
+    max_iterations: usize,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let deadline = options.start();
+    let _span = tracing::info_span!("solve", method = %"lasso").entered();
+    check_shape(acc_vector, s_matrix)?;
+    let n = s_matrix.ncols();
+    let column_norms: Vec<f64> = s_matrix.column_iter().map(|column| column.norm_squared()).collect();
+    let mut x: na::DVector<f64> = na::DVector::zeros(n);
+    // ** the residual, B - Ax, is kept up to date as each flux changes
+    let mut residual = acc_vector.clone();
+    let mut iterations = 0;
+    let mut converged = false;
+
+    while iterations < max_iterations {
+        deadline.check("lasso", iterations)?;
+        let mut largest_change: f64 = 0.0;
+        for (j, column_norm) in column_norms.iter().enumerate() {
+            // ** a reaction that changes no metabolite explains nothing, so it stays at 0
+            if *column_norm == 0.0 {
+                continue;
+            }
+            let column = s_matrix.column(j);
+            let correlation = column.dot(&residual) + column_norm * x[j];
+            let updated = correlation.signum() * (correlation.abs() - lambda).max(0.0) / column_norm;
+            let change = updated - x[j];
+            if change != 0.0 {
+                residual.axpy(-change, &column, 1.0);
+                x[j] = updated;
+                largest_change = largest_change.max(change.abs());
+            }
+        }
+        iterations += 1;
+        if largest_change <= LASSO_TOLERANCE * x.amax().max(1.0) {
+            converged = true;
+            break;
+        }
+    }
+
+    let residual_norm = compute_residual_norm(s_matrix, &x, acc_vector);
+    require_converged(Solution {
+        reactions: x,
+        method: "lasso",
+        iterations: Some(iterations),
+        converged,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// The relative change in the fluxes below which `solve_huber` stops reweighting.
+pub const HUBER_TOLERANCE: f64 = 1e-10;
+
+/// This solves min Σ huber(Ax - B) with the Huber loss, which is quadratic for residuals within
+/// `delta` and linear beyond, so that a few outlying metabolites do not dominate the fit the way
+/// they do least squares. Residuals within delta are fitted as by least squares; delta should be
+/// about the size of the measurement noise of the accumulation vector.
+///
+/// This is iteratively reweighted least squares: starting from the least squares solution, each
+/// iteration weights every metabolite by min(1, delta / |residual|) and solves the weighted least
+/// squares problem with the pseudoinverse, until no flux changes by more than HUBER_TOLERANCE
+/// relative to the largest flux. Returns SmasError::NotConverged if `max_iterations` reweightings
+/// are not enough, and SmasError::Shape if B does not have one entry per row of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `delta` - the residual at which the loss turns from quadratic to linear, above 0
+/// * `max_iterations` - the maximum number of reweightings
+///
+pub fn solve_huber(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    delta: f64,
+    max_iterations: usize,
+) -> Result<Solution, SmasError> {
+    solve_huber_with_options(acc_vector, s_matrix, delta, max_iterations, &SolveOptions::default())
+}
+
+/// This solves min Σ huber(Ax - B) like `solve_huber`, checking the cancellation token and timeout
+/// of the options between reweightings.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `delta` - the residual at which the loss turns from quadratic to linear, above 0
+/// * `max_iterations` - the maximum number of reweightings
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_huber_with_options(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    delta: f64,
+    max_iterations: usize,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let deadline = options.start();
+    let _span = tracing::info_span!("solve", method = %"huber").entered();
+    check_shape(acc_vector, s_matrix)?;
+    let mut x = solve(acc_vector.clone(), s_matrix.clone());
+    let mut iterations = 0;
+    let mut converged = false;
+
+    while iterations < max_iterations {
+        deadline.check("huber", iterations)?;
+        // ** rows are scaled by the square root of their weight, so their squares are weighted
+        let residual = s_matrix * &x - acc_vector;
+        let row_scales = residual.map(|r| if r.abs() <= delta { 1.0 } else { (delta / r.abs()).sqrt() });
+        let mut weighted_matrix = s_matrix.clone();
+        for (mut row, scale) in weighted_matrix.row_iter_mut().zip(row_scales.iter()) {
+            row *= *scale;
+        }
+        let updated = solve(acc_vector.component_mul(&row_scales), weighted_matrix);
+        let change = (&updated - &x).amax();
+        x = updated;
+        iterations += 1;
+        if change <= HUBER_TOLERANCE * x.amax().max(1.0) {
+            converged = true;
+            break;
+        }
+    }
+
+    let residual_norm = compute_residual_norm(s_matrix, &x, acc_vector);
+    require_converged(Solution {
+        reactions: x,
+        method: "huber",
+        iterations: Some(iterations),
+        converged,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// This solves several accumulation vectors (one per experimental condition) jointly, with a
+/// quadratic penalty that encourages the reaction vectors of different conditions to be similar:
+///
+/// minimize Σ_k ||Ax_k - B_k||² + fuse² Σ_{k<l} ||x_k - x_l||²
+///
+/// The stacked least squares problem is solved with the pseudoinverse. With `fuse` = 0 this is
+/// the same as solving each condition separately. Unlike the one-norm penalty of a fused lasso,
+/// the quadratic penalty shrinks differences but does not set them to exactly zero. The result
+/// has one column per condition.
+///
+/// # Arguments
+/// * `acc_vectors` - the accumulation vector of each condition, B_k; (m x 1) each
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `fuse` - the strength of the penalty, lambda
+///
+pub fn solve_joint(
+    acc_vectors: &[na::DVector<f64>],
+    s_matrix: &na::DMatrix<f64>,
+    fuse: f64,
+) -> na::DMatrix<f64> {
+    let _span = tracing::info_span!("solve", method = %"joint", conditions = acc_vectors.len()).entered();
+    let (m, n) = s_matrix.shape();
+    let k = acc_vectors.len();
+    let n_pairs = k * k.saturating_sub(1) / 2;
+
+    let mut system_matrix = na::DMatrix::zeros(k * m + n_pairs * n, k * n);
+    let mut system_vector = na::DVector::zeros(k * m + n_pairs * n);
+    for (c, acc_vector) in acc_vectors.iter().enumerate() {
+        system_matrix.slice_mut((c * m, c * n), (m, n)).copy_from(s_matrix);
+        system_vector.rows_mut(c * m, m).copy_from(acc_vector);
+    }
+
+    let mut row = k * m;
+    for c in 0..k {
+        for d in (c + 1)..k {
+            for i in 0..n {
+                system_matrix[(row + i, c * n + i)] = fuse;
+                system_matrix[(row + i, d * n + i)] = -fuse;
+            }
+            row += n;
+        }
+    }
+
+    let stacked = solve(system_vector, system_matrix);
+    na::DMatrix::from_column_slice(n, k, stacked.as_slice())
+}
+
+/// This computes the Jacobian of the pseudoinverse solution with respect to the accumulation
+/// vector, dx/dB. Since x = A⁺B, the Jacobian is the pseudoinverse A⁺ itself; (n x m).
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn jacobian(s_matrix: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    pseudo_inverse(s_matrix.clone())
+}
+
+/// This computes the numerical rank of a matrix, counting singular values above SVD_EPSILON.
+/// An empty matrix has rank 0.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn rank(s_matrix: &na::DMatrix<f64>) -> usize {
+    if s_matrix.is_empty() {
+        return 0;
+    }
+    s_matrix.rank(SVD_EPSILON)
+}
+
+/// This computes an orthonormal basis of the null space of a matrix, i.e. the directions in which
+/// a reaction vector can move without changing the accumulation it produces.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn null_space(s_matrix: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let n = s_matrix.ncols();
+    if n == 0 {
+        return na::DMatrix::zeros(0, 0);
+    }
+    // ** I - A⁺A projects onto the null space, so its eigenvectors with eigenvalue 1 span it
+    let projector = na::DMatrix::identity(n, n) - jacobian(s_matrix) * s_matrix;
+    let eigen = projector.symmetric_eigen();
+    let columns: Vec<na::DVector<f64>> = eigen.eigenvalues.iter()
+        .zip(eigen.eigenvectors.column_iter())
+        .filter(|(value, _)| **value > 0.5)
+        .map(|(_, vector)| vector.into_owned())
+        .collect();
+    if columns.is_empty() {
+        na::DMatrix::zeros(n, 0)
+    } else {
+        na::DMatrix::from_columns(&columns)
+    }
+}
+
+/// This computes an orthonormal basis of the left null space of a matrix, i.e. the weightings c of
+/// the metabolites with cᵀA = 0, whose weighted total no reaction can change.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn left_null_space(s_matrix: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    null_space(&s_matrix.transpose())
+}
+
+/// This flags the exchange reactions of a stoichiometric matrix: reactions whose nonzero
+/// coefficients all have the same sign, i.e. that only produce or only consume metabolites.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+///
+pub fn exchange_reactions(s_matrix: &na::DMatrix<f64>) -> Vec<bool> {
+    s_matrix.column_iter()
+        .map(|column| column.iter().all(|v| *v >= 0.0) || column.iter().all(|v| *v <= 0.0))
+        .collect()
+}
+
+/// This removes flux around internal loops from a solution, in the style of CycleFreeFlux.
+///
+/// The returned vector minimizes the total absolute flux through internal (non-exchange)
+/// reactions subject to: the same accumulation as the input (Ax = Ax0), exchange fluxes fixed to
+/// their input values, and every internal flux keeping its direction without growing in
+/// magnitude.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the solution to post-process, x0; (n x 1)
+/// * `exchange` - which reactions are exchange reactions, see `exchange_reactions`
+///
+pub fn loopless(
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    exchange: &[bool],
+) -> Result<na::DVector<f64>, SmasError> {
+    let n = reactions.nrows();
+    let mut objective = na::DVector::zeros(n);
+    let mut lower = reactions.clone();
+    let mut upper = reactions.clone();
+    for i in 0..n {
+        if !exchange[i] {
+            let v = reactions[i];
+            objective[i] = v.signum();
+            lower[i] = v.min(0.0);
+            upper[i] = v.max(0.0);
+        }
+    }
+
+    let program = lp::LinearProgram {
+        objective,
+        eq_matrix: s_matrix.clone(),
+        eq_rhs: s_matrix * reactions,
+        lower,
+        upper,
+    };
+    Ok(lp::solve_lp(&program, lp::LP_MAX_ITERATIONS)?.x)
+}
+
+/// The default relative tolerance of the LSQR solver.
+pub const LSQR_TOLERANCE: f64 = 1e-10;
+
+/// This is a matrix that can only be multiplied with, not indexed, e.g. one that is streamed from
+/// disk because it does not fit in memory. Iterative solvers such as LSQR need nothing more.
+pub trait LinearOperator {
+    /// This returns the shape of the matrix, (m, n).
+    fn shape(&self) -> (usize, usize);
+
+    /// This computes Ax.
+    ///
+    /// # Arguments
+    /// * `x` - the vector to multiply; (n x 1)
+    ///
+    fn apply(&self, x: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError>;
+
+    /// This computes Aᵀy.
+    ///
+    /// # Arguments
+    /// * `y` - the vector to multiply; (m x 1)
+    ///
+    fn apply_transpose(&self, y: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError>;
+}
+
+impl LinearOperator for na::DMatrix<f64> {
+    fn shape(&self) -> (usize, usize) {
+        self.shape()
+    }
+
+    fn apply(&self, x: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        Ok(self * x)
+    }
+
+    fn apply_transpose(&self, y: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        Ok(self.tr_mul(y))
+    }
+}
+
+/// This solves min ||Ax - B|| with LSQR (Paige and Saunders, 1982), which only multiplies by A and
+/// Aᵀ, once each per iteration, and keeps four vectors. Started from zero it converges to the
+/// minimum norm solution, like the pseudoinverse. It stops once ||Ax - B|| <= tolerance ||B||
+/// (the system is consistent) or ||Aᵀ(Ax - B)|| <= tolerance ||A|| ||Ax - B|| (x is a least
+/// squares solution), and reports converged = false after `max_iterations` otherwise. Returns
+/// SmasError::Shape if B does not have one entry per row of A, and any error of the operator.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A, as an operator: (m x n)
+/// * `tolerance` - the relative tolerance of the stopping tests
+/// * `max_iterations` - the maximum number of iterations
+///
+pub fn solve_lsqr(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &impl LinearOperator,
+    tolerance: f64,
+    max_iterations: usize,
+) -> Result<Solution, SmasError> {
+    solve_lsqr_with_options(acc_vector, s_matrix, tolerance, max_iterations, &SolveOptions::default())
+}
+
+/// This solves min ||Ax - B|| with LSQR like `solve_lsqr`, checking the cancellation token and
+/// timeout of the options between iterations.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A, as an operator: (m x n)
+/// * `tolerance` - the relative tolerance of the stopping tests
+/// * `max_iterations` - the maximum number of iterations
+/// * `options` - the cancellation token and timeout
+///
+pub fn solve_lsqr_with_options(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &impl LinearOperator,
+    tolerance: f64,
+    max_iterations: usize,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let deadline = options.start();
+    let _span = tracing::info_span!("solve", method = %"lsqr").entered();
+    let (m, n) = s_matrix.shape();
+    if acc_vector.nrows() != m {
+        return Err(SmasError::Shape {
+            expected: m,
+            found: acc_vector.nrows(),
+        });
+    }
+
+    let mut x = na::DVector::zeros(n);
+    let b_norm = acc_vector.norm();
+    let mut u = acc_vector.clone();
+    let mut beta = b_norm;
+    if beta > 0.0 {
+        u /= beta;
+    }
+    let mut v = s_matrix.apply_transpose(&u)?;
+    let mut alpha = v.norm();
+    if alpha > 0.0 {
+        v /= alpha;
+    }
+    let mut w = v.clone();
+    let (mut phi_bar, mut rho_bar) = (beta, alpha);
+    let mut a_norm_squared = 0.0;
+    let mut iterations = 0;
+    // ** B = 0, or B orthogonal to the range of A, has the solution x = 0
+    let mut converged = beta == 0.0 || alpha == 0.0;
+
+    while !converged && iterations < max_iterations {
+        deadline.check("lsqr", iterations)?;
+        iterations += 1;
+        // ** the Golub-Kahan bidiagonalization extends by one step
+        u = s_matrix.apply(&v)? - &u * alpha;
+        beta = u.norm();
+        if beta > 0.0 {
+            u /= beta;
+        }
+        a_norm_squared += alpha * alpha + beta * beta;
+        v = s_matrix.apply_transpose(&u)? - &v * beta;
+        alpha = v.norm();
+        if alpha > 0.0 {
+            v /= alpha;
+        }
+
+        // ** a plane rotation eliminates beta from the bidiagonal, and x and w are updated with it
+        let rho = rho_bar.hypot(beta);
+        let (c, s) = (rho_bar / rho, beta / rho);
+        let theta = s * alpha;
+        rho_bar = -c * alpha;
+        let phi = c * phi_bar;
+        phi_bar *= s;
+        x += &w * (phi / rho);
+        w = &v - &w * (theta / rho);
+
+        let r_norm = phi_bar;
+        let ar_norm = phi_bar * alpha * c.abs();
+        converged = r_norm <= tolerance * b_norm || ar_norm <= tolerance * a_norm_squared.sqrt() * r_norm;
+    }
+
+    let ax = s_matrix.apply(&x)?;
+    let residual_norm = util::sum_squared_delta_with(ax.as_slice(), acc_vector.as_slice(), util::Summation::default()).sqrt();
+    Ok(Solution {
+        reactions: x,
+        method: "lsqr",
+        iterations: Some(iterations),
+        converged,
+        residual_norm,
+        duals: None,
+    })
+}
+
+/// This checks the outcome of an iterative method: if it converged the Solution is returned,
+/// otherwise a SmasError::NotConverged describing the final state is returned instead.
+///
+/// # Arguments
+/// * `solution` - the solution produced by an iterative method
+///
+pub fn require_converged(solution: Solution) -> Result<Solution, SmasError> {
+    if solution.converged {
+        Ok(solution)
+    } else {
+        Err(SmasError::NotConverged {
+            method: solution.method,
+            iterations: solution.iterations.unwrap_or(0),
+            residual_norm: solution.residual_norm,
+        })
+    }
+}
+
+/// This computes the two-norm of the residual, ||Ax - B||, with the default summation method.
+fn compute_residual_norm(
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    acc_vector: &na::DVector<f64>,
+) -> f64 {
+    residual_norm(s_matrix, reactions, acc_vector, util::Summation::default())
+}
+
+/// This computes the two-norm of the residual, ||Ax - B||, accumulating the sum of squares with
+/// the chosen summation method. The residual norms of solutions use the default (compensated).
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the solution vector, x; (n x 1)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `summation` - how the sum of squares is accumulated
+///
+pub fn residual_norm(
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    acc_vector: &na::DVector<f64>,
+    summation: util::Summation,
+) -> f64 {
+    util::sum_squared_delta_with((s_matrix * reactions).as_slice(), acc_vector.as_slice(), summation).sqrt()
+}
+
+/// This computes the residual of each metabolite, Ax - B: positive where the solution produces
+/// more of a metabolite than was measured, negative where it produces less.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the solution vector, x; (n x 1)
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+///
+pub fn residuals(
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    acc_vector: &na::DVector<f64>,
+) -> na::DVector<f64> {
+    s_matrix * reactions - acc_vector
+}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
@@ -28,8 +1263,323 @@ mod tests {
         let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
         let r_vector_truth: na::DVector<f64> = na::DVector::from_row_slice(matrices::R_STD_015);
         let r_vector = solve::solve(acc_vector, s_matrix);
-        for (c, t) in r_vector.row_iter().zip(r_vector_truth.row_iter()) {
-            assert!(util::epsilon_eq(c[0], t[0], 1e-4));
+        assert!(util::vectors_eq(&r_vector, &r_vector_truth, 1e-4, 0.0));
+    }
+
+    #[test]
+    fn test_jacobian() {
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let jacobian = solve::jacobian(&s_matrix);
+        assert_eq!(jacobian.shape(), (28, 39));
+
+        let r_vector = solve::solve(acc_vector.clone(), s_matrix);
+        for (c, t) in (jacobian * acc_vector).iter().zip(r_vector.iter()) {
+            assert!(util::epsilon_eq(*c, *t, 1e-6));
         }
     }
+
+    #[test]
+    fn test_solve_bounded() {
+        let s_matrix = na::DMatrix::identity(2, 2);
+        let acc_vector = na::DVector::from_row_slice(&[-1.0, 3.0]);
+        let mut bounds = constraints::Bounds::unbounded(2);
+        bounds.lower.fill(0.0);
+        bounds.upper[1] = 2.0;
+        let solution = solve::solve_bounded(&acc_vector, &s_matrix, &bounds, solve::BOUNDED_MAX_ITERATIONS).unwrap();
+        assert!(util::epsilon_eq(solution.reactions[0], 0.0, 1e-12));
+        assert!(util::epsilon_eq(solution.reactions[1], 2.0, 1e-12));
+        assert!(solution.converged);
+    }
+
+    #[test]
+    fn test_solve_bounded_reference() {
+        // ** the reference reaction vector is non-negative, so requiring x >= 0 should recover it
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let r_vector_truth: na::DVector<f64> = na::DVector::from_row_slice(matrices::R_STD_015);
+        let mut bounds = constraints::Bounds::unbounded(28);
+        bounds.lower.fill(0.0);
+        let solution = solve::solve_bounded(&acc_vector, &s_matrix, &bounds, solve::BOUNDED_MAX_ITERATIONS).unwrap();
+        for (c, t) in solution.reactions.iter().zip(r_vector_truth.iter()) {
+            assert!(*c >= 0.0);
+            assert!(util::epsilon_eq(*c, *t, 1e-4));
+        }
+    }
+
+    #[test]
+    fn test_solve_nnls() {
+        // ** the exact solution is x = (0, -1); NNLS holds x2 at 0 and fits the first row with x1 = 1
+        let s_matrix = na::DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 0.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, -1.0]);
+        let pinv = solve::solve(acc_vector.clone(), s_matrix.clone());
+        assert!(pinv[1] < 0.0);
+        let solution = solve::solve_nnls(&acc_vector, &s_matrix, solve::BOUNDED_MAX_ITERATIONS).unwrap();
+        assert_eq!(solution.method, "nnls");
+        assert!(solution.converged);
+        assert!(util::epsilon_eq(solution.reactions[0], 1.0, 1e-12));
+        assert!(util::epsilon_eq(solution.reactions[1], 0.0, 1e-12));
+        assert!(solve::solve_nnls(&acc_vector, &na::DMatrix::identity(3, 2), 10).is_err());
+        assert!(matches!(
+            solve::solve_nnls(&acc_vector, &s_matrix, 0),
+            Err(error::SmasError::NotConverged { method: "nnls", iterations: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_solve_qr() {
+        let s_matrix = na::DMatrix::from_row_slice(4, 2, &[1.0, 0.0, 1.0, 1.0, 0.0, 2.0, -1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0, 3.0, 0.5]);
+        let solution = solve::solve_qr(&acc_vector, &s_matrix).unwrap();
+        assert_eq!(solution.method, "qr");
+        assert!(util::vectors_eq(&solution.reactions, &solve::solve(acc_vector.clone(), s_matrix.clone()), 1e-12, 1e-12));
+
+        // ** a reaction that is a multiple of another, or more reactions than metabolites
+        let dependent = na::DMatrix::from_row_slice(3, 2, &[1.0, 2.0, 1.0, 2.0, 0.0, 0.0]);
+        let qr = solve::solve_qr(&na::DVector::from_row_slice(&[1.0, 1.0, 0.0]), &dependent);
+        assert!(matches!(qr, Err(error::SmasError::RankDeficient { rank: 1, columns: 2, .. })));
+        let qr = solve::solve_qr(&na::DVector::from_row_slice(&[1.0]), &na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]));
+        assert!(matches!(qr, Err(error::SmasError::RankDeficient { rank: 1, columns: 2, .. })));
+    }
+
+    #[test]
+    fn test_solve_lasso() {
+        // ** with orthonormal reactions the lasso soft-thresholds each flux by lambda
+        let identity = na::DMatrix::identity(3, 3);
+        let acc_vector = na::DVector::from_row_slice(&[3.0, 0.5, -2.0]);
+        let solution = solve::solve_lasso(&acc_vector, &identity, 1.0, 100).unwrap();
+        assert_eq!(solution.method, "lasso");
+        assert_eq!(solution.reactions, na::DVector::from_row_slice(&[2.0, 0.0, -1.0]));
+        let zero = solve::solve_lasso(&acc_vector, &identity, 3.0, 100).unwrap();
+        assert_eq!(zero.reactions, na::DVector::zeros(3));
+
+        // ** without a penalty it is least squares
+        let s_matrix = na::DMatrix::from_row_slice(4, 2, &[1.0, 0.0, 1.0, 1.0, 0.0, 2.0, -1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0, 3.0, 0.5]);
+        let solution = solve::solve_lasso(&acc_vector, &s_matrix, 0.0, 10_000).unwrap();
+        assert!(util::vectors_eq(&solution.reactions, &solve::solve(acc_vector.clone(), s_matrix.clone()), 1e-8, 1e-8));
+        assert!(matches!(
+            solve::solve_lasso(&acc_vector, &s_matrix, 0.0, 1),
+            Err(error::SmasError::NotConverged { method: "lasso", iterations: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_solve_huber() {
+        // ** a line through five points, one of them an outlier
+        let s_matrix = na::DMatrix::from_row_slice(5, 2, &[1.0, 0.0, 1.0, 1.0, 1.0, 2.0, 1.0, 3.0, 1.0, 4.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 3.0, 5.0, 7.0, 100.0]);
+        let least_squares = solve::solve(acc_vector.clone(), s_matrix.clone());
+        let solution = solve::solve_huber(&acc_vector, &s_matrix, 0.5, 1000).unwrap();
+        assert_eq!(solution.method, "huber");
+        let true_line = na::DVector::from_row_slice(&[1.0, 2.0]);
+        assert!((&solution.reactions - &true_line).amax() < 0.2 * (&least_squares - &true_line).amax());
+
+        // ** a delta larger than every residual is least squares
+        let wide = solve::solve_huber(&acc_vector, &s_matrix, 1e3, 1000).unwrap();
+        assert!(util::vectors_eq(&wide.reactions, &least_squares, 1e-9, 1e-9));
+    }
+
+    #[test]
+    fn test_solve_residual_norm() {
+        // ** one reaction fitted to three metabolites: the mean, the median and the midrange
+        let s_matrix = na::DMatrix::from_element(3, 1, 1.0);
+        let acc_vector = na::DVector::from_row_slice(&[0.0, 0.0, 3.0]);
+        let options = solve::SolveOptions::default();
+        let fit = |norm, bounds| solve::solve_residual_norm(&acc_vector, &s_matrix, norm, bounds, &options).unwrap();
+
+        let l2 = fit(solve::ResidualNorm::L2, None);
+        assert!(util::epsilon_eq(l2.reactions[0], 1.0, 1e-12));
+        assert!(util::vectors_eq(&solve::residuals(&s_matrix, &l2.reactions, &acc_vector), &na::DVector::from_row_slice(&[1.0, 1.0, -2.0]), 1e-12, 0.0));
+        let l1 = fit(solve::ResidualNorm::L1, None);
+        assert!(util::epsilon_eq(l1.reactions[0], 0.0, 1e-9));
+        assert!(util::epsilon_eq(solve::ResidualNorm::L1.compute(&s_matrix, &l1.reactions, &acc_vector), 3.0, 1e-9));
+        let linf = fit(solve::ResidualNorm::LInf, None);
+        assert!(util::epsilon_eq(linf.reactions[0], 1.5, 1e-9));
+        assert!(util::epsilon_eq(solve::ResidualNorm::LInf.compute(&s_matrix, &linf.reactions, &acc_vector), 1.5, 1e-9));
+        // ** the residual norm of a solution is the two-norm whichever norm was minimized
+        assert!(util::epsilon_eq(linf.residual_norm, 6.75f64.sqrt(), 1e-9));
+
+        let mut bounds = constraints::Bounds::unbounded(1);
+        bounds.lower[0] = 0.5;
+        assert!(util::epsilon_eq(fit(solve::ResidualNorm::L1, Some(&bounds)).reactions[0], 0.5, 1e-9));
+        assert!(fit(solve::ResidualNorm::L1, None).format_report_with_norm(solve::ResidualNorm::L1, &s_matrix, &acc_vector)
+            .contains("% residual norm (l1): 3e0"));
+    }
+
+    #[test]
+    fn test_solve_options() {
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let mut bounds = constraints::Bounds::unbounded(28);
+        bounds.lower.fill(0.0);
+
+        // ** the default options never stop a solve, and a token that is not cancelled does not either
+        let cancel = cancel::CancellationToken::new();
+        let options = solve::SolveOptions { timeout: None, cancel_token: Some(cancel.clone()) };
+        assert!(solve::solve_bounded_with_options(&acc_vector, &s_matrix, &bounds, solve::BOUNDED_MAX_ITERATIONS, &options).is_ok());
+        assert!(solve::solve_lsqr_with_options(&acc_vector, &s_matrix, solve::LSQR_TOLERANCE, 1000, &solve::SolveOptions::default()).is_ok());
+
+        cancel.cancel();
+        let cancelled = solve::solve_nnls_with_options(&acc_vector, &s_matrix, solve::BOUNDED_MAX_ITERATIONS, &options);
+        assert!(matches!(cancelled, Err(error::SmasError::Cancelled { method: "nnls", iterations: 0 })));
+        let timed_out = solve::SolveOptions { timeout: Some(std::time::Duration::ZERO), cancel_token: None };
+        let Err(error) = solve::solve_lsqr_with_options(&acc_vector, &s_matrix, solve::LSQR_TOLERANCE, 1000, &timed_out) else {
+            panic!("a solve with no time should time out");
+        };
+        assert_eq!(error.to_string(), "lsqr timed out after 0 iterations");
+    }
+
+    #[test]
+    fn test_solve_joint() {
+        // ** x = 0 and x = 2 in two conditions; with fusion the solutions move toward each other
+        let s_matrix = na::DMatrix::from_row_slice(1, 1, &[1.0]);
+        let acc_vectors = vec![
+            na::DVector::from_row_slice(&[0.0]),
+            na::DVector::from_row_slice(&[2.0]),
+        ];
+        let separate = solve::solve_joint(&acc_vectors, &s_matrix, 0.0);
+        assert!(util::epsilon_eq(separate[(0, 0)], 0.0, 1e-12));
+        assert!(util::epsilon_eq(separate[(0, 1)], 2.0, 1e-12));
+
+        // ** minimizing x1² + (x2 - 2)² + (x1 - x2)² gives x1 = 2/3, x2 = 4/3
+        let fused = solve::solve_joint(&acc_vectors, &s_matrix, 1.0);
+        assert_eq!(fused.shape(), (1, 2));
+        assert!(util::epsilon_eq(fused[(0, 0)], 2.0 / 3.0, 1e-12));
+        assert!(util::epsilon_eq(fused[(0, 1)], 4.0 / 3.0, 1e-12));
+    }
+
+    #[test]
+    fn test_loopless() {
+        // ** an uptake of A, a loop A -> B -> C -> A, and a secretion of B
+        let s_matrix = na::DMatrix::from_row_slice(3, 5, &[
+            1.0, -1.0, 0.0, 1.0, 0.0,
+            0.0, 1.0, -1.0, 0.0, -1.0,
+            0.0, 0.0, 1.0, -1.0, 0.0,
+        ]);
+        let exchange = solve::exchange_reactions(&s_matrix);
+        assert_eq!(exchange, vec![true, false, false, false, true]);
+
+        let reactions = na::DVector::from_row_slice(&[1.0, 3.0, 2.0, 2.0, 1.0]);
+        let loopless = solve::loopless(&s_matrix, &reactions, &exchange).unwrap();
+        let expected = na::DVector::from_row_slice(&[1.0, 1.0, 0.0, 0.0, 1.0]);
+        for (c, t) in loopless.iter().zip(expected.iter()) {
+            assert!(util::epsilon_eq(*c, *t, 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_solve_lsqr() {
+        // ** LSQR agrees with the pseudoinverse on the reference system, to its relative tolerance
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let solution = solve::solve_lsqr(&acc_vector, &s_matrix, solve::LSQR_TOLERANCE, 1000).unwrap();
+        assert!(solution.converged, "{:?}", solution.iterations);
+        let expected = solve::solve(acc_vector.clone(), s_matrix.clone());
+        assert!(util::vectors_eq(&solution.reactions, &expected, 1e-4, 1e-9));
+
+        // ** an inconsistent system ends at the least squares solution, x = 2
+        let s_matrix = na::DMatrix::from_row_slice(2, 1, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 3.0]);
+        let solution = solve::solve_lsqr(&acc_vector, &s_matrix, solve::LSQR_TOLERANCE, 10).unwrap();
+        assert!(util::epsilon_eq(solution.reactions[0], 2.0, 1e-12));
+        assert!(util::epsilon_eq(solution.residual_norm, 2f64.sqrt(), 1e-12));
+
+        let too_few = solve::solve_lsqr(&acc_vector, &util::default_s_matrix(), solve::LSQR_TOLERANCE, 1);
+        assert!(matches!(too_few, Err(error::SmasError::Shape { expected: 39, found: 2 })));
+    }
+
+    #[test]
+    fn test_require_converged() {
+        let solution = solve::Solution {
+            reactions: na::DVector::zeros(2),
+            method: "lsqr",
+            iterations: Some(100),
+            converged: false,
+            residual_norm: 1.0,
+            duals: None,
+        };
+        assert!(matches!(
+            solve::require_converged(solution),
+            Err(error::SmasError::NotConverged { iterations: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn test_solver_concurrent() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<solve::Solver>();
+
+        let solver = solve::Solver::new(util::default_s_matrix());
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let expected = solve::solve(acc_vector.clone(), util::default_s_matrix());
+
+        // ** every thread shares the same Solver and must get the same answer
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|k| {
+                    let solver = &solver;
+                    let acc_vector = &acc_vector * (k as f64 + 1.0);
+                    scope.spawn(move || solver.solve(&acc_vector).unwrap().reactions / (k as f64 + 1.0))
+                })
+                .collect();
+            for handle in handles {
+                assert!(util::vectors_eq(&handle.join().unwrap(), &expected, 1e-6, 1e-9));
+            }
+        });
+
+        assert!(matches!(
+            solver.solve(&na::DVector::zeros(3)),
+            Err(error::SmasError::Shape { expected: 39, found: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_cached_solver() {
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let mut cached = None;
+        let first = solve::cached_solver(&mut cached, &s_matrix).solve(&acc_vector).unwrap();
+        // ** a new Solver clones the matrix, so its buffer shows whether the Solver was replaced
+        let s_matrix_buffer = cached.as_ref().unwrap().s_matrix().as_ptr();
+        assert_ne!(s_matrix_buffer, s_matrix.as_ptr());
+        // ** the same matrix reuses the Solver, a different one replaces it
+        assert_eq!(solve::cached_solver(&mut cached, &s_matrix).s_matrix().as_ptr(), s_matrix_buffer);
+        assert_eq!(first.reactions, solve::solve_pinv(&acc_vector, &s_matrix).unwrap().reactions);
+        let scaled = &s_matrix * 2.0;
+        let second = solve::cached_solver(&mut cached, &scaled).solve(&acc_vector).unwrap();
+        assert_ne!(cached.as_ref().unwrap().s_matrix().as_ptr(), s_matrix_buffer);
+        assert!(util::vectors_eq(&(second.reactions * 2.0), &first.reactions, 1e-6, 1e-9));
+    }
+
+    #[test]
+    fn test_degenerate_shapes() {
+        // ** no metabolites: nothing constrains the reactions, so the minimum norm solution is zero
+        let r_vector = solve::solve(na::DVector::zeros(0), na::DMatrix::zeros(0, 3));
+        assert_eq!(r_vector, na::DVector::zeros(3));
+        assert_eq!(solve::null_space(&na::DMatrix::zeros(0, 3)).shape(), (3, 3));
+
+        // ** no reactions: the solution is empty and the whole accumulation is residual
+        let acc_vector = na::DVector::from_row_slice(&[3.0, 4.0]);
+        let solution = solve::solve_pinv(&acc_vector, &na::DMatrix::zeros(2, 0)).unwrap();
+        assert_eq!(solution.reactions.nrows(), 0);
+        assert!(util::epsilon_eq(solution.residual_norm, 5.0, 1e-12));
+        assert_eq!(solve::null_space(&na::DMatrix::zeros(2, 0)).shape(), (0, 0));
+
+        // ** 1x1, single row and single column systems
+        let r_vector = solve::solve(na::DVector::from_row_slice(&[6.0]), na::DMatrix::from_row_slice(1, 1, &[2.0]));
+        assert!(util::epsilon_eq(r_vector[0], 3.0, 1e-12));
+        let r_vector = solve::solve(na::DVector::from_row_slice(&[2.0]), na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]));
+        assert!(util::vectors_eq(&r_vector, &na::DVector::from_row_slice(&[1.0, 1.0]), 1e-12, 0.0));
+        let r_vector = solve::solve(na::DVector::from_row_slice(&[1.0, 3.0]), na::DMatrix::from_row_slice(2, 1, &[1.0, 1.0]));
+        assert!(util::epsilon_eq(r_vector[0], 2.0, 1e-12));
+
+        // ** an accumulation vector that does not match the matrix has no sensible answer
+        let mismatched = solve::solve_pinv(&na::DVector::zeros(3), &na::DMatrix::zeros(2, 2));
+        assert!(matches!(mismatched, Err(error::SmasError::Shape { expected: 2, found: 3 })));
+        let mismatched = solve::solve_bounded(
+            &na::DVector::zeros(2), &na::DMatrix::zeros(2, 2), &constraints::Bounds::unbounded(3), 10,
+        );
+        assert!(matches!(mismatched, Err(error::SmasError::Shape { .. })));
+    }
 }
@@ -0,0 +1,130 @@
+use crate::audit;
+use crate::sparse;
+
+use nalgebra as na;
+
+/// This is a solver that `--solver auto` can choose.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Method {
+    /// The minimum norm pseudoinverse, from a full SVD
+    Pinv,
+    /// LSQR, which only multiplies by the matrix and its transpose
+    Lsqr,
+    /// Non-negative least squares, for models where every reaction is irreversible
+    Nnls,
+}
+
+impl Method {
+    /// This returns the name of the method, as `--solver` takes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Method::Pinv => "pinv",
+            Method::Lsqr => "lsqr",
+            Method::Nnls => "nnls",
+        }
+    }
+}
+
+/// This is the method `choose_method` picked, with the reasons for picking it.
+pub struct MethodChoice {
+    /// The chosen method
+    pub method: Method,
+    /// Why the method was chosen, one finding per entry, in the order they were considered
+    pub reasons: Vec<String>,
+}
+
+/// This picks a solver for a stoichiometric matrix, as `--solver auto` does:
+///
+/// * nnls if every reaction is known to be irreversible, since pinv and LSQR can give negative fluxes
+/// * pinv if the solve is constrained, e.g. by bounds or another residual norm, which LSQR does not support
+/// * LSQR if the matrix is large and sparse (see `sparse::prefer_sparse`), since a full SVD would cost
+///   far more than the products LSQR needs
+/// * otherwise pinv, whose truncated SVD also handles an ill-conditioned matrix that LSQR would
+///   converge on slowly
+///
+/// The condition number is only computed for matrices small enough for pinv.
+///
+/// # Arguments
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `irreversible` - whether each reaction is irreversible, if known; (n x 1)
+/// * `constrained` - whether the solve has bounds or minimizes another norm than the two-norm
+///
+pub fn choose_method(s_matrix: &na::DMatrix<f64>, irreversible: Option<&[bool]>, constrained: bool) -> MethodChoice {
+    let (m, n) = s_matrix.shape();
+    let nnz = s_matrix.iter().filter(|value| **value != 0.0).count();
+    let density = if m * n == 0 { 0.0 } else { nnz as f64 / (m * n) as f64 };
+    let mut reasons = vec![format!("the matrix has {m} metabolites, {n} reactions and {nnz} nonzero entries (density {density:.3})")];
+
+    if irreversible.is_some_and(|irreversible| !irreversible.is_empty() && irreversible.iter().all(|flag| *flag)) {
+        reasons.push(String::from("every reaction is irreversible, so the fluxes must be non-negative, which only nnls enforces"));
+        return MethodChoice { method: Method::Nnls, reasons };
+    }
+    if constrained {
+        reasons.push(String::from("the solve is constrained by bounds or minimizes another norm, which LSQR does not support"));
+        return MethodChoice { method: Method::Pinv, reasons };
+    }
+    if sparse::prefer_sparse(m, n, nnz) {
+        reasons.push(format!(
+            "the matrix has at least {} entries with at most {} of them nonzero, so LSQR, which only multiplies by the \
+            matrix, costs far less than a full SVD",
+            sparse::AUTO_SPARSE_MIN_ENTRIES,
+            sparse::AUTO_SPARSE_MAX_DENSITY,
+        ));
+        return MethodChoice { method: Method::Lsqr, reasons };
+    }
+
+    reasons.push(String::from("the matrix is small or dense enough for a full SVD, which gives the minimum norm solution directly"));
+    let condition_number = audit::analyze_matrix(s_matrix).condition_number;
+    if condition_number > audit::CONDITION_WARNING {
+        reasons.push(format!(
+            "the condition number is {condition_number:.3e}, so LSQR would converge slowly, while the truncated SVD \
+            discards the smallest singular values"
+        ));
+    } else {
+        reasons.push(format!("the condition number is {condition_number:.3e}"));
+    }
+    MethodChoice { method: Method::Pinv, reasons }
+}
+
+/// This formats a method choice as '%' comment lines: the chosen method, then each reason.
+///
+/// # Arguments
+/// * `choice` - the method choice
+///
+pub fn format_choice(choice: &MethodChoice) -> String {
+    let mut lines = vec![format!("% --solver auto chose {}:", choice.method.name())];
+    lines.extend(choice.reasons.iter().map(|reason| format!("%   {reason}")));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_choose_method() {
+        let s_matrix = util::default_s_matrix();
+        let choice = solve::auto::choose_method(&s_matrix, None, false);
+        assert_eq!(choice.method, solve::auto::Method::Pinv);
+        assert!(choice.reasons[0].starts_with("the matrix has 39 metabolites, 28 reactions"), "{:?}", choice.reasons);
+
+        // ** irreversibility outranks everything else, and constraints rule out LSQR
+        let irreversible = vec![true; s_matrix.ncols()];
+        assert_eq!(solve::auto::choose_method(&s_matrix, Some(&irreversible), true).method, solve::auto::Method::Nnls);
+        let mut reversible = irreversible.clone();
+        reversible[0] = false;
+        assert_eq!(solve::auto::choose_method(&s_matrix, Some(&reversible), false).method, solve::auto::Method::Pinv);
+
+        // ** a large matrix with a few entries per column is solved with LSQR, unless it is constrained
+        let sparse = na::DMatrix::from_fn(1000, 1000, |i, j| if i == j || i == (j + 1) % 1000 { 1.0 } else { 0.0 });
+        assert_eq!(solve::auto::choose_method(&sparse, None, false).method, solve::auto::Method::Lsqr);
+        assert_eq!(solve::auto::choose_method(&sparse, None, true).method, solve::auto::Method::Pinv);
+
+        // ** an ill-conditioned matrix says so
+        let ill = na::DMatrix::from_row_slice(2, 2, &[1e3, 0.0, 0.0, 1e-6]);
+        let output = solve::auto::format_choice(&solve::auto::choose_method(&ill, None, false));
+        assert!(output.starts_with("% --solver auto chose pinv:\n%   the matrix has 2 metabolites"), "{output}");
+        assert!(output.contains("converge slowly"), "{output}");
+    }
+}
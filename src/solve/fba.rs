@@ -0,0 +1,107 @@
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::lp;
+use crate::solve::{check_shape, compute_residual_norm, Duals, Solution, SolveOptions};
+
+use nalgebra as na;
+
+/// This is flux balance analysis (FBA): it finds the fluxes that maximize a linear objective, e.g.
+/// the biomass reaction, among those that balance every metabolite and stay within their bounds,
+///
+/// maximize c·x subject to Ax = B and lower <= x <= upper
+///
+/// with B = 0 for a steady state, or the measured accumulation vector. The solution carries the
+/// duals of the maximization: the shadow price of each metabolite is how much the objective
+/// changes per unit of that metabolite accumulated, negative for the metabolites the objective
+/// needs, and the reduced cost of each reaction how much it changes per unit of flux forced
+/// through that reaction.
+///
+/// Returns SmasError::Infeasible if no fluxes balance the metabolites within the bounds,
+/// SmasError::Unbounded if the objective can grow without limit, e.g. because an uptake reaction
+/// has no upper bound, and SmasError::Shape if B, c or the bounds do not match the shape of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B, or None for a steady state, B = 0; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `objective` - the weight of each reaction in the objective, c; (n x 1)
+/// * `bounds` - the lower and upper bound of each reaction
+/// * `options` - the cancellation token and timeout
+///
+pub fn fba(
+    acc_vector: Option<&na::DVector<f64>>,
+    s_matrix: &na::DMatrix<f64>,
+    objective: &na::DVector<f64>,
+    bounds: &Bounds,
+    options: &SolveOptions,
+) -> Result<Solution, SmasError> {
+    let (m, n) = s_matrix.shape();
+    let acc_vector = acc_vector.cloned().unwrap_or_else(|| na::DVector::zeros(m));
+    check_shape(&acc_vector, s_matrix)?;
+    for length in [objective.nrows(), bounds.lower.nrows(), bounds.upper.nrows()] {
+        if length != n {
+            return Err(SmasError::Shape { expected: n, found: length });
+        }
+    }
+
+    // ** the simplex method minimizes, so the objective is negated, and so are the duals; 0.0 - v
+    // ** rather than -v, so that zero duals are not written as -0
+    let program = lp::LinearProgram {
+        objective: -objective,
+        eq_matrix: s_matrix.clone(),
+        eq_rhs: acc_vector.clone(),
+        lower: bounds.lower.clone(),
+        upper: bounds.upper.clone(),
+    };
+    let solution = lp::solve_lp_with_options(&program, lp::LP_MAX_ITERATIONS, options)?;
+    let residual_norm = compute_residual_norm(s_matrix, &solution.x, &acc_vector);
+    Ok(Solution {
+        reactions: solution.x,
+        method: "fba",
+        iterations: Some(solution.iterations),
+        converged: true,
+        residual_norm,
+        duals: Some(Duals {
+            shadow_prices: solution.shadow_prices.map(|v| 0.0 - v),
+            reduced_costs: solution.reduced_costs.map(|v| 0.0 - v),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_fba() {
+        // ** glc is taken up, split into two g3p, and g3p is drained as the objective
+        let model = io::parse_reaction_equations("uptake: -> glc\nsplit: glc -> 2 g3p\nbiomass: g3p ->").unwrap();
+        let s_matrix = &model.s_matrix.matrix;
+        let objective = na::DVector::from_row_slice(&[0.0, 0.0, 1.0]);
+        let mut bounds = constraints::Bounds::from_directions(&model.reversible);
+        bounds.upper[0] = 10.0;
+        let options = solve::SolveOptions::default();
+
+        let solution = solve::fba::fba(None, s_matrix, &objective, &bounds, &options).unwrap();
+        assert_eq!(solution.method, "fba");
+        assert!(util::vectors_eq(&solution.reactions, &na::DVector::from_row_slice(&[10.0, 10.0, 20.0]), 1e-9, 1e-9));
+        assert!(solution.residual_norm < 1e-9);
+        // ** each unit of glucose uptake is worth two of the objective
+        let duals = solution.duals.unwrap();
+        assert!(util::epsilon_eq(duals.reduced_costs[0], 2.0, 1e-9));
+        assert!(util::epsilon_eq(duals.shadow_prices[0], -2.0, 1e-9));
+
+        // ** accumulating glc leaves less for biomass
+        let acc_vector = na::DVector::from_row_slice(&[4.0, 0.0]);
+        let solution = solve::fba::fba(Some(&acc_vector), s_matrix, &objective, &bounds, &options).unwrap();
+        assert!(util::epsilon_eq(solution.reactions[2], 12.0, 1e-9));
+
+        bounds.upper[0] = f64::INFINITY;
+        let unbounded = solve::fba::fba(None, s_matrix, &objective, &bounds, &options);
+        assert!(matches!(unbounded, Err(error::SmasError::Unbounded)));
+        bounds.lower[2] = 1.0;
+        bounds.upper[0] = 0.0;
+        let infeasible = solve::fba::fba(None, s_matrix, &objective, &bounds, &options);
+        assert!(matches!(infeasible, Err(error::SmasError::Infeasible)));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::constraints::Bounds;
+use crate::error::SmasError;
+use crate::lp;
+use crate::solve::{check_shape, SolveOptions};
+
+use nalgebra as na;
+
+/// This is flux variability analysis (FVA): for each reaction, it finds the smallest and largest
+/// flux among all the reaction vectors that balance every metabolite within a tolerance and stay
+/// within their bounds,
+///
+/// minimize and maximize x_j subject to |Ax - B| <= tolerance and lower <= x <= upper
+///
+/// with B = 0 for a steady state, or the measured accumulation vector. A narrow range means the
+/// data and bounds pin the reaction down; a wide one, that other reactions can take its place. The
+/// result has one row per reaction, with its minimum in the first column and its maximum in the
+/// second; a reaction that can grow without limit has an infinite minimum or maximum.
+///
+/// Returns SmasError::Infeasible if no fluxes balance the metabolites within the bounds, and
+/// SmasError::Shape if B or the bounds do not match the shape of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B, or None for a steady state, B = 0; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `bounds` - the lower and upper bound of each reaction
+/// * `tolerance` - how far each metabolite may be from its accumulation, at least 0
+/// * `options` - the cancellation token and timeout
+///
+pub fn fva(
+    acc_vector: Option<&na::DVector<f64>>,
+    s_matrix: &na::DMatrix<f64>,
+    bounds: &Bounds,
+    tolerance: f64,
+    options: &SolveOptions,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    let (m, n) = s_matrix.shape();
+    let acc_vector = acc_vector.cloned().unwrap_or_else(|| na::DVector::zeros(m));
+    check_shape(&acc_vector, s_matrix)?;
+    for length in [bounds.lower.nrows(), bounds.upper.nrows()] {
+        if length != n {
+            return Err(SmasError::Shape { expected: n, found: length });
+        }
+    }
+
+    // ** the tolerance is a slack variable in [-tolerance, tolerance] for each metabolite, Ax + s = B
+    let mut eq_matrix = na::DMatrix::zeros(m, n + m);
+    eq_matrix.columns_mut(0, n).copy_from(s_matrix);
+    eq_matrix.columns_mut(n, m).fill_with_identity();
+    let lower = bounds.lower.clone().resize_vertically(n + m, -tolerance);
+    let upper = bounds.upper.clone().resize_vertically(n + m, tolerance);
+    let mut program = lp::LinearProgram {
+        objective: na::DVector::zeros(n + m),
+        eq_matrix,
+        eq_rhs: acc_vector,
+        lower,
+        upper,
+    };
+
+    let mut ranges = na::DMatrix::zeros(n, 2);
+    for j in 0..n {
+        // ** the simplex method minimizes, so the maximum is the negated minimum of -x_j
+        for (col, sign) in [(0, 1.0), (1, -1.0)] {
+            program.objective[j] = sign;
+            ranges[(j, col)] = match lp::solve_lp_with_options(&program, lp::LP_MAX_ITERATIONS, options) {
+                Ok(solution) => solution.x[j],
+                Err(SmasError::Unbounded) => -sign * f64::INFINITY,
+                Err(e) => return Err(e),
+            };
+        }
+        program.objective[j] = 0.0;
+    }
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_fva() {
+        // ** glc is taken up and converted to g6p by one of two parallel reactions, then drained
+        let model = io::parse_reaction_equations("uptake: -> glc\nhk: glc -> g6p\nglk: glc -> g6p\ndrain: g6p ->").unwrap();
+        let s_matrix = &model.s_matrix.matrix;
+        let mut bounds = constraints::Bounds::from_directions(&model.reversible);
+        bounds.lower[0] = 3.0;
+        bounds.upper[0] = 3.0;
+        let options = solve::SolveOptions::default();
+
+        // ** the drain is pinned by the uptake and the measured accumulation, the parallel reactions are not
+        let acc_vector = na::DVector::from_row_slice(&[-2.0, 1.0]);
+        let ranges = solve::fva::fva(Some(&acc_vector), s_matrix, &bounds, 0.0, &options).unwrap();
+        let expected = na::DMatrix::from_row_slice(4, 2, &[3.0, 3.0, 0.0, 5.0, 0.0, 5.0, 4.0, 4.0]);
+        assert!((&ranges - &expected).amax() < 1e-9, "{ranges}");
+
+        // ** a tolerance widens the ranges by as much as the metabolites may be off
+        let ranges = solve::fva::fva(Some(&acc_vector), s_matrix, &bounds, 0.5, &options).unwrap();
+        assert!(util::epsilon_eq(ranges[(3, 0)], 3.0, 1e-9) && util::epsilon_eq(ranges[(3, 1)], 5.0, 1e-9), "{ranges}");
+
+        // ** without the uptake bound, every flux can grow without limit at a steady state
+        let ranges = solve::fva::fva(None, s_matrix, &constraints::Bounds::from_directions(&model.reversible), 0.0, &options).unwrap();
+        assert_eq!(ranges[(0, 0)], 0.0);
+        assert_eq!(ranges[(0, 1)], f64::INFINITY);
+
+        // ** glc cannot accumulate faster than it is taken up
+        let acc_vector = na::DVector::from_row_slice(&[20.0, 0.0]);
+        assert!(matches!(solve::fva::fva(Some(&acc_vector), s_matrix, &bounds, 0.0, &options), Err(error::SmasError::Infeasible)));
+    }
+}
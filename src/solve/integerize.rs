@@ -0,0 +1,182 @@
+use crate::error::SmasError;
+use crate::solve::{self, check_shape};
+
+use nalgebra as na;
+
+/// The most nodes `integerize` searches before it gives up.
+pub const INTEGERIZE_MAX_NODES: usize = 10_000;
+
+/// This is a reaction vector rounded by `integerize`.
+pub struct Integerized {
+    /// The rounded reaction vector, every flux a multiple of 1/denominator; (n x 1)
+    pub reactions: na::DVector<f64>,
+    /// The two-norm of the residual of the rounded vector, ||Ax - B||
+    pub residual_norm: f64,
+    /// The two-norm of the residual of the least squares solution it was rounded from
+    pub least_squares_norm: f64,
+    /// The number of nodes of the search, each one a least squares solve
+    pub nodes: usize,
+}
+
+/// This is the state of the branch-and-round search of `integerize`.
+struct Search<'a> {
+    acc_vector: &'a na::DVector<f64>,
+    s_matrix: &'a na::DMatrix<f64>,
+    start: &'a na::DVector<f64>,
+    step: f64,
+    bound: f64,
+    nodes: usize,
+    max_nodes: usize,
+}
+
+impl Search<'_> {
+    /// This fills in the fluxes that are not fixed with the least squares fit that moves them the
+    /// least from the start, and returns the vector with its residual norm.
+    fn relax(&self, fixed: &[Option<f64>]) -> (na::DVector<f64>, f64) {
+        let mut reactions = na::DVector::from_fn(fixed.len(), |j, _| fixed[j].unwrap_or(self.start[j]));
+        let free: Vec<usize> = (0..fixed.len()).filter(|j| fixed[*j].is_none()).collect();
+        if !free.is_empty() {
+            // ** the minimum norm correction keeps the free fluxes as close to the start as the fit allows
+            let residual = self.acc_vector - self.s_matrix * &reactions;
+            let correction = solve::solve(residual, self.s_matrix.select_columns(&free));
+            for (k, j) in free.iter().enumerate() {
+                reactions[*j] += correction[k];
+            }
+        }
+        let residual_norm = (self.s_matrix * &reactions - self.acc_vector).norm();
+        (reactions, residual_norm)
+    }
+
+    /// This fixes one more flux to a multiple of the step, nearest first, and searches on until
+    /// every flux is fixed. Branches whose fit is worse than the bound are abandoned, since fixing
+    /// more fluxes can only make it worse.
+    fn branch(&mut self, fixed: &mut [Option<f64>]) -> Result<Option<na::DVector<f64>>, SmasError> {
+        self.nodes += 1;
+        let (reactions, residual_norm) = self.relax(fixed);
+        if self.nodes > self.max_nodes {
+            return Err(SmasError::NotConverged {
+                method: "integerize",
+                iterations: self.max_nodes,
+                residual_norm,
+            });
+        }
+        if residual_norm > self.bound {
+            return Ok(None);
+        }
+        // ** the flux nearest a multiple of the step is fixed first, as it is the least likely to fail
+        let distance = |j: usize| (reactions[j] / self.step - (reactions[j] / self.step).round()).abs();
+        let Some(j) = (0..fixed.len())
+            .filter(|j| fixed[*j].is_none())
+            .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+        else {
+            return Ok(Some(reactions));
+        };
+        let nearest = (reactions[j] / self.step).round() * self.step;
+        let other = if reactions[j] > nearest { nearest + self.step } else { nearest - self.step };
+        for value in [nearest, other] {
+            fixed[j] = Some(value);
+            if let Some(found) = self.branch(fixed)? {
+                return Ok(Some(found));
+            }
+        }
+        fixed[j] = None;
+        Ok(None)
+    }
+}
+
+/// This rounds a reaction vector to a nearby one whose fluxes are all multiples of
+/// 1/`denominator`, e.g. whole numbers of reaction events, that still fits Ax ≈ B: its residual
+/// norm is at most `tolerance` more than that of the least squares solution. The search fixes one
+/// flux at a time to the nearest multiple, then to the next nearest, and refits the fluxes that
+/// are not yet fixed, which moves them along the null space of A when the fit allows, so that the
+/// rounding of one flux is made up for by the others. Each node is a least squares solve, so it
+/// suits models of tens to hundreds of reactions.
+///
+/// Returns SmasError::Infeasible if the search, which tries the two nearest multiples of each
+/// flux, finds no such vector, SmasError::NotConverged if it finds none within `max_nodes` nodes, and SmasError::Shape if B or x do not match the shape of A.
+///
+/// # Arguments
+/// * `acc_vector` - the accumulation vector, B; (m x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `reactions` - the reaction vector to round, e.g. the least squares solution; (n x 1)
+/// * `denominator` - the fluxes are rounded to multiples of 1/denominator, at least 1
+/// * `tolerance` - how much larger the residual norm may be than that of the least squares solution
+/// * `max_nodes` - the most nodes to search, e.g. INTEGERIZE_MAX_NODES
+///
+pub fn integerize(
+    acc_vector: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    reactions: &na::DVector<f64>,
+    denominator: u32,
+    tolerance: f64,
+    max_nodes: usize,
+) -> Result<Integerized, SmasError> {
+    check_shape(acc_vector, s_matrix)?;
+    if reactions.nrows() != s_matrix.ncols() {
+        return Err(SmasError::Shape { expected: s_matrix.ncols(), found: reactions.nrows() });
+    }
+    let mut search = Search {
+        acc_vector,
+        s_matrix,
+        start: reactions,
+        step: 1.0 / f64::from(denominator.max(1)),
+        bound: f64::INFINITY,
+        nodes: 0,
+        max_nodes,
+    };
+    let mut fixed = vec![None; reactions.nrows()];
+    let (_, least_squares_norm) = search.relax(&fixed);
+    search.bound = least_squares_norm + tolerance;
+    let rounded = search.branch(&mut fixed)?.ok_or(SmasError::Infeasible)?;
+    // ** the fluxes were fixed to multiples of the step, so only roundoff is cleaned up here
+    let rounded = rounded.map(|flux| (flux / search.step).round() * search.step);
+    Ok(Integerized {
+        residual_norm: (s_matrix * &rounded - acc_vector).norm(),
+        reactions: rounded,
+        least_squares_norm,
+        nodes: search.nodes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_integerize() {
+        // ** two reactions make the same metabolite, so the minimum norm solution splits 3 evenly
+        let s_matrix = na::DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let acc_vector = na::DVector::from_row_slice(&[3.0]);
+        let reactions = solve::solve(acc_vector.clone(), s_matrix.clone());
+        let integerized = solve::integerize::integerize(&acc_vector, &s_matrix, &reactions, 1, 1e-9, 100).unwrap();
+        let mut fluxes: Vec<f64> = integerized.reactions.iter().copied().collect();
+        fluxes.sort_by(f64::total_cmp);
+        assert_eq!(fluxes, vec![1.0, 2.0]);
+        assert!(integerized.residual_norm < 1e-12);
+
+        // ** halves are allowed with a denominator of 2, so the even split stands
+        let halves = solve::integerize::integerize(&acc_vector, &s_matrix, &reactions, 2, 1e-9, 100).unwrap();
+        assert_eq!(halves.reactions, na::DVector::from_row_slice(&[1.5, 1.5]));
+
+        // ** whole numbers cannot sum to 3.2, so the fit must be allowed to get worse by 0.2
+        let acc_vector = na::DVector::from_row_slice(&[3.2]);
+        let reactions = solve::solve(acc_vector.clone(), s_matrix.clone());
+        assert!(matches!(
+            solve::integerize::integerize(&acc_vector, &s_matrix, &reactions, 1, 0.1, 100),
+            Err(error::SmasError::Infeasible)
+        ));
+        let integerized = solve::integerize::integerize(&acc_vector, &s_matrix, &reactions, 1, 0.25, 100).unwrap();
+        assert_eq!(integerized.reactions.sum(), 3.0);
+        assert!(util::epsilon_eq(integerized.residual_norm, 0.2, 1e-9));
+
+        // ** at a branch point, the split of 5 is rounded and the fit stays exact
+        let model = io::parse_reaction_equations("hk: glc -> g6p\npgi: g6p -> f6p\nzwf: g6p -> 6pg\nout1: f6p ->\nout2: 6pg ->").unwrap();
+        let s_matrix = model.s_matrix.matrix;
+        let acc_vector = na::DVector::from_row_slice(&[-5.0, 0.0, 0.0, 0.0]);
+        let reactions = solve::solve(acc_vector.clone(), s_matrix.clone());
+        let integerized = solve::integerize::integerize(&acc_vector, &s_matrix, &reactions, 1, 1e-9, 1000).unwrap();
+        assert!(integerized.reactions.iter().all(|flux| flux.fract() == 0.0), "{}", integerized.reactions);
+        assert!(integerized.residual_norm < 1e-9, "{}", integerized.reactions);
+    }
+}
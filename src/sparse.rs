@@ -0,0 +1,212 @@
+use crate::error::SmasError;
+use crate::solve::LinearOperator;
+
+use nalgebra as na;
+
+/// The smallest number of entries (rows x columns) a matrix has before it is solved as a sparse
+/// matrix automatically; smaller matrices are fast enough dense.
+pub const AUTO_SPARSE_MIN_ENTRIES: usize = 1_000_000;
+
+/// The largest fraction of nonzero entries a matrix has to be solved as a sparse matrix
+/// automatically. Genome-scale stoichiometric matrices are typically below 1%.
+pub const AUTO_SPARSE_MAX_DENSITY: f64 = 0.05;
+
+/// This is a sparse matrix in compressed sparse column (CSC) form: the nonzero entries of each
+/// column, i.e. the metabolites of each reaction, are stored together, sorted by row. Memory is
+/// proportional to the number of nonzero entries rather than to rows x columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CscMatrix {
+    /// The number of rows (metabolites)
+    nrows: usize,
+    /// The number of columns (reactions)
+    ncols: usize,
+    /// Where the entries of each column start in `row_indices` and `values`, plus the end; (n + 1)
+    col_offsets: Vec<usize>,
+    /// The row of each nonzero entry
+    row_indices: Vec<usize>,
+    /// The value of each nonzero entry
+    values: Vec<f64>,
+}
+
+impl CscMatrix {
+    /// This builds a sparse matrix from (row, column, value) entries in any order. Entries at the
+    /// same position are summed, and zeros are dropped. Returns SmasError::Shape if an entry is
+    /// outside of the matrix.
+    ///
+    /// # Arguments
+    /// * `nrows` - the number of rows
+    /// * `ncols` - the number of columns
+    /// * `entries` - the (0-based) row, column and value of each entry
+    ///
+    pub fn from_entries(nrows: usize, ncols: usize, mut entries: Vec<(usize, usize, f64)>) -> Result<Self, SmasError> {
+        if let Some((row, col, _)) = entries.iter().find(|(row, col, _)| *row >= nrows || *col >= ncols) {
+            return Err(SmasError::Shape {
+                expected: if *row >= nrows { nrows } else { ncols },
+                found: if *row >= nrows { row + 1 } else { col + 1 },
+            });
+        }
+        entries.sort_by_key(|(row, col, _)| (*col, *row));
+
+        let mut col_offsets = vec![0; ncols + 1];
+        let mut row_indices: Vec<usize> = Vec::with_capacity(entries.len());
+        let mut values: Vec<f64> = Vec::with_capacity(entries.len());
+        let mut last: Option<(usize, usize)> = None;
+        for (row, col, value) in entries {
+            if last == Some((row, col)) {
+                *values.last_mut().unwrap() += value;
+                continue;
+            }
+            last = Some((row, col));
+            row_indices.push(row);
+            values.push(value);
+            col_offsets[col + 1] += 1;
+        }
+        for j in 0..ncols {
+            col_offsets[j + 1] += col_offsets[j];
+        }
+
+        let mut matrix = CscMatrix { nrows, ncols, col_offsets, row_indices, values };
+        matrix.drop_zeros();
+        Ok(matrix)
+    }
+
+    /// This builds a sparse matrix from the nonzero entries of a dense matrix.
+    ///
+    /// # Arguments
+    /// * `matrix` - the dense matrix
+    ///
+    pub fn from_dense(matrix: &na::DMatrix<f64>) -> Self {
+        let entries = matrix.column_iter()
+            .enumerate()
+            .flat_map(|(j, column)| {
+                column.iter()
+                    .enumerate()
+                    .filter(|(_, value)| **value != 0.0)
+                    .map(move |(i, value)| (i, j, *value))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        CscMatrix::from_entries(matrix.nrows(), matrix.ncols(), entries)
+            .expect("the entries of a dense matrix are within it")
+    }
+
+    /// This removes the entries that are exactly zero, e.g. duplicates that cancelled.
+    fn drop_zeros(&mut self) {
+        let mut kept = 0;
+        for j in 0..self.ncols {
+            let (start, end) = (self.col_offsets[j], self.col_offsets[j + 1]);
+            self.col_offsets[j] = kept;
+            for k in start..end {
+                if self.values[k] != 0.0 {
+                    self.row_indices[kept] = self.row_indices[k];
+                    self.values[kept] = self.values[k];
+                    kept += 1;
+                }
+            }
+        }
+        self.col_offsets[self.ncols] = kept;
+        self.row_indices.truncate(kept);
+        self.values.truncate(kept);
+    }
+
+    /// This returns the number of nonzero entries.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// This returns the fraction of the entries that are nonzero, 0 for an empty matrix.
+    pub fn density(&self) -> f64 {
+        match self.nrows * self.ncols {
+            0 => 0.0,
+            size => self.nnz() as f64 / size as f64,
+        }
+    }
+
+    /// This returns the (row, value) pairs of the nonzero entries of a column, sorted by row.
+    ///
+    /// # Arguments
+    /// * `j` - the (0-based) column
+    ///
+    pub fn column(&self, j: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.col_offsets[j]..self.col_offsets[j + 1];
+        self.row_indices[range.clone()].iter().copied().zip(self.values[range].iter().copied())
+    }
+
+    /// This expands the matrix into a dense matrix, e.g. for the direct solvers.
+    pub fn to_dense(&self) -> na::DMatrix<f64> {
+        let mut dense = na::DMatrix::zeros(self.nrows, self.ncols);
+        for j in 0..self.ncols {
+            for (i, value) in self.column(j) {
+                dense[(i, j)] = value;
+            }
+        }
+        dense
+    }
+}
+
+impl LinearOperator for CscMatrix {
+    fn shape(&self) -> (usize, usize) {
+        (self.nrows, self.ncols)
+    }
+
+    fn apply(&self, x: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        let mut y = na::DVector::zeros(self.nrows);
+        for j in 0..self.ncols {
+            for (i, value) in self.column(j) {
+                y[i] += value * x[j];
+            }
+        }
+        Ok(y)
+    }
+
+    fn apply_transpose(&self, y: &na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        Ok(na::DVector::from_fn(self.ncols, |j, _| self.column(j).map(|(i, value)| value * y[i]).sum()))
+    }
+}
+
+/// This decides whether a matrix is large and sparse enough to be solved as a sparse matrix
+/// without being asked to; see AUTO_SPARSE_MIN_ENTRIES and AUTO_SPARSE_MAX_DENSITY.
+///
+/// # Arguments
+/// * `nrows` - the number of rows
+/// * `ncols` - the number of columns
+/// * `nnz` - the number of nonzero entries, or an upper bound on it
+///
+pub fn prefer_sparse(nrows: usize, ncols: usize, nnz: usize) -> bool {
+    let size = nrows.saturating_mul(ncols);
+    size >= AUTO_SPARSE_MIN_ENTRIES && (nnz as f64) <= AUTO_SPARSE_MAX_DENSITY * size as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::solve::LinearOperator;
+    use nalgebra as na;
+
+    #[test]
+    fn test_csc_matrix() {
+        // ** duplicates are summed, and the pair that cancels is dropped
+        let entries = vec![(1, 2, 4.0), (0, 0, 1.0), (2, 0, -1.0), (1, 2, 1.0), (0, 1, 2.0), (0, 1, -2.0)];
+        let sparse = sparse::CscMatrix::from_entries(3, 3, entries).unwrap();
+        let dense = na::DMatrix::from_row_slice(3, 3, &[1.0, 0.0, 0.0, 0.0, 0.0, 5.0, -1.0, 0.0, 0.0]);
+        assert_eq!(sparse.to_dense(), dense);
+        assert_eq!(sparse.nnz(), 3);
+        assert_eq!(sparse, sparse::CscMatrix::from_dense(&dense));
+        assert_eq!(sparse.column(0).collect::<Vec<_>>(), [(0, 1.0), (2, -1.0)]);
+        assert!(sparse::CscMatrix::from_entries(3, 3, vec![(3, 0, 1.0)]).is_err());
+
+        let x = na::DVector::from_row_slice(&[1.0, 2.0, 3.0]);
+        assert_eq!(sparse.apply(&x).unwrap(), &dense * &x);
+        assert_eq!(sparse.apply_transpose(&x).unwrap(), dense.tr_mul(&x));
+
+        // ** LSQR against the sparse matrix finds the minimum norm solution
+        let s_matrix = util::default_s_matrix();
+        let acc_vector: na::DVector<f64> = na::DVector::from_row_slice(matrices::A_STD_015);
+        let solution = solve::solve_lsqr(&acc_vector, &sparse::CscMatrix::from_dense(&s_matrix), solve::LSQR_TOLERANCE, 1000).unwrap();
+        assert!(util::vectors_eq(&solution.reactions, &solve::solve(acc_vector, s_matrix), 1e-4, 1e-9));
+
+        assert!(sparse::prefer_sparse(2000, 5000, 30_000));
+        assert!(!sparse::prefer_sparse(2000, 5000, 3_000_000));
+        assert!(!sparse::prefer_sparse(39, 28, 100));
+    }
+}
@@ -0,0 +1,278 @@
+use crate::util;
+
+use std::fs;
+use std::path::Path;
+
+use nalgebra as na;
+
+/// The environment variable that makes the golden file helpers rewrite golden files instead of
+/// comparing against them.
+pub const UPDATE_GOLDEN_VAR: &str = "SMAS_UPDATE_GOLDEN";
+
+/// This is how close two floats must be to be considered approximately equal.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Tolerance {
+    /// |a - b| <= tolerance
+    Abs(f64),
+    /// |a - b| <= tolerance * max(|a|, |b|)
+    Rel(f64),
+    /// a and b are at most this many representable floats apart
+    Ulps(u64),
+}
+
+/// This is a randomly generated problem with a known reaction vector.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    /// The stoichiometric matrix, A: (m x n)
+    pub s_matrix: na::DMatrix<f64>,
+    /// The reaction vector the accumulation vector was generated from, r; (n x 1)
+    pub reactions: na::DVector<f64>,
+    /// The accumulation vector, B = A r; (m x 1)
+    pub acc_vector: na::DVector<f64>,
+}
+
+/// This generates a random problem with integer stoichiometric coefficients between -2 and 2, of
+/// which roughly `density` are nonzero, and a standard normal reaction vector. The accumulation
+/// vector is generated from the reaction vector, so the system is always consistent.
+///
+/// # Arguments
+/// * `n_metabolites` - the number of rows of the matrix, m
+/// * `n_reactions` - the number of columns of the matrix, n
+/// * `density` - the fraction of nonzero coefficients, between 0 and 1
+/// * `seed` - the seed of the random number generator
+///
+pub fn random_problem(n_metabolites: usize, n_reactions: usize, density: f64, seed: u64) -> Problem {
+    let mut rng = util::SplitMix64::new(seed);
+    let s_matrix = na::DMatrix::from_fn(n_metabolites, n_reactions, |_, _| {
+        if rng.next_f64() < density {
+            let magnitude = if rng.next_f64() < 0.5 { 1.0 } else { 2.0 };
+            if rng.next_f64() < 0.5 { magnitude } else { -magnitude }
+        } else {
+            0.0
+        }
+    });
+    let reactions = na::DVector::from_fn(n_reactions, |_, _| rng.next_gaussian());
+    let acc_vector = &s_matrix * &reactions;
+    Problem {
+        s_matrix,
+        reactions,
+        acc_vector,
+    }
+}
+
+/// This checks whether two floats are approximately equal. NaN is never equal to anything.
+///
+/// # Arguments
+/// * `a` - the first float
+/// * `b` - the second float
+/// * `tolerance` - how close the floats must be
+///
+pub fn approx_eq(a: f64, b: f64, tolerance: Tolerance) -> bool {
+    if a == b {
+        return true;
+    }
+    if a.is_nan() || b.is_nan() {
+        return false;
+    }
+    match tolerance {
+        Tolerance::Abs(tol) => (a - b).abs() <= tol,
+        Tolerance::Rel(tol) => (a - b).abs() <= tol * a.abs().max(b.abs()),
+        Tolerance::Ulps(ulps) => {
+            if a.is_sign_positive() != b.is_sign_positive() {
+                return false;
+            }
+            a.to_bits().abs_diff(b.to_bits()) <= ulps
+        }
+    }
+}
+
+/// This finds the first position where two matrices (or vectors) differ by more than the
+/// tolerance, returning a description of the mismatch, or None if they are approximately equal.
+///
+/// # Arguments
+/// * `actual` - the computed matrix
+/// * `expected` - the expected matrix
+/// * `tolerance` - how close each pair of values must be
+///
+pub fn matrix_mismatch<R, C, S1, S2>(
+    actual: &na::Matrix<f64, R, C, S1>,
+    expected: &na::Matrix<f64, R, C, S2>,
+    tolerance: Tolerance,
+) -> Option<String>
+where
+    R: na::Dim,
+    C: na::Dim,
+    S1: na::RawStorage<f64, R, C>,
+    S2: na::RawStorage<f64, R, C>,
+{
+    if actual.shape() != expected.shape() {
+        return Some(format!("shape {:?} does not match the expected shape {:?}", actual.shape(), expected.shape()));
+    }
+    for j in 0..actual.ncols() {
+        for i in 0..actual.nrows() {
+            let (a, e) = (actual[(i, j)], expected[(i, j)]);
+            if !approx_eq(a, e, tolerance) {
+                return Some(format!("({i}, {j}): {a:e} does not match the expected {e:e} within {tolerance:?}"));
+            }
+        }
+    }
+    None
+}
+
+/// This panics with a description of the first mismatch if two matrices (or vectors) are not
+/// approximately equal.
+///
+/// # Arguments
+/// * `actual` - the computed matrix
+/// * `expected` - the expected matrix
+/// * `tolerance` - how close each pair of values must be
+///
+pub fn assert_matrix_approx_eq<R, C, S1, S2>(
+    actual: &na::Matrix<f64, R, C, S1>,
+    expected: &na::Matrix<f64, R, C, S2>,
+    tolerance: Tolerance,
+) where
+    R: na::Dim,
+    C: na::Dim,
+    S1: na::RawStorage<f64, R, C>,
+    S2: na::RawStorage<f64, R, C>,
+{
+    if let Some(mismatch) = matrix_mismatch(actual, expected, tolerance) {
+        panic!("matrices are not approximately equal: {mismatch}");
+    }
+}
+
+/// This compares output against a golden file, skipping comment lines (those starting with '%')
+/// so that provenance headers do not cause spurious differences. Returns a description of the
+/// first differing line, or None if the output matches.
+///
+/// If the SMAS_UPDATE_GOLDEN environment variable is set, the golden file is written with the
+/// output instead, and the comparison always succeeds.
+///
+/// # Arguments
+/// * `path` - the path to the golden file
+/// * `actual` - the output to compare
+///
+pub fn golden_mismatch<R: AsRef<Path>>(path: R, actual: &str) -> Option<String> {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        if let Err(e) = fs::write(path, actual) {
+            return Some(format!("failed to update {}: {e}", path.display()));
+        }
+        return None;
+    }
+
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) => return Some(format!("failed to read {}: {e}", path.display())),
+    };
+    let data_lines = |text: &str| -> Vec<String> {
+        text.lines()
+            .filter(|line| !line.starts_with('%'))
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    };
+    let actual_lines = data_lines(actual);
+    let expected_lines = data_lines(&expected);
+    for i in 0..actual_lines.len().max(expected_lines.len()) {
+        let a = actual_lines.get(i).map(|l| l.as_str()).unwrap_or("<end of output>");
+        let e = expected_lines.get(i).map(|l| l.as_str()).unwrap_or("<end of file>");
+        if a != e {
+            return Some(format!(
+                "data line {} differs from {}: found \"{a}\", expected \"{e}\"",
+                i + 1,
+                path.display()
+            ));
+        }
+    }
+    None
+}
+
+/// This panics with a description of the first difference if output does not match a golden file.
+///
+/// # Arguments
+/// * `path` - the path to the golden file
+/// * `actual` - the output to compare
+///
+pub fn assert_golden<R: AsRef<Path>>(path: R, actual: &str) {
+    if let Some(mismatch) = golden_mismatch(path, actual) {
+        panic!("{mismatch} (set {UPDATE_GOLDEN_VAR}=1 to update the golden file)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::testing::Tolerance;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_approx_eq() {
+        assert!(testing::approx_eq(1.0, 1.0 + 1e-10, Tolerance::Abs(1e-9)));
+        assert!(!testing::approx_eq(1.0, 1.1, Tolerance::Abs(1e-9)));
+        assert!(testing::approx_eq(1e6, 1e6 + 1.0, Tolerance::Rel(1e-5)));
+        assert!(testing::approx_eq(1.0, 1.0 + f64::EPSILON, Tolerance::Ulps(1)));
+        assert!(!testing::approx_eq(1.0, 1.0 + 4.0 * f64::EPSILON, Tolerance::Ulps(1)));
+        assert!(!testing::approx_eq(f64::NAN, f64::NAN, Tolerance::Abs(1.0)));
+    }
+
+    /// This generates random consistent problems of up to 10 metabolites and 14 reactions.
+    fn problems() -> impl Strategy<Value = testing::Problem> {
+        (1..10usize, 1..14usize, 0.1..1.0f64, any::<u64>())
+            .prop_map(|(m, n, density, seed)| testing::random_problem(m, n, density, seed))
+    }
+
+    proptest! {
+        #[test]
+        fn test_pinv_reproduces_consistent_problems(problem in problems()) {
+            // ** every consistent problem is fitted exactly, whatever its shape or rank
+            let r_vector = solve::solve(problem.acc_vector.clone(), problem.s_matrix.clone());
+            let fitted = &problem.s_matrix * &r_vector;
+            prop_assert!(testing::matrix_mismatch(&fitted, &problem.acc_vector, Tolerance::Abs(1e-8)).is_none());
+        }
+
+        #[test]
+        fn test_pinv_is_minimum_norm(problem in problems()) {
+            // ** of all the reaction vectors that fit, the pseudoinverse picks the shortest
+            let r_vector = solve::solve(problem.acc_vector.clone(), problem.s_matrix.clone());
+            prop_assert!(r_vector.norm() <= problem.reactions.norm() + 1e-8);
+        }
+
+        #[test]
+        fn test_jacobian_is_the_solution_operator(problem in problems()) {
+            let r_vector = solve::solve(problem.acc_vector.clone(), problem.s_matrix.clone());
+            let jacobian = solve::jacobian(&problem.s_matrix);
+            prop_assert!(testing::matrix_mismatch(&(jacobian * &problem.acc_vector), &r_vector, Tolerance::Abs(1e-8)).is_none());
+        }
+
+        #[test]
+        fn test_lsqr_agrees_with_pinv(problem in problems()) {
+            // ** started from zero, LSQR converges to the minimum norm solution of a consistent system;
+            // problems the pseudoinverse does not fit are left to test_pinv_reproduces_consistent_problems
+            let r_vector = solve::solve(problem.acc_vector.clone(), problem.s_matrix.clone());
+            prop_assume!((&problem.s_matrix * &r_vector - &problem.acc_vector).amax() <= 1e-8);
+            let solution = solve::solve_lsqr(&problem.acc_vector, &problem.s_matrix, solve::LSQR_TOLERANCE, 1000).unwrap();
+            prop_assert!(solution.converged);
+            let mismatch = testing::matrix_mismatch(&solution.reactions, &r_vector, Tolerance::Abs(1e-6));
+            prop_assert!(mismatch.is_none(), "{}", mismatch.unwrap());
+        }
+
+        #[test]
+        fn test_approx_eq_is_symmetric(a in -1e3..1e3f64, b in -1e3..1e3f64, tol in 0.0..1.0f64) {
+            for tolerance in [Tolerance::Abs(tol), Tolerance::Rel(tol), Tolerance::Ulps((tol * 1e3) as u64)] {
+                prop_assert_eq!(testing::approx_eq(a, b, tolerance), testing::approx_eq(b, a, tolerance));
+            }
+        }
+    }
+
+    #[test]
+    fn test_golden_pinv_solution() {
+        let golden_path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/golden/rpinv015.txt");
+        let a_vector = io::load_vector(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "resources/astd015.txt")).unwrap();
+        let mut r_vector = solve::solve(a_vector, util::default_s_matrix());
+        util::clamp_to_zero(&mut r_vector, 1e-3);
+        let provenance = io::Provenance::new("smas reaction vector", "solve");
+        let output = io::format_vector_mm_array(&r_vector, io::FloatFormat::Scientific, 3, &provenance);
+        testing::assert_golden(golden_path, &output);
+    }
+}
@@ -0,0 +1,117 @@
+use crate::constraints::Bounds;
+
+use nalgebra as na;
+
+/// The gas constant, R, in kJ/(mol K).
+pub const GAS_CONSTANT: f64 = 8.314462618e-3;
+
+/// The default temperature in Kelvin.
+pub const DEFAULT_TEMPERATURE: f64 = 298.15;
+
+/// The default lowest metabolite concentration in M (1 µM).
+pub const DEFAULT_MIN_CONCENTRATION: f64 = 1e-6;
+
+/// The default highest metabolite concentration in M (10 mM).
+pub const DEFAULT_MAX_CONCENTRATION: f64 = 1e-2;
+
+/// This is the thermodynamically feasible direction of a reaction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// The reaction can only carry non-negative flux
+    Forward,
+    /// The reaction can only carry non-positive flux
+    Backward,
+    /// The reaction can carry flux in either direction
+    Reversible,
+}
+
+/// This derives the feasible direction of each reaction from its standard transformed Gibbs
+/// energy of reaction, ΔG°'.
+///
+/// The Gibbs energy of a reaction is ΔG = ΔG°' + RT ln(Q). With every metabolite concentration
+/// between `min_concentration` and `max_concentration`, |RT ln(Q)| is at most
+/// RT ln(max/min) times the sum of the reaction's absolute stoichiometric coefficients. A reaction
+/// whose |ΔG°'| exceeds that is irreversible in the direction of negative ΔG; every other reaction,
+/// including reactions with an unknown (NaN) ΔG°', is reversible.
+///
+/// # Arguments
+/// * `dg0` - the ΔG°' of each reaction in kJ/mol, NaN if unknown; (n x 1)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `temperature` - the temperature in Kelvin
+/// * `min_concentration` - the lowest metabolite concentration in M
+/// * `max_concentration` - the highest metabolite concentration in M
+///
+pub fn reaction_directions(
+    dg0: &na::DVector<f64>,
+    s_matrix: &na::DMatrix<f64>,
+    temperature: f64,
+    min_concentration: f64,
+    max_concentration: f64,
+) -> Vec<Direction> {
+    let log_range = (max_concentration / min_concentration).ln();
+    dg0.iter()
+        .zip(s_matrix.column_iter())
+        .map(|(dg, column)| {
+            let max_log_q = log_range * column.iter().map(|s| s.abs()).sum::<f64>();
+            let threshold = GAS_CONSTANT * temperature * max_log_q;
+            if dg.is_nan() || dg.abs() <= threshold {
+                Direction::Reversible
+            } else if *dg < 0.0 {
+                Direction::Forward
+            } else {
+                Direction::Backward
+            }
+        })
+        .collect()
+}
+
+/// This converts reaction directions into sign constraints on the reaction vector.
+///
+/// # Arguments
+/// * `directions` - the direction of each reaction
+///
+pub fn direction_bounds(directions: &[Direction]) -> Bounds {
+    let mut bounds = Bounds::unbounded(directions.len());
+    for (i, direction) in directions.iter().enumerate() {
+        match direction {
+            Direction::Forward => bounds.lower[i] = 0.0,
+            Direction::Backward => bounds.upper[i] = 0.0,
+            Direction::Reversible => {}
+        }
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_reaction_directions() {
+        // ** A -> B at 298.15 K: RT ln(1e4) * 2 is about 45.7 kJ/mol
+        let s_matrix = na::DMatrix::from_row_slice(2, 4, &[
+            -1.0, -1.0, -1.0, -1.0,
+            1.0, 1.0, 1.0, 1.0,
+        ]);
+        let dg0 = na::DVector::from_row_slice(&[-60.0, 60.0, -10.0, f64::NAN]);
+        let directions = thermo::reaction_directions(
+            &dg0,
+            &s_matrix,
+            thermo::DEFAULT_TEMPERATURE,
+            thermo::DEFAULT_MIN_CONCENTRATION,
+            thermo::DEFAULT_MAX_CONCENTRATION,
+        );
+        assert_eq!(directions, vec![
+            thermo::Direction::Forward,
+            thermo::Direction::Backward,
+            thermo::Direction::Reversible,
+            thermo::Direction::Reversible,
+        ]);
+
+        let bounds = thermo::direction_bounds(&directions);
+        assert_eq!(bounds.lower[0], 0.0);
+        assert_eq!(bounds.upper[1], 0.0);
+        assert!(bounds.lower[2].is_infinite() && bounds.upper[2].is_infinite());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::error::SmasError;
+use crate::solve;
+
+use nalgebra as na;
+
+/// This is an enum used to parametrize how reaction fluxes may vary over a time course.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimeMode {
+    /// A single flux vector is fitted to every interval
+    Constant,
+    /// A separate flux vector is fitted to each interval
+    Piecewise,
+}
+
+/// This fits reaction fluxes to accumulation measurements taken at several time points.
+///
+/// Column j of `acc_matrix` is the accumulation measured at `times[j]`, relative to a common
+/// starting point. Over the interval from `times[j]` to `times[j+1]` the fluxes x_j are assumed
+/// constant, so the change in accumulation is A x_j (t_{j+1} - t_j). In constant mode one flux
+/// vector is shared by every interval. In piecewise mode each interval has its own flux vector,
+/// and `smooth` penalizes changes between consecutive intervals by smooth² ||x_{j+1} - x_j||².
+///
+/// The result has one column per interval (constant mode repeats the shared flux vector).
+///
+/// # Arguments
+/// * `acc_matrix` - the accumulation at each time point; (m x T)
+/// * `times` - the time of each column of `acc_matrix`, strictly increasing; (T)
+/// * `s_matrix` - the stoichiometric matrix, A: (m x n)
+/// * `mode` - whether the fluxes are constant or piecewise constant
+/// * `smooth` - the smoothness penalty for piecewise mode
+///
+pub fn fit_timecourse(
+    acc_matrix: &na::DMatrix<f64>,
+    times: &[f64],
+    s_matrix: &na::DMatrix<f64>,
+    mode: TimeMode,
+    smooth: f64,
+) -> Result<na::DMatrix<f64>, SmasError> {
+    let (m, n) = s_matrix.shape();
+    if acc_matrix.nrows() != m {
+        return Err(SmasError::Shape {
+            expected: m,
+            found: acc_matrix.nrows(),
+        });
+    }
+    if times.len() != acc_matrix.ncols() {
+        return Err(SmasError::Shape {
+            expected: acc_matrix.ncols(),
+            found: times.len(),
+        });
+    }
+    if times.len() < 2 || times.windows(2).any(|w| w[1] <= w[0]) {
+        return Err(SmasError::InvalidArgument(String::from(
+            "at least two strictly increasing time points are required",
+        )));
+    }
+
+    let n_intervals = times.len() - 1;
+    let n_blocks = match mode {
+        TimeMode::Constant => 1,
+        TimeMode::Piecewise => n_intervals,
+    };
+    let n_smooth_rows = match mode {
+        TimeMode::Constant => 0,
+        TimeMode::Piecewise => (n_intervals - 1) * n,
+    };
+
+    let mut system_matrix = na::DMatrix::zeros(n_intervals * m + n_smooth_rows, n_blocks * n);
+    let mut system_vector = na::DVector::zeros(n_intervals * m + n_smooth_rows);
+    for j in 0..n_intervals {
+        let block = match mode {
+            TimeMode::Constant => 0,
+            TimeMode::Piecewise => j,
+        };
+        let dt = times[j + 1] - times[j];
+        system_matrix.slice_mut((j * m, block * n), (m, n)).copy_from(&(s_matrix * dt));
+        system_vector.rows_mut(j * m, m).copy_from(&(acc_matrix.column(j + 1) - acc_matrix.column(j)));
+    }
+    for j in 0..n_smooth_rows / n.max(1) {
+        for i in 0..n {
+            let row = n_intervals * m + j * n + i;
+            system_matrix[(row, j * n + i)] = -smooth;
+            system_matrix[(row, (j + 1) * n + i)] = smooth;
+        }
+    }
+
+    let stacked = solve::solve(system_vector, system_matrix);
+    let blocks = na::DMatrix::from_column_slice(n, n_blocks, stacked.as_slice());
+    Ok(match mode {
+        TimeMode::Constant => na::DMatrix::from_fn(n, n_intervals, |i, _| blocks[(i, 0)]),
+        TimeMode::Piecewise => blocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_fit_timecourse() {
+        // ** a single metabolite produced by one reaction: 2 per unit time, then 4 per unit time
+        let s_matrix = na::DMatrix::from_row_slice(1, 1, &[1.0]);
+        let acc_matrix = na::DMatrix::from_row_slice(1, 3, &[0.0, 2.0, 6.0]);
+        let times = [0.0, 1.0, 2.0];
+
+        let piecewise = timecourse::fit_timecourse(&acc_matrix, &times, &s_matrix, timecourse::TimeMode::Piecewise, 0.0).unwrap();
+        assert!(util::epsilon_eq(piecewise[(0, 0)], 2.0, 1e-12));
+        assert!(util::epsilon_eq(piecewise[(0, 1)], 4.0, 1e-12));
+
+        let constant = timecourse::fit_timecourse(&acc_matrix, &times, &s_matrix, timecourse::TimeMode::Constant, 0.0).unwrap();
+        assert_eq!(constant.shape(), (1, 2));
+        assert!(util::epsilon_eq(constant[(0, 0)], 3.0, 1e-12));
+        assert!(util::epsilon_eq(constant[(0, 1)], 3.0, 1e-12));
+
+        // ** a strong smoothness penalty pulls the piecewise fluxes toward the constant fit
+        let smoothed = timecourse::fit_timecourse(&acc_matrix, &times, &s_matrix, timecourse::TimeMode::Piecewise, 1e3).unwrap();
+        assert!(util::epsilon_eq(smoothed[(0, 0)], 3.0, 1e-3));
+        assert!(util::epsilon_eq(smoothed[(0, 1)], 3.0, 1e-3));
+    }
+}
@@ -0,0 +1,429 @@
+use crate::heatmap::HeatmapStyle;
+use crate::io::LabeledMatrix;
+use crate::util;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use nalgebra as na;
+use ratatui::backend::Backend;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, Tabs};
+use ratatui::{Frame, Terminal};
+
+/// This is a pane of the dashboard, one of which is shown at a time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pane {
+    /// A heatmap of the stoichiometric matrix, one row per metabolite
+    Matrix,
+    /// A bar chart of the solution, one bar per reaction
+    Solution,
+    /// A bar chart of the residual Ax - B, one bar per metabolite
+    Residuals,
+    /// A table comparing the solution with a ground truth reaction vector
+    Validation,
+}
+
+/// The panes in the order they are shown in the tab bar.
+pub const PANES: [Pane; 4] = [Pane::Matrix, Pane::Solution, Pane::Residuals, Pane::Validation];
+
+impl Pane {
+    /// This returns the title of the pane shown in the tab bar.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Pane::Matrix => "Matrix",
+            Pane::Solution => "Solution",
+            Pane::Residuals => "Residuals",
+            Pane::Validation => "Validation",
+        }
+    }
+
+    /// This returns the position of the pane in the tab bar.
+    fn index(&self) -> usize {
+        PANES.iter().position(|pane| pane == self).unwrap()
+    }
+}
+
+/// This is the state of the dashboard: the model, the solve it shows, and where the user is.
+pub struct Dashboard {
+    /// The labeled stoichiometric matrix, A: (m x n)
+    pub s_matrix: LabeledMatrix,
+    /// The accumulation vector, B; (m x 1)
+    pub acc_vector: na::DVector<f64>,
+    /// The solution, x; (n x 1)
+    pub reactions: na::DVector<f64>,
+    /// The ground truth reaction vector for the validation pane, if there is one; (n x 1)
+    pub truth: Option<na::DVector<f64>>,
+    /// The tolerance of the validation pane
+    pub epsilon: f64,
+    /// The pane being shown
+    pub pane: Pane,
+    /// The first line of the pane being shown
+    pub scroll: usize,
+    /// The number of lines of the pane that were visible when it was last drawn
+    page_height: usize,
+}
+
+impl Dashboard {
+    /// This creates a dashboard showing the matrix pane.
+    ///
+    /// # Arguments
+    /// * `s_matrix` - the labeled stoichiometric matrix, A: (m x n)
+    /// * `acc_vector` - the accumulation vector, B; (m x 1)
+    /// * `reactions` - the solution, x; (n x 1)
+    /// * `truth` - the ground truth reaction vector, if there is one; (n x 1)
+    /// * `epsilon` - the tolerance of the validation pane
+    ///
+    pub fn new(
+        s_matrix: LabeledMatrix,
+        acc_vector: na::DVector<f64>,
+        reactions: na::DVector<f64>,
+        truth: Option<na::DVector<f64>>,
+        epsilon: f64,
+    ) -> Self {
+        Dashboard {
+            s_matrix,
+            acc_vector,
+            reactions,
+            truth,
+            epsilon,
+            pane: Pane::Matrix,
+            scroll: 0,
+            page_height: 1,
+        }
+    }
+
+    /// This handles one key press: Left/Right, h/l or Tab/Shift-Tab move between panes, 1-4 jump
+    /// to a pane, Up/Down or j/k scroll by a line, PageUp/PageDown by a page, Home/End jump to the
+    /// top or bottom, and q, Esc or Ctrl-C quit. Returns false once the user has quit.
+    ///
+    /// # Arguments
+    /// * `key` - the key that was pressed
+    ///
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let index = self.pane.index();
+        let pane = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => PANES[(index + 1) % PANES.len()],
+            KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => PANES[(index + PANES.len() - 1) % PANES.len()],
+            KeyCode::Char(key @ '1'..='4') => PANES[key as usize - '1' as usize],
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.scroll += 1;
+                return true;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.scroll = self.scroll.saturating_sub(1);
+                return true;
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => {
+                self.scroll += self.page_height;
+                return true;
+            }
+            KeyCode::PageUp => {
+                self.scroll = self.scroll.saturating_sub(self.page_height);
+                return true;
+            }
+            KeyCode::Home => {
+                self.scroll = 0;
+                return true;
+            }
+            KeyCode::End => {
+                // ** drawing clamps the scroll to the last page
+                self.scroll = usize::MAX;
+                return true;
+            }
+            _ => return true,
+        };
+        if pane != self.pane {
+            self.pane = pane;
+            self.scroll = 0;
+        }
+        true
+    }
+
+    /// This returns every line of the pane being shown, before scrolling. The validation pane is
+    /// a table and has none when there is a ground truth to compare with.
+    fn pane_lines(&self, width: usize) -> Vec<Line<'static>> {
+        match self.pane {
+            Pane::Matrix => self.matrix_lines(width),
+            Pane::Solution => {
+                let labels: Vec<String> = (0..self.reactions.nrows()).map(|j| self.s_matrix.col_label(j)).collect();
+                bar_lines(&labels, &self.reactions, width)
+            }
+            Pane::Residuals => {
+                let residual = &self.s_matrix.matrix * &self.reactions - &self.acc_vector;
+                let labels: Vec<String> = (0..residual.nrows()).map(|i| self.s_matrix.row_label(i)).collect();
+                let mut lines = vec![Line::from(format!("||Ax - B|| = {:.3e}", residual.norm()))];
+                lines.extend(bar_lines(&labels, &residual, width));
+                lines
+            }
+            Pane::Validation => match &self.truth {
+                None => vec![Line::from("no ground truth reaction vector was given (-r)")],
+                Some(truth) if truth.nrows() != self.reactions.nrows() => vec![Line::from(format!(
+                    "the ground truth has {} reactions, but the solution has {}",
+                    truth.nrows(),
+                    self.reactions.nrows()
+                ))],
+                Some(_) => vec![],
+            },
+        }
+    }
+
+    /// This draws the matrix as a heatmap: one row per metabolite and one cell per reaction,
+    /// shaded by magnitude, green for products and red for substrates.
+    fn matrix_lines(&self, width: usize) -> Vec<Line<'static>> {
+        let matrix = &self.s_matrix.matrix;
+        let labels: Vec<String> = (0..matrix.nrows()).map(|i| self.s_matrix.row_label(i)).collect();
+        let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+        let max = matrix.amax();
+        let style = HeatmapStyle { magnitude: true, color: true, ascii: false };
+        let n_visible = matrix.ncols().min(width.saturating_sub(label_width + 1));
+
+        let mut lines = vec![Line::from(format!(
+            "{} metabolites x {} reactions, |max| = {max:.3e}{}",
+            matrix.nrows(),
+            matrix.ncols(),
+            if n_visible < matrix.ncols() { format!(", showing the first {n_visible} reactions") } else { String::new() }
+        ))];
+        for (label, row) in labels.iter().zip(matrix.row_iter()) {
+            let mut spans = vec![Span::raw(format!("{label:<label_width$} "))];
+            for val in row.iter().take(n_visible) {
+                if *val == 0.0 {
+                    spans.push(Span::raw("·"));
+                    continue;
+                }
+                let color = if *val > 0.0 { Color::Green } else { Color::Red };
+                spans.push(Span::styled(style.cell(*val, max).to_string(), Style::default().fg(color)));
+            }
+            lines.push(Line::from(spans));
+        }
+        lines
+    }
+
+    /// This compares the solution with the ground truth, one row per reaction, starting at the
+    /// scrolled to reaction.
+    fn validation_table(&self, truth: &na::DVector<f64>) -> Table<'static> {
+        let n_within = util::count_epsilon_eq(self.reactions.as_slice(), truth.as_slice(), self.epsilon);
+        let rows: Vec<Row> = self.reactions.iter()
+            .zip(truth.iter())
+            .enumerate()
+            .skip(self.scroll)
+            .map(|(j, (computed, expected))| {
+                let (color, mark) = if util::epsilon_eq(*expected, *computed, self.epsilon) {
+                    (Color::Green, "ok")
+                } else {
+                    (Color::Red, "FAIL")
+                };
+                Row::new(vec![
+                    Cell::from(self.s_matrix.col_label(j)),
+                    Cell::from(format!("{computed:>12.3e}")),
+                    Cell::from(format!("{expected:>12.3e}")),
+                    Cell::from(format!("{:>12.3e}", (computed - expected).abs())),
+                    Cell::from(Span::styled(mark, Style::default().fg(color))),
+                ])
+            })
+            .collect();
+        let widths = [
+            Constraint::Min(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(12),
+            Constraint::Length(4),
+        ];
+        Table::new(rows, widths)
+            .header(
+                Row::new(vec!["reaction", "    computed", "        true", "     |delta|", ""])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(pane_block(Pane::Validation).title_bottom(format!(
+                "{n_within}/{} within epsilon {:e}",
+                truth.nrows(),
+                self.epsilon
+            )))
+    }
+
+    /// This draws the dashboard: a tab bar, the visible part of the pane, and a footer with the
+    /// keys. Scrolling past the end of the pane is clamped.
+    ///
+    /// # Arguments
+    /// * `frame` - the frame to draw on, which has the size of the terminal
+    ///
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, body_area, footer_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let titles = PANES.iter().enumerate().map(|(i, pane)| format!("{} {}", i + 1, pane.title()));
+        frame.render_widget(
+            Tabs::new(titles)
+                .select(self.pane.index())
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            tabs_area,
+        );
+
+        // ** the body is drawn in a block with a border on every side
+        self.page_height = usize::from(body_area.height.saturating_sub(2)).max(1);
+        let width = usize::from(body_area.width.saturating_sub(2));
+        let n_lines = match (&self.truth, self.pane) {
+            (Some(truth), Pane::Validation) if truth.nrows() == self.reactions.nrows() => {
+                // ** the table header takes a line of the page
+                self.page_height = self.page_height.saturating_sub(1).max(1);
+                truth.nrows()
+            }
+            _ => self.pane_lines(width).len(),
+        };
+        self.scroll = self.scroll.min(n_lines.saturating_sub(self.page_height));
+
+        match (&self.truth, self.pane) {
+            (Some(truth), Pane::Validation) if truth.nrows() == self.reactions.nrows() => {
+                frame.render_widget(self.validation_table(truth), body_area);
+            }
+            _ => {
+                let scroll = u16::try_from(self.scroll).unwrap_or(u16::MAX);
+                frame.render_widget(
+                    Paragraph::new(self.pane_lines(width)).block(pane_block(self.pane)).scroll((scroll, 0)),
+                    body_area,
+                );
+            }
+        }
+
+        let footer = format!(
+            "←/→: pane  1-4: jump  ↑/↓ PgUp/PgDn: scroll  q: quit  (lines {}-{} of {})",
+            (self.scroll + 1).min(n_lines),
+            (self.scroll + self.page_height).min(n_lines),
+            n_lines
+        );
+        frame.render_widget(Paragraph::new(footer).style(Style::default().add_modifier(Modifier::DIM)), footer_area);
+    }
+}
+
+/// This returns the bordered block a pane is drawn in, titled with the pane's title.
+fn pane_block(pane: Pane) -> Block<'static> {
+    Block::default().borders(Borders::ALL).title(pane.title())
+}
+
+/// This draws one horizontal bar per value, scaled to the largest magnitude, green for positive
+/// and red for negative values.
+fn bar_lines(labels: &[String], values: &na::DVector<f64>, width: usize) -> Vec<Line<'static>> {
+    let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+    let bar_width = width.saturating_sub(label_width + 13).max(1);
+    let max = values.amax();
+    labels.iter()
+        .zip(values.iter())
+        .map(|(label, val)| {
+            let length = if max > 0.0 { (val.abs() / max * bar_width as f64).round() as usize } else { 0 };
+            let color = if *val < 0.0 { Color::Red } else { Color::Green };
+            Line::from(vec![
+                Span::raw(format!("{label:<label_width$} {val:>10.3e} ")),
+                Span::styled("█".repeat(length), Style::default().fg(color)),
+            ])
+        })
+        .collect()
+}
+
+/// This runs the dashboard on a terminal until the user quits or the events end, redrawing it
+/// after every event so that it follows the size of the terminal.
+///
+/// # Arguments
+/// * `dashboard` - the dashboard to run
+/// * `terminal` - the terminal to draw on
+/// * `events` - the terminal events, e.g. read with crossterm::event::read
+///
+pub fn run_events<B: Backend>(
+    dashboard: &mut Dashboard,
+    terminal: &mut Terminal<B>,
+    events: impl IntoIterator<Item = std::io::Result<Event>>,
+) -> std::io::Result<()> {
+    terminal.draw(|frame| dashboard.draw(frame))?;
+    for event in events {
+        // ** key releases are reported on some platforms too, and must not act twice
+        if let Event::Key(key) = event? {
+            if key.kind == KeyEventKind::Press && !dashboard.handle_key(key) {
+                return Ok(());
+            }
+        }
+        terminal.draw(|frame| dashboard.draw(frame))?;
+    }
+    Ok(())
+}
+
+/// This runs the dashboard full screen on the terminal until the user quits. Keys are read one
+/// at a time in raw mode, and the terminal is restored afterwards, or if smas panics.
+///
+/// # Arguments
+/// * `dashboard` - the dashboard to run
+///
+pub fn run(dashboard: &mut Dashboard) -> std::io::Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = run_events(dashboard, &mut terminal, std::iter::repeat_with(event::read));
+    ratatui::try_restore()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use nalgebra as na;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    /// This returns the text on the screen of a test terminal, one line per row.
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_dashboard() {
+        let s_matrix = io::LabeledMatrix {
+            matrix: na::DMatrix::from_row_slice(2, 2, &[1.0, -1.0, 0.0, 2.0]),
+            row_labels: Some(vec![String::from("g6p"), String::from("f6p")]),
+            col_labels: Some(vec![String::from("hk"), String::from("pgi")]),
+        };
+        let acc_vector = na::DVector::from_row_slice(&[1.0, 2.0]);
+        let reactions = solve::solve(acc_vector.clone(), s_matrix.matrix.clone());
+        let truth = na::DVector::from_row_slice(&[2.0, 5.0]);
+        let mut dashboard = tui::Dashboard::new(s_matrix, acc_vector, reactions, Some(truth), 1e-6);
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+
+        // ** keys move between panes, wrapping around, and q quits
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        assert!(screen(&terminal).contains("2 metabolites x 2 reactions"), "{}", screen(&terminal));
+        assert!(dashboard.handle_key(key(KeyCode::Left)));
+        assert_eq!(dashboard.pane, tui::Pane::Validation);
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        assert!(screen(&terminal).contains("1/2 within epsilon"), "{}", screen(&terminal));
+        assert!(screen(&terminal).contains("FAIL"), "{}", screen(&terminal));
+        assert!(dashboard.handle_key(key(KeyCode::Char('2'))));
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        assert!(screen(&terminal).contains("pgi"));
+
+        // ** scrolling stops at the last page of a small terminal
+        let mut small = Terminal::new(TestBackend::new(80, 6)).unwrap();
+        dashboard.handle_key(key(KeyCode::Char('3')));
+        dashboard.handle_key(key(KeyCode::Down));
+        dashboard.handle_key(key(KeyCode::PageDown));
+        small.draw(|frame| dashboard.draw(frame)).unwrap();
+        assert_eq!(dashboard.scroll, 1);
+        assert!(screen(&small).contains("lines 2-3 of 3"), "{}", screen(&small));
+
+        // ** keys are read one at a time, and releases are ignored
+        let release = KeyEvent { kind: crossterm::event::KeyEventKind::Release, ..key(KeyCode::Right) };
+        let events = [key(KeyCode::Char('1')), key(KeyCode::Tab), release, key(KeyCode::Char('q')), key(KeyCode::Char('4'))]
+            .map(|key| Ok(Event::Key(key)));
+        tui::run_events(&mut dashboard, &mut terminal, events).unwrap();
+        assert_eq!(dashboard.pane, tui::Pane::Solution);
+        assert!(!dashboard.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+    }
+}
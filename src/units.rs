@@ -0,0 +1,123 @@
+use crate::error::SmasError;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The amount units, with their size in mmol.
+const AMOUNTS: [(&str, f64); 6] = [
+    ("mol", 1e3),
+    ("mmol", 1.0),
+    ("umol", 1e-3),
+    ("µmol", 1e-3),
+    ("nmol", 1e-6),
+    ("pmol", 1e-9),
+];
+
+/// The time units, with their length in seconds, which keeps common factors exact.
+const TIMES: [(&str, f64); 7] = [
+    ("s", 1.0),
+    ("sec", 1.0),
+    ("min", 60.0),
+    ("h", 3600.0),
+    ("hr", 3600.0),
+    ("d", 86400.0),
+    ("day", 86400.0),
+];
+
+/// This is the unit of an accumulation or flux value: an amount, e.g. "umol", optionally per a
+/// time, e.g. "umol/min". Units are declared in vector files with a "%units: umol/min" comment.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Unit {
+    /// The amount, e.g. "umol"
+    pub amount: String,
+    /// The time the amount is per, e.g. "min", if the values are rates
+    pub time: Option<String>,
+}
+
+impl FromStr for Unit {
+    type Err = SmasError;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        let error = SmasError::InvalidArgument;
+        let (amount, time) = match unit.trim().split_once('/') {
+            Some((amount, time)) => (amount.trim(), Some(time.trim())),
+            None => (unit.trim(), None),
+        };
+        if !AMOUNTS.iter().any(|(name, _)| *name == amount) {
+            let names: Vec<&str> = AMOUNTS.iter().map(|(name, _)| *name).collect();
+            return Err(error(format!("unknown amount unit \"{amount}\", expected one of: {}", names.join(", "))));
+        }
+        if let Some(time) = time {
+            if !TIMES.iter().any(|(name, _)| *name == time) {
+                let names: Vec<&str> = TIMES.iter().map(|(name, _)| *name).collect();
+                return Err(error(format!("unknown time unit \"{time}\", expected one of: {}", names.join(", "))));
+            }
+        }
+        Ok(Unit {
+            amount: amount.to_string(),
+            time: time.map(String::from),
+        })
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.time {
+            Some(time) => write!(f, "{}/{time}", self.amount),
+            None => write!(f, "{}", self.amount),
+        }
+    }
+}
+
+impl Unit {
+    /// This returns the size of the amount in mmol, and the length of the time in seconds, or 1
+    /// if the unit is not a rate.
+    fn size(&self) -> (f64, f64) {
+        let amount = AMOUNTS.iter().find(|(name, _)| *name == self.amount).map_or(1.0, |(_, size)| *size);
+        let time = match &self.time {
+            Some(time) => TIMES.iter().find(|(name, _)| name == time).map_or(1.0, |(_, size)| *size),
+            None => 1.0,
+        };
+        (amount, time)
+    }
+
+    /// This returns the factor that converts values in this unit to another, e.g. 0.06 from
+    /// umol/min to mmol/h. Returns SmasError::InvalidArgument if only one of the units is a rate, since an
+    /// amount cannot be converted to a rate.
+    ///
+    /// # Arguments
+    /// * `to` - the unit to convert to
+    ///
+    pub fn conversion_factor(&self, to: &Unit) -> Result<f64, SmasError> {
+        if self.time.is_some() != to.time.is_some() {
+            return Err(SmasError::InvalidArgument(
+                format!("cannot convert {self} to {to}: only one of them is a rate"),
+            ));
+        }
+        let ((from_amount, from_time), (to_amount, to_time)) = (self.size(), to.size());
+        Ok((from_amount / to_amount) * (to_time / from_time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_conversion_factor() {
+        let from: units::Unit = "umol/min".parse().unwrap();
+        let to: units::Unit = "mmol/h".parse().unwrap();
+        assert_eq!(from.conversion_factor(&to).unwrap(), 0.06);
+        assert!((to.conversion_factor(&from).unwrap() - 1.0 / 0.06).abs() < 1e-12);
+        let factor = "µmol".parse::<units::Unit>().unwrap().conversion_factor(&"mol".parse().unwrap()).unwrap();
+        assert!((factor - 1e-6).abs() < 1e-20);
+        assert_eq!(from.to_string(), "umol/min");
+
+        assert!(matches!(
+            from.conversion_factor(&"mmol".parse().unwrap()),
+            Err(error::SmasError::InvalidArgument(_))
+        ));
+        assert!("kg/min".parse::<units::Unit>().is_err());
+        assert!("umol/week".parse::<units::Unit>().is_err());
+    }
+}
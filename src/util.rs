@@ -1,3 +1,5 @@
+use crate::error::SmasError;
+
 use nalgebra as na;
 
 
@@ -16,6 +18,478 @@ pub fn epsilon_eq(a: f64, b: f64, epsilon: f64) -> bool {
     (a - b).abs() < epsilon
 }
 
+/// This is a small seedable pseudo random number generator (SplitMix64), so that randomized
+/// computations are reproducible from their seed.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// This creates a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// This draws the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// This draws a uniform value in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// This draws a standard normal value with the Box-Muller transform.
+    pub fn next_gaussian(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+/// This sets every value of a vector whose magnitude is below `epsilon` to exactly zero.
+///
+/// # Arguments
+///
+/// - `vector` - the vector to be clamped in place
+/// - `epsilon` - values with an absolute value smaller than this are considered equal to 0
+///
+pub fn clamp_to_zero(vector: &mut na::DVector<f64>, epsilon: f64) {
+    for val in vector.iter_mut() {
+        if epsilon_eq(*val, 0.0, epsilon) {
+            *val = 0.0;
+        }
+    }
+}
+
+/// This loads four consecutive values into a SIMD vector.
+#[cfg(feature = "simd")]
+fn load_f64x4(values: &[f64]) -> wide::f64x4 {
+    wide::f64x4::new([values[0], values[1], values[2], values[3]])
+}
+
+/// This computes the largest absolute difference between two slices of equal length,
+/// max |a_i - b_i|, four values at a time when the simd feature is enabled. The result is NaN if
+/// any difference is NaN, so that a NaN in either slice is never reported as a match.
+///
+/// # Arguments
+///
+/// - `a` - the first slice
+/// - `b` - the second slice
+///
+pub fn max_abs_delta(a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len(), "slices must have equal lengths");
+    #[cfg(feature = "simd")]
+    {
+        let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+        let tail = a_chunks.remainder().iter()
+            .zip(b_chunks.remainder())
+            .fold(0.0, |max: f64, (x, y)| max_or_nan(max, (x - y).abs()));
+        // ** the lane maximum ignores NaN like f64::max, so NaN lanes are tracked separately
+        let (max, nan) = a_chunks.zip(b_chunks)
+            .fold((wide::f64x4::ZERO, wide::f64x4::ZERO), |(max, nan), (x, y)| {
+                let delta = (load_f64x4(x) - load_f64x4(y)).abs();
+                (max.max(delta), nan | delta.is_nan())
+            });
+        if nan.any() {
+            return f64::NAN;
+        }
+        max.to_array().iter().fold(tail, |max, x| max_or_nan(max, *x))
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        a.iter().zip(b).fold(0.0, |max: f64, (x, y)| max_or_nan(max, (x - y).abs()))
+    }
+}
+
+/// This is the larger of two floats like f64::max, except that it is NaN if either float is NaN.
+fn max_or_nan(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+/// This computes the sum of squared differences between two slices of equal length,
+/// sum (a_i - b_i)², four values at a time when the simd feature is enabled.
+///
+/// # Arguments
+///
+/// - `a` - the first slice
+/// - `b` - the second slice
+///
+pub fn sum_squared_delta(a: &[f64], b: &[f64]) -> f64 {
+    assert_eq!(a.len(), b.len(), "slices must have equal lengths");
+    #[cfg(feature = "simd")]
+    {
+        let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+        let tail: f64 = a_chunks.remainder().iter()
+            .zip(b_chunks.remainder())
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        let sum = a_chunks.zip(b_chunks).fold(wide::f64x4::ZERO, |sum, (x, y)| {
+            let delta = load_f64x4(x) - load_f64x4(y);
+            sum + delta * delta
+        });
+        sum.reduce_add() + tail
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+    }
+}
+
+/// This is how a sum of floats is accumulated. Long sums can lose precision when each value is
+/// added to a much larger running total, so the choice trades speed for reproducibility.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Summation {
+    /// Add the values in order (four lanes at a time with the simd feature); the fastest, but the
+    /// error can grow with the number of values
+    Naive,
+    /// Neumaier's compensated (Kahan) summation; the error does not grow with the number of values
+    #[default]
+    Compensated,
+    /// Add the values in pairs recursively; the error grows with the logarithm of the number of values
+    Pairwise,
+}
+
+/// Sums of at most this many values are added in order by pairwise summation.
+const PAIRWISE_BLOCK: usize = 8;
+
+/// This sums values by pairwise summation.
+fn pairwise_sum(values: &[f64]) -> f64 {
+    if values.len() <= PAIRWISE_BLOCK {
+        return values.iter().sum();
+    }
+    let (left, right) = values.split_at(values.len() / 2);
+    pairwise_sum(left) + pairwise_sum(right)
+}
+
+/// This sums values with the chosen summation method.
+///
+/// # Arguments
+///
+/// - `values` - the values to sum
+/// - `summation` - how the sum is accumulated
+///
+pub fn sum_with(values: &[f64], summation: Summation) -> f64 {
+    match summation {
+        Summation::Naive => values.iter().sum(),
+        Summation::Compensated => {
+            let mut sum = 0.0;
+            let mut compensation = 0.0;
+            for value in values {
+                let total = sum + value;
+                // ** the low order bits lost from whichever operand is smaller
+                if f64::abs(sum) >= value.abs() {
+                    compensation += (sum - total) + value;
+                } else {
+                    compensation += (value - total) + sum;
+                }
+                sum = total;
+            }
+            sum + compensation
+        }
+        Summation::Pairwise => pairwise_sum(values),
+    }
+}
+
+/// This computes the sum of squared differences between two slices of equal length,
+/// sum (a_i - b_i)², with the chosen summation method. Naive summation is `sum_squared_delta`.
+///
+/// # Arguments
+///
+/// - `a` - the first slice
+/// - `b` - the second slice
+/// - `summation` - how the sum is accumulated
+///
+pub fn sum_squared_delta_with(a: &[f64], b: &[f64], summation: Summation) -> f64 {
+    if summation == Summation::Naive {
+        return sum_squared_delta(a, b);
+    }
+    assert_eq!(a.len(), b.len(), "slices must have equal lengths");
+    let squares: Vec<f64> = a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).collect();
+    sum_with(&squares, summation)
+}
+
+/// This counts the positions where two slices of equal length differ by less than `epsilon`,
+/// i.e. where `epsilon_eq` holds, four values at a time when the simd feature is enabled.
+///
+/// # Arguments
+///
+/// - `a` - the first slice
+/// - `b` - the second slice
+/// - `epsilon` - the value epsilon that each difference is compared to
+///
+pub fn count_epsilon_eq(a: &[f64], b: &[f64], epsilon: f64) -> usize {
+    assert_eq!(a.len(), b.len(), "slices must have equal lengths");
+    #[cfg(feature = "simd")]
+    {
+        let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+        let tail = a_chunks.remainder().iter()
+            .zip(b_chunks.remainder())
+            .filter(|(x, y)| epsilon_eq(**x, **y, epsilon))
+            .count();
+        let threshold = wide::f64x4::splat(epsilon);
+        let count: u32 = a_chunks.zip(b_chunks)
+            .map(|(x, y)| (load_f64x4(x) - load_f64x4(y)).abs().simd_lt(threshold).to_bitmask().count_ones())
+            .sum();
+        count as usize + tail
+    }
+    #[cfg(not(feature = "simd"))]
+    {
+        a.iter().zip(b).filter(|(x, y)| epsilon_eq(**x, **y, epsilon)).count()
+    }
+}
+
+/// This computes the max-norm distance between two vectors or matrices, max |a_ij - b_ij|.
+/// The distance between values of different shapes is infinite.
+///
+/// # Arguments
+///
+/// - `a` - the first vector or matrix
+/// - `b` - the second vector or matrix
+///
+pub fn max_norm_distance<R, C, S1, S2>(a: &na::Matrix<f64, R, C, S1>, b: &na::Matrix<f64, R, C, S2>) -> f64
+    where
+        R: na::Dim,
+        C: na::Dim,
+        S1: na::RawStorage<f64, R, C> + na::IsContiguous,
+        S2: na::RawStorage<f64, R, C> + na::IsContiguous,
+{
+    if a.shape() != b.shape() {
+        return f64::INFINITY;
+    }
+    max_abs_delta(a.as_slice(), b.as_slice())
+}
+
+/// This compares two vectors or matrices entry by entry. Each pair of entries must be within
+/// `abs_tol` of each other, or within `rel_tol` times the larger of their magnitudes.
+fn entries_eq<R, C, S1, S2>(
+    a: &na::Matrix<f64, R, C, S1>,
+    b: &na::Matrix<f64, R, C, S2>,
+    abs_tol: f64,
+    rel_tol: f64,
+) -> bool
+    where
+        R: na::Dim,
+        C: na::Dim,
+        S1: na::RawStorage<f64, R, C>,
+        S2: na::RawStorage<f64, R, C>,
+{
+    a.shape() == b.shape() && a.iter().zip(b.iter()).all(|(x, y)| {
+        let delta = (x - y).abs();
+        delta <= abs_tol || delta <= rel_tol * x.abs().max(y.abs())
+    })
+}
+
+/// Compare two vectors to see if they are equal for practical purposes.
+///
+/// The vectors are equal if they have the same length and every pair of entries is within
+/// `abs_tol` of each other, or within `rel_tol` times the larger of their magnitudes.
+///
+/// # Arguments
+///
+/// - `a` - the first vector to compare
+/// - `b` - the second vector to compare
+/// - `abs_tol` - the absolute tolerance
+/// - `rel_tol` - the relative tolerance
+///
+pub fn vectors_eq(a: &na::DVector<f64>, b: &na::DVector<f64>, abs_tol: f64, rel_tol: f64) -> bool {
+    entries_eq(a, b, abs_tol, rel_tol)
+}
+
+/// Compare two matrices to see if they are equal for practical purposes.
+///
+/// The matrices are equal if they have the same shape and every pair of entries is within
+/// `abs_tol` of each other, or within `rel_tol` times the larger of their magnitudes.
+///
+/// # Arguments
+///
+/// - `a` - the first matrix to compare
+/// - `b` - the second matrix to compare
+/// - `abs_tol` - the absolute tolerance
+/// - `rel_tol` - the relative tolerance
+///
+pub fn matrix_eq(a: &na::DMatrix<f64>, b: &na::DMatrix<f64>, abs_tol: f64, rel_tol: f64) -> bool {
+    entries_eq(a, b, abs_tol, rel_tol)
+}
+
+/// This is one element-wise step of the preprocessing applied to an accumulation vector before it
+/// is solved, e.g. subtracting the accumulation of a control condition.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Preprocess {
+    /// Add another vector of the same length
+    Add(na::DVector<f64>),
+    /// Subtract another vector of the same length
+    Subtract(na::DVector<f64>),
+    /// Multiply every value by a constant
+    Scale(f64),
+}
+
+impl Preprocess {
+    /// This applies the step to a vector. Returns SmasError::Shape if the vector added or
+    /// subtracted has a different length.
+    ///
+    /// # Arguments
+    /// * `vector` - the vector to be preprocessed
+    ///
+    pub fn apply(&self, vector: na::DVector<f64>) -> Result<na::DVector<f64>, SmasError> {
+        let check_length = |other: &na::DVector<f64>| {
+            if other.nrows() != vector.nrows() {
+                return Err(SmasError::Shape {
+                    expected: vector.nrows(),
+                    found: other.nrows(),
+                });
+            }
+            Ok(())
+        };
+        match self {
+            Preprocess::Add(other) => {
+                check_length(other)?;
+                Ok(vector + other)
+            }
+            Preprocess::Subtract(other) => {
+                check_length(other)?;
+                Ok(vector - other)
+            }
+            Preprocess::Scale(factor) => Ok(vector * *factor),
+        }
+    }
+}
+
+/// This applies a pipeline of preprocessing steps to a vector, in order.
+///
+/// # Arguments
+/// * `vector` - the vector to be preprocessed
+/// * `steps` - the steps, applied first to last
+///
+pub fn preprocess(vector: na::DVector<f64>, steps: &[Preprocess]) -> Result<na::DVector<f64>, SmasError> {
+    steps.iter().try_fold(vector, |vector, step| step.apply(vector))
+}
+
+/// This returns whether a string matches a glob pattern, where '*' matches any run of characters
+/// and '?' matches any one character.
+pub(crate) fn glob_match(text: &[char], pattern: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_match(&text[skip..], rest)),
+        Some((c, rest)) => text.split_first().is_some_and(|(t, text)| (*c == '?' || c == t) && glob_match(text, rest)),
+    }
+}
+
+/// This strips the shortest suffix matching any of the glob patterns from a label, e.g. "_c"
+/// from "glc__D_c" with the pattern "_?". A label that no pattern matches, or that would be left
+/// empty, is returned whole.
+///
+/// # Arguments
+/// * `label` - the label
+/// * `patterns` - the suffix patterns, where '*' matches any run of characters and '?' any one
+///
+pub fn strip_suffix_pattern<'a>(label: &'a str, patterns: &[String]) -> &'a str {
+    let chars: Vec<char> = label.chars().collect();
+    let patterns: Vec<Vec<char>> = patterns.iter().map(|pattern| pattern.chars().collect()).collect();
+    (1..chars.len()).rev()
+        .find(|start| patterns.iter().any(|pattern| glob_match(&chars[*start..], pattern)))
+        .map_or(label, |start| &label[..label.char_indices().nth(start).unwrap().0])
+}
+
+/// This is a set of rows that are merged into one because their labels differ only by a suffix.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MergedRows {
+    /// The label of the merged row: the labels without their suffix
+    pub label: String,
+    /// The rows that are merged, in order
+    pub rows: Vec<usize>,
+}
+
+/// This groups rows whose labels are the same once the suffix matching a pattern is stripped, e.g.
+/// protonation states or compartment tags, so that they can be merged with `merge_rows`. Every
+/// row is in exactly one group, in order of first appearance; most groups hold a single row.
+///
+/// # Arguments
+/// * `labels` - the label of each row
+/// * `patterns` - the suffix patterns; see `strip_suffix_pattern`
+///
+pub fn group_rows_by_suffix(labels: &[String], patterns: &[String]) -> Vec<MergedRows> {
+    let mut groups: Vec<MergedRows> = vec!();
+    for (i, label) in labels.iter().enumerate() {
+        let base = strip_suffix_pattern(label, patterns);
+        match groups.iter_mut().find(|group| group.label == base) {
+            Some(group) => group.rows.push(i),
+            None => groups.push(MergedRows { label: base.to_string(), rows: vec![i] }),
+        }
+    }
+    // ** a row that is alone keeps its label, suffix and all
+    for group in groups.iter_mut().filter(|group| group.rows.len() == 1) {
+        group.label = labels[group.rows[0]].clone();
+    }
+    groups
+}
+
+/// This merges the rows of a matrix, e.g. a stoichiometric matrix or accumulation vector, by
+/// summing the rows of each group.
+///
+/// # Arguments
+/// * `matrix` - the matrix, with one row per row of the groups
+/// * `groups` - the groups of rows, one per row of the merged matrix
+///
+pub fn merge_rows(matrix: &na::DMatrix<f64>, groups: &[MergedRows]) -> na::DMatrix<f64> {
+    na::DMatrix::from_fn(groups.len(), matrix.ncols(), |i, j| groups[i].rows.iter().map(|row| matrix[(*row, j)]).sum())
+}
+
+/// This formats the merged groups of more than one row as '%' comment lines, with the labels of
+/// the rows that were merged.
+///
+/// # Arguments
+/// * `groups` - the groups of rows
+/// * `labels` - the label of each row before merging
+///
+pub fn format_merged_rows(groups: &[MergedRows], labels: &[String]) -> String {
+    let merged: Vec<String> = groups.iter()
+        .filter(|group| group.rows.len() > 1)
+        .map(|group| {
+            let names: Vec<&str> = group.rows.iter().map(|row| labels[*row].as_str()).collect();
+            format!("% merged {} into {}", names.join(" + "), group.label)
+        })
+        .collect();
+    if merged.is_empty() {
+        return String::from("% no rows were merged");
+    }
+    merged.join("\n")
+}
+
+/// This returns the current UTC date formatted as YYYY-MM-DD.
+pub fn utc_date_string() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// This converts a count of days since 1970-01-01 into a (year, month, day) civil date.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // ** see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
 /// This returns the default stoichiometric matrix as a nalgebra::DMatrix<f64>.
 pub fn default_s_matrix() -> na::DMatrix<f64> {
     na::DMatrix::from_row_slice(39, 28, crate::matrices::S_MAT)
@@ -36,3 +510,106 @@ pub fn print_matrix<R, C, S>(matrix: &na::Matrix<f64, R, C, S>)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use nalgebra as na;
+
+    #[test]
+    fn test_vectors_eq() {
+        let a = na::DVector::from_row_slice(&[1.0, 1000.0]);
+        let b = na::DVector::from_row_slice(&[1.0005, 1000.5]);
+        assert!(util::epsilon_eq(util::max_norm_distance(&a, &b), 0.5, 1e-12));
+        assert!(!util::vectors_eq(&a, &b, 1e-3, 0.0));
+        assert!(util::vectors_eq(&a, &b, 1e-3, 1e-3));
+        assert!(!util::vectors_eq(&a, &na::DVector::from_row_slice(&[1.0]), 1.0, 1.0));
+        assert!(util::max_norm_distance(&a, &na::DVector::from_row_slice(&[1.0])).is_infinite());
+        assert!(util::matrix_eq(&na::DMatrix::identity(2, 2), &na::DMatrix::identity(2, 2), 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_slice_deltas() {
+        // ** 7 values cover both the four wide chunk and the scalar tail
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let b = [1.0, 2.5, 3.0, 1.0, 5.0, 6.0, 9.0];
+        assert_eq!(util::max_abs_delta(&a, &b), 3.0);
+        assert_eq!(util::sum_squared_delta(&a, &b), 0.25 + 9.0 + 4.0);
+        assert_eq!(util::count_epsilon_eq(&a, &b, 1.0), 5);
+    }
+
+    #[test]
+    fn test_max_abs_delta_nan() {
+        // ** a NaN in the four wide chunk, in the scalar tail, or on either side is never a match
+        let a = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        for i in 0..a.len() {
+            let mut b = a;
+            b[i] = f64::NAN;
+            assert!(util::max_abs_delta(&a, &b).is_nan(), "NaN at {i}");
+            assert!(util::max_abs_delta(&b, &a).is_nan(), "NaN at {i}");
+        }
+        assert_eq!(util::max_abs_delta(&a, &a), 0.0);
+        assert!(util::max_norm_distance(
+            &na::DVector::from_row_slice(&[f64::NAN]),
+            &na::DVector::from_row_slice(&[f64::NAN]),
+        ).is_nan());
+    }
+
+    #[test]
+    fn test_sum_with() {
+        // ** 1 followed by many values below half an ulp of 1 is lost by naive summation
+        let mut values = vec![1.0];
+        values.extend(std::iter::repeat_n(1e-17, 100_000));
+        let exact = 1.0 + 1e-12;
+        assert_eq!(util::sum_with(&values, util::Summation::Naive), 1.0);
+        assert!((util::sum_with(&values, util::Summation::Compensated) - exact).abs() < 1e-16);
+        assert!((util::sum_with(&values, util::Summation::Pairwise) - exact).abs() < 1e-15);
+
+        let (a, b) = ([1.0, 2.0, 3.0], [1.0, 2.5, 1.0]);
+        for summation in [util::Summation::Naive, util::Summation::Compensated, util::Summation::Pairwise] {
+            assert_eq!(util::sum_squared_delta_with(&a, &b, summation), 4.25);
+        }
+    }
+
+    #[test]
+    fn test_preprocess() {
+        let treated = na::DVector::from_row_slice(&[3.0, 5.0]);
+        let control = na::DVector::from_row_slice(&[1.0, 1.0]);
+
+        // ** the steps do not commute, so their order matters
+        let steps = [util::Preprocess::Subtract(control.clone()), util::Preprocess::Scale(0.5)];
+        assert_eq!(util::preprocess(treated.clone(), &steps).unwrap(), na::DVector::from_row_slice(&[1.0, 2.0]));
+        let steps = [util::Preprocess::Scale(0.5), util::Preprocess::Add(control)];
+        assert_eq!(util::preprocess(treated.clone(), &steps).unwrap(), na::DVector::from_row_slice(&[2.5, 3.5]));
+        assert_eq!(util::preprocess(treated.clone(), &[]).unwrap(), treated);
+
+        let mismatched = [util::Preprocess::Add(na::DVector::zeros(3))];
+        assert!(matches!(util::preprocess(treated, &mismatched), Err(error::SmasError::Shape { expected: 2, found: 3 })));
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(util::civil_from_days(0), (1970, 1, 1));
+        assert_eq!(util::civil_from_days(19723), (2024, 1, 1));
+        assert_eq!(util::civil_from_days(19782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_merge_rows() {
+        let patterns = vec![String::from("_?"), String::from("[*]")];
+        assert_eq!(util::strip_suffix_pattern("glc__D_c", &patterns), "glc__D");
+        assert_eq!(util::strip_suffix_pattern("atp[cytosol]", &patterns), "atp");
+        assert_eq!(util::strip_suffix_pattern("_c", &patterns), "_c");
+        assert_eq!(util::strip_suffix_pattern("h2o", &patterns), "h2o");
+
+        let labels: Vec<String> = ["glc__D_e", "h2o", "glc__D_c", "atp[c]", "atp", "pi_c"].iter().map(|l| l.to_string()).collect();
+        let groups = util::group_rows_by_suffix(&labels, &patterns);
+        assert_eq!(groups.iter().map(|group| group.label.as_str()).collect::<Vec<&str>>(), ["glc__D", "h2o", "atp", "pi_c"]);
+        assert_eq!(groups[0].rows, [0, 2]);
+        assert_eq!(groups[2].rows, [3, 4]);
+
+        let matrix = na::DMatrix::from_row_slice(6, 2, &[1.0, 0.0, 2.0, 2.0, -1.0, 1.0, 3.0, 0.0, 4.0, -1.0, 5.0, 5.0]);
+        let merged = util::merge_rows(&matrix, &groups);
+        assert_eq!(merged, na::DMatrix::from_row_slice(4, 2, &[0.0, 1.0, 2.0, 2.0, 7.0, -1.0, 5.0, 5.0]));
+        assert_eq!(util::format_merged_rows(&groups, &labels), "% merged glc__D_e + glc__D_c into glc__D\n% merged atp[c] + atp into atp");
+    }
+}
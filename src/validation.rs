@@ -0,0 +1,256 @@
+use crate::error::SmasError;
+use crate::io::{self, FloatFormat, ParseMode};
+use crate::util;
+
+use nalgebra as na;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// This is one row of a validation manifest: a computed reaction vector and the true reaction
+/// vector it is compared to.
+pub struct ValidationPair {
+    /// The path of the computed reaction vector file
+    pub computed: PathBuf,
+    /// The path of the true reaction vector file
+    pub truth: PathBuf,
+}
+
+/// This summarizes the comparison of a computed reaction vector with a true one.
+pub struct PairSummary {
+    /// The number of reactions
+    pub n_reactions: usize,
+    /// The number of reactions whose computed flux is within epsilon of the true flux
+    pub n_within: usize,
+    /// The largest |delta| of any reaction
+    pub max_delta: f64,
+    /// The sum of the squared deltas of every reaction
+    pub sum_squared_delta: f64,
+}
+
+impl PairSummary {
+    /// This returns whether every reaction is within epsilon of its true flux.
+    pub fn passed(&self) -> bool {
+        self.n_within == self.n_reactions
+    }
+
+    /// This returns the root mean square delta of the reactions, 0 if there are none.
+    pub fn rms_delta(&self) -> f64 {
+        if self.n_reactions == 0 {
+            0.0
+        } else {
+            (self.sum_squared_delta / self.n_reactions as f64).sqrt()
+        }
+    }
+}
+
+/// This parses a validation manifest: one "computed,truth" row of comma separated paths per pair.
+/// A first row of "computed,truth" is a header, and blank lines and lines starting with '#' are
+/// skipped. Relative paths are relative to the directory of the manifest.
+///
+/// Returns SmasError::Parse if a row does not have two paths.
+///
+/// # Arguments
+/// * `contents` - the contents of the manifest
+/// * `base_dir` - the directory relative paths are resolved against
+///
+pub fn parse_pairs_manifest(contents: &str, base_dir: &Path) -> Result<Vec<ValidationPair>, SmasError> {
+    let mut pairs = vec!();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = trimmed.split(',').map(|field| field.trim().trim_matches('"')).collect();
+        if fields.len() != 2 || fields.iter().any(|field| field.is_empty()) {
+            return Err(SmasError::Parse {
+                line: i + 1,
+                message: format!("expected a computed path and a truth path separated by a comma, found \"{trimmed}\""),
+            });
+        }
+        if pairs.is_empty() && fields[0].eq_ignore_ascii_case("computed") && fields[1].eq_ignore_ascii_case("truth") {
+            continue;
+        }
+        pairs.push(ValidationPair {
+            computed: base_dir.join(fields[0]),
+            truth: base_dir.join(fields[1]),
+        });
+    }
+    Ok(pairs)
+}
+
+/// This reads a validation manifest file; see `parse_pairs_manifest`.
+///
+/// # Arguments
+/// * `path` - the path to the manifest
+///
+pub fn load_pairs_manifest<R: AsRef<Path>>(path: R) -> Result<Vec<ValidationPair>, SmasError> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).map_err(SmasError::Io)?;
+    parse_pairs_manifest(&contents, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// This compares a computed reaction vector with a true one, reaction by reaction.
+///
+/// Returns SmasError::Shape if the vectors have different lengths.
+///
+/// # Arguments
+/// * `reactions_computed` - the computed reaction vector
+/// * `reactions_true` - the true reaction vector
+/// * `epsilon` - the largest |delta| of a reaction within epsilon
+/// * `summation` - how the squared deltas are accumulated
+///
+pub fn compare_vectors(
+    reactions_computed: &na::DVector<f64>,
+    reactions_true: &na::DVector<f64>,
+    epsilon: f64,
+    summation: util::Summation,
+) -> Result<PairSummary, SmasError> {
+    if reactions_computed.nrows() != reactions_true.nrows() {
+        return Err(SmasError::Shape {
+            expected: reactions_true.nrows(),
+            found: reactions_computed.nrows(),
+        });
+    }
+    let (computed, truth) = (reactions_computed.as_slice(), reactions_true.as_slice());
+    Ok(PairSummary {
+        n_reactions: computed.len(),
+        n_within: util::count_epsilon_eq(computed, truth, epsilon),
+        max_delta: util::max_abs_delta(computed, truth),
+        sum_squared_delta: util::sum_squared_delta_with(computed, truth, summation),
+    })
+}
+
+/// This loads and compares one pair of a manifest.
+fn validate_pair(pair: &ValidationPair, mode: ParseMode, epsilon: f64, summation: util::Summation) -> Result<PairSummary, SmasError> {
+    let reactions_computed = io::load_vector_with_mode(&pair.computed, mode)?;
+    let reactions_true = io::load_vector_with_mode(&pair.truth, mode)?;
+    compare_vectors(&reactions_computed, &reactions_true, epsilon, summation)
+}
+
+/// This compares every pair of a manifest, on up to `jobs` threads. A pair whose files cannot be
+/// loaded or have different lengths gets its error, and the other pairs are still compared. The
+/// results are in the order of the pairs.
+///
+/// # Arguments
+/// * `pairs` - the pairs of computed and true reaction vector files
+/// * `mode` - whether deviations from the Matrix Market array format are errors
+/// * `epsilon` - the largest |delta| of a reaction within epsilon
+/// * `summation` - how the squared deltas are accumulated
+/// * `jobs` - the number of threads, at least 1
+///
+pub fn validate_pairs(
+    pairs: &[ValidationPair],
+    mode: ParseMode,
+    epsilon: f64,
+    summation: util::Summation,
+    jobs: usize,
+) -> Vec<Result<PairSummary, SmasError>> {
+    // ** each thread takes the next pair that no other thread has taken, until none are left
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<PairSummary, SmasError>>>> = Mutex::new((0..pairs.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, pairs.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= pairs.len() {
+                    break;
+                }
+                let result = validate_pair(&pairs[i], mode, epsilon, summation);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter()
+        .map(|result| result.expect("every pair is compared"))
+        .collect()
+}
+
+/// This formats the results of a batch validation: one row per pair, with the number of
+/// reactions within epsilon, the largest and root mean square delta, and whether it passed,
+/// followed by a summary over every pair.
+///
+/// # Arguments
+/// * `pairs` - the pairs of computed and true reaction vector files
+/// * `results` - the result of comparing each pair
+/// * `float_format` - how to format the floats: scientific or decimal
+/// * `float_precision` - how many positions the floats have past the decimal point
+///
+pub fn format_pair_results(
+    pairs: &[ValidationPair],
+    results: &[Result<PairSummary, SmasError>],
+    float_format: FloatFormat,
+    float_precision: usize,
+) -> String {
+    let format_float = |value: f64| io::format_float(value, float_format, float_precision);
+    let mut result_string = String::from("% computed \t truth \t within epsilon \t max |delta| \t rms delta \t passed");
+    let (mut n_passed, mut n_errors, mut n_reactions, mut max_delta, mut sum_squared_delta) = (0, 0, 0, 0.0_f64, 0.0);
+    for (pair, result) in pairs.iter().zip(results) {
+        let (computed, truth) = (pair.computed.display(), pair.truth.display());
+        match result {
+            Ok(summary) => {
+                result_string.push_str(&format!(
+                    "\n  {computed}\t{truth}\t{}/{}\t{}\t{}\t{}",
+                    summary.n_within,
+                    summary.n_reactions,
+                    format_float(summary.max_delta),
+                    format_float(summary.rms_delta()),
+                    summary.passed()
+                ));
+                n_passed += usize::from(summary.passed());
+                n_reactions += summary.n_reactions;
+                max_delta = max_delta.max(summary.max_delta);
+                sum_squared_delta += summary.sum_squared_delta;
+            }
+            Err(e) => {
+                n_errors += 1;
+                result_string.push_str(&format!("\n% error: {computed}\t{truth}\t{e}"));
+            }
+        }
+    }
+    let rms_delta = if n_reactions == 0 { 0.0 } else { (sum_squared_delta / n_reactions as f64).sqrt() };
+    result_string.push_str(&format!(
+        "\n% {n_passed}/{} pairs passed, {} failed, {n_errors} errors, max |delta|: {}, rms delta: {}",
+        pairs.len(),
+        pairs.len() - n_passed - n_errors,
+        format_float(max_delta),
+        format_float(rms_delta)
+    ));
+    result_string
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_validate_pairs() {
+        let pairs = validation::parse_pairs_manifest("computed,truth\n# a comment\n\na.txt, b.txt\n\"c.txt\",/d.txt\n", Path::new("runs")).unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((pairs[0].computed.as_path(), pairs[0].truth.as_path()), (Path::new("runs/a.txt"), Path::new("runs/b.txt")));
+        assert_eq!(pairs[1].truth.as_path(), Path::new("/d.txt"));
+        assert!(matches!(validation::parse_pairs_manifest("a.txt\n", Path::new("")), Err(error::SmasError::Parse { line: 1, .. })));
+
+        let dir = std::env::temp_dir().join(format!("smas_validation_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("truth.txt"), "2 1\n1\n2\n").unwrap();
+        std::fs::write(dir.join("good.txt"), "2 1\n1.0001\n2\n").unwrap();
+        std::fs::write(dir.join("bad.txt"), "2 1\n1\n3\n").unwrap();
+        std::fs::write(dir.join("short.txt"), "1 1\n1\n").unwrap();
+        let manifest = "good.txt,truth.txt\nbad.txt,truth.txt\nshort.txt,truth.txt\nmissing.txt,truth.txt\n";
+        let pairs = validation::parse_pairs_manifest(manifest, &dir).unwrap();
+        let results = validation::validate_pairs(&pairs, io::ParseMode::Lenient, 1e-3, util::Summation::default(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // ** the results are in manifest order, whichever thread compared them
+        assert!(results[0].as_ref().unwrap().passed());
+        let bad = results[1].as_ref().unwrap();
+        assert_eq!((bad.n_within, bad.n_reactions, bad.max_delta), (1, 2, 1.0));
+        assert!(matches!(results[2], Err(error::SmasError::Shape { expected: 2, found: 1 })));
+        assert!(matches!(results[3], Err(error::SmasError::Io(_))));
+
+        let output = validation::format_pair_results(&pairs, &results, io::FloatFormat::Decimal, 1);
+        assert!(output.ends_with("% 1/4 pairs passed, 1 failed, 2 errors, max |delta|: 1.0, rms delta: 0.5"), "{output}");
+    }
+}
@@ -1,5 +1,6 @@
 use wasm_bindgen::prelude::*;
 
+use crate::explain;
 use crate::solve;
 use crate::io;
 use crate::util;
@@ -22,4 +23,23 @@ pub fn solve_default(vector_string: String) -> String {
     let acc_vector = io::parse_vector(&vector_string);
     let result = solve::solve(acc_vector, util::default_s_matrix());
     io::format_vector_flat(&result, io::FloatFormat::Decimal, 5)
-}
\ No newline at end of file
+}
+
+#[wasm_bindgen]
+/// This narrates the default solve step by step, for the web tool. This uses the default
+/// stoichiometric matrix.
+///
+/// # Arguments
+/// * `vector_string` - The accumulation vector, B; (m x n); formatted as a whitespace delimted string
+///
+pub fn explain_default(vector_string: String) -> String {
+    let acc_vector = io::parse_vector(&vector_string);
+    let s_matrix = util::default_s_matrix();
+    match solve::solve_pinv(&acc_vector, &s_matrix) {
+        Ok(solution) => {
+            let row_labels: Vec<String> = (0..s_matrix.nrows()).map(|i| format!("M{}", i + 1)).collect();
+            explain::explain_solve(&acc_vector, &s_matrix, &solution, &row_labels)
+        }
+        Err(e) => format!("% failed to solve: {e}"),
+    }
+}